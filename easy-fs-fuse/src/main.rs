@@ -1,7 +1,7 @@
 use clap::{App, Arg};
-use easy_fs::{BlockDevice, EasyFileSystem};
+use easy_fs::{BlockDevice, EasyFileSystem, NAME_LENGTH_LIMIT};
 use std::fs::{read_dir, File, OpenOptions};
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
 use std::sync::Arc;
 use std::sync::Mutex;
 
@@ -24,6 +24,11 @@ impl BlockDevice for BlockFile {
         assert_eq!(file.write(buf).unwrap(), BLOCK_SZ, "Not a complete block!");
     }
 
+    fn num_blocks(&self) -> usize {
+        let file = self.0.lock().unwrap();
+        file.metadata().expect("Error when reading metadata!").len() as usize / BLOCK_SZ
+    }
+
     fn handle_irq(&self) {
         unimplemented!();
     }
@@ -75,6 +80,15 @@ fn easy_fs_pack() -> std::io::Result<()> {
         })
         .collect();
     for app in apps {
+        if app.len() > NAME_LENGTH_LIMIT {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "file name {} is longer than {} bytes",
+                    app, NAME_LENGTH_LIMIT
+                ),
+            ));
+        }
         // load app data from host file system
         let mut host_file = File::open(format!("{}{}", target_path, app)).unwrap();
         let mut all_data: Vec<u8> = Vec::new();
@@ -153,3 +167,43 @@ fn efs_test() -> std::io::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn efs_long_name_test() -> std::io::Result<()> {
+    let block_file = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open("target/fs_long_name.img")?;
+        f.set_len(8192 * 512).unwrap();
+        f
+    })));
+    EasyFileSystem::create(block_file.clone(), 4096, 1);
+    let efs = EasyFileSystem::open(block_file.clone());
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let names: Vec<String> = (0..200)
+        .map(|i| format!("{}{}", "x".repeat(i % 64), i))
+        .chain(std::iter::once("y".repeat(NAME_LENGTH_LIMIT)))
+        .collect();
+    for name in names.iter() {
+        assert!(root_inode.create(name).is_some());
+    }
+    assert!(root_inode.create(&names[0]).is_none());
+    assert!(root_inode
+        .create(&"z".repeat(NAME_LENGTH_LIMIT + 1))
+        .is_none());
+    assert_eq!(root_inode.ls(), names);
+    // a freshly opened file system has to rebuild its directory index from disk
+    let efs = EasyFileSystem::open(block_file);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    for (i, name) in names.iter().enumerate() {
+        let inode = root_inode.find(name).unwrap();
+        inode.write_at(0, &i.to_le_bytes());
+        let mut buf = [0u8; 8];
+        assert_eq!(inode.read_at(0, &mut buf), 8);
+        assert_eq!(usize::from_le_bytes(buf), i);
+    }
+    assert!(root_inode.find("missing").is_none());
+    Ok(())
+}
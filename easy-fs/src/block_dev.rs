@@ -3,5 +3,9 @@ use core::any::Any;
 pub trait BlockDevice: Send + Sync + Any {
     fn read_block(&self, block_id: usize, buf: &mut [u8]);
     fn write_block(&self, block_id: usize, buf: &[u8]);
+    /// Size of the device in blocks, `usize::MAX` when it is not known.
+    fn num_blocks(&self) -> usize {
+        usize::MAX
+    }
     fn handle_irq(&self);
 }
@@ -0,0 +1,46 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+/// In-memory hash index of one directory, so that a lookup does not have to
+/// walk every `DirEntry`.
+///
+/// It is built by a single scan the first time the directory is searched and
+/// then kept in step by `Inode::create`. Buckets only store where candidates
+/// live; names are compared against the disk copy, so hash collisions are fine.
+pub struct DirIndex {
+    /// name hash -> (entry offset, inode number)
+    buckets: BTreeMap<u32, Vec<(u32, u32)>>,
+    /// Offset of the last entry in the directory, new entries are carved out of it.
+    pub last: Option<u32>,
+}
+
+impl DirIndex {
+    pub fn new() -> Self {
+        Self {
+            buckets: BTreeMap::new(),
+            last: None,
+        }
+    }
+
+    pub fn insert(&mut self, name: &str, offset: u32, inode_id: u32) {
+        self.buckets
+            .entry(name_hash(name))
+            .or_default()
+            .push((offset, inode_id));
+    }
+
+    /// Entries whose name hashes like `name`.
+    pub fn candidates(&self, name: &str) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.buckets
+            .get(&name_hash(name))
+            .into_iter()
+            .flat_map(|bucket| bucket.iter().copied())
+    }
+}
+
+/// 32-bit FNV-1a.
+fn name_hash(name: &str) -> u32 {
+    name.bytes().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
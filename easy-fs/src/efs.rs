@@ -1,8 +1,9 @@
 use super::{
-    block_cache_sync_all, get_block_cache, Bitmap, BlockDevice, DiskInode, DiskInodeType, Inode,
-    SuperBlock,
+    block_cache_sync_all, get_block_cache, Bitmap, BlockDevice, DirIndex, DiskInode, DiskInodeType,
+    Inode, SuperBlock,
 };
 use crate::BLOCK_SZ;
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use spin::Mutex;
 
@@ -12,6 +13,8 @@ pub struct EasyFileSystem {
     pub data_bitmap: Bitmap,
     inode_area_start_block: u32,
     data_area_start_block: u32,
+    /// directory inode id -> its lookup index, built lazily.
+    dir_indexes: BTreeMap<u32, DirIndex>,
 }
 
 type DataBlock = [u8; BLOCK_SZ];
@@ -41,6 +44,7 @@ impl EasyFileSystem {
             data_bitmap,
             inode_area_start_block: 1 + inode_bitmap_blocks,
             data_area_start_block: 1 + inode_total_blocks + data_bitmap_blocks,
+            dir_indexes: BTreeMap::new(),
         };
         // clear all blocks
         for i in 0..total_blocks {
@@ -95,6 +99,7 @@ impl EasyFileSystem {
                     ),
                     inode_area_start_block: 1 + super_block.inode_bitmap_blocks,
                     data_area_start_block: 1 + inode_total_blocks + super_block.data_bitmap_blocks,
+                    dir_indexes: BTreeMap::new(),
                 };
                 Arc::new(Mutex::new(efs))
             })
@@ -105,7 +110,7 @@ impl EasyFileSystem {
         // acquire efs lock temporarily
         let (block_id, block_offset) = efs.lock().get_disk_inode_pos(0);
         // release efs lock
-        Inode::new(block_id, block_offset, Arc::clone(efs), block_device)
+        Inode::new(0, block_id, block_offset, Arc::clone(efs), block_device)
     }
 
     pub fn get_disk_inode_pos(&self, inode_id: u32) -> (u32, usize) {
@@ -118,6 +123,15 @@ impl EasyFileSystem {
         )
     }
 
+    /// Lookup index of directory `inode_id`, created by `build` on first use.
+    pub(crate) fn dir_index(
+        &mut self,
+        inode_id: u32,
+        build: impl FnOnce() -> DirIndex,
+    ) -> &mut DirIndex {
+        self.dir_indexes.entry(inode_id).or_insert_with(build)
+    }
+
     pub fn get_data_block_id(&self, data_block_id: u32) -> u32 {
         self.data_area_start_block + data_block_id
     }
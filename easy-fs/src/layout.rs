@@ -3,9 +3,9 @@ use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::fmt::{Debug, Formatter, Result};
 
-const EFS_MAGIC: u32 = 0x3b800001;
+const EFS_MAGIC: u32 = 0x3b800002;
 const INODE_DIRECT_COUNT: usize = 28;
-const NAME_LENGTH_LIMIT: usize = 27;
+pub const NAME_LENGTH_LIMIT: usize = 255;
 const INODE_INDIRECT1_COUNT: usize = BLOCK_SZ / 4;
 const INODE_INDIRECT2_COUNT: usize = INODE_INDIRECT1_COUNT * INODE_INDIRECT1_COUNT;
 const DIRECT_BOUND: usize = INODE_DIRECT_COUNT;
@@ -58,10 +58,10 @@ impl SuperBlock {
     }
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy)]
 pub enum DiskInodeType {
-    File,
-    Directory,
+    File = 1,
+    Directory = 2,
 }
 
 type IndirectBlock = [u32; BLOCK_SZ / 4];
@@ -370,40 +370,83 @@ impl DiskInode {
     }
 }
 
+/// Directory data is a sequence of variable-length records, laid out like ext2:
+/// a record never crosses a block boundary, and the last record of a block
+/// stretches to the end of it so that a new name can be carved out of the slack.
 #[repr(C)]
 pub struct DirEntry {
-    name: [u8; NAME_LENGTH_LIMIT + 1],
     inode_number: u32,
+    rec_len: u16,
+    name_len: u8,
+    file_type: u8,
+    name: [u8; NAME_LENGTH_LIMIT + 1],
 }
 
-pub const DIRENT_SZ: usize = 32;
+/// Size of the fixed part of a `DirEntry` on disk.
+pub const DIRENT_HEADER_SZ: usize = 8;
 
 impl DirEntry {
     pub fn empty() -> Self {
         Self {
-            name: [0u8; NAME_LENGTH_LIMIT + 1],
             inode_number: 0,
+            rec_len: 0,
+            name_len: 0,
+            file_type: 0,
+            name: [0u8; NAME_LENGTH_LIMIT + 1],
         }
     }
-    pub fn new(name: &str, inode_number: u32) -> Self {
+    /// The record takes exactly the space it needs; `set_rec_len` widens it.
+    pub fn new(name: &str, inode_number: u32, type_: DiskInodeType) -> Self {
+        assert!(name.len() <= NAME_LENGTH_LIMIT);
         let mut bytes = [0u8; NAME_LENGTH_LIMIT + 1];
         bytes[..name.len()].copy_from_slice(name.as_bytes());
         Self {
-            name: bytes,
             inode_number,
+            rec_len: Self::min_rec_len(name.len()) as u16,
+            name_len: name.len() as u8,
+            file_type: type_ as u8,
+            name: bytes,
         }
     }
+    /// Smallest record holding a name of `name_len` bytes, kept 4-byte aligned.
+    pub fn min_rec_len(name_len: usize) -> usize {
+        (DIRENT_HEADER_SZ + name_len + 3) & !3
+    }
+    /// Header and name as written to disk.
     pub fn as_bytes(&self) -> &[u8] {
-        unsafe { core::slice::from_raw_parts(self as *const _ as usize as *const u8, DIRENT_SZ) }
+        unsafe {
+            core::slice::from_raw_parts(
+                self as *const _ as usize as *const u8,
+                DIRENT_HEADER_SZ + self.name_len as usize,
+            )
+        }
+    }
+    pub fn header_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            core::slice::from_raw_parts_mut(self as *mut _ as usize as *mut u8, DIRENT_HEADER_SZ)
+        }
     }
-    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
-        unsafe { core::slice::from_raw_parts_mut(self as *mut _ as usize as *mut u8, DIRENT_SZ) }
+    pub fn name_bytes_mut(&mut self) -> &mut [u8] {
+        let len = self.name_len as usize;
+        &mut self.name[..len]
     }
     pub fn name(&self) -> &str {
-        let len = (0usize..).find(|i| self.name[*i] == 0).unwrap();
-        core::str::from_utf8(&self.name[..len]).unwrap()
+        core::str::from_utf8(&self.name[..self.name_len as usize]).unwrap()
+    }
+    pub fn name_len(&self) -> usize {
+        self.name_len as usize
     }
     pub fn inode_number(&self) -> u32 {
         self.inode_number
     }
+    pub fn rec_len(&self) -> usize {
+        self.rec_len as usize
+    }
+    pub fn set_rec_len(&mut self, rec_len: usize) {
+        self.rec_len = rec_len as u16;
+    }
+    /// Space left behind the name that a new record could take.
+    pub fn slack(&self) -> usize {
+        self.rec_len() - Self::min_rec_len(self.name_len())
+    }
 }
@@ -5,14 +5,18 @@ extern crate alloc;
 mod bitmap;
 mod block_cache;
 mod block_dev;
+mod dir_index;
 mod efs;
 mod layout;
 mod vfs;
 
 pub const BLOCK_SZ: usize = 512;
 use bitmap::Bitmap;
-use block_cache::{block_cache_sync_all, get_block_cache};
+use block_cache::block_cache_sync_all;
+pub use block_cache::get_block_cache;
 pub use block_dev::BlockDevice;
+use dir_index::DirIndex;
 pub use efs::EasyFileSystem;
+pub use layout::NAME_LENGTH_LIMIT;
 use layout::*;
 pub use vfs::Inode;
@@ -1,6 +1,6 @@
 use super::{
-    block_cache_sync_all, get_block_cache, BlockDevice, DirEntry, DiskInode, DiskInodeType,
-    EasyFileSystem, DIRENT_SZ,
+    block_cache_sync_all, get_block_cache, BlockDevice, DirEntry, DirIndex, DiskInode,
+    DiskInodeType, EasyFileSystem, BLOCK_SZ, DIRENT_HEADER_SZ, NAME_LENGTH_LIMIT,
 };
 use alloc::string::String;
 use alloc::sync::Arc;
@@ -8,6 +8,7 @@ use alloc::vec::Vec;
 use spin::{Mutex, MutexGuard};
 
 pub struct Inode {
+    inode_id: u32,
     block_id: usize,
     block_offset: usize,
     fs: Arc<Mutex<EasyFileSystem>>,
@@ -17,12 +18,14 @@ pub struct Inode {
 impl Inode {
     /// We should not acquire efs lock here.
     pub fn new(
+        inode_id: u32,
         block_id: u32,
         block_offset: usize,
         fs: Arc<Mutex<EasyFileSystem>>,
         block_device: Arc<dyn BlockDevice>,
     ) -> Self {
         Self {
+            inode_id,
             block_id: block_id as usize,
             block_offset,
             fs,
@@ -42,35 +45,80 @@ impl Inode {
             .modify(self.block_offset, f)
     }
 
-    fn find_inode_id(&self, name: &str, disk_inode: &DiskInode) -> Option<u32> {
-        // assert it is a directory
-        assert!(disk_inode.is_dir());
-        let file_count = (disk_inode.size as usize) / DIRENT_SZ;
+    /// Read the entry at `offset`; only the header is loaded unless `with_name`.
+    fn read_dirent(&self, offset: usize, disk_inode: &DiskInode, with_name: bool) -> DirEntry {
         let mut dirent = DirEntry::empty();
-        for i in 0..file_count {
+        assert_eq!(
+            disk_inode.read_at(offset, dirent.header_bytes_mut(), &self.block_device),
+            DIRENT_HEADER_SZ,
+        );
+        if with_name {
+            let name_len = dirent.name_len();
             assert_eq!(
-                disk_inode.read_at(DIRENT_SZ * i, dirent.as_bytes_mut(), &self.block_device,),
-                DIRENT_SZ,
+                disk_inode.read_at(
+                    offset + DIRENT_HEADER_SZ,
+                    dirent.name_bytes_mut(),
+                    &self.block_device
+                ),
+                name_len,
             );
-            if dirent.name() == name {
-                return Some(dirent.inode_number() as u32);
-            }
         }
-        None
+        dirent
+    }
+
+    /// Offsets of all entries in directory order.
+    fn dirent_offsets(&self, disk_inode: &DiskInode) -> Vec<usize> {
+        let mut offsets = Vec::new();
+        let mut offset = 0usize;
+        while offset < disk_inode.size as usize {
+            offsets.push(offset);
+            offset += self.read_dirent(offset, disk_inode, false).rec_len();
+        }
+        offsets
+    }
+
+    fn build_dir_index(&self, disk_inode: &DiskInode) -> DirIndex {
+        let mut index = DirIndex::new();
+        for offset in self.dirent_offsets(disk_inode) {
+            let dirent = self.read_dirent(offset, disk_inode, true);
+            index.insert(dirent.name(), offset as u32, dirent.inode_number());
+            index.last = Some(offset as u32);
+        }
+        index
+    }
+
+    fn find_inode_id(
+        &self,
+        name: &str,
+        disk_inode: &DiskInode,
+        fs: &mut EasyFileSystem,
+    ) -> Option<u32> {
+        // assert it is a directory
+        assert!(disk_inode.is_dir());
+        if name.len() > NAME_LENGTH_LIMIT {
+            return None;
+        }
+        let index = fs.dir_index(self.inode_id, || self.build_dir_index(disk_inode));
+        index
+            .candidates(name)
+            .find(|&(offset, _)| self.read_dirent(offset as usize, disk_inode, true).name() == name)
+            .map(|(_, inode_id)| inode_id)
     }
 
     pub fn find(&self, name: &str) -> Option<Arc<Inode>> {
-        let fs = self.fs.lock();
+        let mut fs = self.fs.lock();
         self.read_disk_inode(|disk_inode| {
-            self.find_inode_id(name, disk_inode).map(|inode_id| {
-                let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
-                Arc::new(Self::new(
-                    block_id,
-                    block_offset,
-                    self.fs.clone(),
-                    self.block_device.clone(),
-                ))
-            })
+            self.find_inode_id(name, disk_inode, &mut fs)
+                .map(|inode_id| {
+                    let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+                    Arc::new(Self::new(
+                        inode_id,
+                        block_id,
+                        block_offset,
+                        self.fs.clone(),
+                        self.block_device.clone(),
+                    ))
+                })
         })
     }
 
@@ -91,15 +139,54 @@ impl Inode {
         disk_inode.increase_size(new_size, v, &self.block_device);
     }
 
+    /// Append `dirent` to this directory, reusing the slack of the last entry when the
+    /// new record fits there and starting a new block otherwise.
+    fn append_dirent(
+        &self,
+        mut dirent: DirEntry,
+        disk_inode: &mut DiskInode,
+        fs: &mut MutexGuard<EasyFileSystem>,
+    ) {
+        let need = dirent.rec_len();
+        let last = fs
+            .dir_index(self.inode_id, || self.build_dir_index(disk_inode))
+            .last
+            .map(|offset| offset as usize);
+        let offset = match last {
+            Some(last) if self.read_dirent(last, disk_inode, false).slack() >= need => {
+                // shrink the last entry to its own size and take over the rest
+                let mut last_dirent = self.read_dirent(last, disk_inode, true);
+                let last_len = DirEntry::min_rec_len(last_dirent.name_len());
+                dirent.set_rec_len(last_dirent.rec_len() - last_len);
+                last_dirent.set_rec_len(last_len);
+                disk_inode.write_at(last, last_dirent.as_bytes(), &self.block_device);
+                last + last_len
+            }
+            _ => {
+                let offset = disk_inode.size as usize;
+                self.increase_size((offset + BLOCK_SZ) as u32, disk_inode, fs);
+                dirent.set_rec_len(BLOCK_SZ);
+                offset
+            }
+        };
+        disk_inode.write_at(offset, dirent.as_bytes(), &self.block_device);
+        let index = fs.dir_index(self.inode_id, DirIndex::new);
+        index.insert(dirent.name(), offset as u32, dirent.inode_number());
+        index.last = Some(offset as u32);
+    }
+
     pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
+        if name.is_empty() || name.len() > NAME_LENGTH_LIMIT {
+            return None;
+        }
         let mut fs = self.fs.lock();
-        let op = |root_inode: &mut DiskInode| {
+        let op = |root_inode: &DiskInode| {
             // assert it is a directory
             assert!(root_inode.is_dir());
             // has the file been created?
-            self.find_inode_id(name, root_inode)
+            self.find_inode_id(name, root_inode, &mut fs)
         };
-        if self.modify_disk_inode(op).is_some() {
+        if self.read_disk_inode(op).is_some() {
             return None;
         }
         // create a new file
@@ -114,23 +201,15 @@ impl Inode {
             });
         self.modify_disk_inode(|root_inode| {
             // append file in the dirent
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let new_size = (file_count + 1) * DIRENT_SZ;
-            // increase size
-            self.increase_size(new_size as u32, root_inode, &mut fs);
-            // write dirent
-            let dirent = DirEntry::new(name, new_inode_id);
-            root_inode.write_at(
-                file_count * DIRENT_SZ,
-                dirent.as_bytes(),
-                &self.block_device,
-            );
+            let dirent = DirEntry::new(name, new_inode_id, DiskInodeType::File);
+            self.append_dirent(dirent, root_inode, &mut fs);
         });
 
         let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
         block_cache_sync_all();
         // return inode
         Some(Arc::new(Self::new(
+            new_inode_id,
             block_id,
             block_offset,
             self.fs.clone(),
@@ -142,17 +221,10 @@ impl Inode {
     pub fn ls(&self) -> Vec<String> {
         let _fs = self.fs.lock();
         self.read_disk_inode(|disk_inode| {
-            let file_count = (disk_inode.size as usize) / DIRENT_SZ;
-            let mut v: Vec<String> = Vec::new();
-            for i in 0..file_count {
-                let mut dirent = DirEntry::empty();
-                assert_eq!(
-                    disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device,),
-                    DIRENT_SZ,
-                );
-                v.push(String::from(dirent.name()));
-            }
-            v
+            self.dirent_offsets(disk_inode)
+                .into_iter()
+                .map(|offset| String::from(self.read_dirent(offset, disk_inode, true).name()))
+                .collect()
         })
     }
 
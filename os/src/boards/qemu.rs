@@ -1,64 +1,220 @@
 pub const CLOCK_FREQ: usize = 10_000_000;
 pub const MEMORY_END: usize = 0x8800_0000;
 
-pub const MMIO: &[(usize, usize)] = &[
-    (0x0010_0000, 0x00_2000), // VIRT_TEST/RTC  in virt machine
-    (0x2000000, 0x10000),     // core local interrupter (CLINT)
-    (0xc000000, 0x210000),    // VIRT_PLIC in virt machine
-    (0x10000000, 0x9000),     // VIRT_UART0 with GPU  in virt machine
-];
-
-pub type BlockDeviceImpl = crate::drivers::block::VirtIOBlock;
-pub type CharDeviceImpl = crate::drivers::chardev::NS16550a<VIRT_UART>;
-
-pub const VIRT_PLIC: usize = 0xC00_0000;
-pub const VIRT_UART: usize = 0x1000_0000;
+pub type CharDeviceImpl = crate::drivers::chardev::NS16550a;
+
 #[allow(unused)]
 pub const VIRTGPU_XRES: u32 = 1280;
 #[allow(unused)]
 pub const VIRTGPU_YRES: u32 = 800;
 
-use crate::drivers::{
-    block::BLOCK_DEVICE,
-    chardev::{
-        CharDevice,
-        UART,
-    },
-    plic::{
-        IntrTargetPriority,
-        PLIC,
+use crate::{
+    config::PAGE_SIZE,
+    drivers::{
+        chardev::{
+            CharDevice,
+            UART,
+        },
+        pci,
+        plic::{
+            IntrTargetPriority,
+            PLIC,
+        },
+        virtio,
     },
-    KEYBOARD_DEVICE,
-    MOUSE_DEVICE,
+    fdt::Fdt,
+    fs::console_receive,
+    sbi::shutdown,
+    sync::UPIntrFreeCell,
 };
+use alloc::{
+    collections::BTreeMap,
+    sync::Arc,
+    vec::Vec,
+};
+use lazy_static::*;
+
+type IrqHandler = Arc<dyn Fn() + Send + Sync>;
+
+/// Devices without a driver here yet, whose registers are mapped all the
+/// same.
+const OTHER_DEVICES: &[&str] = &["sifive,test0"];
+
+/// What the device tree told us about the machine.
+struct Platform {
+    plic: usize,
+    uart: usize,
+    uart_irq: usize,
+    rtc: usize,
+    /// Registers of the devices above and `OTHER_DEVICES`.
+    mmio: Vec<(usize, usize)>,
+}
+
+lazy_static! {
+    static ref PLATFORM: UPIntrFreeCell<Platform> = unsafe {
+        UPIntrFreeCell::new(Platform {
+            plic: 0,
+            uart: 0,
+            uart_irq: 0,
+            rtc: 0,
+            mmio: Vec::new(),
+        })
+    };
+    /// Handlers and names of every interrupt source drivers asked for. PCI
+    /// functions share their INTx lines, so a source may have several.
+    static ref IRQ_HANDLERS: UPIntrFreeCell<BTreeMap<usize, Vec<(&'static str, IrqHandler)>>> =
+        unsafe { UPIntrFreeCell::new(BTreeMap::new()) };
+}
+
+/// Find the devices in the device tree at `dtb`. Runs before paging, when
+/// device registers can be read where they are, and before anything is
+/// printed, which needs the UART.
+pub fn probe(dtb: usize) {
+    let nodes = match unsafe { Fdt::from_addr(dtb) } {
+        Some(fdt) => fdt.nodes(),
+        None => Vec::new(),
+    };
+    let mut platform = PLATFORM.exclusive_access();
+    for node in nodes.iter() {
+        let (base, size) = match node.reg().first() {
+            Some(&reg) => reg,
+            None => continue,
+        };
+        if node.is_compatible("virtio,mmio") {
+            if let Some(irq) = node.irq() {
+                virtio::add_mmio_slot(base, size, irq);
+            }
+            continue;
+        }
+        if node.is_compatible("riscv,plic0") || node.is_compatible("sifive,plic-1.0.0") {
+            platform.plic = base;
+        } else if node.is_compatible("ns16550a") && platform.uart == 0 {
+            platform.uart = base;
+            platform.uart_irq = node.irq().unwrap_or(0);
+        } else if node.is_compatible("google,goldfish-rtc") {
+            platform.rtc = base;
+        } else if !OTHER_DEVICES
+            .iter()
+            .any(|device| node.is_compatible(device))
+        {
+            continue;
+        }
+        platform.mmio.push((base, size));
+    }
+    let (uart, plic) = (platform.uart, platform.plic);
+    drop(platform);
+    // without a console there is no way to even say what went wrong
+    if uart == 0 {
+        shutdown(true);
+    }
+    if plic == 0 {
+        panic!("no PLIC in the device tree");
+    }
+    if let Some(host) = nodes
+        .iter()
+        .find(|node| node.is_compatible("pci-host-ecam-generic"))
+    {
+        pci::probe(host);
+    }
+}
 
+pub fn uart_base() -> usize {
+    PLATFORM.exclusive_access().uart
+}
+
+/// The Goldfish RTC, which QEMU's virt machine has and others may not.
+pub fn rtc_base() -> Option<usize> {
+    Some(PLATFORM.exclusive_access().rtc).filter(|base| *base != 0)
+}
+
+/// Device registers, which the kernel maps as they are. Regions sharing a
+/// page are merged.
+pub fn mmio_regions() -> Vec<(usize, usize)> {
+    let mut regions = PLATFORM.exclusive_access().mmio.clone();
+    regions.extend(virtio::mmio_regions());
+    regions.extend(pci::mmio_regions());
+    regions.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (base, size) in regions {
+        let start = base & !(PAGE_SIZE - 1);
+        let end = (base + size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        match merged.last_mut() {
+            Some(last) if start <= last.0 + last.1 => last.1 = last.1.max(end - last.0),
+            _ => merged.push((start, end - start)),
+        }
+    }
+    merged
+}
+
+fn plic() -> PLIC {
+    unsafe { PLIC::new(PLATFORM.exclusive_access().plic) }
+}
+
+/// Route interrupt source `irq` to `handler`, shown as `name` in
+/// `/proc/interrupts`. Handlers of a shared source all run on every
+/// interrupt from it.
+pub fn register_irq(irq: usize, name: &'static str, handler: impl Fn() + Send + Sync + 'static) {
+    let handler: IrqHandler = Arc::new(handler);
+    IRQ_HANDLERS
+        .exclusive_access()
+        .entry(irq)
+        .or_default()
+        .push((name, handler));
+    let mut plic = plic();
+    plic.enable(0, IntrTargetPriority::Supervisor, irq);
+    plic.set_priority(irq, 1);
+}
+
+/// Interrupts from the console. The virtio drivers register theirs as they
+/// bring up their devices.
 pub fn device_init() {
     use riscv::register::sie;
-    let mut plic = unsafe { PLIC::new(VIRT_PLIC) };
+    let mut plic = plic();
     let hart_id: usize = 0;
-    let supervisor = IntrTargetPriority::Supervisor;
-    let machine = IntrTargetPriority::Machine;
-    plic.set_threshold(hart_id, supervisor, 0);
-    plic.set_threshold(hart_id, machine, 1);
-    //irq nums: 5 keyboard, 6 mouse, 8 block, 10 uart
-    for intr_src_id in [5usize, 6, 8, 10] {
-        plic.enable(hart_id, supervisor, intr_src_id);
-        plic.set_priority(intr_src_id, 1);
+    plic.set_threshold(hart_id, IntrTargetPriority::Supervisor, 0);
+    plic.set_threshold(hart_id, IntrTargetPriority::Machine, 1);
+    let uart_irq = PLATFORM.exclusive_access().uart_irq;
+    if uart_irq != 0 {
+        register_irq(uart_irq, "uart", || {
+            UART.handle_irq();
+            console_receive();
+        });
     }
     unsafe {
         sie::set_sext();
     }
 }
 
+/// Device behind an interrupt, the first one of a shared source, for
+/// `/proc/interrupts`.
+pub fn irq_name(irq: usize) -> &'static str {
+    if irq == 0 {
+        return "timer";
+    }
+    IRQ_HANDLERS
+        .exclusive_access()
+        .get(&irq)
+        .and_then(|handlers| handlers.first())
+        .map_or("unknown", |(name, _)| *name)
+}
+
 pub fn irq_handler() {
-    let mut plic = unsafe { PLIC::new(VIRT_PLIC) };
+    let mut plic = plic();
     let intr_src_id = plic.claim(0, IntrTargetPriority::Supervisor);
-    match intr_src_id {
-        5 => KEYBOARD_DEVICE.handle_irq(),
-        6 => MOUSE_DEVICE.handle_irq(),
-        8 => BLOCK_DEVICE.handle_irq(),
-        10 => UART.handle_irq(),
-        _ => panic!("unsupported IRQ {}", intr_src_id),
+    // a spurious claim, nothing is pending after all
+    if intr_src_id == 0 {
+        return;
+    }
+    crate::trap::count_interrupt(intr_src_id);
+    let handlers = IRQ_HANDLERS.exclusive_access().get(&intr_src_id).cloned();
+    match handlers {
+        Some(handlers) => {
+            for (_, handler) in handlers {
+                handler();
+            }
+        }
+        None => panic!("unsupported IRQ {}", intr_src_id),
     }
     plic.complete(0, IntrTargetPriority::Supervisor, intr_src_id);
+    crate::net::net_bottom_half();
 }
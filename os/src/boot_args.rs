@@ -0,0 +1,38 @@
+//! The kernel command line, `/chosen/bootargs` in the device tree.
+
+use alloc::string::String;
+use lazy_static::lazy_static;
+use log::info;
+
+use crate::{
+    fdt::Fdt,
+    sync::UPIntrFreeCell,
+};
+
+lazy_static! {
+    static ref BOOT_ARGS: UPIntrFreeCell<String> = unsafe { UPIntrFreeCell::new(String::new()) };
+}
+
+/// Copy the command line out of the device tree at `dtb`, before its
+/// memory is handed out.
+pub fn init(dtb: usize) {
+    let bootargs = unsafe { Fdt::from_addr(dtb) }
+        .and_then(|fdt| fdt.property("/chosen", "bootargs"))
+        .and_then(|value| core::str::from_utf8(value).ok())
+        .map(|args| args.trim_end_matches('\0').trim());
+    if let Some(bootargs) = bootargs {
+        info!("KERN: boot args \"{}\"", bootargs);
+        *BOOT_ARGS.exclusive_access() = String::from(bootargs);
+    }
+}
+
+/// The value of `key=value` on the command line.
+pub fn boot_arg(key: &str) -> Option<String> {
+    BOOT_ARGS
+        .exclusive_access()
+        .split_whitespace()
+        .find_map(|arg| match arg.split_once('=') {
+            Some((k, value)) if k == key => Some(String::from(value)),
+            _ => None,
+        })
+}
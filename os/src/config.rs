@@ -11,6 +11,8 @@ pub const USER_STACK_TOP: usize = 0x0003_ffff_f000;
 pub const USER_STACK_BOTTOM: usize = USER_STACK_TOP - TASK_STACK_SIZE;
 
 pub const USER_STACK_SIZE: usize = 4096 * 2;
+/// Where `mmap` starts looking for space when the caller gives no address.
+pub const MMAP_BASE: usize = 0x0002_0000_0000;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
 
@@ -20,5 +22,4 @@ pub const TRAP_CONTEXT_BASE: usize = TRAMPOLINE - PAGE_SIZE;
 pub use crate::board::{
     CLOCK_FREQ,
     MEMORY_END,
-    MMIO,
 };
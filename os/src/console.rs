@@ -2,22 +2,41 @@ use crate::drivers::chardev::{
     CharDevice,
     UART,
 };
-use core::fmt::{
-    self,
-    Write,
+use core::{
+    fmt::{
+        self,
+        Write,
+    },
+    sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
 };
 
+/// Set by the panic handler. From then on output skips the UART's
+/// transmit ring, as no interrupt may come to drain it.
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
 struct Stdout;
 
 impl Write for Stdout {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            UART.write(c as u8);
+        if PANICKING.load(Ordering::Relaxed) {
+            UART.write_panic(s.as_bytes());
+            return Ok(());
+        }
+        for c in s.bytes() {
+            UART.write(c);
         }
         Ok(())
     }
 }
 
+/// Print synchronously from now on, for the panic handler.
+pub fn enter_panic() {
+    PANICKING.store(true, Ordering::Relaxed);
+}
+
 pub fn print(args: fmt::Arguments) {
     Stdout.write_fmt(args).unwrap();
 }
@@ -1,19 +1,16 @@
 mod virtio_blk;
 
-pub use virtio_blk::VirtIOBlock;
+pub use virtio_blk::{
+    probe,
+    VirtIOBlock,
+};
 
-use crate::board::BlockDeviceImpl;
-use alloc::sync::Arc;
+use super::block_device;
 use easy_fs::BlockDevice;
-use lazy_static::*;
-
-lazy_static! {
-    pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = Arc::new(BlockDeviceImpl::new());
-}
 
 #[allow(unused)]
 pub fn block_device_test() {
-    let block_device = BLOCK_DEVICE.clone();
+    let block_device = block_device().expect("no block device");
     let mut write_buffer = [0u8; 512];
     let mut read_buffer = [0u8; 512];
     for i in 0..512 {
@@ -1,6 +1,14 @@
 use super::BlockDevice;
 use crate::{
-    drivers::bus::virtio::VirtioHal,
+    board::register_irq,
+    drivers::{
+        add_block_device,
+        bus::virtio::{
+            VirtioHal,
+            VirtioSlot,
+            VirtioTransport,
+        },
+    },
     sync::{
         Condvar,
         UPIntrFreeCell,
@@ -8,90 +16,129 @@ use crate::{
     task::schedule,
     DEV_NON_BLOCKING_ACCESS,
 };
-use alloc::collections::BTreeMap;
+use alloc::{
+    collections::BTreeMap,
+    sync::Arc,
+};
 use virtio_drivers::{
-    BlkResp,
-    RespStatus,
-    VirtIOBlk,
-    VirtIOHeader,
+    device::blk::{
+        BlkReq,
+        BlkResp,
+        VirtIOBlk,
+    },
+    transport::Transport,
 };
 
-#[allow(unused)]
-const VIRTIO0: usize = 0x10008000;
-
-pub struct VirtIOBlock {
-    virtio_blk: UPIntrFreeCell<VirtIOBlk<'static, VirtioHal>>,
+pub struct VirtIOBlock<T: Transport> {
+    virtio_blk: UPIntrFreeCell<VirtIOBlk<VirtioHal, T>>,
     condvars: BTreeMap<u16, Condvar>,
 }
 
-impl BlockDevice for VirtIOBlock {
+// the device is only reached through the cell
+unsafe impl<T: Transport> Send for VirtIOBlock<T> {}
+
+impl<T: Transport + 'static> BlockDevice for VirtIOBlock<T> {
     fn read_block(&self, block_id: usize, buf: &mut [u8]) {
         let nb = *DEV_NON_BLOCKING_ACCESS.exclusive_access();
         if nb {
+            let mut req = BlkReq::default();
             let mut resp = BlkResp::default();
-            let task_ctx_ptr = self.virtio_blk.exclusive_session(|blk| {
-                let token = unsafe { blk.read_block_nb(block_id, buf, &mut resp).unwrap() };
-                self.condvars.get(&token).unwrap().wait_no_sched()
+            let (token, task_ctx_ptr) = self.virtio_blk.exclusive_session(|blk| {
+                let token = unsafe {
+                    blk.read_blocks_nb(block_id, &mut req, buf, &mut resp)
+                        .unwrap()
+                };
+                (token, self.condvars.get(&token).unwrap().wait_no_sched())
             });
             schedule(task_ctx_ptr);
-            assert_eq!(
-                resp.status(),
-                RespStatus::Ok,
-                "Error when reading VirtIOBlk"
-            );
+            self.complete(|blk| unsafe { blk.complete_read_blocks(token, &req, buf, &mut resp) })
+                .expect("Error when reading VirtIOBlk");
         } else {
             self.virtio_blk
                 .exclusive_access()
-                .read_block(block_id, buf)
+                .read_blocks(block_id, buf)
                 .expect("Error when reading VirtIOBlk");
         }
     }
     fn write_block(&self, block_id: usize, buf: &[u8]) {
         let nb = *DEV_NON_BLOCKING_ACCESS.exclusive_access();
         if nb {
+            let mut req = BlkReq::default();
             let mut resp = BlkResp::default();
-            let task_ctx_ptr = self.virtio_blk.exclusive_session(|blk| {
-                let token = unsafe { blk.write_block_nb(block_id, buf, &mut resp).unwrap() };
-                self.condvars.get(&token).unwrap().wait_no_sched()
+            let (token, task_ctx_ptr) = self.virtio_blk.exclusive_session(|blk| {
+                let token = unsafe {
+                    blk.write_blocks_nb(block_id, &mut req, buf, &mut resp)
+                        .unwrap()
+                };
+                (token, self.condvars.get(&token).unwrap().wait_no_sched())
             });
             schedule(task_ctx_ptr);
-            assert_eq!(
-                resp.status(),
-                RespStatus::Ok,
-                "Error when writing VirtIOBlk"
-            );
+            self.complete(|blk| unsafe { blk.complete_write_blocks(token, &req, buf, &mut resp) })
+                .expect("Error when writing VirtIOBlk");
         } else {
             self.virtio_blk
                 .exclusive_access()
-                .write_block(block_id, buf)
+                .write_blocks(block_id, buf)
                 .expect("Error when writing VirtIOBlk");
         }
     }
+    fn num_blocks(&self) -> usize {
+        self.virtio_blk.exclusive_access().capacity() as usize
+    }
+    /// Wakes the waiter of the request the device finished first. Requests
+    /// are taken back in the order they finished, so the others are woken
+    /// one after the other in `complete`.
     fn handle_irq(&self) {
         self.virtio_blk.exclusive_session(|blk| {
-            while let Ok(token) = blk.pop_used() {
+            blk.ack_interrupt();
+            if let Some(token) = blk.peek_used() {
                 self.condvars.get(&token).unwrap().signal();
             }
         });
     }
 }
 
-impl VirtIOBlock {
-    pub fn new() -> Self {
-        let virtio_blk = unsafe {
-            UPIntrFreeCell::new(
-                VirtIOBlk::<VirtioHal>::new(&mut *(VIRTIO0 as *mut VirtIOHeader)).unwrap(),
-            )
-        };
+impl<T: Transport> VirtIOBlock<T> {
+    pub fn new(transport: T) -> virtio_drivers::Result<Self> {
+        let virtio_blk = unsafe { UPIntrFreeCell::new(VirtIOBlk::<VirtioHal, T>::new(transport)?) };
         let mut condvars = BTreeMap::new();
         let channels = virtio_blk.exclusive_access().virt_queue_size();
         for i in 0..channels {
             let condvar = Condvar::new();
             condvars.insert(i, condvar);
         }
-        Self {
+        Ok(Self {
             virtio_blk,
             condvars,
-        }
+        })
+    }
+
+    /// Take back a finished request, then wake the waiter of the next one
+    /// if the device finished that too.
+    fn complete(
+        &self, take: impl FnOnce(&mut VirtIOBlk<VirtioHal, T>) -> virtio_drivers::Result,
+    ) -> virtio_drivers::Result {
+        self.virtio_blk.exclusive_session(|blk| {
+            let result = take(blk);
+            if let Some(token) = blk.peek_used() {
+                self.condvars.get(&token).unwrap().signal();
+            }
+            result
+        })
+    }
+}
+
+fn add<T: Transport + 'static>(transport: T, irq: usize) -> virtio_drivers::Result {
+    let device = Arc::new(VirtIOBlock::new(transport)?);
+    let irq_device = device.clone();
+    register_irq(irq, "virtio-blk", move || irq_device.handle_irq());
+    add_block_device(device);
+    Ok(())
+}
+
+pub fn probe(slot: &VirtioSlot) -> virtio_drivers::Result {
+    match slot.transport()? {
+        VirtioTransport::Mmio(transport) => add(transport, slot.irq()),
+        VirtioTransport::Pci(transport) => add(transport, slot.irq()),
     }
 }
@@ -0,0 +1,357 @@
+//! PCI functions behind the ECAM host bridge of QEMU's virt machine.
+//! Nothing sets them up before the kernel does, so BARs get addresses from
+//! the bridge's windows here and INTx pins are routed to the PLIC. Only
+//! bus 0 is scanned, bridges are left alone.
+
+use crate::{
+    fdt::{
+        read_cells,
+        FdtNode,
+    },
+    sync::UPIntrFreeCell,
+};
+use alloc::vec::Vec;
+use core::fmt;
+use lazy_static::*;
+
+const PCI_VENDOR_ID: usize = 0x00;
+/// The command register, with the status register in the upper half.
+const PCI_COMMAND: usize = 0x04;
+const PCI_HEADER_TYPE: usize = 0x0e;
+const PCI_BAR0: usize = 0x10;
+const PCI_CAPABILITY_LIST: usize = 0x34;
+const PCI_INTERRUPT_LINE: usize = 0x3c;
+const PCI_INTERRUPT_PIN: usize = 0x3d;
+
+const PCI_COMMAND_IO: u32 = 1 << 0;
+const PCI_COMMAND_MEMORY: u32 = 1 << 1;
+const PCI_COMMAND_MASTER: u32 = 1 << 2;
+const PCI_COMMAND_INTX_DISABLE: u32 = 1 << 10;
+const PCI_STATUS_CAP_LIST: u32 = 1 << (16 + 4);
+
+const PCI_CAP_ID_MSI: u8 = 0x05;
+const PCI_CAP_ID_MSIX: u8 = 0x11;
+const PCI_MSI_ENABLE: u32 = 1 << 16;
+const PCI_MSIX_ENABLE: u32 = 1 << 31;
+
+/// Space codes in the high cell of a PCI address in `ranges`.
+const SPACE_IO: usize = 1;
+const SPACE_MEM32: usize = 2;
+
+/// Configuration space of bus 0, 32 devices of 8 functions.
+const ECAM_BUS_SIZE: usize = 32 * 8 * 0x1000;
+
+lazy_static! {
+    static ref FUNCTIONS: UPIntrFreeCell<Vec<PciFunction>> =
+        unsafe { UPIntrFreeCell::new(Vec::new()) };
+    /// Where the host bridge's configuration space starts, 0 without one.
+    static ref ECAM: UPIntrFreeCell<usize> = unsafe { UPIntrFreeCell::new(0) };
+}
+
+/// A function on bus 0, set up and ready for a driver.
+#[derive(Copy, Clone)]
+pub struct PciFunction {
+    pub device: u8,
+    pub function: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    /// CPU address and size of every BAR that got room, I/O ones included.
+    pub bars: [Option<(usize, usize)>; 6],
+    /// The PLIC source its INTx pin is wired to, 0 without a pin.
+    pub irq: usize,
+}
+
+impl fmt::Display for PciFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "00:{:02x}.{}", self.device, self.function)
+    }
+}
+
+/// Configuration space of one function.
+#[derive(Copy, Clone)]
+struct Config(usize);
+
+impl Config {
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { ((self.0 + offset) as *const u32).read_volatile() }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { ((self.0 + offset) as *mut u32).write_volatile(value) }
+    }
+
+    fn read_u8(&self, offset: usize) -> u8 {
+        (self.read(offset & !3) >> ((offset & 3) * 8)) as u8
+    }
+}
+
+/// Bus addresses the bridge forwards, handed out to BARs in order.
+struct Window {
+    pci: usize,
+    cpu: usize,
+    size: usize,
+    next: usize,
+}
+
+impl Window {
+    /// Room for `size` bytes aligned to `size`, as a PCI and a CPU address.
+    fn alloc(&mut self, size: usize) -> Option<(usize, usize)> {
+        let at = (self.next + size - 1) & !(size - 1);
+        if at + size > self.pci + self.size {
+            return None;
+        }
+        self.next = at + size;
+        Some((at, at - self.pci + self.cpu))
+    }
+}
+
+/// The I/O and the 32-bit memory window from `ranges`. The 64-bit window
+/// is not used, 32-bit BARs could not point into it.
+fn windows(node: &FdtNode) -> (Option<Window>, Option<Window>) {
+    let value = node.property("ranges").unwrap_or(&[]);
+    let size_cells = node
+        .property("#size-cells")
+        .and_then(|cells| read_cells(cells, 0, 1))
+        .unwrap_or(2);
+    // a PCI address takes three cells, the CPU address is the parent's
+    let cells = 3 + node.address_cells + size_cells;
+    let (mut io, mut mem) = (None, None);
+    let entries = (0..value.len() / 4 / cells).filter_map(|i| {
+        let at = i * cells;
+        let space = (read_cells(value, at, 1)? >> 24) & 3;
+        let pci = read_cells(value, at + 1, 2)?;
+        let cpu = read_cells(value, at + 3, node.address_cells)?;
+        let size = read_cells(value, at + 3 + node.address_cells, size_cells)?;
+        Some((
+            space,
+            Window {
+                pci,
+                cpu,
+                size,
+                next: pci,
+            },
+        ))
+    });
+    for (space, window) in entries {
+        match space {
+            SPACE_IO => io = Some(window),
+            SPACE_MEM32 => mem = Some(window),
+            _ => {}
+        }
+    }
+    (io, mem)
+}
+
+/// `interrupt-map` with its mask.
+struct InterruptMap {
+    address_mask: usize,
+    pin_mask: usize,
+    /// Device and function bits of the address, pin and PLIC source.
+    entries: Vec<(usize, usize, usize)>,
+}
+
+impl InterruptMap {
+    /// Entries are laid out the way QEMU writes them: three address
+    /// cells, the pin, the PLIC's phandle and the source.
+    fn new(node: &FdtNode) -> Self {
+        let mask = node.property("interrupt-map-mask").unwrap_or(&[]);
+        let value = node.property("interrupt-map").unwrap_or(&[]);
+        Self {
+            address_mask: read_cells(mask, 0, 1).unwrap_or(0),
+            pin_mask: read_cells(mask, 3, 1).unwrap_or(0),
+            entries: value
+                .chunks_exact(6 * 4)
+                .filter_map(|entry| {
+                    Some((
+                        read_cells(entry, 0, 1)?,
+                        read_cells(entry, 3, 1)?,
+                        read_cells(entry, 5, 1)?,
+                    ))
+                })
+                .collect(),
+        }
+    }
+
+    fn route(&self, device: u8, function: u8, pin: usize) -> usize {
+        let address = (device as usize) << 11 | (function as usize) << 8;
+        self.entries
+            .iter()
+            .find(|&&(entry_address, entry_pin, _)| {
+                entry_address == address & self.address_mask && entry_pin == pin & self.pin_mask
+            })
+            .map_or(0, |&(_, _, irq)| irq)
+    }
+}
+
+/// Size every BAR by writing all ones to it, and place it in the window
+/// of its kind. A BAR that does not fit keeps what it had and is left out.
+fn assign_bars(
+    config: Config, io: &mut Option<Window>, mem: &mut Option<Window>,
+) -> [Option<(usize, usize)>; 6] {
+    let mut bars = [None; 6];
+    let mut bar = 0;
+    while bar < 6 {
+        let offset = PCI_BAR0 + bar * 4;
+        let original = config.read(offset);
+        let is_io = original & 1 == 1;
+        let is_64 = !is_io && (original >> 1) & 3 == 2 && bar < 5;
+        let original_high = if is_64 { config.read(offset + 4) } else { 0 };
+        config.write(offset, 0xffff_ffff);
+        let mask = config.read(offset);
+        let size = if is_io {
+            // the upper half of an I/O BAR may be hardwired to 0
+            ((!(mask & !0x3)).wrapping_add(1) & 0xffff) as usize
+        } else {
+            let mut mask = (mask & !0xf) as u64 | 0xffff_ffff_0000_0000;
+            if is_64 {
+                config.write(offset + 4, 0xffff_ffff);
+                mask = (mask & 0xffff_ffff) | (config.read(offset + 4) as u64) << 32;
+            }
+            (!mask).wrapping_add(1) as usize
+        };
+        let window = if is_io { io.as_mut() } else { mem.as_mut() };
+        match window
+            .filter(|_| mask != 0 && size != 0)
+            .and_then(|window| window.alloc(size))
+        {
+            Some((pci, cpu)) => {
+                config.write(offset, pci as u32);
+                if is_64 {
+                    config.write(offset + 4, (pci >> 32) as u32);
+                }
+                bars[bar] = Some((cpu, size));
+            }
+            None => {
+                config.write(offset, original);
+                if is_64 {
+                    config.write(offset + 4, original_high);
+                }
+            }
+        }
+        bar += if is_64 { 2 } else { 1 };
+    }
+    bars
+}
+
+/// MSI and MSI-X are writes to a doorbell the PLIC does not have, so both
+/// stay off and functions interrupt through their INTx pin.
+fn disable_msi(config: Config) {
+    if config.read(PCI_COMMAND) & PCI_STATUS_CAP_LIST == 0 {
+        return;
+    }
+    let mut at = config.read_u8(PCI_CAPABILITY_LIST) as usize & !3;
+    // 48 capabilities fill the space, a longer list loops
+    for _ in 0..48 {
+        if at == 0 {
+            break;
+        }
+        let header = config.read(at);
+        match header as u8 {
+            PCI_CAP_ID_MSI => config.write(at, header & !PCI_MSI_ENABLE),
+            PCI_CAP_ID_MSIX => config.write(at, header & !PCI_MSIX_ENABLE),
+            _ => {}
+        }
+        at = (header >> 8) as u8 as usize & !3;
+    }
+}
+
+fn setup(
+    config: Config, device: u8, function: u8, io: &mut Option<Window>, mem: &mut Option<Window>,
+    interrupt_map: &InterruptMap,
+) -> PciFunction {
+    let id = config.read(PCI_VENDOR_ID);
+    // writing the status half back as zeros leaves it alone
+    let command = config.read(PCI_COMMAND) & 0xffff;
+    // no decoding while the BARs move
+    config.write(
+        PCI_COMMAND,
+        command & !(PCI_COMMAND_IO | PCI_COMMAND_MEMORY),
+    );
+    let bars = assign_bars(config, io, mem);
+    disable_msi(config);
+    let pin = config.read_u8(PCI_INTERRUPT_PIN) as usize;
+    let irq = if pin == 0 {
+        0
+    } else {
+        interrupt_map.route(device, function, pin)
+    };
+    let line = config.read(PCI_INTERRUPT_LINE);
+    config.write(PCI_INTERRUPT_LINE, (line & !0xff) | (irq as u32 & 0xff));
+    config.write(
+        PCI_COMMAND,
+        (command | PCI_COMMAND_IO | PCI_COMMAND_MEMORY | PCI_COMMAND_MASTER)
+            & !PCI_COMMAND_INTX_DISABLE,
+    );
+    PciFunction {
+        device,
+        function,
+        vendor_id: id as u16,
+        device_id: (id >> 16) as u16,
+        bars,
+        irq,
+    }
+}
+
+/// Set up every function on bus 0 of the host bridge `node`. Runs before
+/// paging, when config space and BARs are reachable where they are.
+pub fn probe(node: &FdtNode) {
+    let ecam = match node.reg().first() {
+        Some(&(base, _)) => base,
+        None => return,
+    };
+    *ECAM.exclusive_access() = ecam;
+    let (mut io, mut mem) = windows(node);
+    let interrupt_map = InterruptMap::new(node);
+    let mut functions = FUNCTIONS.exclusive_access();
+    for device in 0..32u8 {
+        for function in 0..8u8 {
+            let config = Config(ecam + ((device as usize) << 15 | (function as usize) << 12));
+            if config.read(PCI_VENDOR_ID) & 0xffff == 0xffff {
+                if function == 0 {
+                    break;
+                }
+                continue;
+            }
+            let header_type = config.read_u8(PCI_HEADER_TYPE);
+            if header_type & 0x7f == 0 {
+                functions.push(setup(
+                    config,
+                    device,
+                    function,
+                    &mut io,
+                    &mut mem,
+                    &interrupt_map,
+                ));
+            }
+            // only function 0 tells whether there are others
+            if function == 0 && header_type & 0x80 == 0 {
+                break;
+            }
+        }
+    }
+}
+
+pub fn functions() -> Vec<PciFunction> {
+    FUNCTIONS.exclusive_access().clone()
+}
+
+/// Configuration space of the host bridge, for drivers that look at the
+/// capabilities of their function.
+pub fn ecam() -> usize {
+    *ECAM.exclusive_access()
+}
+
+/// Configuration space of bus 0 and the BARs handed out, for the kernel
+/// page table.
+pub fn mmio_regions() -> Vec<(usize, usize)> {
+    let ecam = ecam();
+    let mut regions: Vec<(usize, usize)> = FUNCTIONS
+        .exclusive_access()
+        .iter()
+        .flat_map(|function| function.bars.iter().flatten().copied())
+        .collect();
+    if ecam != 0 {
+        regions.push((ecam, ECAM_BUS_SIZE));
+    }
+    regions
+}
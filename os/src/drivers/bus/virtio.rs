@@ -1,3 +1,7 @@
+use super::pci::{
+    self,
+    PciFunction,
+};
 use crate::{
     mm::{
         frame_alloc_more,
@@ -13,28 +17,241 @@ use crate::{
     sync::UPIntrFreeCell,
 };
 use alloc::vec::Vec;
+use core::{
+    fmt,
+    ptr::NonNull,
+};
 use lazy_static::*;
-use virtio_drivers::Hal;
+use log::{
+    info,
+    warn,
+};
+use virtio_drivers::{
+    transport::{
+        mmio::{
+            MmioTransport,
+            VirtIOHeader,
+        },
+        pci::{
+            bus::{
+                Cam,
+                DeviceFunction,
+                PciRoot,
+            },
+            PciTransport,
+        },
+    },
+    BufferDirection,
+    Error,
+    Hal,
+};
+
+pub const VIRTIO_ID_NET: u32 = 1;
+pub const VIRTIO_ID_BLOCK: u32 = 2;
+pub const VIRTIO_ID_ENTROPY: u32 = 4;
+pub const VIRTIO_ID_GPU: u32 = 16;
+pub const VIRTIO_ID_INPUT: u32 = 18;
+
+/// PCI vendor ID of every virtio device.
+const VIRTIO_PCI_VENDOR: u16 = 0x1af4;
+
+/// "virt" in little endian, at the start of every virtio-mmio transport.
+const VIRTIO_MMIO_MAGIC: u32 = 0x7472_6976;
+const VIRTIO_MMIO_DEVICE_ID: usize = 0x8;
 
 lazy_static! {
     static ref QUEUE_FRAMES: UPIntrFreeCell<Vec<FrameTracker>> =
         unsafe { UPIntrFreeCell::new(Vec::new()) };
+    static ref MMIO_SLOTS: UPIntrFreeCell<Vec<VirtioMmio>> =
+        unsafe { UPIntrFreeCell::new(Vec::new()) };
+    static ref DRIVERS: UPIntrFreeCell<Vec<VirtioDriver>> =
+        unsafe { UPIntrFreeCell::new(Vec::new()) };
+}
+
+/// A virtio-mmio transport with a device behind it.
+#[derive(Copy, Clone)]
+pub struct VirtioMmio {
+    pub base: usize,
+    pub size: usize,
+    pub irq: usize,
+    pub device_id: u32,
+}
+
+/// Look at the transport the device tree lists at `base`. Empty ones,
+/// with device ID 0, are left out. Its registers must be reachable, which
+/// before paging they are.
+pub fn add_mmio_slot(base: usize, size: usize, irq: usize) {
+    let register = |offset: usize| unsafe { ((base + offset) as *const u32).read_volatile() };
+    if register(0) != VIRTIO_MMIO_MAGIC {
+        return;
+    }
+    let device_id = register(VIRTIO_MMIO_DEVICE_ID);
+    if device_id != 0 {
+        let mut slots = MMIO_SLOTS.exclusive_access();
+        let at = slots.partition_point(|slot| slot.base < base);
+        slots.insert(
+            at,
+            VirtioMmio {
+                base,
+                size,
+                irq,
+                device_id,
+            },
+        );
+    }
+}
+
+/// Where a virtio device sits: in a virtio-mmio slot or behind a PCI
+/// function with the modern virtio-pci capabilities.
+#[derive(Copy, Clone)]
+pub enum VirtioSlot {
+    Mmio(VirtioMmio),
+    Pci(PciFunction),
+}
+
+/// The transport of a slot, which the virtio-drivers drivers are generic
+/// over. A driver brings up its device with whichever it gets.
+pub enum VirtioTransport {
+    Mmio(MmioTransport),
+    Pci(PciTransport),
+}
+
+impl VirtioSlot {
+    /// The PLIC source of the device. A PCI function shares its INTx line
+    /// with others, so handlers must check that it was their device.
+    pub fn irq(&self) -> usize {
+        match self {
+            Self::Mmio(slot) => slot.irq,
+            Self::Pci(function) => function.irq,
+        }
+    }
+
+    fn device_id(&self) -> Option<u32> {
+        match self {
+            Self::Mmio(slot) => Some(slot.device_id),
+            Self::Pci(function) => pci_device_id(function),
+        }
+    }
+
+    pub fn transport(&self) -> virtio_drivers::Result<VirtioTransport> {
+        match self {
+            Self::Mmio(slot) => {
+                let header =
+                    NonNull::new(slot.base as *mut VirtIOHeader).ok_or(Error::InvalidParam)?;
+                unsafe { MmioTransport::new(header) }
+                    .map(VirtioTransport::Mmio)
+                    .map_err(|_| Error::Unsupported)
+            }
+            Self::Pci(function) => {
+                let mut root = unsafe { PciRoot::new(pci::ecam() as *mut u8, Cam::Ecam) };
+                let device_function = DeviceFunction {
+                    bus: 0,
+                    device: function.device,
+                    function: function.function,
+                };
+                PciTransport::new::<VirtioHal>(&mut root, device_function)
+                    .map(VirtioTransport::Pci)
+                    .map_err(|_| Error::Unsupported)
+            }
+        }
+    }
+}
+
+impl fmt::Display for VirtioSlot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Mmio(slot) => write!(f, "at {:#x}", slot.base),
+            Self::Pci(function) => write!(f, "on PCI {}", function),
+        }
+    }
+}
+
+/// A driver for one type of virtio device. `probe` brings up the device
+/// in a slot and adds it to the device registry.
+#[derive(Copy, Clone)]
+pub struct VirtioDriver {
+    pub name: &'static str,
+    pub device_id: u32,
+    pub probe: fn(&VirtioSlot) -> virtio_drivers::Result,
+}
+
+pub fn register_driver(driver: VirtioDriver) {
+    DRIVERS.exclusive_access().push(driver);
+}
+
+fn find_driver(device_id: u32) -> Option<VirtioDriver> {
+    DRIVERS
+        .exclusive_access()
+        .iter()
+        .find(|driver| driver.device_id == device_id)
+        .copied()
+}
+
+/// The virtio device type of a PCI function. Modern devices carry it in
+/// their PCI device ID, transitional ones number differently.
+fn pci_device_id(function: &PciFunction) -> Option<u32> {
+    if function.vendor_id != VIRTIO_PCI_VENDOR {
+        return None;
+    }
+    match function.device_id {
+        0x1000 => Some(VIRTIO_ID_NET),
+        0x1001 => Some(VIRTIO_ID_BLOCK),
+        0x1005 => Some(VIRTIO_ID_ENTROPY),
+        0x1040..=0x107f => Some((function.device_id - 0x1040) as u32),
+        _ => None,
+    }
+}
+
+/// Hand every slot to the driver for its device type. Devices that no
+/// driver takes, or that fail to come up, are left out.
+pub fn probe_all() {
+    let mut slots: Vec<VirtioSlot> = MMIO_SLOTS
+        .exclusive_access()
+        .iter()
+        .map(|slot| VirtioSlot::Mmio(*slot))
+        .collect();
+    slots.extend(pci::functions().into_iter().map(VirtioSlot::Pci));
+    for slot in slots.iter() {
+        let device_id = match slot.device_id() {
+            Some(device_id) => device_id,
+            None => continue,
+        };
+        let driver = match find_driver(device_id) {
+            Some(driver) => driver,
+            None => {
+                info!("KERN: no driver for virtio device {} {}", device_id, slot);
+                continue;
+            }
+        };
+        match (driver.probe)(slot) {
+            Ok(()) => info!("KERN: {} {}, irq {}", driver.name, slot, slot.irq()),
+            Err(err) => warn!("KERN: {} {} failed: {:?}", driver.name, slot, err),
+        }
+    }
+}
+
+/// Registers of every transport in use, for the kernel page table.
+pub fn mmio_regions() -> Vec<(usize, usize)> {
+    MMIO_SLOTS
+        .exclusive_access()
+        .iter()
+        .map(|slot| (slot.base, slot.size))
+        .collect()
 }
 
 pub struct VirtioHal;
 
-impl Hal for VirtioHal {
-    fn dma_alloc(pages: usize) -> usize {
-        let trakcers = frame_alloc_more(pages);
-        let ppn_base = trakcers.as_ref().unwrap().last().unwrap().ppn;
-        QUEUE_FRAMES
-            .exclusive_access()
-            .append(&mut trakcers.unwrap());
+/// Kernel memory is mapped where it is, DMA needs no copies.
+unsafe impl Hal for VirtioHal {
+    fn dma_alloc(pages: usize, _direction: BufferDirection) -> (usize, NonNull<u8>) {
+        let mut trackers = frame_alloc_more(pages).unwrap();
+        let ppn_base = trackers.last().unwrap().ppn;
+        QUEUE_FRAMES.exclusive_access().append(&mut trackers);
         let pa: PhysAddr = ppn_base.into();
-        pa.0
+        (pa.0, NonNull::new(pa.0 as *mut u8).unwrap())
     }
 
-    fn dma_dealloc(pa: usize, pages: usize) -> i32 {
+    unsafe fn dma_dealloc(pa: usize, _vaddr: NonNull<u8>, pages: usize) -> i32 {
         let pa = PhysAddr::from(pa);
         let mut ppn_base: PhysPageNum = pa.into();
         for _ in 0..pages {
@@ -44,14 +261,17 @@ impl Hal for VirtioHal {
         0
     }
 
-    fn phys_to_virt(addr: usize) -> usize {
-        addr
+    unsafe fn mmio_phys_to_virt(pa: usize, _size: usize) -> NonNull<u8> {
+        NonNull::new(pa as *mut u8).unwrap()
     }
 
-    fn virt_to_phys(vaddr: usize) -> usize {
+    /// Buffers may be on a kernel stack, which is not where its frames are.
+    unsafe fn share(buffer: NonNull<[u8]>, _direction: BufferDirection) -> usize {
         PageTable::from_token(kernel_token())
-            .translate_va(VirtAddr::from(vaddr))
+            .translate_va(VirtAddr::from(buffer.as_ptr() as *mut u8 as usize))
             .unwrap()
             .0
     }
+
+    unsafe fn unshare(_pa: usize, _buffer: NonNull<[u8]>, _direction: BufferDirection) {}
 }
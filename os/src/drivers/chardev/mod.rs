@@ -1,6 +1,9 @@
 mod ns16550a;
 
-use crate::board::CharDeviceImpl;
+use crate::board::{
+    uart_base,
+    CharDeviceImpl,
+};
 use alloc::sync::Arc;
 use lazy_static::*;
 pub use ns16550a::NS16550a;
@@ -13,5 +16,5 @@ pub trait CharDevice {
 }
 
 lazy_static! {
-    pub static ref UART: Arc<CharDeviceImpl> = Arc::new(CharDeviceImpl::new());
+    pub static ref UART: Arc<CharDeviceImpl> = Arc::new(CharDeviceImpl::new(uart_base()));
 }
@@ -6,11 +6,16 @@ use crate::{
     sync::{
         Condvar,
         UPIntrFreeCell,
+        WaitQueue,
+    },
+    task::{
+        current_task,
+        schedule,
     },
-    task::schedule,
 };
 use alloc::collections::VecDeque;
 use bitflags::*;
+use riscv::register::sstatus;
 use volatile::{
     ReadOnly,
     Volatile,
@@ -24,6 +29,13 @@ bitflags! {
         const TX_EMPTY = 1 << 1;
     }
 
+    /// FIFO Control Register
+    pub struct FCR: u8 {
+        const ENABLE = 1 << 0;
+        const CLEAR_RX = 1 << 1;
+        const CLEAR_TX = 1 << 2;
+    }
+
     /// LineStatusRegister
     pub struct LSR: u8 {
         const DATA_AVAILABLE = 1 << 0;
@@ -67,8 +79,8 @@ struct WriteWithoutDLAB {
     pub thr: WriteOnly<u8>,
     /// interrupt enable register
     pub ier: Volatile<IER>,
-    /// ignore FCR
-    _padding0: ReadOnly<u8>,
+    /// FIFO control register
+    pub fcr: WriteOnly<FCR>,
     /// line control register
     pub lcr: Volatile<u8>,
     /// modem control register
@@ -105,6 +117,9 @@ impl NS16550aRaw {
         read_end.mcr.write(mcr);
         let ier = IER::RX_AVAILABLE;
         read_end.ier.write(ier);
+        self.write_end()
+            .fcr
+            .write(FCR::ENABLE | FCR::CLEAR_RX | FCR::CLEAR_TX);
     }
 
     pub fn read(&mut self) -> Option<u8> {
@@ -126,38 +141,171 @@ impl NS16550aRaw {
             }
         }
     }
+
+    /// With the FIFO on, THR empty means the whole FIFO is.
+    fn tx_empty(&mut self) -> bool {
+        self.write_end().lsr.read().contains(LSR::THR_EMPTY)
+    }
+
+    /// Write THR without looking whether there is room.
+    fn send(&mut self, ch: u8) {
+        self.write_end().thr.write(ch);
+    }
+
+    fn set_tx_interrupt(&mut self, enable: bool) {
+        let mut ier = IER::RX_AVAILABLE;
+        ier.set(IER::TX_EMPTY, enable);
+        self.write_end().ier.write(ier);
+    }
+}
+
+/// Bytes the transmitter can take at once.
+const FIFO_SIZE: usize = 16;
+const TX_RING_SIZE: usize = 4096;
+
+/// Bytes written but not handed to the transmit FIFO yet.
+struct TxRing {
+    buffer: [u8; TX_RING_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl TxRing {
+    fn new() -> Self {
+        Self {
+            buffer: [0; TX_RING_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// False when the ring is full.
+    fn push(&mut self, ch: u8) -> bool {
+        if self.len == TX_RING_SIZE {
+            return false;
+        }
+        self.buffer[(self.head + self.len) % TX_RING_SIZE] = ch;
+        self.len += 1;
+        true
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let ch = self.buffer[self.head];
+        self.head = (self.head + 1) % TX_RING_SIZE;
+        self.len -= 1;
+        Some(ch)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
 }
 
 struct NS16550aInner {
     ns16550a: NS16550aRaw,
     read_buffer: VecDeque<u8>,
+    tx_ring: TxRing,
+}
+
+impl NS16550aInner {
+    /// Fill the transmit FIFO from the ring if it ran empty, keeping the
+    /// THR-empty interrupt on while bytes are left. Returns how many moved.
+    fn start_tx(&mut self) -> usize {
+        let mut sent = 0;
+        if self.ns16550a.tx_empty() {
+            while sent < FIFO_SIZE {
+                match self.tx_ring.pop() {
+                    Some(ch) => self.ns16550a.send(ch),
+                    None => break,
+                }
+                sent += 1;
+            }
+        }
+        let pending = !self.tx_ring.is_empty();
+        self.ns16550a.set_tx_interrupt(pending);
+        sent
+    }
+
+    /// Write out all of the ring, waiting on the transmitter.
+    fn flush_tx(&mut self) -> usize {
+        let mut sent = 0;
+        while let Some(ch) = self.tx_ring.pop() {
+            self.ns16550a.write(ch);
+            sent += 1;
+        }
+        self.ns16550a.set_tx_interrupt(false);
+        sent
+    }
 }
 
-pub struct NS16550a<const BASE_ADDR: usize> {
+pub struct NS16550a {
+    base_addr: usize,
     inner: UPIntrFreeCell<NS16550aInner>,
     condvar: Condvar,
+    /// Writers waiting for room in the ring.
+    tx_wait_queue: WaitQueue,
 }
 
-impl<const BASE_ADDR: usize> NS16550a<BASE_ADDR> {
-    pub fn new() -> Self {
+impl NS16550a {
+    pub fn new(base_addr: usize) -> Self {
         let inner = NS16550aInner {
-            ns16550a: NS16550aRaw::new(BASE_ADDR),
+            ns16550a: NS16550aRaw::new(base_addr),
             read_buffer: VecDeque::new(),
+            tx_ring: TxRing::new(),
         };
         //inner.ns16550a.init();
         Self {
+            base_addr,
             inner: unsafe { UPIntrFreeCell::new(inner) },
             condvar: Condvar::new(),
+            tx_wait_queue: WaitQueue::new(),
+        }
+    }
+
+    /// Write `bytes` out before returning, after what is queued. For when
+    /// no interrupt would drain the ring.
+    pub fn write_sync(&self, bytes: &[u8]) {
+        let mut inner = self.inner.exclusive_access();
+        let flushed = inner.flush_tx();
+        for ch in bytes {
+            inner.ns16550a.write(*ch);
+        }
+        drop(inner);
+        if flushed > 0 {
+            self.tx_wait_queue.wake_all();
         }
     }
 
-    pub fn read_buffer_is_empty(&self) -> bool {
+    /// Output of the panic handler. It waits on the transmitter and does
+    /// not need the UART to be free, the kernel may have panicked using it.
+    pub fn write_panic(&self, bytes: &[u8]) {
+        match self.inner.try_exclusive_access() {
+            Some(mut inner) => {
+                inner.flush_tx();
+                for ch in bytes {
+                    inner.ns16550a.write(*ch);
+                }
+            }
+            None => {
+                let mut ns16550a = NS16550aRaw::new(self.base_addr);
+                for ch in bytes {
+                    ns16550a.write(*ch);
+                }
+            }
+        }
+    }
+
+    /// A byte that has already arrived, without waiting for one.
+    pub fn try_read(&self) -> Option<u8> {
         self.inner
-            .exclusive_session(|inner| inner.read_buffer.is_empty())
+            .exclusive_session(|inner| inner.read_buffer.pop_front())
     }
 }
 
-impl<const BASE_ADDR: usize> CharDevice for NS16550a<BASE_ADDR> {
+impl CharDevice for NS16550a {
     fn init(&self) {
         let mut inner = self.inner.exclusive_access();
         inner.ns16550a.init();
@@ -176,20 +324,44 @@ impl<const BASE_ADDR: usize> CharDevice for NS16550a<BASE_ADDR> {
             }
         }
     }
+    /// Queue `ch` for the THR-empty interrupt, waiting only while the ring
+    /// is full. With interrupts masked none would come to drain it, so the
+    /// byte goes out at once.
     fn write(&self, ch: u8) {
-        let mut inner = self.inner.exclusive_access();
-        inner.ns16550a.write(ch);
+        if !sstatus::read().sie() {
+            self.write_sync(&[ch]);
+            return;
+        }
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if inner.tx_ring.push(ch) {
+                inner.start_tx();
+                return;
+            }
+            // the idle loop has nobody to block, it makes room itself
+            if current_task().is_none() {
+                inner.flush_tx();
+                continue;
+            }
+            let task_ctx_ptr = self.tx_wait_queue.wait_no_sched();
+            drop(inner);
+            schedule(task_ctx_ptr);
+        }
     }
     fn handle_irq(&self) {
         let mut count = 0;
-        self.inner.exclusive_session(|inner| {
+        let sent = self.inner.exclusive_session(|inner| {
             while let Some(ch) = inner.ns16550a.read() {
                 count += 1;
                 inner.read_buffer.push_back(ch);
             }
+            inner.start_tx()
         });
         if count > 0 {
             self.condvar.signal();
         }
+        if sent > 0 {
+            self.tx_wait_queue.wake_all();
+        }
     }
 }
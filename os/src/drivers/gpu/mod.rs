@@ -1,5 +1,12 @@
 use crate::{
-    drivers::bus::virtio::VirtioHal,
+    drivers::{
+        add_gpu_device,
+        bus::virtio::{
+            VirtioHal,
+            VirtioSlot,
+            VirtioTransport,
+        },
+    },
     sync::UPIntrFreeCell,
 };
 use alloc::{
@@ -10,31 +17,29 @@ use core::any::Any;
 use embedded_graphics::pixelcolor::Rgb888;
 use tinybmp::Bmp;
 use virtio_drivers::{
-    VirtIOGpu,
-    VirtIOHeader,
+    device::gpu::VirtIOGpu,
+    transport::Transport,
 };
-const VIRTIO7: usize = 0x10007000;
 pub trait GpuDevice: Send + Sync + Any {
     fn get_framebuffer(&self) -> &mut [u8];
     fn flush(&self);
 }
 
-lazy_static::lazy_static!(
-    pub static ref GPU_DEVICE: Arc<dyn GpuDevice> = Arc::new(VirtIOGpuWrapper::new());
-);
-
-pub struct VirtIOGpuWrapper {
-    gpu: UPIntrFreeCell<VirtIOGpu<'static, VirtioHal>>,
+pub struct VirtIOGpuWrapper<T: Transport> {
+    gpu: UPIntrFreeCell<VirtIOGpu<VirtioHal, T>>,
     fb: &'static [u8],
 }
+
+// the device is only reached through the cell
+unsafe impl<T: Transport> Send for VirtIOGpuWrapper<T> {}
+
 static BMP_DATA: &[u8] = include_bytes!("../../assert/mouse.bmp");
-impl VirtIOGpuWrapper {
-    pub fn new() -> Self {
+impl<T: Transport> VirtIOGpuWrapper<T> {
+    pub fn new(transport: T) -> virtio_drivers::Result<Self> {
         unsafe {
-            let mut virtio =
-                VirtIOGpu::<VirtioHal>::new(&mut *(VIRTIO7 as *mut VirtIOHeader)).unwrap();
+            let mut virtio = VirtIOGpu::<VirtioHal, T>::new(transport)?;
 
-            let fbuffer = virtio.setup_framebuffer().unwrap();
+            let fbuffer = virtio.setup_framebuffer()?;
             let len = fbuffer.len();
             let ptr = fbuffer.as_mut_ptr();
             let fb = core::slice::from_raw_parts_mut(ptr, len);
@@ -51,17 +56,30 @@ impl VirtIOGpuWrapper {
                     b.push(0xff)
                 }
             }
-            virtio.setup_cursor(b.as_slice(), 50, 50, 50, 50).unwrap();
+            virtio.setup_cursor(b.as_slice(), 50, 50, 50, 50)?;
 
-            Self {
+            Ok(Self {
                 gpu: UPIntrFreeCell::new(virtio),
                 fb,
-            }
+            })
+        }
+    }
+}
+
+/// The GPU has no interrupt we use, flushing waits for it.
+pub fn probe(slot: &VirtioSlot) -> virtio_drivers::Result {
+    match slot.transport()? {
+        VirtioTransport::Mmio(transport) => {
+            add_gpu_device(Arc::new(VirtIOGpuWrapper::new(transport)?))
+        }
+        VirtioTransport::Pci(transport) => {
+            add_gpu_device(Arc::new(VirtIOGpuWrapper::new(transport)?))
         }
     }
+    Ok(())
 }
 
-impl GpuDevice for VirtIOGpuWrapper {
+impl<T: Transport + 'static> GpuDevice for VirtIOGpuWrapper<T> {
     fn flush(&self) {
         self.gpu.exclusive_access().flush().unwrap();
     }
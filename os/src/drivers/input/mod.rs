@@ -1,8 +1,18 @@
 use crate::{
-    drivers::bus::virtio::VirtioHal,
+    board::register_irq,
+    drivers::{
+        add_input_device,
+        bus::virtio::{
+            VirtioHal,
+            VirtioSlot,
+            VirtioTransport,
+        },
+        InputKind,
+    },
     sync::{
         Condvar,
         UPIntrFreeCell,
+        WaitQueue,
     },
     task::schedule,
 };
@@ -12,54 +22,92 @@ use alloc::{
 };
 use core::any::Any;
 use virtio_drivers::{
-    VirtIOHeader,
-    VirtIOInput,
+    device::input::{
+        InputConfigSelect,
+        VirtIOInput,
+    },
+    transport::Transport,
 };
 
-const VIRTIO5: usize = 0x10005000;
-const VIRTIO6: usize = 0x10006000;
-
-struct VirtIOInputInner {
-    virtio_input: VirtIOInput<'static, VirtioHal>,
+struct VirtIOInputInner<T: Transport> {
+    virtio_input: VirtIOInput<VirtioHal, T>,
     events: VecDeque<u64>,
 }
 
-struct VirtIOInputWrapper {
-    inner: UPIntrFreeCell<VirtIOInputInner>,
+struct VirtIOInputWrapper<T: Transport> {
+    inner: UPIntrFreeCell<VirtIOInputInner<T>>,
     condvar: Condvar,
+    wait_queue: WaitQueue,
 }
 
+// the device is only reached through the cell
+unsafe impl<T: Transport> Send for VirtIOInputWrapper<T> {}
+
 pub trait InputDevice: Send + Sync + Any {
     fn read_event(&self) -> u64;
     fn handle_irq(&self);
     fn is_empty(&self) -> bool;
+    /// Woken when events arrive, for `poll`.
+    fn wait_queue(&self) -> &WaitQueue;
 }
 
-lazy_static::lazy_static!(
-    pub static ref KEYBOARD_DEVICE: Arc<dyn InputDevice> = Arc::new(VirtIOInputWrapper::new(VIRTIO5));
-    pub static ref MOUSE_DEVICE: Arc<dyn InputDevice> = Arc::new(VirtIOInputWrapper::new(VIRTIO6));
-);
-
-impl VirtIOInputWrapper {
-    pub fn new(addr: usize) -> Self {
+impl<T: Transport> VirtIOInputWrapper<T> {
+    pub fn new(transport: T) -> virtio_drivers::Result<Self> {
         let inner = VirtIOInputInner {
-            virtio_input: unsafe {
-                VirtIOInput::<VirtioHal>::new(&mut *(addr as *mut VirtIOHeader)).unwrap()
-            },
+            virtio_input: VirtIOInput::<VirtioHal, T>::new(transport)?,
             events: VecDeque::new(),
         };
-        Self {
+        Ok(Self {
             inner: unsafe { UPIntrFreeCell::new(inner) },
             condvar: Condvar::new(),
-        }
+            wait_queue: WaitQueue::new(),
+        })
+    }
+
+    /// Whether the device name, like "QEMU Virtio Keyboard", says keyboard.
+    fn is_keyboard(&self) -> bool {
+        let mut name = [0u8; 128];
+        let size =
+            self.inner
+                .exclusive_access()
+                .virtio_input
+                .query_config_select(InputConfigSelect::IdName, 0, &mut name) as usize;
+        name[..size.min(name.len())]
+            .windows(8)
+            .any(|w| w == b"Keyboard")
     }
 }
 
-impl InputDevice for VirtIOInputWrapper {
+/// A keyboard by its name, anything else is taken for a mouse or a tablet.
+fn add<T: Transport + 'static>(transport: T, irq: usize) -> virtio_drivers::Result {
+    let device = Arc::new(VirtIOInputWrapper::new(transport)?);
+    let (kind, name) = if device.is_keyboard() {
+        (InputKind::Keyboard, "virtio-keyboard")
+    } else {
+        (InputKind::Mouse, "virtio-mouse")
+    };
+    let irq_device = device.clone();
+    register_irq(irq, name, move || irq_device.handle_irq());
+    add_input_device(kind, device);
+    Ok(())
+}
+
+pub fn probe(slot: &VirtioSlot) -> virtio_drivers::Result {
+    match slot.transport()? {
+        VirtioTransport::Mmio(transport) => add(transport, slot.irq()),
+        VirtioTransport::Pci(transport) => add(transport, slot.irq()),
+    }
+}
+
+impl<T: Transport + 'static> InputDevice for VirtIOInputWrapper<T> {
     fn is_empty(&self) -> bool {
         self.inner.exclusive_access().events.is_empty()
     }
 
+    fn wait_queue(&self) -> &WaitQueue {
+        &self.wait_queue
+    }
+
     fn read_event(&self) -> u64 {
         loop {
             let mut inner = self.inner.exclusive_access();
@@ -88,6 +136,7 @@ impl InputDevice for VirtIOInputWrapper {
         });
         if count > 0 {
             self.condvar.signal();
+            self.wait_queue.wake_all();
         };
     }
 }
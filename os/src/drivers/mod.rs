@@ -5,9 +5,61 @@ pub mod gpu;
 pub mod input;
 pub mod net;
 pub mod plic;
+pub mod registry;
+pub mod rng;
+pub mod rtc;
 
-pub use block::BLOCK_DEVICE;
 pub use bus::*;
-pub use gpu::*;
-pub use input::*;
-pub use net::*;
+pub use gpu::GpuDevice;
+pub use input::InputDevice;
+pub use net::{
+    LoopbackDevice,
+    NetDevice,
+};
+pub use registry::*;
+pub use rng::RngDevice;
+
+use bus::virtio::{
+    self,
+    register_driver,
+    VirtioDriver,
+    VIRTIO_ID_BLOCK,
+    VIRTIO_ID_ENTROPY,
+    VIRTIO_ID_GPU,
+    VIRTIO_ID_INPUT,
+    VIRTIO_ID_NET,
+};
+
+/// Register the virtio drivers and bring up the devices the bus has.
+pub fn init() {
+    for driver in [
+        VirtioDriver {
+            name: "virtio-blk",
+            device_id: VIRTIO_ID_BLOCK,
+            probe: block::probe,
+        },
+        VirtioDriver {
+            name: "virtio-gpu",
+            device_id: VIRTIO_ID_GPU,
+            probe: gpu::probe,
+        },
+        VirtioDriver {
+            name: "virtio-input",
+            device_id: VIRTIO_ID_INPUT,
+            probe: input::probe,
+        },
+        VirtioDriver {
+            name: "virtio-net",
+            device_id: VIRTIO_ID_NET,
+            probe: net::probe,
+        },
+        VirtioDriver {
+            name: "virtio-rng",
+            device_id: VIRTIO_ID_ENTROPY,
+            probe: rng::probe,
+        },
+    ] {
+        register_driver(driver);
+    }
+    virtio::probe_all();
+}
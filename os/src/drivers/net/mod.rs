@@ -1,51 +1,101 @@
 use core::any::Any;
 
 use crate::{
-    drivers::virtio::VirtioHal,
+    board::register_irq,
+    drivers::{
+        add_net_device,
+        virtio::{
+            VirtioHal,
+            VirtioSlot,
+            VirtioTransport,
+        },
+    },
     sync::UPIntrFreeCell,
 };
 use alloc::sync::Arc;
-use lazy_static::*;
 use virtio_drivers::{
-    VirtIOHeader,
-    VirtIONet,
+    device::net::VirtIONet,
+    transport::Transport,
 };
 
-const VIRTIO8: usize = 0x10004000;
+mod loopback;
 
-lazy_static! {
-    pub static ref NET_DEVICE: Arc<dyn NetDevice> = Arc::new(VirtIONetWrapper::new());
-}
+pub use loopback::LoopbackDevice;
 
 pub trait NetDevice: Send + Sync + Any {
     fn transmit(&self, data: &[u8]);
     fn receive(&self, data: &mut [u8]) -> usize;
+    /// A received packet is waiting, `receive` would not block.
+    fn can_recv(&self) -> bool;
+    /// Acknowledge the device interrupt, `false` if it was not ours.
+    fn ack_interrupt(&self) -> bool;
+    fn mac(&self) -> [u8; 6];
 }
 
-pub struct VirtIONetWrapper(UPIntrFreeCell<VirtIONet<'static, VirtioHal>>);
+/// Receive buffers queued at the device.
+const NET_QUEUE_SIZE: usize = 16;
+/// Room for a full ethernet frame.
+const NET_BUFFER_LEN: usize = 2048;
+
+pub struct VirtIONetWrapper<T: Transport>(UPIntrFreeCell<VirtIONet<VirtioHal, T, NET_QUEUE_SIZE>>);
 
-impl NetDevice for VirtIONetWrapper {
+// the device is only reached through the cell
+unsafe impl<T: Transport> Send for VirtIONetWrapper<T> {}
+
+impl<T: Transport + 'static> NetDevice for VirtIONetWrapper<T> {
     fn transmit(&self, data: &[u8]) {
-        self.0
-            .exclusive_access()
-            .send(data)
-            .expect("can't send data")
+        let mut net = self.0.exclusive_access();
+        let mut tx_buf = net.new_tx_buffer(data.len());
+        tx_buf.packet_mut().copy_from_slice(data);
+        net.send(tx_buf).expect("can't send data")
     }
 
+    /// The buffer goes back to the device once the frame is copied out.
     fn receive(&self, data: &mut [u8]) -> usize {
-        self.0
-            .exclusive_access()
-            .recv(data)
-            .expect("can't receive data")
+        let mut net = self.0.exclusive_access();
+        let rx_buf = net.receive().expect("can't receive data");
+        let len = rx_buf.packet_len().min(data.len());
+        data[..len].copy_from_slice(&rx_buf.packet()[..len]);
+        net.recycle_rx_buffer(rx_buf)
+            .expect("can't recycle receive buffer");
+        len
+    }
+
+    fn can_recv(&self) -> bool {
+        self.0.exclusive_access().can_recv()
+    }
+
+    fn ack_interrupt(&self) -> bool {
+        self.0.exclusive_access().ack_interrupt()
     }
+
+    fn mac(&self) -> [u8; 6] {
+        self.0.exclusive_access().mac_address()
+    }
+}
+
+impl<T: Transport> VirtIONetWrapper<T> {
+    pub fn new(transport: T) -> virtio_drivers::Result<Self> {
+        let virtio = VirtIONet::<VirtioHal, T, NET_QUEUE_SIZE>::new(transport, NET_BUFFER_LEN)?;
+        Ok(VirtIONetWrapper(unsafe { UPIntrFreeCell::new(virtio) }))
+    }
+}
+
+/// The interrupt is only acknowledged, the net bottom half that runs after
+/// every interrupt takes the frames.
+fn add<T: Transport + 'static>(transport: T, irq: usize) -> virtio_drivers::Result {
+    let device = Arc::new(VirtIONetWrapper::new(transport)?);
+    let irq_device = device.clone();
+    register_irq(irq, "virtio-net", move || {
+        irq_device.ack_interrupt();
+    });
+    add_net_device(device);
+    Ok(())
 }
 
-impl VirtIONetWrapper {
-    pub fn new() -> Self {
-        unsafe {
-            let virtio = VirtIONet::<VirtioHal>::new(&mut *(VIRTIO8 as *mut VirtIOHeader))
-                .expect("can't create net device by virtio");
-            VirtIONetWrapper(UPIntrFreeCell::new(virtio))
-        }
+pub fn probe(slot: &VirtioSlot) -> virtio_drivers::Result {
+    match slot.transport()? {
+        VirtioTransport::Mmio(transport) => add(transport, slot.irq()),
+        VirtioTransport::Pci(transport) => add(transport, slot.irq()),
     }
 }
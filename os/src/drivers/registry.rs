@@ -0,0 +1,91 @@
+//! The devices the drivers brought up. A device the machine does not have
+//! is simply not here, and whatever needs it does without.
+
+use super::{
+    GpuDevice,
+    InputDevice,
+    NetDevice,
+    RngDevice,
+};
+use crate::sync::UPIntrFreeCell;
+use alloc::{
+    sync::Arc,
+    vec::Vec,
+};
+use easy_fs::BlockDevice;
+use lazy_static::*;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum InputKind {
+    Keyboard,
+    /// A mouse or a tablet.
+    Mouse,
+}
+
+struct Registry {
+    block: Vec<Arc<dyn BlockDevice>>,
+    gpu: Vec<Arc<dyn GpuDevice>>,
+    input: Vec<(InputKind, Arc<dyn InputDevice>)>,
+    net: Vec<Arc<dyn NetDevice>>,
+    rng: Vec<Arc<dyn RngDevice>>,
+}
+
+lazy_static! {
+    static ref REGISTRY: UPIntrFreeCell<Registry> = unsafe {
+        UPIntrFreeCell::new(Registry {
+            block: Vec::new(),
+            gpu: Vec::new(),
+            input: Vec::new(),
+            net: Vec::new(),
+            rng: Vec::new(),
+        })
+    };
+}
+
+pub fn add_block_device(device: Arc<dyn BlockDevice>) {
+    REGISTRY.exclusive_access().block.push(device);
+}
+
+pub fn add_gpu_device(device: Arc<dyn GpuDevice>) {
+    REGISTRY.exclusive_access().gpu.push(device);
+}
+
+pub fn add_input_device(kind: InputKind, device: Arc<dyn InputDevice>) {
+    REGISTRY.exclusive_access().input.push((kind, device));
+}
+
+pub fn add_net_device(device: Arc<dyn NetDevice>) {
+    REGISTRY.exclusive_access().net.push(device);
+}
+
+pub fn add_rng_device(device: Arc<dyn RngDevice>) {
+    REGISTRY.exclusive_access().rng.push(device);
+}
+
+/// The first disk, which holds the root file system.
+pub fn block_device() -> Option<Arc<dyn BlockDevice>> {
+    REGISTRY.exclusive_access().block.first().cloned()
+}
+
+pub fn gpu_device() -> Option<Arc<dyn GpuDevice>> {
+    REGISTRY.exclusive_access().gpu.first().cloned()
+}
+
+/// The first input device of `kind`.
+pub fn input_device(kind: InputKind) -> Option<Arc<dyn InputDevice>> {
+    REGISTRY
+        .exclusive_access()
+        .input
+        .iter()
+        .find(|(k, _)| *k == kind)
+        .map(|(_, device)| device.clone())
+}
+
+/// The first network card, which becomes eth0.
+pub fn net_device() -> Option<Arc<dyn NetDevice>> {
+    REGISTRY.exclusive_access().net.first().cloned()
+}
+
+pub fn rng_device() -> Option<Arc<dyn RngDevice>> {
+    REGISTRY.exclusive_access().rng.first().cloned()
+}
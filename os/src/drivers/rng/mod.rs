@@ -0,0 +1,158 @@
+//! virtio-entropy, driven here by hand since the virtio-drivers version in
+//! use has no driver for it. It must be a legacy virtio-mmio device, which
+//! QEMU's are unless told otherwise. One on PCI is not taken, the kernel
+//! generator then seeds itself from timer jitter.
+
+use crate::{
+    config::PAGE_SIZE,
+    drivers::{
+        add_rng_device,
+        bus::virtio::{
+            VirtioHal,
+            VirtioSlot,
+        },
+    },
+    sync::UPIntrFreeCell,
+};
+use alloc::sync::Arc;
+use core::{
+    hint::spin_loop,
+    ptr,
+    slice,
+    sync::atomic::{
+        fence,
+        Ordering,
+    },
+};
+use virtio_drivers::{
+    BufferDirection,
+    Error,
+    Hal,
+};
+
+pub trait RngDevice: Send + Sync {
+    /// Fill the start of `buf` from the device, returning how much of it.
+    fn read(&self, buf: &mut [u8]) -> usize;
+}
+
+const VIRTIO_MMIO_VERSION: usize = 0x04;
+const VIRTIO_MMIO_GUEST_FEATURES: usize = 0x20;
+const VIRTIO_MMIO_GUEST_PAGE_SIZE: usize = 0x28;
+const VIRTIO_MMIO_QUEUE_SEL: usize = 0x30;
+const VIRTIO_MMIO_QUEUE_NUM_MAX: usize = 0x34;
+const VIRTIO_MMIO_QUEUE_NUM: usize = 0x38;
+const VIRTIO_MMIO_QUEUE_ALIGN: usize = 0x3c;
+const VIRTIO_MMIO_QUEUE_PFN: usize = 0x40;
+const VIRTIO_MMIO_QUEUE_NOTIFY: usize = 0x50;
+const VIRTIO_MMIO_INTERRUPT_STATUS: usize = 0x60;
+const VIRTIO_MMIO_INTERRUPT_ACK: usize = 0x64;
+const VIRTIO_MMIO_STATUS: usize = 0x70;
+
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+const QUEUE_SIZE: u16 = 4;
+/// The queue takes two pages: descriptors with the available ring after
+/// them, and the used ring on the second. The third is the buffer the
+/// device fills.
+const QUEUE_PAGES: usize = 3;
+const AVAIL: usize = 16 * QUEUE_SIZE as usize;
+const USED: usize = PAGE_SIZE;
+const BUFFER: usize = 2 * PAGE_SIZE;
+
+struct VirtIORngInner {
+    base: usize,
+    /// Where the queue pages are, for us and for the device.
+    queue: usize,
+    queue_pa: usize,
+    avail_idx: u16,
+    used_idx: u16,
+}
+
+/// Requests are polled, the device answers each as soon as it is told.
+pub struct VirtIORng(UPIntrFreeCell<VirtIORngInner>);
+
+fn register(base: usize, offset: usize) -> *mut u32 {
+    (base + offset) as *mut u32
+}
+
+impl VirtIORng {
+    pub fn new(base: usize) -> virtio_drivers::Result<Self> {
+        unsafe {
+            if register(base, VIRTIO_MMIO_VERSION).read_volatile() != 1 {
+                return Err(Error::NotReady);
+            }
+            register(base, VIRTIO_MMIO_STATUS).write_volatile(0);
+            register(base, VIRTIO_MMIO_STATUS).write_volatile(STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+            // there are no features worth having
+            register(base, VIRTIO_MMIO_GUEST_FEATURES).write_volatile(0);
+            register(base, VIRTIO_MMIO_GUEST_PAGE_SIZE).write_volatile(PAGE_SIZE as u32);
+            register(base, VIRTIO_MMIO_QUEUE_SEL).write_volatile(0);
+            if register(base, VIRTIO_MMIO_QUEUE_NUM_MAX).read_volatile() < QUEUE_SIZE as u32 {
+                return Err(Error::NotReady);
+            }
+            let (queue_pa, queue) = VirtioHal::dma_alloc(QUEUE_PAGES, BufferDirection::Both);
+            let queue = queue.as_ptr() as usize;
+            ptr::write_bytes(queue as *mut u8, 0, QUEUE_PAGES * PAGE_SIZE);
+            register(base, VIRTIO_MMIO_QUEUE_NUM).write_volatile(QUEUE_SIZE as u32);
+            register(base, VIRTIO_MMIO_QUEUE_ALIGN).write_volatile(PAGE_SIZE as u32);
+            register(base, VIRTIO_MMIO_QUEUE_PFN).write_volatile((queue_pa / PAGE_SIZE) as u32);
+            register(base, VIRTIO_MMIO_STATUS)
+                .write_volatile(STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_DRIVER_OK);
+            Ok(Self(UPIntrFreeCell::new(VirtIORngInner {
+                base,
+                queue,
+                queue_pa,
+                avail_idx: 0,
+                used_idx: 0,
+            })))
+        }
+    }
+}
+
+impl RngDevice for VirtIORng {
+    fn read(&self, buf: &mut [u8]) -> usize {
+        let mut inner = self.0.exclusive_access();
+        let len = buf.len().min(PAGE_SIZE);
+        let (base, queue) = (inner.base, inner.queue);
+        unsafe {
+            // one descriptor, the whole buffer, written by the device
+            (queue as *mut u64).write_volatile((inner.queue_pa + BUFFER) as u64);
+            ((queue + 8) as *mut u32).write_volatile(len as u32);
+            ((queue + 12) as *mut u16).write_volatile(VIRTQ_DESC_F_WRITE);
+            let slot = (inner.avail_idx % QUEUE_SIZE) as usize;
+            ((queue + AVAIL + 4 + 2 * slot) as *mut u16).write_volatile(0);
+            inner.avail_idx = inner.avail_idx.wrapping_add(1);
+            fence(Ordering::SeqCst);
+            ((queue + AVAIL + 2) as *mut u16).write_volatile(inner.avail_idx);
+            fence(Ordering::SeqCst);
+            register(base, VIRTIO_MMIO_QUEUE_NOTIFY).write_volatile(0);
+            while ((queue + USED + 2) as *const u16).read_volatile() == inner.used_idx {
+                spin_loop();
+            }
+            fence(Ordering::SeqCst);
+            let slot = (inner.used_idx % QUEUE_SIZE) as usize;
+            let filled = ((queue + USED + 4 + 8 * slot + 4) as *const u32).read_volatile() as usize;
+            inner.used_idx = inner.used_idx.wrapping_add(1);
+            let status = register(base, VIRTIO_MMIO_INTERRUPT_STATUS).read_volatile();
+            register(base, VIRTIO_MMIO_INTERRUPT_ACK).write_volatile(status);
+            let filled = filled.min(len);
+            buf[..filled]
+                .copy_from_slice(slice::from_raw_parts((queue + BUFFER) as *const u8, filled));
+            filled
+        }
+    }
+}
+
+/// The device interrupt is left off, `read` waits for its answers. Only
+/// virtio-mmio is spoken.
+pub fn probe(slot: &VirtioSlot) -> virtio_drivers::Result {
+    match slot {
+        VirtioSlot::Mmio(slot) => add_rng_device(Arc::new(VirtIORng::new(slot.base)?)),
+        VirtioSlot::Pci(_) => return Err(Error::Unsupported),
+    }
+    Ok(())
+}
@@ -0,0 +1,23 @@
+//! The Goldfish RTC, wall-clock time in nanoseconds since the epoch.
+
+const TIMER_TIME_LOW: usize = 0x00;
+const TIMER_TIME_HIGH: usize = 0x04;
+
+pub struct GoldfishRtc {
+    base: usize,
+}
+
+impl GoldfishRtc {
+    pub fn new(base: usize) -> Self {
+        Self { base }
+    }
+
+    /// Reading the low half latches the high half, so low goes first.
+    pub fn read_ns(&self) -> u64 {
+        unsafe {
+            let low = ((self.base + TIMER_TIME_LOW) as *const u32).read_volatile();
+            let high = ((self.base + TIMER_TIME_HIGH) as *const u32).read_volatile();
+            (high as u64) << 32 | low as u64
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Just enough of the flattened device tree format to read properties
+//! and find devices.
+
+use alloc::vec::Vec;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+
+fn be32(addr: usize) -> u32 {
+    u32::from_be(unsafe { (addr as *const u32).read_unaligned() })
+}
+
+/// `count` cells from `at` in `value` as one number, `None` past its end.
+pub fn read_cells(value: &[u8], at: usize, count: usize) -> Option<usize> {
+    let bytes = value.get(at * 4..(at + count) * 4)?;
+    Some(bytes.chunks(4).fold(0, |acc, cell| {
+        (acc << 32) | u32::from_be_bytes([cell[0], cell[1], cell[2], cell[3]]) as usize
+    }))
+}
+
+fn cstr(addr: usize) -> &'static [u8] {
+    let mut len = 0;
+    while unsafe { *((addr + len) as *const u8) } != 0 {
+        len += 1;
+    }
+    unsafe { core::slice::from_raw_parts(addr as *const u8, len) }
+}
+
+/// A node with its properties.
+pub struct FdtNode {
+    pub name: &'static [u8],
+    props: Vec<(&'static [u8], &'static [u8])>,
+    /// The parent's `#address-cells` and `#size-cells`, which `reg` is
+    /// written in.
+    pub address_cells: usize,
+    pub size_cells: usize,
+}
+
+impl FdtNode {
+    pub fn property(&self, name: &str) -> Option<&'static [u8]> {
+        self.props
+            .iter()
+            .find(|(prop, _)| *prop == name.as_bytes())
+            .map(|(_, value)| *value)
+    }
+
+    /// `compatible` lists `compatible` among its strings.
+    pub fn is_compatible(&self, compatible: &str) -> bool {
+        self.property("compatible").map_or(false, |value| {
+            value
+                .split(|b| *b == 0)
+                .any(|entry| entry == compatible.as_bytes())
+        })
+    }
+
+    /// The `(address, size)` pairs of `reg`.
+    pub fn reg(&self) -> Vec<(usize, usize)> {
+        let value = match self.property("reg") {
+            Some(value) => value,
+            None => return Vec::new(),
+        };
+        let cells = self.address_cells + self.size_cells;
+        (0..value.len() / 4 / cells.max(1))
+            .filter_map(|i| {
+                let address = read_cells(value, i * cells, self.address_cells)?;
+                let size = read_cells(value, i * cells + self.address_cells, self.size_cells)?;
+                Some((address, size))
+            })
+            .collect()
+    }
+
+    /// The first cell of `interrupts`, the source number for the PLIC.
+    pub fn irq(&self) -> Option<usize> {
+        read_cells(self.property("interrupts")?, 0, 1)
+    }
+}
+
+/// The device tree blob the firmware hands over in `a1`.
+pub struct Fdt {
+    structs: usize,
+    strings: usize,
+}
+
+impl Fdt {
+    /// # Safety
+    ///
+    /// `addr` must be mapped, and the blob must stay untouched while it is
+    /// read.
+    pub unsafe fn from_addr(addr: usize) -> Option<Self> {
+        if addr == 0 || be32(addr) != FDT_MAGIC {
+            return None;
+        }
+        Some(Self {
+            structs: addr + be32(addr + 8) as usize,
+            strings: addr + be32(addr + 12) as usize,
+        })
+    }
+
+    /// The value of property `name` of the node at `path`, like `/chosen`.
+    /// A path component without a unit address matches any.
+    pub fn property(&self, path: &str, name: &str) -> Option<&'static [u8]> {
+        let wanted: Vec<&[u8]> = path
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .map(str::as_bytes)
+            .collect();
+        let mut offset = self.structs;
+        // open nodes, the root included, and how many of them below the
+        // root are on `path`
+        let mut depth = 0;
+        let mut matched = 0;
+        loop {
+            let token = be32(offset);
+            offset += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let node = cstr(offset);
+                    offset = (offset + node.len() + 1 + 3) & !3;
+                    if depth > 0 && matched == depth - 1 && matched < wanted.len() {
+                        let without_unit = node.split(|b| *b == b'@').next().unwrap();
+                        if node == wanted[matched] || without_unit == wanted[matched] {
+                            matched += 1;
+                        }
+                    }
+                    depth += 1;
+                }
+                FDT_END_NODE => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return None;
+                    }
+                    matched = matched.min(depth - 1);
+                }
+                FDT_PROP => {
+                    let len = be32(offset) as usize;
+                    let name_offset = be32(offset + 4) as usize;
+                    let value = offset + 8;
+                    offset = (value + len + 3) & !3;
+                    if depth == wanted.len() + 1
+                        && matched == wanted.len()
+                        && cstr(self.strings + name_offset) == name.as_bytes()
+                    {
+                        return Some(unsafe {
+                            core::slice::from_raw_parts(value as *const u8, len)
+                        });
+                    }
+                }
+                FDT_NOP => {}
+                // FDT_END, or something we do not understand
+                _ => return None,
+            }
+        }
+    }
+
+    /// Every node, parents before their children.
+    pub fn nodes(&self) -> Vec<FdtNode> {
+        let mut nodes: Vec<FdtNode> = Vec::new();
+        // indices in `nodes` of the open nodes, with the cell sizes their
+        // children use
+        let mut open: Vec<(usize, usize, usize)> = Vec::new();
+        let mut offset = self.structs;
+        loop {
+            let token = be32(offset);
+            offset += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = cstr(offset);
+                    offset = (offset + name.len() + 1 + 3) & !3;
+                    let (address_cells, size_cells) = open
+                        .last()
+                        .map_or((2, 1), |&(_, address, size)| (address, size));
+                    open.push((nodes.len(), 2, 1));
+                    nodes.push(FdtNode {
+                        name,
+                        props: Vec::new(),
+                        address_cells,
+                        size_cells,
+                    });
+                }
+                FDT_END_NODE => {
+                    open.pop();
+                    if open.is_empty() {
+                        return nodes;
+                    }
+                }
+                FDT_PROP => {
+                    let len = be32(offset) as usize;
+                    let name = cstr(self.strings + be32(offset + 4) as usize);
+                    let value =
+                        unsafe { core::slice::from_raw_parts((offset + 8) as *const u8, len) };
+                    offset = (offset + 8 + len + 3) & !3;
+                    let top = match open.last_mut() {
+                        Some(top) => top,
+                        None => return nodes,
+                    };
+                    match name {
+                        b"#address-cells" => top.1 = read_cells(value, 0, 1).unwrap_or(2),
+                        b"#size-cells" => top.2 = read_cells(value, 0, 1).unwrap_or(1),
+                        _ => {}
+                    }
+                    nodes[top.0].props.push((name, value));
+                }
+                FDT_NOP => {}
+                _ => return nodes,
+            }
+        }
+    }
+}
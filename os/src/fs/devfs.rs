@@ -0,0 +1,527 @@
+use super::{
+    console,
+    pty::{
+        open_pty_master,
+        open_pty_slave,
+    },
+    File,
+    FilePage,
+    FileSystem,
+    OpenFlags,
+    PollEvents,
+    TtyFile,
+};
+use crate::{
+    drivers::{
+        block_device,
+        gpu_device,
+        input_device,
+        GpuDevice,
+        InputDevice,
+        InputKind,
+    },
+    mm::{
+        PhysAddr,
+        UserBuffer,
+    },
+    net::pcap,
+    random,
+    sync::{
+        UPIntrFreeCell,
+        WaitQueue,
+    },
+};
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::Arc,
+    vec::Vec,
+};
+use easy_fs::{
+    get_block_cache,
+    BlockDevice,
+    BLOCK_SZ,
+};
+use shared_defination::error::{
+    EEXIST,
+    EINVAL,
+    ENOSPC,
+};
+
+const S_IFMT: u32 = 0o170000;
+const S_IFCHR: u32 = 0o020000;
+const S_IFBLK: u32 = 0o060000;
+
+// major numbers, the same as Linux uses
+const MEM_MAJOR: u32 = 1;
+const TTY_MAJOR: u32 = 5;
+const MISC_MAJOR: u32 = 10;
+const INPUT_MAJOR: u32 = 13;
+const FB_MAJOR: u32 = 29;
+const PTY_SLAVE_MAJOR: u32 = 136;
+const VIRTBLK_MAJOR: u32 = 254;
+/// From the misc minors Linux leaves for local use.
+const PCAP_MINOR: u32 = 240;
+
+#[derive(Copy, Clone, PartialEq)]
+enum NodeType {
+    Char,
+    Block,
+}
+
+#[derive(Copy, Clone)]
+struct DevNode {
+    type_: NodeType,
+    major: u32,
+    minor: u32,
+}
+
+/// Device nodes mounted at `/dev`. Nodes only name a device number,
+/// `mknod` may add more of them.
+pub struct DevFs {
+    nodes: UPIntrFreeCell<BTreeMap<String, DevNode>>,
+}
+
+impl DevFs {
+    pub fn new() -> Self {
+        let mut nodes = BTreeMap::new();
+        for (name, type_, major, minor) in [
+            ("null", NodeType::Char, MEM_MAJOR, 3),
+            ("zero", NodeType::Char, MEM_MAJOR, 5),
+            ("random", NodeType::Char, MEM_MAJOR, 8),
+            ("urandom", NodeType::Char, MEM_MAJOR, 9),
+            ("tty", NodeType::Char, TTY_MAJOR, 0),
+            ("console", NodeType::Char, TTY_MAJOR, 1),
+            ("ptmx", NodeType::Char, TTY_MAJOR, 2),
+            ("pcap", NodeType::Char, MISC_MAJOR, PCAP_MINOR),
+            ("fb0", NodeType::Char, FB_MAJOR, 0),
+            ("input/event0", NodeType::Char, INPUT_MAJOR, 64),
+            ("input/event1", NodeType::Char, INPUT_MAJOR, 65),
+            ("vda", NodeType::Block, VIRTBLK_MAJOR, 0),
+        ] {
+            nodes.insert(
+                String::from(name),
+                DevNode {
+                    type_,
+                    major,
+                    minor,
+                },
+            );
+        }
+        Self {
+            nodes: unsafe { UPIntrFreeCell::new(nodes) },
+        }
+    }
+}
+
+impl FileSystem for DevFs {
+    fn fs_type(&self) -> &'static str {
+        "devfs"
+    }
+
+    fn open(&self, path: &str, flags: OpenFlags) -> Option<Arc<dyn File + Send + Sync>> {
+        let node = self.nodes.exclusive_access().get(path).copied();
+        let node = node.or_else(|| pts_node(path))?;
+        let (readable, writable) = flags.read_write();
+        open_device(node, readable, writable)
+    }
+
+    fn mknod(&self, path: &str, mode: u32, dev: u64) -> isize {
+        let type_ = match mode & S_IFMT {
+            S_IFCHR => NodeType::Char,
+            S_IFBLK => NodeType::Block,
+            _ => return -(EINVAL as isize),
+        };
+        let mut nodes = self.nodes.exclusive_access();
+        if path.is_empty() || nodes.contains_key(path) {
+            return -(EEXIST as isize);
+        }
+        let (major, minor) = decode_dev(dev);
+        nodes.insert(
+            String::from(path),
+            DevNode {
+                type_,
+                major,
+                minor,
+            },
+        );
+        0
+    }
+}
+
+/// `pts/N`, which is there for as long as pseudo-terminal N is.
+fn pts_node(path: &str) -> Option<DevNode> {
+    Some(DevNode {
+        type_: NodeType::Char,
+        major: PTY_SLAVE_MAJOR,
+        minor: path.strip_prefix("pts/")?.parse().ok()?,
+    })
+}
+
+/// Linux `dev_t` layout: major in bits 8..20, minor in bits 0..8 and 20..32.
+fn decode_dev(dev: u64) -> (u32, u32) {
+    let major = ((dev >> 8) & 0xfff) as u32;
+    let minor = ((dev & 0xff) | ((dev >> 12) & 0xfff00)) as u32;
+    (major, minor)
+}
+
+/// A node whose number no driver claims, or whose device the machine
+/// lacks, can not be opened.
+fn open_device(
+    node: DevNode, readable: bool, writable: bool,
+) -> Option<Arc<dyn File + Send + Sync>> {
+    let file: Arc<dyn File + Send + Sync> = match (node.type_, node.major, node.minor) {
+        (NodeType::Char, MEM_MAJOR, 3) => Arc::new(NullDev { readable, writable }),
+        (NodeType::Char, MEM_MAJOR, 5) => Arc::new(ZeroDev { readable, writable }),
+        (NodeType::Char, MEM_MAJOR, 8 | 9) => Arc::new(RandomDev { readable, writable }),
+        (NodeType::Char, TTY_MAJOR, 0 | 1) => Arc::new(TtyFile::new(console())),
+        (NodeType::Char, TTY_MAJOR, 2) => open_pty_master(),
+        (NodeType::Char, PTY_SLAVE_MAJOR, index) => open_pty_slave(index as usize)?,
+        (NodeType::Char, MISC_MAJOR, PCAP_MINOR) => Arc::new(PcapDev::new(readable, writable)),
+        (NodeType::Char, FB_MAJOR, 0) => Arc::new(FbDev::new(gpu_device()?)),
+        (NodeType::Char, INPUT_MAJOR, 64) => Arc::new(EventDev(input_device(InputKind::Keyboard)?)),
+        (NodeType::Char, INPUT_MAJOR, 65) => Arc::new(EventDev(input_device(InputKind::Mouse)?)),
+        (NodeType::Block, VIRTBLK_MAJOR, 0) => {
+            Arc::new(RawBlockDev::new(block_device()?, readable, writable))
+        }
+        _ => return None,
+    };
+    Some(file)
+}
+
+/// `/dev/null`: reads hit end of file, writes are dropped.
+struct NullDev {
+    readable: bool,
+    writable: bool,
+}
+
+impl File for NullDev {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        buf.len()
+    }
+    fn reopen(&self, readable: bool, writable: bool) -> Option<Arc<dyn File + Send + Sync>> {
+        Some(Arc::new(NullDev { readable, writable }))
+    }
+}
+
+/// `/dev/zero`
+struct ZeroDev {
+    readable: bool,
+    writable: bool,
+}
+
+impl File for ZeroDev {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        for slice in buf.buffers.iter_mut() {
+            slice.fill(0);
+        }
+        buf.len()
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        buf.len()
+    }
+    fn reopen(&self, readable: bool, writable: bool) -> Option<Arc<dyn File + Send + Sync>> {
+        Some(Arc::new(ZeroDev { readable, writable }))
+    }
+}
+
+/// `/dev/random` and `/dev/urandom`, both the kernel generator, which is
+/// seeded before anyone can open them.
+struct RandomDev {
+    readable: bool,
+    writable: bool,
+}
+
+impl File for RandomDev {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        for slice in buf.buffers.iter_mut() {
+            random::fill(slice);
+        }
+        buf.len()
+    }
+    /// Writing stirs the written bytes into the generator.
+    fn write(&self, buf: UserBuffer) -> usize {
+        for slice in buf.buffers.iter() {
+            random::mix(slice);
+        }
+        buf.len()
+    }
+    fn reopen(&self, readable: bool, writable: bool) -> Option<Arc<dyn File + Send + Sync>> {
+        Some(Arc::new(RandomDev { readable, writable }))
+    }
+}
+
+/// `/dev/pcap`, the packet capture. Reading gives the capture as it was at
+/// open, in libpcap format. Writing `1` starts a new capture, `0` stops it.
+struct PcapDev {
+    readable: bool,
+    writable: bool,
+    data: Vec<u8>,
+    offset: UPIntrFreeCell<usize>,
+}
+
+impl PcapDev {
+    fn new(readable: bool, writable: bool) -> Self {
+        Self {
+            readable,
+            writable,
+            data: if readable {
+                pcap::snapshot()
+            } else {
+                Vec::new()
+            },
+            offset: unsafe { UPIntrFreeCell::new(0) },
+        }
+    }
+}
+
+impl File for PcapDev {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        let mut offset = self.offset.exclusive_access();
+        let start = *offset;
+        for slice in buf.buffers.iter_mut() {
+            let len = slice.len().min(self.data.len() - *offset);
+            slice[..len].copy_from_slice(&self.data[*offset..*offset + len]);
+            *offset += len;
+        }
+        *offset - start
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        let first = buf
+            .buffers
+            .iter()
+            .flat_map(|slice| slice.iter())
+            .find(|byte| !byte.is_ascii_whitespace());
+        match first {
+            Some(b'1') => pcap::set_capturing(true),
+            Some(b'0') => pcap::set_capturing(false),
+            _ => return -(EINVAL as isize) as usize,
+        }
+        buf.len()
+    }
+    /// A capture taken anew.
+    fn reopen(&self, readable: bool, writable: bool) -> Option<Arc<dyn File + Send + Sync>> {
+        Some(Arc::new(PcapDev::new(readable, writable)))
+    }
+}
+
+/// `/dev/fb0`, the virtio-gpu framebuffer. Writes are flushed to the screen,
+/// a mapping has to be flushed with `sys_framebuffer_flush`.
+struct FbDev {
+    gpu: Arc<dyn GpuDevice>,
+    offset: UPIntrFreeCell<usize>,
+}
+
+impl FbDev {
+    fn new(gpu: Arc<dyn GpuDevice>) -> Self {
+        Self {
+            gpu,
+            offset: unsafe { UPIntrFreeCell::new(0) },
+        }
+    }
+}
+
+impl File for FbDev {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        let fb = self.gpu.get_framebuffer();
+        let mut offset = self.offset.exclusive_access();
+        let start = *offset;
+        for slice in buf.buffers.iter_mut() {
+            let len = slice.len().min(fb.len() - *offset);
+            slice[..len].copy_from_slice(&fb[*offset..*offset + len]);
+            *offset += len;
+        }
+        *offset - start
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        let fb = self.gpu.get_framebuffer();
+        let mut offset = self.offset.exclusive_access();
+        let start = *offset;
+        for slice in buf.buffers.iter() {
+            let len = slice.len().min(fb.len() - *offset);
+            fb[*offset..*offset + len].copy_from_slice(&slice[..len]);
+            *offset += len;
+        }
+        drop(offset);
+        self.gpu.flush();
+        *self.offset.exclusive_access() - start
+    }
+    fn mmap_page(&self, offset: usize) -> Option<FilePage> {
+        let fb = self.gpu.get_framebuffer();
+        if offset >= fb.len() {
+            return None;
+        }
+        let pa = PhysAddr::from(fb.as_ptr() as usize + offset);
+        Some(FilePage::Device(pa.floor()))
+    }
+    fn reopen(&self, _readable: bool, _writable: bool) -> Option<Arc<dyn File + Send + Sync>> {
+        Some(Arc::new(FbDev::new(self.gpu.clone())))
+    }
+}
+
+/// `/dev/input/eventN`. Every event is 8 bytes, encoded as for `sys_event_get`:
+/// type in bits 48..64, code in 32..48 and value in 0..32.
+struct EventDev(Arc<dyn InputDevice>);
+
+impl File for EventDev {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    /// Wait for one event, then take whatever else is queued and fits.
+    fn read(&self, buf: UserBuffer) -> usize {
+        let mut count = 0;
+        let len = buf.len();
+        let mut bytes = buf.into_iter();
+        while count + 8 <= len {
+            if count > 0 && self.0.is_empty() {
+                break;
+            }
+            let event = self.0.read_event();
+            for b in event.to_le_bytes() {
+                unsafe {
+                    bytes.next().unwrap().write_volatile(b);
+                }
+            }
+            count += 8;
+        }
+        count
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn poll_ready(&self, events: PollEvents) -> PollEvents {
+        if self.0.is_empty() {
+            PollEvents::empty()
+        } else {
+            events & PollEvents::IN
+        }
+    }
+    fn wait_queue(&self) -> Option<&WaitQueue> {
+        Some(self.0.wait_queue())
+    }
+}
+
+/// `/dev/vda`, the raw virtio-blk disk. Goes through the easy-fs block cache
+/// so it sees, and is seen by, the root file system on the same disk.
+struct RawBlockDev {
+    readable: bool,
+    writable: bool,
+    device: Arc<dyn BlockDevice>,
+    offset: UPIntrFreeCell<usize>,
+}
+
+impl RawBlockDev {
+    fn new(device: Arc<dyn BlockDevice>, readable: bool, writable: bool) -> Self {
+        Self {
+            readable,
+            writable,
+            device,
+            offset: unsafe { UPIntrFreeCell::new(0) },
+        }
+    }
+}
+
+impl File for RawBlockDev {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    /// Reads stop at the end of the disk.
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        let size = self.device.num_blocks().saturating_mul(BLOCK_SZ);
+        let mut offset = *self.offset.exclusive_access();
+        let start = offset;
+        for slice in buf.buffers.iter_mut() {
+            let mut done = 0;
+            while done < slice.len() && offset < size {
+                let in_block = offset % BLOCK_SZ;
+                let len = (BLOCK_SZ - in_block).min(slice.len() - done);
+                get_block_cache(offset / BLOCK_SZ, self.device.clone())
+                    .lock()
+                    .read(0, |block: &[u8; BLOCK_SZ]| {
+                        slice[done..done + len].copy_from_slice(&block[in_block..in_block + len]);
+                    });
+                done += len;
+                offset += len;
+            }
+            if done < slice.len() {
+                break;
+            }
+        }
+        *self.offset.exclusive_access() = offset;
+        offset - start
+    }
+    /// Writes stop at the end of the disk, with `ENOSPC` if nothing fit.
+    fn write(&self, buf: UserBuffer) -> usize {
+        let size = self.device.num_blocks().saturating_mul(BLOCK_SZ);
+        let mut offset = *self.offset.exclusive_access();
+        let start = offset;
+        for slice in buf.buffers.iter() {
+            let mut done = 0;
+            while done < slice.len() && offset < size {
+                let in_block = offset % BLOCK_SZ;
+                let len = (BLOCK_SZ - in_block).min(slice.len() - done);
+                get_block_cache(offset / BLOCK_SZ, self.device.clone())
+                    .lock()
+                    .modify(0, |block: &mut [u8; BLOCK_SZ]| {
+                        block[in_block..in_block + len].copy_from_slice(&slice[done..done + len]);
+                    });
+                done += len;
+                offset += len;
+            }
+            if done < slice.len() {
+                break;
+            }
+        }
+        if offset == start && buf.len() > 0 {
+            return -(ENOSPC as isize) as usize;
+        }
+        *self.offset.exclusive_access() = offset;
+        offset - start
+    }
+    fn reopen(&self, readable: bool, writable: bool) -> Option<Arc<dyn File + Send + Sync>> {
+        Some(Arc::new(RawBlockDev::new(
+            self.device.clone(),
+            readable,
+            writable,
+        )))
+    }
+}
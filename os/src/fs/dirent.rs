@@ -0,0 +1,46 @@
+use crate::mm::UserBuffer;
+use alloc::{
+    string::String,
+    vec::Vec,
+};
+use shared_defination::error::EINVAL;
+
+pub const DT_DIR: u8 = 4;
+pub const DT_REG: u8 = 8;
+pub const DT_LNK: u8 = 10;
+
+/// Size of the fixed part of `struct linux_dirent64`:
+/// d_ino: u64, d_off: i64, d_reclen: u16, d_type: u8.
+const DIRENT64_HEADER: usize = 19;
+
+/// Write the `(name, d_type)` entries from `*pos` on into `buf` as `linux_dirent64`
+/// records, as many as fit. `*pos` is advanced past the written ones.
+/// Returns the number of bytes written, 0 at the end of the directory.
+pub fn fill_dirents(entries: &[(String, u8)], pos: &mut usize, buf: UserBuffer) -> isize {
+    let capacity = buf.len();
+    let mut records: Vec<u8> = Vec::new();
+    while let Some((name, d_type)) = entries.get(*pos) {
+        let reclen = (DIRENT64_HEADER + name.len() + 1 + 7) & !7;
+        if records.len() + reclen > capacity {
+            break;
+        }
+        let start = records.len();
+        records.extend_from_slice(&(*pos as u64 + 1).to_le_bytes());
+        records.extend_from_slice(&(*pos as i64 + 1).to_le_bytes());
+        records.extend_from_slice(&(reclen as u16).to_le_bytes());
+        records.push(*d_type);
+        records.extend_from_slice(name.as_bytes());
+        records.resize(start + reclen, 0);
+        *pos += 1;
+    }
+    if records.is_empty() && *pos < entries.len() {
+        // not even one record fits
+        return -(EINVAL as isize);
+    }
+    for (dst, src) in buf.into_iter().zip(records.iter()) {
+        unsafe {
+            dst.write_volatile(*src);
+        }
+    }
+    records.len() as isize
+}
@@ -1,6 +1,9 @@
-use super::File;
+use super::{
+    File,
+    FileSystem,
+};
 use crate::{
-    drivers::BLOCK_DEVICE,
+    drivers::block_device,
     mm::UserBuffer,
     sync::UPIntrFreeCell,
 };
@@ -14,6 +17,7 @@ use easy_fs::{
     Inode,
 };
 use lazy_static::*;
+use shared_defination::error::EINVAL;
 
 pub struct OSInode {
     readable: bool,
@@ -52,7 +56,7 @@ impl OSInode {
 
 lazy_static! {
     pub static ref ROOT_INODE: Arc<Inode> = {
-        let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
+        let efs = EasyFileSystem::open(block_device().expect("no disk for the root file system"));
         Arc::new(EasyFileSystem::root_inode(&efs))
     };
 }
@@ -72,6 +76,8 @@ bitflags! {
         const RDWR = 1 << 1;
         const CREATE = 1 << 9;
         const TRUNC = 1 << 10;
+        const NONBLOCK = 1 << 11;
+        const CLOEXEC = 1 << 19;
     }
 }
 
@@ -79,9 +85,10 @@ impl OpenFlags {
     /// Do not check validity for simplicity
     /// Return (readable, writable)
     pub fn read_write(&self) -> (bool, bool) {
-        if self.is_empty() {
+        let mode = self.difference(Self::NONBLOCK | Self::CLOEXEC);
+        if mode.is_empty() {
             (true, false)
-        } else if self.contains(Self::WRONLY) {
+        } else if mode.contains(Self::WRONLY) {
             (false, true)
         } else {
             (true, true)
@@ -143,4 +150,28 @@ impl File for OSInode {
         }
         total_write_size
     }
+    /// easy-fs can only drop all of a file's blocks.
+    fn truncate(&self, len: usize) -> isize {
+        if len != 0 {
+            return -(EINVAL as isize);
+        }
+        self.inner.exclusive_access().inode.clear();
+        0
+    }
+    fn reopen(&self, readable: bool, writable: bool) -> Option<Arc<dyn File + Send + Sync>> {
+        let inode = self.inner.exclusive_access().inode.clone();
+        Some(Arc::new(OSInode::new(readable, writable, inode)))
+    }
+}
+
+/// The easy-fs image on the first disk, mounted at `/`.
+pub struct RootFs;
+
+impl FileSystem for RootFs {
+    fn fs_type(&self) -> &'static str {
+        "easyfs"
+    }
+    fn open(&self, path: &str, flags: OpenFlags) -> Option<Arc<dyn File + Send + Sync>> {
+        open_file(path, flags).map(|inode| inode as Arc<dyn File + Send + Sync>)
+    }
 }
@@ -1,25 +1,146 @@
+mod devfs;
+mod dirent;
 mod inode;
+mod mount;
 mod pipe;
-mod stdio;
+mod poll;
+mod procfs;
+mod pty;
+mod timerfd;
+mod tmpfs;
+mod tty;
 
-use crate::mm::UserBuffer;
+use crate::{
+    mm::{
+        FrameTracker,
+        PhysPageNum,
+        UserBuffer,
+    },
+    net::{
+        Socket,
+        UnixSocket,
+    },
+    sync::WaitQueue,
+};
+use alloc::sync::Arc;
+use shared_defination::error::{
+    EINVAL,
+    ENOTDIR,
+    ENOTTY,
+};
 
 pub trait File: Send + Sync {
     fn readable(&self) -> bool;
     fn writable(&self) -> bool;
     fn read(&self, buf: UserBuffer) -> usize;
     fn write(&self, buf: UserBuffer) -> usize;
+    /// Device specific request, files that are not devices answer `ENOTTY`.
+    fn ioctl(&self, _cmd: u32, _arg: usize) -> isize {
+        -(ENOTTY as isize)
+    }
+    /// The page holding byte `offset` of the file, for `mmap`.
+    /// `None` means the file can not be mapped (or `offset` is past its end).
+    fn mmap_page(&self, _offset: usize) -> Option<FilePage> {
+        None
+    }
+    /// Next entries of a directory as `linux_dirent64` records, see `fill_dirents`.
+    fn getdents(&self, _buf: UserBuffer) -> isize {
+        -(ENOTDIR as isize)
+    }
+    /// Another open file of the same file, with an offset and mode of its
+    /// own, as opening `/proc/<pid>/fd/N` gives. `None` for files without
+    /// either, which can be shared as they are.
+    fn reopen(&self, _readable: bool, _writable: bool) -> Option<Arc<dyn File + Send + Sync>> {
+        None
+    }
+    /// Cut or extend the file to `len` bytes.
+    fn truncate(&self, _len: usize) -> isize {
+        -(EINVAL as isize)
+    }
+    /// Which of `events` can be done now without blocking.
+    fn poll_ready(&self, events: PollEvents) -> PollEvents {
+        let mut ready = PollEvents::empty();
+        if self.readable() {
+            ready |= PollEvents::IN;
+        }
+        if self.writable() {
+            ready |= PollEvents::OUT;
+        }
+        ready & events
+    }
+    /// Woken whenever `poll_ready` may change. Files without one are asked
+    /// again every tick while somebody polls them.
+    fn wait_queue(&self) -> Option<&WaitQueue> {
+        None
+    }
+    /// `O_NONBLOCK`, only kept by files that can block. Reads and writes
+    /// that would have to wait fail with `EAGAIN` while it is set.
+    fn nonblocking(&self) -> bool {
+        false
+    }
+    fn set_nonblocking(&self, _nonblocking: bool) {}
+    /// The epoll instance behind an epoll fd.
+    fn as_epoll(&self) -> Option<&Epoll> {
+        None
+    }
+    /// The timer behind a timerfd.
+    fn as_timerfd(&self) -> Option<&TimerFd> {
+        None
+    }
+    /// The socket behind a socket fd.
+    fn as_socket(&self) -> Option<&dyn Socket> {
+        None
+    }
+    /// The socket behind an `AF_UNIX` socket fd.
+    fn as_unix_socket(&self) -> Option<&UnixSocket> {
+        None
+    }
+}
+
+/// A page handed out by `File::mmap_page`.
+pub enum FilePage {
+    /// Device memory such as the framebuffer, it is never freed.
+    Device(PhysPageNum),
+    /// A frame owned by the file, the mapping keeps it alive.
+    Frame(Arc<FrameTracker>),
 }
 
+pub use devfs::DevFs;
+pub use dirent::{
+    fill_dirents,
+    DT_DIR,
+    DT_LNK,
+    DT_REG,
+};
 pub use inode::{
     list_apps,
     open_file,
     OpenFlags,
     OSInode,
+    RootFs,
     ROOT_INODE,
 };
+pub use mount::{
+    absolute_path,
+    mkdir,
+    mknod,
+    mount,
+    mount_list,
+    open,
+    unlink,
+    FileSystem,
+};
 pub use pipe::make_pipe;
-pub use stdio::{
-    Stdin,
-    Stdout,
+pub use poll::{
+    poll_wait,
+    Epoll,
+    PollEvents,
+};
+pub use procfs::ProcFs;
+pub use timerfd::TimerFd;
+pub use tmpfs::TmpFs;
+pub use tty::{
+    console,
+    console_receive,
+    TtyFile,
 };
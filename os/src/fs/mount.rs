@@ -0,0 +1,150 @@
+use super::{
+    DevFs,
+    File,
+    OpenFlags,
+    ProcFs,
+    RootFs,
+};
+use crate::sync::UPIntrFreeCell;
+use alloc::{
+    string::String,
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use lazy_static::*;
+use shared_defination::error::{
+    EBUSY,
+    EPERM,
+};
+
+/// A file system that can be mounted somewhere in the tree.
+/// Paths handed to it are relative to its mount point, without a leading `/`.
+pub trait FileSystem: Send + Sync {
+    /// Name shown in the mount table.
+    fn fs_type(&self) -> &'static str;
+    fn open(&self, path: &str, flags: OpenFlags) -> Option<Arc<dyn File + Send + Sync>>;
+    /// Create a device node, only devfs knows about devices.
+    fn mknod(&self, _path: &str, _mode: u32, _dev: u64) -> isize {
+        -(EPERM as isize)
+    }
+    fn mkdir(&self, _path: &str) -> isize {
+        -(EPERM as isize)
+    }
+    /// Remove a file, or an empty directory when `rmdir` is set.
+    fn unlink(&self, _path: &str, _rmdir: bool) -> isize {
+        -(EPERM as isize)
+    }
+}
+
+pub struct MountPoint {
+    /// Absolute path, "/" for the root file system.
+    pub path: String,
+    pub fs: Arc<dyn FileSystem>,
+}
+
+lazy_static! {
+    pub static ref MOUNT_TABLE: UPIntrFreeCell<Vec<MountPoint>> = unsafe {
+        UPIntrFreeCell::new(vec![
+            MountPoint {
+                path: String::from("/"),
+                fs: Arc::new(RootFs),
+            },
+            MountPoint {
+                path: String::from("/dev"),
+                fs: Arc::new(DevFs::new()),
+            },
+            MountPoint {
+                path: String::from("/proc"),
+                fs: Arc::new(ProcFs),
+            },
+        ])
+    };
+}
+
+pub fn mount(path: &str, fs: Arc<dyn FileSystem>) -> isize {
+    let mut table = MOUNT_TABLE.exclusive_access();
+    if table.iter().any(|mp| mp.path == path) {
+        return -(EBUSY as isize);
+    }
+    table.push(MountPoint {
+        path: String::from(path),
+        fs,
+    });
+    0
+}
+
+/// (mount point, file system type) of every mount, in mount order.
+pub fn mount_list() -> Vec<(String, &'static str)> {
+    MOUNT_TABLE
+        .exclusive_access()
+        .iter()
+        .map(|mp| (mp.path.clone(), mp.fs.fs_type()))
+        .collect()
+}
+
+/// Find the file system `path` (absolute and normalized) lives on,
+/// returns it with the rest of the path inside that file system.
+pub fn lookup(path: &str) -> (Arc<dyn FileSystem>, String) {
+    let table = MOUNT_TABLE.exclusive_access();
+    let mp = table
+        .iter()
+        .filter(|mp| {
+            mp.path == "/"
+                || path == mp.path
+                || (path.starts_with(mp.path.as_str()) && path.as_bytes()[mp.path.len()] == b'/')
+        })
+        .max_by_key(|mp| mp.path.len())
+        .unwrap();
+    let rest = path[mp.path.len()..].trim_start_matches('/');
+    (mp.fs.clone(), String::from(rest))
+}
+
+/// Open an absolute path on whatever file system it is mounted on.
+pub fn open(path: &str, flags: OpenFlags) -> Option<Arc<dyn File + Send + Sync>> {
+    let (fs, rest) = lookup(path);
+    fs.open(rest.as_str(), flags)
+}
+
+pub fn mknod(path: &str, mode: u32, dev: u64) -> isize {
+    let (fs, rest) = lookup(path);
+    fs.mknod(rest.as_str(), mode, dev)
+}
+
+pub fn mkdir(path: &str) -> isize {
+    let (fs, rest) = lookup(path);
+    fs.mkdir(rest.as_str())
+}
+
+pub fn unlink(path: &str, rmdir: bool) -> isize {
+    let (fs, rest) = lookup(path);
+    fs.unlink(rest.as_str(), rmdir)
+}
+
+/// Join `path` onto `cwd` and resolve `.` and `..`.
+pub fn absolute_path(cwd: &str, path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    let joined = if path.starts_with('/') {
+        [path, ""]
+    } else {
+        [cwd, path]
+    };
+    for part in joined.iter().flat_map(|s| s.split('/')) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    let mut abs = String::new();
+    for part in parts {
+        abs.push('/');
+        abs.push_str(part);
+    }
+    if abs.is_empty() {
+        abs.push('/');
+    }
+    abs
+}
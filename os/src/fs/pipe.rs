@@ -1,34 +1,56 @@
-use super::File;
+use super::{
+    File,
+    PollEvents,
+};
 use crate::{
     mm::UserBuffer,
-    sync::UPIntrFreeCell,
+    sync::{
+        UPIntrFreeCell,
+        WaitQueue,
+    },
 };
 use alloc::sync::{
     Arc,
     Weak,
 };
+use core::sync::atomic::{
+    AtomicBool,
+    Ordering,
+};
+use shared_defination::error::EAGAIN;
 
-use crate::task::suspend_current_and_run_next;
+use crate::task::schedule;
 
 pub struct Pipe {
     readable: bool,
     writable: bool,
     buffer: Arc<UPIntrFreeCell<PipeRingBuffer>>,
+    /// Shared by both ends, woken on every read, write and close.
+    wait_queue: Arc<WaitQueue>,
+    nonblock: AtomicBool,
 }
 
 impl Pipe {
-    pub fn read_end_with_buffer(buffer: Arc<UPIntrFreeCell<PipeRingBuffer>>) -> Self {
+    pub fn read_end_with_buffer(
+        buffer: Arc<UPIntrFreeCell<PipeRingBuffer>>, wait_queue: Arc<WaitQueue>,
+    ) -> Self {
         Self {
             readable: true,
             writable: false,
             buffer,
+            wait_queue,
+            nonblock: AtomicBool::new(false),
         }
     }
-    pub fn write_end_with_buffer(buffer: Arc<UPIntrFreeCell<PipeRingBuffer>>) -> Self {
+    pub fn write_end_with_buffer(
+        buffer: Arc<UPIntrFreeCell<PipeRingBuffer>>, wait_queue: Arc<WaitQueue>,
+    ) -> Self {
         Self {
             readable: false,
             writable: true,
             buffer,
+            wait_queue,
+            nonblock: AtomicBool::new(false),
         }
     }
 }
@@ -104,8 +126,12 @@ impl PipeRingBuffer {
 /// Return (read_end, write_end)
 pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
     let buffer = Arc::new(unsafe { UPIntrFreeCell::new(PipeRingBuffer::new()) });
-    let read_end = Arc::new(Pipe::read_end_with_buffer(buffer.clone()));
-    let write_end = Arc::new(Pipe::write_end_with_buffer(buffer.clone()));
+    let wait_queue = Arc::new(WaitQueue::new());
+    let read_end = Arc::new(Pipe::read_end_with_buffer(
+        buffer.clone(),
+        wait_queue.clone(),
+    ));
+    let write_end = Arc::new(Pipe::write_end_with_buffer(buffer.clone(), wait_queue));
     buffer.exclusive_access().set_write_end(&write_end);
     (read_end, write_end)
 }
@@ -129,10 +155,19 @@ impl File for Pipe {
                 if ring_buffer.all_write_ends_closed() {
                     return already_read;
                 }
+                if self.nonblocking() {
+                    return match already_read {
+                        0 => -(EAGAIN as isize) as usize,
+                        _ => already_read,
+                    };
+                }
+                let task_ctx_ptr = self.wait_queue.wait_no_sched();
                 drop(ring_buffer);
-                suspend_current_and_run_next();
+                schedule(task_ctx_ptr);
                 continue;
             }
+            // there is room for the writers now
+            self.wait_queue.wake_all();
             for _ in 0..loop_read {
                 if let Some(byte_ref) = buf_iter.next() {
                     unsafe {
@@ -157,10 +192,19 @@ impl File for Pipe {
             let mut ring_buffer = self.buffer.exclusive_access();
             let loop_write = ring_buffer.available_write();
             if loop_write == 0 {
+                if self.nonblocking() {
+                    return match already_write {
+                        0 => -(EAGAIN as isize) as usize,
+                        _ => already_write,
+                    };
+                }
+                let task_ctx_ptr = self.wait_queue.wait_no_sched();
                 drop(ring_buffer);
-                suspend_current_and_run_next();
+                schedule(task_ctx_ptr);
                 continue;
             }
+            // there is data for the readers now
+            self.wait_queue.wake_all();
             // write at most loop_write bytes
             for _ in 0..loop_write {
                 if let Some(byte_ref) = buf_iter.next() {
@@ -175,4 +219,36 @@ impl File for Pipe {
             }
         }
     }
+    fn poll_ready(&self, events: PollEvents) -> PollEvents {
+        let ring_buffer = self.buffer.exclusive_access();
+        let mut ready = PollEvents::empty();
+        if self.readable {
+            if ring_buffer.available_read() > 0 {
+                ready |= PollEvents::IN;
+            }
+            if ring_buffer.all_write_ends_closed() {
+                ready |= PollEvents::HUP;
+            }
+        }
+        if self.writable && ring_buffer.available_write() > 0 {
+            ready |= PollEvents::OUT;
+        }
+        ready & events
+    }
+    fn wait_queue(&self) -> Option<&WaitQueue> {
+        Some(&self.wait_queue)
+    }
+    fn nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Relaxed)
+    }
+    fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblock.store(nonblocking, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        // readers blocked on an empty pipe see EOF once the write end is gone
+        self.wait_queue.wake_all();
+    }
 }
@@ -0,0 +1,243 @@
+use super::File;
+use crate::{
+    mm::UserBuffer,
+    sync::{
+        intr_free,
+        UPIntrFreeCell,
+    },
+    task::{
+        block_current_task,
+        current_task,
+        schedule,
+    },
+    timer::{
+        add_timer,
+        get_time_ms,
+        remove_timer,
+        TICK_MS,
+    },
+};
+use alloc::{
+    collections::BTreeMap,
+    sync::{
+        Arc,
+        Weak,
+    },
+    vec::Vec,
+};
+use bitflags::*;
+use shared_defination::error::{
+    EEXIST,
+    EINVAL,
+    ELOOP,
+    ENOENT,
+};
+
+bitflags! {
+    /// `poll` events, epoll uses the same bits in a `u32`.
+    pub struct PollEvents: u16 {
+        const IN = 0x001;
+        const PRI = 0x002;
+        const OUT = 0x004;
+        const ERR = 0x008;
+        const HUP = 0x010;
+        const NVAL = 0x020;
+    }
+}
+
+/// Sleep until `scan` finds something, waking up whenever one of `files`
+/// may have changed. Returns `None` once `deadline_ms` has passed, a `None`
+/// deadline waits forever.
+pub fn poll_wait<T>(
+    files: &[Arc<dyn File + Send + Sync>], deadline_ms: Option<usize>,
+    mut scan: impl FnMut() -> Option<T>,
+) -> Option<T> {
+    let task = current_task().unwrap();
+    // files that can not tell us when they change are checked every tick
+    let needs_tick = files.iter().any(|file| file.wait_queue().is_none());
+    loop {
+        let mut found = None;
+        // masked, so a wakeup can not slip in between the scan and blocking
+        let task_ctx_ptr = intr_free(|| {
+            found = scan();
+            let now = get_time_ms();
+            if found.is_some() || deadline_ms.map_or(false, |deadline| deadline <= now) {
+                return None;
+            }
+            for file in files {
+                if let Some(wait_queue) = file.wait_queue() {
+                    wait_queue.register(task.clone());
+                }
+            }
+            let wake_ms = match (deadline_ms, needs_tick) {
+                (Some(deadline), true) => Some(deadline.min(now + TICK_MS)),
+                (None, true) => Some(now + TICK_MS),
+                (deadline, false) => deadline,
+            };
+            if let Some(wake_ms) = wake_ms {
+                add_timer(wake_ms, task.clone());
+            }
+            Some(block_current_task())
+        });
+        match task_ctx_ptr {
+            Some(task_ctx_ptr) => schedule(task_ctx_ptr),
+            None => return found,
+        }
+        for file in files {
+            if let Some(wait_queue) = file.wait_queue() {
+                wait_queue.unregister(&task);
+            }
+        }
+        remove_timer(&task);
+    }
+}
+
+const EPOLL_CTL_ADD: usize = 1;
+const EPOLL_CTL_DEL: usize = 2;
+const EPOLL_CTL_MOD: usize = 3;
+/// Report once, then stay quiet until the next `EPOLL_CTL_MOD`.
+const EPOLLONESHOT: u32 = 1 << 30;
+
+struct EpollInterest {
+    /// Closing the last fd of the file drops it from the set, as on Linux.
+    file: Weak<dyn File + Send + Sync>,
+    events: u32,
+    data: u64,
+}
+
+/// The file behind an epoll fd. Always level-triggered, `EPOLLET` is
+/// accepted but treated as level.
+pub struct Epoll {
+    interests: UPIntrFreeCell<BTreeMap<usize, EpollInterest>>,
+}
+
+impl Epoll {
+    pub fn new() -> Self {
+        Self {
+            interests: unsafe { UPIntrFreeCell::new(BTreeMap::new()) },
+        }
+    }
+
+    pub fn ctl(
+        &self, op: usize, fd: usize, file: &Arc<dyn File + Send + Sync>, events: u32, data: u64,
+    ) -> isize {
+        // a set watching itself, even through other sets, would never stop polling
+        if op == EPOLL_CTL_ADD && file.as_epoll().map_or(false, |epoll| epoll.reaches(self)) {
+            return -(ELOOP as isize);
+        }
+        let mut interests = self.interests.exclusive_access();
+        // a closed fd number may have been reused for another file
+        if interests
+            .get(&fd)
+            .map_or(false, |interest| interest.file.upgrade().is_none())
+        {
+            interests.remove(&fd);
+        }
+        let interest = EpollInterest {
+            file: Arc::downgrade(file),
+            events,
+            data,
+        };
+        match op {
+            EPOLL_CTL_ADD => {
+                if interests.contains_key(&fd) {
+                    return -(EEXIST as isize);
+                }
+                interests.insert(fd, interest);
+            }
+            EPOLL_CTL_MOD => match interests.get_mut(&fd) {
+                Some(old) => *old = interest,
+                None => return -(ENOENT as isize),
+            },
+            EPOLL_CTL_DEL => {
+                if interests.remove(&fd).is_none() {
+                    return -(ENOENT as isize);
+                }
+            }
+            _ => return -(EINVAL as isize),
+        }
+        0
+    }
+
+    /// Files in the set that are still open.
+    pub fn files(&self) -> Vec<Arc<dyn File + Send + Sync>> {
+        self.interests
+            .exclusive_access()
+            .values()
+            .filter_map(|interest| interest.file.upgrade())
+            .collect()
+    }
+
+    /// Whether `target` is this set or in it, directly or through nested sets.
+    fn reaches(&self, target: &Epoll) -> bool {
+        core::ptr::eq(self, target)
+            || self
+                .files()
+                .iter()
+                .any(|file| file.as_epoll().map_or(false, |epoll| epoll.reaches(target)))
+    }
+
+    /// Up to `max` ready `(events, data)` pairs.
+    pub fn ready(&self, max: usize) -> Vec<(u32, u64)> {
+        let mut ready = Vec::new();
+        let mut interests = self.interests.exclusive_access();
+        for interest in interests.values_mut() {
+            if ready.len() == max {
+                break;
+            }
+            let revents = interest_ready(interest);
+            if revents != 0 {
+                ready.push((revents, interest.data));
+                if interest.events & EPOLLONESHOT != 0 {
+                    interest.events = 0;
+                }
+            }
+        }
+        ready
+    }
+}
+
+fn interest_ready(interest: &EpollInterest) -> u32 {
+    let file = match interest.file.upgrade() {
+        Some(file) => file,
+        None => return 0,
+    };
+    if interest.events == 0 {
+        return 0;
+    }
+    let events = PollEvents::from_bits_truncate(interest.events as u16);
+    // errors and hang-ups are reported even when not asked for
+    file.poll_ready(events | PollEvents::ERR | PollEvents::HUP)
+        .bits() as u32
+}
+
+impl File for Epoll {
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    /// Readable when any file in the set is ready, so epoll fds nest.
+    fn poll_ready(&self, events: PollEvents) -> PollEvents {
+        let any_ready = self
+            .interests
+            .exclusive_access()
+            .values()
+            .any(|interest| interest_ready(interest) != 0);
+        if any_ready {
+            events & PollEvents::IN
+        } else {
+            PollEvents::empty()
+        }
+    }
+    fn as_epoll(&self) -> Option<&Epoll> {
+        Some(self)
+    }
+}
@@ -0,0 +1,400 @@
+use super::{
+    fill_dirents,
+    mount_list,
+    File,
+    FileSystem,
+    OpenFlags,
+    DT_DIR,
+    DT_LNK,
+    DT_REG,
+};
+use crate::{
+    board::irq_name,
+    config::PAGE_SIZE,
+    mm::{
+        frame_stats,
+        MapPermission,
+        MapType,
+        UserBuffer,
+    },
+    sync::UPIntrFreeCell,
+    task::{
+        current_process,
+        pid2process,
+        pid_list,
+        TaskStatus,
+    },
+    timer::get_time_ms,
+    trap::interrupt_counts,
+};
+use alloc::{
+    format,
+    string::{
+        String,
+        ToString,
+    },
+    sync::Arc,
+    vec::Vec,
+};
+use core::fmt::Write;
+
+/// Kernel state as text, mounted at `/proc`. Every read generates the
+/// content again, nothing is stored.
+pub struct ProcFs;
+
+#[derive(Copy, Clone)]
+enum ProcFileKind {
+    Meminfo,
+    Mounts,
+    Uptime,
+    Interrupts,
+    Stat(usize),
+    Status(usize),
+    Maps(usize),
+    Cmdline(usize),
+    Cwd(usize),
+}
+
+#[derive(Copy, Clone)]
+enum ProcDirKind {
+    Root,
+    Process(usize),
+    Fd(usize),
+}
+
+impl FileSystem for ProcFs {
+    fn fs_type(&self) -> &'static str {
+        "proc"
+    }
+
+    fn open(&self, path: &str, flags: OpenFlags) -> Option<Arc<dyn File + Send + Sync>> {
+        let parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
+        let kind = match parts.as_slice() {
+            [] => return Some(Arc::new(ProcDir::new(ProcDirKind::Root))),
+            ["meminfo"] => ProcFileKind::Meminfo,
+            ["mounts"] => ProcFileKind::Mounts,
+            ["uptime"] => ProcFileKind::Uptime,
+            ["interrupts"] => ProcFileKind::Interrupts,
+            [pid, rest @ ..] => {
+                let pid = parse_pid(pid)?;
+                match rest {
+                    [] => return Some(Arc::new(ProcDir::new(ProcDirKind::Process(pid)))),
+                    ["fd"] => return Some(Arc::new(ProcDir::new(ProcDirKind::Fd(pid)))),
+                    ["fd", fd] => return open_fd(pid, fd.parse().ok()?, flags),
+                    ["stat"] => ProcFileKind::Stat(pid),
+                    ["status"] => ProcFileKind::Status(pid),
+                    ["maps"] => ProcFileKind::Maps(pid),
+                    ["cmdline"] => ProcFileKind::Cmdline(pid),
+                    ["cwd"] => ProcFileKind::Cwd(pid),
+                    _ => return None,
+                }
+            }
+        };
+        if flags.read_write().1 {
+            return None;
+        }
+        Some(Arc::new(ProcFile::new(kind)))
+    }
+}
+
+/// Follow `/proc/<pid>/fd/N` to the file it names, opened anew with an
+/// offset of its own. It can not be opened for more than the process has
+/// it open for.
+fn open_fd(pid: usize, fd: usize, flags: OpenFlags) -> Option<Arc<dyn File + Send + Sync>> {
+    let process = pid2process(pid)?;
+    let file = process.inner_exclusive_access().fd_table.get(fd)?.clone()?;
+    let (readable, writable) = flags.read_write();
+    // directories are neither readable nor writable, but can be opened
+    if writable && !file.writable() || readable && file.writable() && !file.readable() {
+        return None;
+    }
+    Some(file.reopen(readable, writable).unwrap_or(file))
+}
+
+/// `self` or a live pid.
+fn parse_pid(name: &str) -> Option<usize> {
+    let pid = if name == "self" {
+        current_process().getpid()
+    } else {
+        name.parse().ok()?
+    };
+    pid2process(pid).map(|_| pid)
+}
+
+struct ProcFile {
+    kind: ProcFileKind,
+    offset: UPIntrFreeCell<usize>,
+}
+
+impl ProcFile {
+    fn new(kind: ProcFileKind) -> Self {
+        Self {
+            kind,
+            offset: unsafe { UPIntrFreeCell::new(0) },
+        }
+    }
+}
+
+impl File for ProcFile {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        // the process may have gone away since open
+        let content = generate(self.kind).unwrap_or_default();
+        let mut offset = self.offset.exclusive_access();
+        let start = *offset;
+        for slice in buf.buffers.iter_mut() {
+            let begin = (*offset).min(content.len());
+            let len = slice.len().min(content.len() - begin);
+            slice[..len].copy_from_slice(&content[begin..begin + len]);
+            *offset += len;
+        }
+        *offset - start
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn reopen(&self, _readable: bool, _writable: bool) -> Option<Arc<dyn File + Send + Sync>> {
+        Some(Arc::new(ProcFile::new(self.kind)))
+    }
+}
+
+struct ProcDir {
+    kind: ProcDirKind,
+    pos: UPIntrFreeCell<usize>,
+}
+
+impl ProcDir {
+    fn new(kind: ProcDirKind) -> Self {
+        Self {
+            kind,
+            pos: unsafe { UPIntrFreeCell::new(0) },
+        }
+    }
+
+    fn entries(&self) -> Vec<(String, u8)> {
+        match self.kind {
+            ProcDirKind::Root => {
+                let mut entries: Vec<(String, u8)> = ["meminfo", "mounts", "uptime", "interrupts"]
+                    .iter()
+                    .map(|name| (name.to_string(), DT_REG))
+                    .collect();
+                entries.push((String::from("self"), DT_LNK));
+                for pid in pid_list() {
+                    entries.push((pid.to_string(), DT_DIR));
+                }
+                entries
+            }
+            ProcDirKind::Process(_) => {
+                let mut entries: Vec<(String, u8)> = ["stat", "status", "maps", "cmdline", "cwd"]
+                    .iter()
+                    .map(|name| (name.to_string(), DT_REG))
+                    .collect();
+                entries.push((String::from("fd"), DT_DIR));
+                entries
+            }
+            ProcDirKind::Fd(pid) => match pid2process(pid) {
+                Some(process) => process
+                    .inner_exclusive_access()
+                    .fd_table
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, file)| file.is_some())
+                    .map(|(fd, _)| (fd.to_string(), DT_LNK))
+                    .collect(),
+                None => Vec::new(),
+            },
+        }
+    }
+}
+
+impl File for ProcDir {
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn getdents(&self, buf: UserBuffer) -> isize {
+        let entries = self.entries();
+        let mut pos = self.pos.exclusive_access();
+        fill_dirents(&entries, &mut pos, buf)
+    }
+    fn reopen(&self, _readable: bool, _writable: bool) -> Option<Arc<dyn File + Send + Sync>> {
+        Some(Arc::new(ProcDir::new(self.kind)))
+    }
+}
+
+fn generate(kind: ProcFileKind) -> Option<Vec<u8>> {
+    let text = match kind {
+        ProcFileKind::Meminfo => {
+            let (total, free) = frame_stats();
+            let kb = PAGE_SIZE / 1024;
+            format!(
+                "MemTotal:       {:8} kB\nMemFree:        {:8} kB\nMemAvailable:   {:8} kB\n",
+                total * kb,
+                free * kb,
+                free * kb
+            )
+        }
+        ProcFileKind::Mounts => {
+            let mut text = String::new();
+            for (path, fs_type) in mount_list() {
+                writeln!(text, "{} {} {} rw 0 0", fs_type, path, fs_type).unwrap();
+            }
+            text
+        }
+        ProcFileKind::Uptime => {
+            let ms = get_time_ms();
+            format!("{}.{:02} 0.00\n", ms / 1000, ms % 1000 / 10)
+        }
+        ProcFileKind::Interrupts => {
+            let mut text = String::from("           CPU0\n");
+            for (irq, count) in interrupt_counts() {
+                writeln!(text, "{:>4}: {:>10}  {}", irq, count, irq_name(irq)).unwrap();
+            }
+            text
+        }
+        ProcFileKind::Stat(pid) => {
+            let info = ProcessInfo::collect(pid)?;
+            format!(
+                "{} ({}) {} {} {} {} 0 0 0 0 0 0 0 0 0 0 0 20 0 {} 0 0 {} {}\n",
+                pid,
+                info.comm,
+                info.state,
+                info.ppid,
+                pid,
+                pid,
+                info.threads,
+                info.vm_size,
+                info.rss_pages
+            )
+        }
+        ProcFileKind::Status(pid) => {
+            let info = ProcessInfo::collect(pid)?;
+            let state = match info.state {
+                'R' => "R (running)",
+                'S' => "S (sleeping)",
+                _ => "Z (zombie)",
+            };
+            format!(
+                "Name:\t{}\nState:\t{}\nPid:\t{}\nPPid:\t{}\nThreads:\t{}\nVmSize:\t{:8} kB\nVmRSS:\t{:8} kB\nFDSize:\t{}\n",
+                info.comm,
+                state,
+                pid,
+                info.ppid,
+                info.threads,
+                info.vm_size / 1024,
+                info.rss_pages * PAGE_SIZE / 1024,
+                info.fd_size
+            )
+        }
+        ProcFileKind::Maps(pid) => {
+            let process = pid2process(pid)?;
+            let inner = process.inner_exclusive_access();
+            let mut text = String::new();
+            for (start, end, perm, map_type) in inner.memory_set.area_ranges() {
+                writeln!(
+                    text,
+                    "{:08x}-{:08x} {}{}{}{} 00000000 00:00 0",
+                    start.0,
+                    end.0,
+                    if perm.contains(MapPermission::R) {
+                        'r'
+                    } else {
+                        '-'
+                    },
+                    if perm.contains(MapPermission::W) {
+                        'w'
+                    } else {
+                        '-'
+                    },
+                    if perm.contains(MapPermission::X) {
+                        'x'
+                    } else {
+                        '-'
+                    },
+                    if map_type == MapType::File { 's' } else { 'p' },
+                )
+                .unwrap();
+            }
+            text
+        }
+        ProcFileKind::Cmdline(pid) => {
+            let process = pid2process(pid)?;
+            let inner = process.inner_exclusive_access();
+            let mut bytes = Vec::new();
+            for arg in inner.cmdline.iter() {
+                bytes.extend_from_slice(arg.as_bytes());
+                bytes.push(0);
+            }
+            return Some(bytes);
+        }
+        ProcFileKind::Cwd(pid) => pid2process(pid)?.getcwd(),
+    };
+    Some(text.into_bytes())
+}
+
+/// What `stat` and `status` report about one process.
+struct ProcessInfo {
+    comm: String,
+    state: char,
+    ppid: usize,
+    threads: usize,
+    vm_size: usize,
+    rss_pages: usize,
+    fd_size: usize,
+}
+
+impl ProcessInfo {
+    fn collect(pid: usize) -> Option<Self> {
+        let process = pid2process(pid)?;
+        let inner = process.inner_exclusive_access();
+        let comm = inner
+            .cmdline
+            .first()
+            .map(|arg0| arg0.rsplit('/').next().unwrap().to_string())
+            .unwrap_or_default();
+        let state = if inner.is_zombie {
+            'Z'
+        } else {
+            match inner.tasks.first() {
+                Some(Some(task))
+                    if task.inner_exclusive_access().task_status == TaskStatus::Blocked =>
+                {
+                    'S'
+                }
+                _ => 'R',
+            }
+        };
+        let ppid = inner
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade())
+            .map_or(0, |parent| parent.getpid());
+        Some(Self {
+            comm,
+            state,
+            ppid,
+            threads: inner.tasks.iter().filter(|task| task.is_some()).count(),
+            vm_size: inner
+                .memory_set
+                .area_ranges()
+                .iter()
+                .map(|(start, end, _, _)| end.0 - start.0)
+                .sum(),
+            rss_pages: inner.memory_set.resident_pages(),
+            fd_size: inner.fd_table.len(),
+        })
+    }
+}
@@ -0,0 +1,333 @@
+//! Pseudo-terminals. Opening `/dev/ptmx` makes a pair: a master, held by
+//! a terminal emulator or a remote shell, and a terminal at `/dev/pts/N`
+//! whose device is that master. What the master writes is the terminal's
+//! input, and what is written to the terminal is read from the master.
+
+use super::{
+    tty::{
+        Tty,
+        TtyDriver,
+        TtyFile,
+    },
+    File,
+    PollEvents,
+};
+use crate::{
+    mm::{
+        translated_ref,
+        translated_refmut,
+        UserBuffer,
+    },
+    sync::{
+        UPIntrFreeCell,
+        WaitQueue,
+    },
+    syscall::user_space::__user,
+    task::{
+        current_signal_pending,
+        current_user_token,
+        schedule,
+    },
+};
+use alloc::{
+    boxed::Box,
+    collections::VecDeque,
+    sync::{
+        Arc,
+        Weak,
+    },
+    vec::Vec,
+};
+use core::sync::atomic::{
+    AtomicBool,
+    Ordering,
+};
+use lazy_static::*;
+use shared_defination::error::{
+    EAGAIN,
+    EIO,
+};
+
+const TIOCGPTN: u32 = 0x80045430;
+const TIOCSPTLCK: u32 = 0x40045431;
+
+/// Terminal output the master has not read, at most.
+const PTY_BUF_SIZE: usize = 4096;
+
+struct PtyInner {
+    /// Terminal output on its way to the master.
+    output: VecDeque<u8>,
+    /// `/dev/pts/N` can not be opened while locked, as it is at first.
+    locked: bool,
+    /// Open files of the terminal end.
+    slaves: usize,
+    /// The terminal end was opened and closed again, so the master reads
+    /// `EIO` once it took the output.
+    slave_closed: bool,
+    master_closed: bool,
+}
+
+pub struct Pty {
+    index: usize,
+    tty: Arc<Tty>,
+    inner: UPIntrFreeCell<PtyInner>,
+    /// Woken when the master has output to read, and when the terminal
+    /// end has room to write.
+    wait_queue: WaitQueue,
+}
+
+impl Pty {
+    fn new(index: usize) -> Arc<Self> {
+        Arc::new_cyclic(|me| Self {
+            index,
+            tty: Arc::new(Tty::new(Box::new(PtyDriver(me.clone())))),
+            inner: unsafe {
+                UPIntrFreeCell::new(PtyInner {
+                    output: VecDeque::new(),
+                    locked: true,
+                    slaves: 0,
+                    slave_closed: false,
+                    master_closed: false,
+                })
+            },
+            wait_queue: WaitQueue::new(),
+        })
+    }
+
+    /// Queue as much of `bytes` as fits for the master.
+    fn push_output(&self, bytes: &[u8]) -> usize {
+        let count = self.inner.exclusive_session(|inner| {
+            if inner.master_closed {
+                return 0;
+            }
+            let count = bytes.len().min(PTY_BUF_SIZE - inner.output.len());
+            inner.output.extend(&bytes[..count]);
+            count
+        });
+        if count != 0 {
+            self.wait_queue.wake_all();
+        }
+        count
+    }
+}
+
+/// The device of the terminal end is the master's read buffer.
+struct PtyDriver(Weak<Pty>);
+
+impl TtyDriver for PtyDriver {
+    /// Waits while the master has not read enough to make room.
+    fn write(&self, bytes: &[u8]) -> usize {
+        let pty = match self.0.upgrade() {
+            Some(pty) => pty,
+            None => return 0,
+        };
+        let mut written = 0;
+        while written < bytes.len() {
+            let inner = pty.inner.exclusive_access();
+            if inner.master_closed {
+                break;
+            }
+            if inner.output.len() == PTY_BUF_SIZE {
+                if current_signal_pending() {
+                    break;
+                }
+                let task_ctx_ptr = pty.wait_queue.wait_no_sched();
+                drop(inner);
+                schedule(task_ctx_ptr);
+                continue;
+            }
+            drop(inner);
+            written += pty.push_output(&bytes[written..]);
+        }
+        written
+    }
+    fn echo(&self, bytes: &[u8]) {
+        if let Some(pty) = self.0.upgrade() {
+            pty.push_output(bytes);
+        }
+    }
+}
+
+lazy_static! {
+    /// Pseudo-terminals by number, a dead entry is free to reuse.
+    static ref PTYS: UPIntrFreeCell<Vec<Weak<Pty>>> = unsafe { UPIntrFreeCell::new(Vec::new()) };
+}
+
+/// `/dev/ptmx`: a new pair, numbered with the lowest free number.
+pub fn open_pty_master() -> Arc<PtyMaster> {
+    let mut ptys = PTYS.exclusive_access();
+    let index = ptys
+        .iter()
+        .position(|pty| pty.strong_count() == 0)
+        .unwrap_or(ptys.len());
+    let pty = Pty::new(index);
+    if index == ptys.len() {
+        ptys.push(Arc::downgrade(&pty));
+    } else {
+        ptys[index] = Arc::downgrade(&pty);
+    }
+    Arc::new(PtyMaster {
+        pty,
+        nonblock: AtomicBool::new(false),
+    })
+}
+
+/// `/dev/pts/N`, once it is unlocked and while its master is open.
+pub fn open_pty_slave(index: usize) -> Option<Arc<PtySlave>> {
+    let pty = PTYS.exclusive_access().get(index)?.upgrade()?;
+    let mut inner = pty.inner.exclusive_access();
+    if inner.locked || inner.master_closed {
+        return None;
+    }
+    inner.slaves += 1;
+    inner.slave_closed = false;
+    drop(inner);
+    Some(Arc::new(PtySlave {
+        file: TtyFile::new(pty.tty.clone()),
+        pty,
+    }))
+}
+
+pub struct PtyMaster {
+    pty: Arc<Pty>,
+    nonblock: AtomicBool,
+}
+
+impl File for PtyMaster {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    /// Whatever the terminal wrote so far, waiting until it wrote something.
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        let data: Vec<u8> = loop {
+            let mut inner = self.pty.inner.exclusive_access();
+            if !inner.output.is_empty() {
+                let count = buf.len().min(inner.output.len());
+                break inner.output.drain(..count).collect();
+            }
+            if inner.slave_closed {
+                return -(EIO as isize) as usize;
+            }
+            if self.nonblocking() {
+                return -(EAGAIN as isize) as usize;
+            }
+            let task_ctx_ptr = self.pty.wait_queue.wait_no_sched();
+            drop(inner);
+            schedule(task_ctx_ptr);
+        };
+        // there is room for the terminal's writers now
+        self.pty.wait_queue.wake_all();
+        let mut copied = 0;
+        for slice in buf.buffers.iter_mut() {
+            let count = slice.len().min(data.len() - copied);
+            slice[..count].copy_from_slice(&data[copied..copied + count]);
+            copied += count;
+        }
+        data.len()
+    }
+    /// Typed into the terminal. Input beyond what the terminal holds is
+    /// dropped, as from the UART.
+    fn write(&self, buf: UserBuffer) -> usize {
+        for slice in buf.buffers.iter() {
+            self.pty.tty.receive(slice);
+        }
+        // writers in a group that was just signalled have to give up
+        self.pty.wait_queue.wake_all();
+        buf.len()
+    }
+    /// The number and lock requests, anything else goes to the terminal.
+    fn ioctl(&self, cmd: u32, arg: usize) -> isize {
+        let token = current_user_token();
+        match cmd {
+            TIOCGPTN => {
+                *translated_refmut(token, __user::new(arg as *mut u32)) = self.pty.index as u32;
+            }
+            TIOCSPTLCK => {
+                let lock = *translated_ref(token, __user::new(arg as *const i32));
+                self.pty.inner.exclusive_access().locked = lock != 0;
+            }
+            _ => return self.pty.tty.ioctl(cmd, arg),
+        }
+        0
+    }
+    fn poll_ready(&self, events: PollEvents) -> PollEvents {
+        let inner = self.pty.inner.exclusive_access();
+        let mut ready = PollEvents::OUT;
+        if !inner.output.is_empty() {
+            ready |= PollEvents::IN;
+        }
+        if inner.slave_closed {
+            ready |= PollEvents::HUP;
+        }
+        ready & events
+    }
+    fn wait_queue(&self) -> Option<&WaitQueue> {
+        Some(&self.pty.wait_queue)
+    }
+    fn nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Relaxed)
+    }
+    fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblock.store(nonblocking, Ordering::Relaxed);
+    }
+}
+
+impl Drop for PtyMaster {
+    /// The terminal hangs up, and its blocked writers give up.
+    fn drop(&mut self) {
+        self.pty.inner.exclusive_access().master_closed = true;
+        self.pty.tty.hangup();
+        self.pty.wait_queue.wake_all();
+    }
+}
+
+/// An open `/dev/pts/N`, a terminal like the console.
+pub struct PtySlave {
+    pty: Arc<Pty>,
+    file: TtyFile,
+}
+
+impl File for PtySlave {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, buf: UserBuffer) -> usize {
+        self.file.read(buf)
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        self.file.write(buf)
+    }
+    fn ioctl(&self, cmd: u32, arg: usize) -> isize {
+        self.file.ioctl(cmd, arg)
+    }
+    fn poll_ready(&self, events: PollEvents) -> PollEvents {
+        self.file.poll_ready(events)
+    }
+    fn wait_queue(&self) -> Option<&WaitQueue> {
+        self.file.wait_queue()
+    }
+    fn nonblocking(&self) -> bool {
+        self.file.nonblocking()
+    }
+    fn set_nonblocking(&self, nonblocking: bool) {
+        self.file.set_nonblocking(nonblocking);
+    }
+}
+
+impl Drop for PtySlave {
+    fn drop(&mut self) {
+        let mut inner = self.pty.inner.exclusive_access();
+        inner.slaves -= 1;
+        if inner.slaves == 0 {
+            inner.slave_closed = true;
+            drop(inner);
+            self.pty.wait_queue.wake_all();
+        }
+    }
+}
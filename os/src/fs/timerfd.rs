@@ -0,0 +1,113 @@
+//! `timerfd_create` timers. Reading one gives the number of expirations
+//! since the last read as a `u64`, and blocks while there were none.
+
+use super::{
+    File,
+    PollEvents,
+};
+use crate::{
+    mm::UserBuffer,
+    sync::{
+        intr_free,
+        WaitQueue,
+    },
+    task::schedule,
+    timer::{
+        IntervalTimer,
+        TimerNotify,
+    },
+};
+use alloc::sync::Arc;
+use core::sync::atomic::{
+    AtomicBool,
+    Ordering,
+};
+use shared_defination::error::{
+    EAGAIN,
+    EINVAL,
+};
+
+pub struct TimerFd {
+    timer: Arc<IntervalTimer>,
+    /// The clock absolute deadlines are on.
+    clock: usize,
+    nonblock: AtomicBool,
+}
+
+impl TimerFd {
+    pub fn new(clock: usize, nonblocking: bool) -> Self {
+        Self {
+            timer: IntervalTimer::new(TimerNotify::Wake(WaitQueue::new())),
+            clock,
+            nonblock: AtomicBool::new(nonblocking),
+        }
+    }
+
+    pub fn clock(&self) -> usize {
+        self.clock
+    }
+
+    pub fn timer(&self) -> &Arc<IntervalTimer> {
+        &self.timer
+    }
+}
+
+impl File for TimerFd {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        if buf.len() < 8 {
+            return -(EINVAL as isize) as usize;
+        }
+        let count = loop {
+            let mut count = 0;
+            // masked, so the timer can not go off between the check and blocking
+            let task_ctx_ptr = intr_free(|| {
+                count = self.timer.take_expirations();
+                if count != 0 || self.nonblocking() {
+                    return None;
+                }
+                Some(self.wait_queue().unwrap().wait_no_sched())
+            });
+            match task_ctx_ptr {
+                Some(task_ctx_ptr) => schedule(task_ctx_ptr),
+                None if count == 0 => return -(EAGAIN as isize) as usize,
+                None => break count,
+            }
+        };
+        let bytes = count.to_ne_bytes();
+        let mut copied = 0;
+        for slice in buf.buffers.iter_mut() {
+            let len = slice.len().min(bytes.len() - copied);
+            slice[..len].copy_from_slice(&bytes[copied..copied + len]);
+            copied += len;
+        }
+        bytes.len()
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        -(EINVAL as isize) as usize
+    }
+    fn poll_ready(&self, events: PollEvents) -> PollEvents {
+        if self.timer.expirations() != 0 {
+            events & PollEvents::IN
+        } else {
+            PollEvents::empty()
+        }
+    }
+    fn wait_queue(&self) -> Option<&WaitQueue> {
+        self.timer.wait_queue()
+    }
+    fn nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Relaxed)
+    }
+    fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblock.store(nonblocking, Ordering::Relaxed);
+    }
+    fn as_timerfd(&self) -> Option<&TimerFd> {
+        Some(self)
+    }
+}
@@ -0,0 +1,456 @@
+use super::{
+    fill_dirents,
+    File,
+    FilePage,
+    FileSystem,
+    OpenFlags,
+    DT_DIR,
+    DT_REG,
+};
+use crate::{
+    config::PAGE_SIZE,
+    mm::{
+        frame_alloc,
+        frame_stats,
+        FrameTracker,
+        UserBuffer,
+    },
+    sync::UPIntrFreeCell,
+};
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::Arc,
+    vec::Vec,
+};
+use core::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+use shared_defination::error::{
+    EEXIST,
+    EISDIR,
+    ENOENT,
+    ENOSPC,
+    ENOTDIR,
+    ENOTEMPTY,
+};
+
+/// In-memory file system. File data lives in frames taken from the frame
+/// allocator on first write, up to the size of the mount, half of memory
+/// unless `size=` says otherwise. A full tmpfs fails writes with `ENOSPC`.
+pub struct TmpFs {
+    root: Arc<TmpDirInode>,
+    pages: Arc<PageCount>,
+}
+
+/// Frames the files of a mount hold, against its size.
+struct PageCount {
+    used: AtomicUsize,
+    max: usize,
+}
+
+impl PageCount {
+    fn take(&self) -> bool {
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                (used < self.max).then_some(used + 1)
+            })
+            .is_ok()
+    }
+
+    fn give_back(&self, pages: usize) {
+        self.used.fetch_sub(pages, Ordering::Relaxed);
+    }
+}
+
+#[derive(Clone)]
+enum TmpNode {
+    File(Arc<TmpFileInode>),
+    Dir(Arc<TmpDirInode>),
+}
+
+struct TmpDirInode {
+    entries: UPIntrFreeCell<BTreeMap<String, TmpNode>>,
+}
+
+struct TmpFileInode {
+    data: UPIntrFreeCell<TmpFileData>,
+}
+
+/// Pages that were never written are holes and read as zeros.
+struct TmpFileData {
+    size: usize,
+    pages: Vec<Option<Arc<FrameTracker>>>,
+    count: Arc<PageCount>,
+}
+
+impl TmpDirInode {
+    fn new() -> Self {
+        Self {
+            entries: unsafe { UPIntrFreeCell::new(BTreeMap::new()) },
+        }
+    }
+}
+
+impl TmpFileInode {
+    fn new(count: Arc<PageCount>) -> Self {
+        Self {
+            data: unsafe {
+                UPIntrFreeCell::new(TmpFileData {
+                    size: 0,
+                    pages: Vec::new(),
+                    count,
+                })
+            },
+        }
+    }
+}
+
+impl TmpFileData {
+    /// Page `index`, allocated on demand. `None` when the mount is full or
+    /// out of frames.
+    fn page(&mut self, index: usize) -> Option<Arc<FrameTracker>> {
+        if self.pages.len() <= index {
+            self.pages.resize(index + 1, None);
+        }
+        if self.pages[index].is_none() {
+            if !self.count.take() {
+                return None;
+            }
+            match frame_alloc() {
+                Some(frame) => self.pages[index] = Some(Arc::new(frame)),
+                None => {
+                    self.count.give_back(1);
+                    return None;
+                }
+            }
+        }
+        self.pages[index].clone()
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let end = (offset + buf.len()).min(self.size);
+        let mut pos = offset;
+        while pos < end {
+            let in_page = pos % PAGE_SIZE;
+            let len = (PAGE_SIZE - in_page).min(end - pos);
+            let dst = &mut buf[pos - offset..pos - offset + len];
+            match self.pages.get(pos / PAGE_SIZE) {
+                Some(Some(frame)) => {
+                    dst.copy_from_slice(&frame.ppn.get_bytes_array()[in_page..in_page + len])
+                }
+                _ => dst.fill(0),
+            }
+            pos += len;
+        }
+        end.saturating_sub(offset)
+    }
+
+    /// Stops early when the mount is full.
+    fn write_at(&mut self, offset: usize, buf: &[u8]) -> usize {
+        let end = offset + buf.len();
+        let mut pos = offset;
+        while pos < end {
+            let in_page = pos % PAGE_SIZE;
+            let len = (PAGE_SIZE - in_page).min(end - pos);
+            let frame = match self.page(pos / PAGE_SIZE) {
+                Some(frame) => frame,
+                None => break,
+            };
+            frame.ppn.get_bytes_array()[in_page..in_page + len]
+                .copy_from_slice(&buf[pos - offset..pos - offset + len]);
+            pos += len;
+        }
+        self.size = self.size.max(pos);
+        pos - offset
+    }
+
+    /// Frames past the new end go back to the allocator. A page still mapped
+    /// somewhere stays alive for that mapping only.
+    fn truncate(&mut self, len: usize) {
+        let pages = (len + PAGE_SIZE - 1) / PAGE_SIZE;
+        if pages < self.pages.len() {
+            let freed = self.pages[pages..].iter().flatten().count();
+            self.count.give_back(freed);
+        }
+        self.pages.truncate(pages);
+        // growing again later must read zeros, not the old tail
+        if len % PAGE_SIZE != 0 {
+            if let Some(Some(frame)) = self.pages.get(len / PAGE_SIZE) {
+                frame.ppn.get_bytes_array()[len % PAGE_SIZE..].fill(0);
+            }
+        }
+        self.size = len;
+    }
+}
+
+impl Drop for TmpFileData {
+    fn drop(&mut self) {
+        self.count.give_back(self.pages.iter().flatten().count());
+    }
+}
+
+impl TmpFs {
+    pub fn new() -> Self {
+        Self::with_size(frame_stats().0 / 2 * PAGE_SIZE)
+    }
+
+    /// At most `size` bytes of file data, in whole pages.
+    pub fn with_size(size: usize) -> Self {
+        Self {
+            root: Arc::new(TmpDirInode::new()),
+            pages: Arc::new(PageCount {
+                used: AtomicUsize::new(0),
+                max: size / PAGE_SIZE,
+            }),
+        }
+    }
+
+    /// Mount options, comma separated. Only `size=N` is known, in bytes
+    /// with an optional `k`, `m` or `g`, or as `N%` of memory.
+    pub fn with_options(options: &str) -> Option<Self> {
+        let mut size = None;
+        for option in options.split(',').filter(|option| !option.is_empty()) {
+            let value = option.strip_prefix("size=")?;
+            if let Some(percent) = value.strip_suffix('%') {
+                let percent: usize = percent.parse().ok()?;
+                size = Some(frame_stats().0 * percent / 100 * PAGE_SIZE);
+                continue;
+            }
+            let (number, unit) = match value.as_bytes().last()? {
+                b'k' | b'K' => (&value[..value.len() - 1], 1 << 10),
+                b'm' | b'M' => (&value[..value.len() - 1], 1 << 20),
+                b'g' | b'G' => (&value[..value.len() - 1], 1 << 30),
+                _ => (value, 1),
+            };
+            size = Some(number.parse::<usize>().ok()?.checked_mul(unit)?);
+        }
+        Some(size.map_or_else(Self::new, Self::with_size))
+    }
+
+    fn lookup(&self, path: &str) -> Option<TmpNode> {
+        let mut node = TmpNode::Dir(self.root.clone());
+        for part in path.split('/').filter(|p| !p.is_empty()) {
+            let dir = match node {
+                TmpNode::Dir(dir) => dir,
+                TmpNode::File(_) => return None,
+            };
+            node = dir.entries.exclusive_access().get(part)?.clone();
+        }
+        Some(node)
+    }
+
+    /// Directory holding the last component of `path`, and that component.
+    fn parent<'a>(&self, path: &'a str) -> Option<(Arc<TmpDirInode>, &'a str)> {
+        let path = path.trim_end_matches('/');
+        let (dir, name) = match path.rfind('/') {
+            Some(pos) => (&path[..pos], &path[pos + 1..]),
+            None => ("", path),
+        };
+        if name.is_empty() {
+            return None;
+        }
+        match self.lookup(dir)? {
+            TmpNode::Dir(dir) => Some((dir, name)),
+            TmpNode::File(_) => None,
+        }
+    }
+}
+
+impl FileSystem for TmpFs {
+    fn fs_type(&self) -> &'static str {
+        "tmpfs"
+    }
+
+    fn open(&self, path: &str, flags: OpenFlags) -> Option<Arc<dyn File + Send + Sync>> {
+        let (readable, writable) = flags.read_write();
+        let node = match self.lookup(path) {
+            Some(node) => node,
+            None if flags.contains(OpenFlags::CREATE) => {
+                let (dir, name) = self.parent(path)?;
+                let file = TmpNode::File(Arc::new(TmpFileInode::new(self.pages.clone())));
+                dir.entries
+                    .exclusive_access()
+                    .insert(String::from(name), file.clone());
+                file
+            }
+            None => return None,
+        };
+        match node {
+            TmpNode::Dir(dir) => {
+                if writable {
+                    return None;
+                }
+                Some(Arc::new(TmpDir {
+                    dir,
+                    pos: unsafe { UPIntrFreeCell::new(0) },
+                }))
+            }
+            TmpNode::File(inode) => {
+                if flags.contains(OpenFlags::TRUNC) {
+                    inode.data.exclusive_access().truncate(0);
+                }
+                Some(Arc::new(TmpFile {
+                    readable,
+                    writable,
+                    inode,
+                    offset: unsafe { UPIntrFreeCell::new(0) },
+                }))
+            }
+        }
+    }
+
+    fn mkdir(&self, path: &str) -> isize {
+        let (dir, name) = match self.parent(path) {
+            Some(parent) => parent,
+            None => return -(ENOENT as isize),
+        };
+        let mut entries = dir.entries.exclusive_access();
+        if entries.contains_key(name) {
+            return -(EEXIST as isize);
+        }
+        entries.insert(
+            String::from(name),
+            TmpNode::Dir(Arc::new(TmpDirInode::new())),
+        );
+        0
+    }
+
+    fn unlink(&self, path: &str, rmdir: bool) -> isize {
+        let (dir, name) = match self.parent(path) {
+            Some(parent) => parent,
+            None => return -(ENOENT as isize),
+        };
+        let mut entries = dir.entries.exclusive_access();
+        match (entries.get(name), rmdir) {
+            (None, _) => return -(ENOENT as isize),
+            (Some(TmpNode::File(_)), true) => return -(ENOTDIR as isize),
+            (Some(TmpNode::Dir(_)), false) => return -(EISDIR as isize),
+            (Some(TmpNode::Dir(sub)), true) => {
+                if !sub.entries.exclusive_access().is_empty() {
+                    return -(ENOTEMPTY as isize);
+                }
+            }
+            (Some(TmpNode::File(_)), false) => {}
+        }
+        // open files keep their data until closed
+        entries.remove(name);
+        0
+    }
+}
+
+struct TmpFile {
+    readable: bool,
+    writable: bool,
+    inode: Arc<TmpFileInode>,
+    offset: UPIntrFreeCell<usize>,
+}
+
+impl File for TmpFile {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        let data = self.inode.data.exclusive_access();
+        let mut offset = self.offset.exclusive_access();
+        let mut total_read_size = 0usize;
+        for slice in buf.buffers.iter_mut() {
+            let read_size = data.read_at(*offset, slice);
+            *offset += read_size;
+            total_read_size += read_size;
+            if read_size < slice.len() {
+                break;
+            }
+        }
+        total_read_size
+    }
+    /// `ENOSPC` when the mount is full before anything was written.
+    fn write(&self, buf: UserBuffer) -> usize {
+        let mut data = self.inode.data.exclusive_access();
+        let mut offset = self.offset.exclusive_access();
+        let mut total_write_size = 0usize;
+        for slice in buf.buffers.iter() {
+            let write_size = data.write_at(*offset, slice);
+            *offset += write_size;
+            total_write_size += write_size;
+            if write_size < slice.len() {
+                break;
+            }
+        }
+        if total_write_size == 0 && buf.len() > 0 {
+            return -(ENOSPC as isize) as usize;
+        }
+        total_write_size
+    }
+    fn mmap_page(&self, offset: usize) -> Option<FilePage> {
+        let mut data = self.inode.data.exclusive_access();
+        if offset >= (data.size + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE {
+            return None;
+        }
+        data.page(offset / PAGE_SIZE).map(FilePage::Frame)
+    }
+    /// A file can not grow past the size of the mount, even with holes.
+    fn truncate(&self, len: usize) -> isize {
+        let mut data = self.inode.data.exclusive_access();
+        if len > data.count.max * PAGE_SIZE {
+            return -(ENOSPC as isize);
+        }
+        data.truncate(len);
+        0
+    }
+    fn reopen(&self, readable: bool, writable: bool) -> Option<Arc<dyn File + Send + Sync>> {
+        Some(Arc::new(TmpFile {
+            readable,
+            writable,
+            inode: self.inode.clone(),
+            offset: unsafe { UPIntrFreeCell::new(0) },
+        }))
+    }
+}
+
+struct TmpDir {
+    dir: Arc<TmpDirInode>,
+    pos: UPIntrFreeCell<usize>,
+}
+
+impl File for TmpDir {
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn getdents(&self, buf: UserBuffer) -> isize {
+        let entries: Vec<(String, u8)> = self
+            .dir
+            .entries
+            .exclusive_access()
+            .iter()
+            .map(|(name, node)| {
+                let d_type = match node {
+                    TmpNode::File(_) => DT_REG,
+                    TmpNode::Dir(_) => DT_DIR,
+                };
+                (name.clone(), d_type)
+            })
+            .collect();
+        let mut pos = self.pos.exclusive_access();
+        fill_dirents(&entries, &mut pos, buf)
+    }
+    fn reopen(&self, _readable: bool, _writable: bool) -> Option<Arc<dyn File + Send + Sync>> {
+        Some(Arc::new(TmpDir {
+            dir: self.dir.clone(),
+            pos: unsafe { UPIntrFreeCell::new(0) },
+        }))
+    }
+}
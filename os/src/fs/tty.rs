@@ -0,0 +1,666 @@
+//! Terminals. A `Tty` runs the line discipline between a device, which
+//! hands it input and takes its output, and the processes that read and
+//! write it: canonical mode with line editing, echo, and the keys that
+//! signal the foreground process group, all set up through `termios`.
+
+use super::{
+    File,
+    PollEvents,
+};
+use crate::{
+    drivers::chardev::{
+        CharDevice,
+        UART,
+    },
+    mm::{
+        translated_ref,
+        translated_refmut,
+        UserBuffer,
+    },
+    sync::{
+        intr_free,
+        UPIntrFreeCell,
+        WaitQueue,
+    },
+    syscall::user_space::__user,
+    task::{
+        current_signal_pending,
+        current_user_token,
+        schedule,
+        signal_process_group,
+        SignalFlags,
+    },
+};
+use alloc::{
+    boxed::Box,
+    collections::VecDeque,
+    sync::Arc,
+    vec::Vec,
+};
+use core::sync::atomic::{
+    AtomicBool,
+    Ordering,
+};
+use lazy_static::*;
+use shared_defination::error::{
+    EAGAIN,
+    EINTR,
+    EINVAL,
+    EIO,
+    ENOTTY,
+};
+
+pub const NCCS: usize = 19;
+
+// c_iflag
+const ISTRIP: u32 = 0o40;
+const INLCR: u32 = 0o100;
+const IGNCR: u32 = 0o200;
+const ICRNL: u32 = 0o400;
+
+// c_oflag
+const OPOST: u32 = 0o1;
+const ONLCR: u32 = 0o4;
+
+// c_cflag, only kept for whoever asks
+const B38400: u32 = 0o17;
+const CS8: u32 = 0o60;
+const CREAD: u32 = 0o200;
+const HUPCL: u32 = 0o2000;
+
+// c_lflag
+const ISIG: u32 = 0o1;
+const ICANON: u32 = 0o2;
+const ECHO: u32 = 0o10;
+const ECHOE: u32 = 0o20;
+const ECHOK: u32 = 0o40;
+const ECHONL: u32 = 0o100;
+const NOFLSH: u32 = 0o200;
+const ECHOCTL: u32 = 0o1000;
+const ECHOKE: u32 = 0o4000;
+const IEXTEN: u32 = 0o100000;
+
+// c_cc
+const VINTR: usize = 0;
+const VQUIT: usize = 1;
+const VERASE: usize = 2;
+const VKILL: usize = 3;
+const VEOF: usize = 4;
+const VMIN: usize = 6;
+const VSUSP: usize = 10;
+const VEOL: usize = 11;
+const VWERASE: usize = 14;
+const VEOL2: usize = 16;
+
+const TCGETS: u32 = 0x5401;
+const TCSETS: u32 = 0x5402;
+const TCSETSW: u32 = 0x5403;
+const TCSETSF: u32 = 0x5404;
+const TIOCGPGRP: u32 = 0x540f;
+const TIOCSPGRP: u32 = 0x5410;
+const TIOCGWINSZ: u32 = 0x5413;
+const TIOCSWINSZ: u32 = 0x5414;
+
+/// Input a terminal holds, read or not.
+const TTY_BUF_SIZE: usize = 4096;
+
+/// The kernel `struct termios` of `TCGETS` and `TCSETS`.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Termios {
+    pub iflag: u32,
+    pub oflag: u32,
+    pub cflag: u32,
+    pub lflag: u32,
+    pub line: u8,
+    pub cc: [u8; NCCS],
+}
+
+impl Termios {
+    /// What Linux starts a terminal with, less flow control. `VSUSP` is off
+    /// too: jobs can not be stopped, so `^Z` reaches the program as it is.
+    pub fn new() -> Self {
+        Self {
+            iflag: ICRNL,
+            oflag: OPOST | ONLCR,
+            cflag: B38400 | CS8 | CREAD | HUPCL,
+            lflag: ISIG | ICANON | ECHO | ECHOE | ECHOK | ECHOCTL | ECHOKE | IEXTEN,
+            line: 0,
+            // ^C ^\ DEL ^U ^D, VTIME 0, VMIN 1, ^Q ^S, no VSUSP, ^R ^O ^W ^V
+            cc: [
+                3, 0x1c, 0x7f, 0x15, 4, 0, 1, 0, 0x11, 0x13, 0, 0, 0x12, 0x0f, 0x17, 0x16, 0, 0, 0,
+            ],
+        }
+    }
+
+    fn canonical(&self) -> bool {
+        self.lflag & ICANON != 0
+    }
+
+    /// Whether `ch` is special character `index`, which 0 turns off.
+    fn is(&self, ch: u8, index: usize) -> bool {
+        self.cc[index] != 0 && self.cc[index] == ch
+    }
+}
+
+/// `struct winsize`, which the kernel only keeps for whoever draws on the
+/// terminal.
+#[repr(C)]
+#[derive(Copy, Clone, Default, PartialEq)]
+pub struct WinSize {
+    pub row: u16,
+    pub col: u16,
+    pub xpixel: u16,
+    pub ypixel: u16,
+}
+
+fn is_control(ch: u8) -> bool {
+    (ch < b' ' || ch == 0x7f) && ch != b'\t' && ch != b'\n'
+}
+
+/// The device end of a terminal, which output and echo are written to.
+pub trait TtyDriver: Send + Sync {
+    /// Output of a process, which may wait for room. Returns how much of
+    /// `bytes` went out, less when the wait was cut short.
+    fn write(&self, bytes: &[u8]) -> usize;
+    /// Echo comes with the input, maybe in an interrupt, so it must not
+    /// wait. What does not fit may be dropped.
+    fn echo(&self, bytes: &[u8]) {
+        self.write(bytes);
+    }
+}
+
+struct TtyInner {
+    termios: Termios,
+    /// What `read` can have: finished lines in canonical mode, all of it
+    /// otherwise.
+    input: VecDeque<u8>,
+    /// Lengths of the lines in `input`, where 0 is an end of file.
+    lines: VecDeque<usize>,
+    /// The line being edited in canonical mode.
+    edit: Vec<u8>,
+    /// The process group that reads and gets the signals, 0 for none.
+    foreground: usize,
+    winsize: WinSize,
+    /// The device is gone: reads see end of file, writes fail.
+    hung_up: bool,
+}
+
+impl TtyInner {
+    fn input_ready(&self) -> bool {
+        if self.termios.canonical() {
+            !self.lines.is_empty()
+        } else {
+            !self.input.is_empty()
+        }
+    }
+
+    /// At most `len` bytes of input, `None` while a read has to wait. A
+    /// canonical read ends with the line.
+    fn take_input(&mut self, len: usize) -> Option<Vec<u8>> {
+        let count = if self.termios.canonical() {
+            let line = self.lines.front_mut()?;
+            let count = len.min(*line);
+            *line -= count;
+            if *line == 0 {
+                self.lines.pop_front();
+            }
+            count
+        } else {
+            // VMIN 0 does not wait at all
+            if self.input.len() < (self.termios.cc[VMIN] as usize).min(len) {
+                return None;
+            }
+            len.min(self.input.len())
+        };
+        Some(self.input.drain(..count).collect())
+    }
+
+    fn flush_input(&mut self) {
+        self.input.clear();
+        self.lines.clear();
+        self.edit.clear();
+    }
+
+    fn set_termios(&mut self, termios: Termios) {
+        match (self.termios.canonical(), termios.canonical()) {
+            // the line so far can be read at once
+            (true, false) => {
+                self.input.extend(self.edit.drain(..));
+                self.lines.clear();
+            }
+            // and raw input is read as a line
+            (false, true) if !self.input.is_empty() => {
+                self.lines.clear();
+                self.lines.push_back(self.input.len());
+            }
+            _ => {}
+        }
+        self.termios = termios;
+    }
+
+    fn echo(&self, ch: u8, echo: &mut Vec<u8>) {
+        if self.termios.lflag & ECHO == 0 {
+            return;
+        }
+        if self.termios.lflag & ECHOCTL != 0 && is_control(ch) {
+            echo.extend_from_slice(&[b'^', ch ^ 0x40]);
+        } else {
+            echo.push(ch);
+        }
+    }
+
+    /// Take the last character of the line being edited off the screen.
+    fn erase(&mut self, echo: &mut Vec<u8>) {
+        let ch = match self.edit.pop() {
+            Some(ch) => ch,
+            None => return,
+        };
+        let lflag = self.termios.lflag;
+        if lflag & ECHO == 0 {
+            return;
+        }
+        if lflag & ECHOE == 0 {
+            echo.push(self.termios.cc[VERASE]);
+            return;
+        }
+        let width = if lflag & ECHOCTL != 0 && is_control(ch) {
+            2
+        } else {
+            1
+        };
+        for _ in 0..width {
+            echo.extend_from_slice(b"\x08 \x08");
+        }
+    }
+
+    fn finish_line(&mut self) {
+        self.lines.push_back(self.edit.len());
+        self.input.extend(self.edit.drain(..));
+    }
+
+    /// Take a byte from the device, adding what to echo to `echo`.
+    /// Returns the signal it stands for, if any.
+    fn receive(&mut self, mut ch: u8, echo: &mut Vec<u8>) -> Option<SignalFlags> {
+        let termios = self.termios;
+        if termios.iflag & ISTRIP != 0 {
+            ch &= 0x7f;
+        }
+        if ch == b'\r' {
+            if termios.iflag & IGNCR != 0 {
+                return None;
+            }
+            if termios.iflag & ICRNL != 0 {
+                ch = b'\n';
+            }
+        } else if ch == b'\n' && termios.iflag & INLCR != 0 {
+            ch = b'\r';
+        }
+        if termios.lflag & ISIG != 0 {
+            let signal = if termios.is(ch, VINTR) {
+                Some(SignalFlags::SIGINT)
+            } else if termios.is(ch, VQUIT) {
+                Some(SignalFlags::SIGQUIT)
+            } else if termios.is(ch, VSUSP) {
+                Some(SignalFlags::SIGTSTP)
+            } else {
+                None
+            };
+            if signal.is_some() {
+                if termios.lflag & NOFLSH == 0 {
+                    self.flush_input();
+                }
+                self.echo(ch, echo);
+                return signal;
+            }
+        }
+        let pending = self.input.len() + self.edit.len();
+        if !termios.canonical() {
+            if pending < TTY_BUF_SIZE {
+                self.input.push_back(ch);
+                self.echo(ch, echo);
+            }
+            return None;
+        }
+        if termios.is(ch, VERASE) {
+            self.erase(echo);
+        } else if termios.is(ch, VWERASE) && termios.lflag & IEXTEN != 0 {
+            while self.edit.last() == Some(&b' ') {
+                self.erase(echo);
+            }
+            while self.edit.last().map_or(false, |ch| *ch != b' ') {
+                self.erase(echo);
+            }
+        } else if termios.is(ch, VKILL) {
+            if termios.lflag & (ECHOKE | ECHOE) == ECHOKE | ECHOE {
+                while !self.edit.is_empty() {
+                    self.erase(echo);
+                }
+            } else {
+                self.edit.clear();
+                self.echo(ch, echo);
+                if termios.lflag & ECHOK != 0 {
+                    self.echo(b'\n', echo);
+                }
+            }
+        } else if termios.is(ch, VEOF) {
+            // ends the line without being part of it, on its own it is
+            // an end of file
+            self.finish_line();
+        } else if ch == b'\n' || termios.is(ch, VEOL) || termios.is(ch, VEOL2) {
+            if pending < TTY_BUF_SIZE {
+                self.edit.push(ch);
+                if ch == b'\n' && termios.lflag & (ECHO | ECHONL) == ECHONL {
+                    echo.push(ch);
+                } else {
+                    self.echo(ch, echo);
+                }
+                self.finish_line();
+            }
+        } else if pending + 1 < TTY_BUF_SIZE {
+            // one place is kept for the end of the line
+            self.edit.push(ch);
+            self.echo(ch, echo);
+        }
+        None
+    }
+}
+
+/// A terminal, with what it was given by its device but not yet read.
+pub struct Tty {
+    inner: UPIntrFreeCell<TtyInner>,
+    driver: Box<dyn TtyDriver>,
+    /// Woken when there is input to read or a reader was signalled.
+    wait_queue: WaitQueue,
+}
+
+impl Tty {
+    pub fn new(driver: Box<dyn TtyDriver>) -> Self {
+        Self {
+            inner: unsafe {
+                UPIntrFreeCell::new(TtyInner {
+                    termios: Termios::new(),
+                    input: VecDeque::new(),
+                    lines: VecDeque::new(),
+                    edit: Vec::new(),
+                    foreground: 0,
+                    winsize: WinSize::default(),
+                    hung_up: false,
+                })
+            },
+            driver,
+            wait_queue: WaitQueue::new(),
+        }
+    }
+
+    /// Input from the device, which may come in an interrupt.
+    pub fn receive(&self, bytes: &[u8]) {
+        let mut echo = Vec::new();
+        let mut signals = SignalFlags::empty();
+        let (foreground, ready) = self.inner.exclusive_session(|inner| {
+            for ch in bytes {
+                if let Some(signal) = inner.receive(*ch, &mut echo) {
+                    signals |= signal;
+                }
+            }
+            (inner.foreground, inner.input_ready())
+        });
+        self.output(&echo, true);
+        if !signals.is_empty() && foreground != 0 {
+            signal_process_group(foreground, signals);
+        }
+        // signalled readers have to give up
+        if ready || !signals.is_empty() {
+            self.wait_queue.wake_all();
+        }
+    }
+
+    /// Write `bytes` to the device, `\n` becoming `\r\n` under `ONLCR`.
+    /// Returns how many of them went out.
+    fn output(&self, bytes: &[u8], echo: bool) -> usize {
+        let send = |bytes: &[u8]| {
+            if echo {
+                self.driver.echo(bytes);
+                bytes.len()
+            } else {
+                self.driver.write(bytes)
+            }
+        };
+        let oflag = self.inner.exclusive_access().termios.oflag;
+        if oflag & (OPOST | ONLCR) != OPOST | ONLCR {
+            return send(bytes);
+        }
+        let mut written = 0;
+        for line in bytes.split_inclusive(|ch| *ch == b'\n') {
+            let sent = match line.split_last() {
+                Some((b'\n', text)) => match send(text) {
+                    count if count < text.len() => count,
+                    count => count + (send(b"\r\n") == 2) as usize,
+                },
+                _ => send(line),
+            };
+            written += sent;
+            if sent < line.len() {
+                break;
+            }
+        }
+        written
+    }
+
+    /// Output of a process.
+    pub fn write(&self, bytes: &[u8]) -> isize {
+        if self.hung_up() {
+            return -(EIO as isize);
+        }
+        self.output(bytes, false) as isize
+    }
+
+    /// The device went away. The foreground group gets SIGHUP and readers
+    /// wake up to an end of file.
+    pub fn hangup(&self) {
+        let foreground = self.inner.exclusive_session(|inner| {
+            inner.hung_up = true;
+            inner.foreground
+        });
+        if foreground != 0 {
+            signal_process_group(foreground, SignalFlags::SIGHUP);
+        }
+        self.wait_queue.wake_all();
+    }
+
+    pub fn input_ready(&self) -> bool {
+        self.inner.exclusive_access().input_ready()
+    }
+
+    pub fn hung_up(&self) -> bool {
+        self.inner.exclusive_access().hung_up
+    }
+
+    pub fn wait_queue(&self) -> &WaitQueue {
+        &self.wait_queue
+    }
+
+    /// Block until there is input, which is at most one line in canonical
+    /// mode. A signal that ends the reader cuts the wait short.
+    pub fn read(&self, mut buf: UserBuffer, nonblocking: bool) -> usize {
+        let len = buf.len();
+        let data = loop {
+            let mut data = None;
+            let mut hung_up = false;
+            // masked, so input can not slip in between the check and blocking
+            let task_ctx_ptr = intr_free(|| {
+                let mut inner = self.inner.exclusive_access();
+                data = inner.take_input(len);
+                hung_up = inner.hung_up;
+                if data.is_some() || hung_up || nonblocking || current_signal_pending() {
+                    return None;
+                }
+                drop(inner);
+                Some(self.wait_queue.wait_no_sched())
+            });
+            match (task_ctx_ptr, data) {
+                (Some(task_ctx_ptr), _) => schedule(task_ctx_ptr),
+                (None, Some(data)) => break data,
+                (None, None) if hung_up => return 0,
+                (None, None) if nonblocking => return -(EAGAIN as isize) as usize,
+                (None, None) => return -(EINTR as isize) as usize,
+            }
+        };
+        let mut copied = 0;
+        for slice in buf.buffers.iter_mut() {
+            let count = slice.len().min(data.len() - copied);
+            slice[..count].copy_from_slice(&data[copied..copied + count]);
+            copied += count;
+        }
+        data.len()
+    }
+
+    /// The termios, foreground process group and window size requests.
+    pub fn ioctl(&self, cmd: u32, arg: usize) -> isize {
+        let token = current_user_token();
+        match cmd {
+            TCGETS => {
+                *translated_refmut(token, __user::new(arg as *mut Termios)) =
+                    self.inner.exclusive_access().termios;
+            }
+            // output is written at once, so there is none to wait for
+            TCSETS | TCSETSW | TCSETSF => {
+                let termios = *translated_ref(token, __user::new(arg as *const Termios));
+                let mut inner = self.inner.exclusive_access();
+                if cmd == TCSETSF {
+                    inner.flush_input();
+                }
+                inner.set_termios(termios);
+                let ready = inner.input_ready();
+                drop(inner);
+                if ready {
+                    self.wait_queue.wake_all();
+                }
+            }
+            TIOCGPGRP => {
+                *translated_refmut(token, __user::new(arg as *mut i32)) =
+                    self.inner.exclusive_access().foreground as i32;
+            }
+            TIOCSPGRP => {
+                let pgid = *translated_ref(token, __user::new(arg as *const i32));
+                if pgid <= 0 {
+                    return -(EINVAL as isize);
+                }
+                self.inner.exclusive_access().foreground = pgid as usize;
+            }
+            TIOCGWINSZ => {
+                *translated_refmut(token, __user::new(arg as *mut WinSize)) =
+                    self.inner.exclusive_access().winsize;
+            }
+            // whoever draws on the terminal is told about the new size
+            TIOCSWINSZ => {
+                let winsize = *translated_ref(token, __user::new(arg as *const WinSize));
+                let (changed, foreground) = self.inner.exclusive_session(|inner| {
+                    let changed = inner.winsize != winsize;
+                    inner.winsize = winsize;
+                    (changed, inner.foreground)
+                });
+                if changed && foreground != 0 {
+                    signal_process_group(foreground, SignalFlags::SIGWINCH);
+                }
+            }
+            _ => return -(ENOTTY as isize),
+        }
+        0
+    }
+}
+
+/// An open terminal.
+pub struct TtyFile {
+    tty: Arc<Tty>,
+    nonblock: AtomicBool,
+}
+
+impl TtyFile {
+    pub fn new(tty: Arc<Tty>) -> Self {
+        Self {
+            tty,
+            nonblock: AtomicBool::new(false),
+        }
+    }
+}
+
+impl File for TtyFile {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, buf: UserBuffer) -> usize {
+        self.tty.read(buf, self.nonblocking())
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        let mut written = 0;
+        for slice in buf.buffers.iter() {
+            let count = self.tty.write(slice);
+            if count < 0 {
+                return count as usize;
+            }
+            written += count as usize;
+            if (count as usize) < slice.len() {
+                break;
+            }
+        }
+        written
+    }
+    fn ioctl(&self, cmd: u32, arg: usize) -> isize {
+        self.tty.ioctl(cmd, arg)
+    }
+    fn poll_ready(&self, events: PollEvents) -> PollEvents {
+        let mut ready = PollEvents::OUT;
+        if self.tty.input_ready() {
+            ready |= PollEvents::IN;
+        }
+        if self.tty.hung_up() {
+            ready |= PollEvents::IN | PollEvents::HUP;
+        }
+        ready & events
+    }
+    fn wait_queue(&self) -> Option<&WaitQueue> {
+        Some(self.tty.wait_queue())
+    }
+    fn nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Relaxed)
+    }
+    fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblock.store(nonblocking, Ordering::Relaxed);
+    }
+}
+
+/// The console writes to the UART, which sends from its transmit ring.
+struct UartDriver;
+
+impl TtyDriver for UartDriver {
+    fn write(&self, bytes: &[u8]) -> usize {
+        for ch in bytes {
+            UART.write(*ch);
+        }
+        bytes.len()
+    }
+}
+
+lazy_static! {
+    static ref CONSOLE: Arc<Tty> = Arc::new(Tty::new(Box::new(UartDriver)));
+}
+
+/// The terminal on the UART, which processes start with as fds 0 to 2.
+pub fn console() -> Arc<Tty> {
+    CONSOLE.clone()
+}
+
+/// Hand what the UART received to the console, from its interrupt.
+pub fn console_receive() {
+    let mut bytes = Vec::new();
+    while let Some(ch) = UART.try_read() {
+        bytes.push(ch);
+    }
+    if !bytes.is_empty() {
+        CONSOLE.receive(&bytes);
+    }
+}
@@ -1,6 +1,6 @@
 use log::{info, trace};
 
-use crate::{timer::get_time, syscall::process::sys_yield};
+use crate::{syscall::time::sleep_until, timer::{get_time_ns, NSEC_PER_SEC}};
 
 const LOOP_SIZE: usize = 5;
 
@@ -15,12 +15,10 @@ pub fn main() -> i32 {
             val, i, LOOP_SIZE
         );
     }
-    let mut time = get_time();
+    let mut time = get_time_ns();
     loop {
-        while get_time() < time {
-            sys_yield();
-        }
-        time = get_time() + 10_000_000;
+        sleep_until(time);
+        time = get_time_ns() + NSEC_PER_SEC;
         trace!("[kthread] time = {}", time);
     }
     #[allow(unreachable_code)]
@@ -1,4 +1,5 @@
 use crate::{
+    console,
     sbi::shutdown,
     task::current_kstack_top,
 };
@@ -10,6 +11,7 @@ use log::*;
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    console::enter_panic();
     if let Some(location) = info.location() {
         error!(
             "[kernel] Panicked at {}:{} {}",
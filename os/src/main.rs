@@ -3,12 +3,6 @@
 #![feature(panic_info_message)]
 #![feature(alloc_error_handler)]
 
-//use crate::drivers::{GPU_DEVICE, KEYBOARD_DEVICE, MOUSE_DEVICE, INPUT_CONDVAR};
-use crate::drivers::{
-    GPU_DEVICE,
-    KEYBOARD_DEVICE,
-    MOUSE_DEVICE,
-};
 extern crate alloc;
 
 #[macro_use]
@@ -19,10 +13,12 @@ use log::*;
 #[path = "boards/qemu.rs"]
 mod board;
 
+mod boot_args;
 #[macro_use]
 mod console;
 mod config;
 mod drivers;
+mod fdt;
 mod fpu;
 mod fs;
 mod kpthread_test;
@@ -30,6 +26,7 @@ mod lang_items;
 mod logging;
 mod mm;
 mod net;
+mod random;
 mod sbi;
 mod sync;
 mod syscall;
@@ -82,23 +79,28 @@ fn debug_log() {
     );
 }
 
+/// The firmware passes our hart id and the device tree.
 #[no_mangle]
-pub fn rust_main() -> ! {
+pub fn rust_main(_hart_id: usize, dtb: usize) -> ! {
     clear_bss();
     logging::init();
     fpu::fpu_enable();
+    mm::init_heap();
+    // before anything is printed, the console is found there
+    board::probe(dtb);
+    boot_args::init(dtb);
     mm::init();
     UART.init();
-    info!("KERN: init gpu");
-    let _gpu = GPU_DEVICE.clone();
-    info!("KERN: init keyboard");
-    let _keyboard = KEYBOARD_DEVICE.clone();
-    info!("KERN: init mouse");
-    let _mouse = MOUSE_DEVICE.clone();
+    timer::init_realtime();
+    info!("KERN: init devices");
+    drivers::init();
+    random::init();
     info!("KERN: init trap");
     trap::init();
     trap::enable_timer_interrupt();
     board::device_init();
+    info!("KERN: init net");
+    net::init();
     fs::list_apps();
     // debug_log();
     task::add_kpthread();
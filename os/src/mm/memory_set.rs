@@ -12,21 +12,26 @@ use super::{
     VirtPageNum,
 };
 use crate::{
+    board::mmio_regions,
     config::{
         KERNEL_THREAD_USER_STACK_BOTTOM,
         KERNEL_THREAD_USER_STACK_TOP,
         MEMORY_END,
-        MMIO,
         PAGE_SIZE,
         TRAMPOLINE,
         USER_STACK_BOTTOM,
         USER_STACK_TOP,
     },
+    fs::{
+        File,
+        FilePage,
+    },
     sync::UPIntrFreeCell,
 };
 use alloc::{
     collections::BTreeMap,
     sync::Arc,
+    vec::Vec,
 };
 use core::arch::asm;
 use lazy_static::*;
@@ -165,11 +170,11 @@ impl MemorySet {
             None,
         );
         //println!("mapping memory-mapped registers");
-        for pair in MMIO {
+        for (base, size) in mmio_regions() {
             memory_set.push(
                 MapArea::new(
-                    (*pair).0.into(),
-                    ((*pair).0 + (*pair).1).into(),
+                    base.into(),
+                    (base + size).into(),
                     MapType::Identical,
                     MapPermission::R | MapPermission::W,
                 ),
@@ -289,6 +294,10 @@ impl MemorySet {
         for area in user_space.areas.iter() {
             let new_area = MapArea::from_another(area.1);
             memory_set.push(new_area, None);
+            // file pages are shared, not copied
+            if area.1.map_type == MapType::File {
+                continue;
+            }
             // copy data from another space
             for vpn in area.1.vpn_range {
                 let src_ppn = user_space.translate(vpn).unwrap().ppn();
@@ -347,6 +356,71 @@ impl MemorySet {
         0
     }
 
+    /// Map `[start_addr, end_addr)` to the pages of `file` starting at `offset`.
+    pub fn mmap_file(
+        &mut self, start_addr: VirtAddr, end_addr: VirtAddr, pte_flag: PTEFlags,
+        file: Arc<dyn File + Send + Sync>, offset: usize,
+    ) -> isize {
+        assert!(end_addr > start_addr);
+        let mut area = MapArea::new(
+            start_addr,
+            end_addr,
+            MapType::File,
+            MapPermission::from_bits_truncate(pte_flag.bits()),
+        );
+        area.file = Some((file, offset));
+        self.push(area, None);
+        0
+    }
+
+    /// Lowest page aligned hole of `len` bytes at or above `base`.
+    pub fn find_free_area(&self, base: usize, len: usize) -> VirtAddr {
+        let mut start = VirtAddr::from(base).floor();
+        let pages = VirtAddr::from(len).ceil().0;
+        for area in self.areas.values() {
+            let (area_start, area_end) = (area.vpn_range.get_start(), area.vpn_range.get_end());
+            if area_end <= start {
+                continue;
+            }
+            if area_start.0 >= start.0 + pages {
+                break;
+            }
+            start = area_end;
+        }
+        start.into()
+    }
+
+    /// Whether no area maps any page of `[start_addr, end_addr)`.
+    pub fn is_free(&self, start_addr: VirtAddr, end_addr: VirtAddr) -> bool {
+        let (start, end) = (start_addr.floor(), end_addr.ceil());
+        self.areas
+            .values()
+            .all(|area| area.vpn_range.get_end() <= start || area.vpn_range.get_start() >= end)
+    }
+
+    /// (start, end, permission, type) of every area, for `/proc/<pid>/maps`.
+    pub fn area_ranges(&self) -> Vec<(VirtAddr, VirtAddr, MapPermission, MapType)> {
+        self.areas
+            .values()
+            .map(|area| {
+                (
+                    area.vpn_range.get_start().into(),
+                    area.vpn_range.get_end().into(),
+                    area.map_perm,
+                    area.map_type,
+                )
+            })
+            .collect()
+    }
+
+    /// Number of frames owned by this address space.
+    pub fn resident_pages(&self) -> usize {
+        self.areas
+            .values()
+            .map(|area| area.data_frames.len() + area.shared_frames.len())
+            .sum()
+    }
+
     pub fn munmap(&mut self, start_addr: VirtAddr) -> isize {
         self.remove_area_with_start_vpn(start_addr.floor().into());
         0
@@ -356,8 +430,12 @@ impl MemorySet {
 pub struct MapArea {
     vpn_range: VPNRange,
     data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    /// frames of a `MapType::File` area, owned together with the file.
+    shared_frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
     map_type: MapType,
     map_perm: MapPermission,
+    /// backing file of a `MapType::File` area and the file offset of its first page.
+    file: Option<(Arc<dyn File + Send + Sync>, usize)>,
 }
 
 impl MapArea {
@@ -369,16 +447,20 @@ impl MapArea {
         Self {
             vpn_range: VPNRange::new(start_vpn, end_vpn),
             data_frames: BTreeMap::new(),
+            shared_frames: BTreeMap::new(),
             map_type,
             map_perm,
+            file: None,
         }
     }
     pub fn from_another(another: &MapArea) -> Self {
         Self {
             vpn_range: VPNRange::new(another.vpn_range.get_start(), another.vpn_range.get_end()),
             data_frames: BTreeMap::new(),
+            shared_frames: BTreeMap::new(),
             map_type: another.map_type,
             map_perm: another.map_perm,
+            file: another.file.clone(),
         }
     }
     pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
@@ -397,6 +479,19 @@ impl MapArea {
                 assert!(vpn.0 < (1usize << 27));
                 ppn = PhysPageNum((vpn.0 as isize + pn_offset) as usize);
             }
+            MapType::File => {
+                let (file, offset) = self.file.as_ref().unwrap();
+                let page_offset = offset + (vpn.0 - self.vpn_range.get_start().0) * PAGE_SIZE;
+                match file.mmap_page(page_offset) {
+                    Some(FilePage::Device(device_ppn)) => ppn = device_ppn,
+                    Some(FilePage::Frame(frame)) => {
+                        ppn = frame.ppn;
+                        self.shared_frames.insert(vpn, frame);
+                    }
+                    // past the end of the file, leave it unmapped
+                    None => return,
+                }
+            }
         }
         let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
         page_table.map(vpn, ppn, pte_flags);
@@ -405,6 +500,13 @@ impl MapArea {
         if self.map_type == MapType::Framed {
             self.data_frames.remove(&vpn);
         }
+        if self.map_type == MapType::File {
+            self.shared_frames.remove(&vpn);
+            // pages past the end of the file were never mapped
+            if !page_table.translate(vpn).map_or(false, |pte| pte.is_valid()) {
+                return;
+            }
+        }
         page_table.unmap(vpn);
     }
     pub fn map(&mut self, page_table: &mut PageTable) {
@@ -479,6 +581,8 @@ pub enum MapType {
     Framed,
     /// offset of page num
     Linear(isize),
+    /// pages come from `File::mmap_page` and are shared with the file
+    File,
 }
 
 bitflags! {
@@ -16,6 +16,7 @@ pub use frame_allocator::{
     frame_alloc,
     frame_alloc_more,
     frame_dealloc,
+    frame_stats,
     FrameTracker,
 };
 pub use memory_set::{
@@ -37,8 +38,12 @@ pub use page_table::{
     UserBuffer,
 };
 
-pub fn init() {
+/// The heap alone, enough to read the device tree before paging is on.
+pub fn init_heap() {
     heap_allocator::init_heap();
+}
+
+pub fn init() {
     frame_allocator::init_frame_allocator();
     KERNEL_SPACE.exclusive_access().activate();
 }
@@ -0,0 +1,205 @@
+use alloc::{
+    collections::{
+        BTreeMap,
+        VecDeque,
+    },
+    sync::Arc,
+    vec::Vec,
+};
+use lazy_static::lazy_static;
+
+use crate::{
+    sync::UPIntrFreeCell,
+    timer::get_time_ms,
+};
+
+use super::{
+    iface::NetInterface,
+    ETHERTYPE_ARP,
+    ETHERTYPE_IPV4,
+    ETH_HEADER_LEN,
+};
+
+const ARP_LEN: usize = 28;
+const ARP_HTYPE_ETHERNET: u16 = 1;
+const ARP_REQUEST: u16 = 1;
+const ARP_REPLY: u16 = 2;
+/// Time between requests for an unanswered address.
+const ARP_RETRY_MS: usize = 1000;
+/// Requests after the first one before the address is given up.
+const ARP_MAX_RETRIES: usize = 3;
+/// How long an answer is trusted before the address is asked for again.
+const ARP_REACHABLE_MS: usize = 60_000;
+/// Frames kept while an address is resolved, older ones are dropped.
+const ARP_QUEUE_LEN: usize = 3;
+
+enum Neighbour {
+    Incomplete {
+        retries: usize,
+        next_request: usize,
+        queue: VecDeque<Vec<u8>>,
+    },
+    Reachable {
+        hw_addr: [u8; 6],
+        expires: usize,
+    },
+}
+
+struct ArpEntry {
+    iface: Arc<NetInterface>,
+    neighbour: Neighbour,
+}
+
+lazy_static! {
+    /// Neighbours by IP address.
+    static ref ARP_TABLE: UPIntrFreeCell<BTreeMap<u32, ArpEntry>> =
+        unsafe { UPIntrFreeCell::new(BTreeMap::new()) };
+}
+
+fn send_arp(iface: &NetInterface, op: u16, dest_hw: [u8; 6], target_hw: [u8; 6], target_ip: u32) {
+    let mut frame = Vec::with_capacity(ETH_HEADER_LEN + ARP_LEN);
+    frame.extend_from_slice(&dest_hw);
+    frame.extend_from_slice(&iface.hw_addr());
+    frame.extend_from_slice(&ETHERTYPE_ARP.to_be_bytes());
+    frame.extend_from_slice(&ARP_HTYPE_ETHERNET.to_be_bytes());
+    frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+    frame.extend_from_slice(&[6, 4]);
+    frame.extend_from_slice(&op.to_be_bytes());
+    frame.extend_from_slice(&iface.hw_addr());
+    frame.extend_from_slice(&iface.ip().to_u32().to_be_bytes());
+    frame.extend_from_slice(&target_hw);
+    frame.extend_from_slice(&target_ip.to_be_bytes());
+    iface.transmit(&frame);
+}
+
+fn send_request(iface: &NetInterface, ip: u32) {
+    send_arp(iface, ARP_REQUEST, [0xff; 6], [0; 6], ip);
+}
+
+/// Send `frame` to the neighbour `ip` once its hardware address is known.
+pub fn transmit(iface: &Arc<NetInterface>, ip: u32, mut frame: Vec<u8>) {
+    let mut table = ARP_TABLE.exclusive_access();
+    match table.get_mut(&ip).map(|entry| &mut entry.neighbour) {
+        Some(Neighbour::Reachable { hw_addr, .. }) => {
+            frame[..6].copy_from_slice(hw_addr);
+            drop(table);
+            iface.transmit(&frame);
+        }
+        Some(Neighbour::Incomplete { queue, .. }) => {
+            if queue.len() == ARP_QUEUE_LEN {
+                queue.pop_front();
+            }
+            queue.push_back(frame);
+        }
+        None => {
+            table.insert(
+                ip,
+                ArpEntry {
+                    iface: iface.clone(),
+                    neighbour: Neighbour::Incomplete {
+                        retries: 0,
+                        next_request: get_time_ms() + ARP_RETRY_MS,
+                        queue: VecDeque::from([frame]),
+                    },
+                },
+            );
+            drop(table);
+            send_request(iface, ip);
+        }
+    }
+}
+
+/// Learn the sender's address as RFC 826 does, and answer requests for
+/// ours.
+pub fn arp_input(iface: &Arc<NetInterface>, frame: &[u8]) {
+    if frame.len() < ETH_HEADER_LEN + ARP_LEN {
+        return;
+    }
+    let arp = &frame[ETH_HEADER_LEN..];
+    let be16 = |at: usize| u16::from_be_bytes([arp[at], arp[at + 1]]);
+    let be32 = |at: usize| u32::from_be_bytes([arp[at], arp[at + 1], arp[at + 2], arp[at + 3]]);
+    if be16(0) != ARP_HTYPE_ETHERNET || be16(2) != ETHERTYPE_IPV4 || arp[4] != 6 || arp[5] != 4 {
+        return;
+    }
+    let op = be16(6);
+    let mut sender_hw = [0u8; 6];
+    sender_hw.copy_from_slice(&arp[8..14]);
+    let sender_ip = be32(14);
+    let target_ip = be32(24);
+    let for_us = iface.is_configured() && target_ip == iface.ip().to_u32();
+
+    let mut table = ARP_TABLE.exclusive_access();
+    // only neighbours we talk to are kept, unless they talk to us
+    if for_us || table.contains_key(&sender_ip) {
+        let reachable = Neighbour::Reachable {
+            hw_addr: sender_hw,
+            expires: get_time_ms() + ARP_REACHABLE_MS,
+        };
+        let old = table.insert(
+            sender_ip,
+            ArpEntry {
+                iface: iface.clone(),
+                neighbour: reachable,
+            },
+        );
+        drop(table);
+        if let Some(ArpEntry {
+            iface,
+            neighbour: Neighbour::Incomplete { queue, .. },
+        }) = old
+        {
+            for mut frame in queue {
+                frame[..6].copy_from_slice(&sender_hw);
+                iface.transmit(&frame);
+            }
+        }
+    } else {
+        drop(table);
+    }
+    if for_us && op == ARP_REQUEST {
+        send_arp(iface, ARP_REPLY, sender_hw, sender_hw, sender_ip);
+    }
+}
+
+/// Ask again for unanswered addresses, give up on those that never answer
+/// and forget the ones that are too old.
+/// When `arp_tick` next has something to do.
+pub fn next_timeout() -> Option<usize> {
+    ARP_TABLE
+        .exclusive_access()
+        .values()
+        .map(|entry| match &entry.neighbour {
+            Neighbour::Incomplete { next_request, .. } => *next_request,
+            Neighbour::Reachable { expires, .. } => *expires,
+        })
+        .min()
+}
+
+pub fn arp_tick() {
+    let now = get_time_ms();
+    let mut requests = Vec::new();
+    ARP_TABLE
+        .exclusive_access()
+        .retain(|ip, entry| match &mut entry.neighbour {
+            Neighbour::Reachable { expires, .. } => *expires > now,
+            Neighbour::Incomplete {
+                retries,
+                next_request,
+                ..
+            } => {
+                if *next_request > now {
+                    true
+                } else if *retries == ARP_MAX_RETRIES {
+                    false
+                } else {
+                    *retries += 1;
+                    *next_request = now + ARP_RETRY_MS;
+                    requests.push((entry.iface.clone(), *ip));
+                    true
+                }
+            }
+        });
+    for (iface, ip) in requests {
+        send_request(&iface, ip);
+    }
+}
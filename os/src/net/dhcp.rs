@@ -0,0 +1,360 @@
+//! DHCPv4 client of RFC 2131, keeping the address of one interface leased.
+
+use alloc::{
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use lazy_static::lazy_static;
+use log::{
+    info,
+    warn,
+};
+use lose_net_stack::IPv4;
+
+use crate::{
+    sync::UPIntrFreeCell,
+    timer::get_time_ms,
+};
+
+use super::{
+    iface::{
+        set_default_gateway,
+        NetInterface,
+    },
+    ipv4::{
+        ipv4_frame,
+        Dotted,
+        Ipv4Packet,
+    },
+    IPPROTO_UDP,
+};
+
+const DHCP_SERVER_PORT: u16 = 67;
+const DHCP_CLIENT_PORT: u16 = 68;
+const UDP_HEADER_LEN: usize = 8;
+/// The fixed BOOTP fields, up to the options.
+const BOOTP_LEN: usize = 236;
+/// Some servers ignore shorter messages.
+const BOOTP_MIN_LEN: usize = 300;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+const BOOTREQUEST: u8 = 1;
+const BOOTREPLY: u8 = 2;
+const HTYPE_ETHERNET: u8 = 1;
+/// Answers are broadcast, we can not take unicast before having an address.
+const FLAG_BROADCAST: u16 = 0x8000;
+
+const OPT_PAD: u8 = 0;
+const OPT_SUBNET_MASK: u8 = 1;
+const OPT_ROUTER: u8 = 3;
+const OPT_REQUESTED_IP: u8 = 50;
+const OPT_LEASE_TIME: u8 = 51;
+const OPT_MESSAGE_TYPE: u8 = 53;
+const OPT_SERVER_ID: u8 = 54;
+const OPT_PARAMETER_LIST: u8 = 55;
+const OPT_RENEWAL_TIME: u8 = 58;
+const OPT_END: u8 = 255;
+
+const DHCPDISCOVER: u8 = 1;
+const DHCPOFFER: u8 = 2;
+const DHCPREQUEST: u8 = 3;
+const DHCPACK: u8 = 5;
+const DHCPNAK: u8 = 6;
+
+/// First wait for an answer, doubled on every try up to the longest.
+const RETRY_MIN_MS: usize = 2000;
+const RETRY_MAX_MS: usize = 32_000;
+/// Requests for an offered address before discovering again.
+const REQUEST_TRIES: usize = 4;
+
+enum DhcpState {
+    /// Discovering, waiting for an offer.
+    Selecting,
+    /// Asked `server` for the address it `offered`.
+    Requesting { server: u32, offered: u32 },
+    /// The address is ours until `lease_end`, renewed from `renew_at`.
+    Bound { renew_at: usize, lease_end: usize },
+    /// Asked for our address again, lost at `lease_end`.
+    Renewing { lease_end: usize },
+}
+
+struct DhcpClient {
+    iface: Arc<NetInterface>,
+    xid: u32,
+    state: DhcpState,
+    /// Messages sent in this state.
+    tries: usize,
+    /// When the last message is given up on.
+    next_send: usize,
+}
+
+lazy_static! {
+    static ref DHCP_CLIENT: UPIntrFreeCell<Option<DhcpClient>> =
+        unsafe { UPIntrFreeCell::new(None) };
+}
+
+/// What the options of a reply tell us.
+#[derive(Default)]
+struct DhcpOptions {
+    message_type: Option<u8>,
+    netmask: Option<u32>,
+    router: Option<u32>,
+    server: Option<u32>,
+    lease_secs: Option<u32>,
+    renew_secs: Option<u32>,
+}
+
+impl DhcpOptions {
+    fn parse(mut options: &[u8]) -> Self {
+        let mut parsed = Self::default();
+        while let [code, rest @ ..] = options {
+            match *code {
+                OPT_END => break,
+                OPT_PAD => {
+                    options = rest;
+                    continue;
+                }
+                _ => {}
+            }
+            let (len, rest) = match rest {
+                [len, rest @ ..] if rest.len() >= *len as usize => (*len as usize, rest),
+                _ => break,
+            };
+            let value = &rest[..len];
+            let addr =
+                || (len >= 4).then(|| u32::from_be_bytes([value[0], value[1], value[2], value[3]]));
+            match *code {
+                OPT_MESSAGE_TYPE if len == 1 => parsed.message_type = Some(value[0]),
+                OPT_SUBNET_MASK => parsed.netmask = addr(),
+                // the first of the routers will do
+                OPT_ROUTER => parsed.router = addr(),
+                OPT_SERVER_ID => parsed.server = addr(),
+                OPT_LEASE_TIME => parsed.lease_secs = addr(),
+                OPT_RENEWAL_TIME => parsed.renew_secs = addr(),
+                _ => {}
+            }
+            options = &rest[len..];
+        }
+        parsed
+    }
+}
+
+/// Seconds from now as a deadline, an infinite lease never runs out.
+fn deadline(now: usize, secs: u32) -> usize {
+    if secs == u32::MAX {
+        usize::MAX
+    } else {
+        now + secs as usize * 1000
+    }
+}
+
+impl DhcpClient {
+    /// A DHCP message from `ciaddr` to the broadcast address.
+    fn frame(&self, ciaddr: u32, options: &[u8]) -> Vec<u8> {
+        let hw_addr = self.iface.hw_addr();
+        let mut bootp = vec![0u8; BOOTP_LEN];
+        bootp[0] = BOOTREQUEST;
+        bootp[1] = HTYPE_ETHERNET;
+        bootp[2] = hw_addr.len() as u8;
+        bootp[4..8].copy_from_slice(&self.xid.to_be_bytes());
+        bootp[10..12].copy_from_slice(&FLAG_BROADCAST.to_be_bytes());
+        bootp[12..16].copy_from_slice(&ciaddr.to_be_bytes());
+        bootp[28..34].copy_from_slice(&hw_addr);
+        bootp.extend_from_slice(&MAGIC_COOKIE);
+        bootp.extend_from_slice(options);
+        bootp.extend_from_slice(&[
+            OPT_PARAMETER_LIST,
+            4,
+            OPT_SUBNET_MASK,
+            OPT_ROUTER,
+            OPT_LEASE_TIME,
+            OPT_RENEWAL_TIME,
+            OPT_END,
+        ]);
+        bootp.resize(bootp.len().max(BOOTP_MIN_LEN), OPT_PAD);
+
+        // a zero checksum means none, which IPv4 allows
+        let udp_len = (UDP_HEADER_LEN + bootp.len()) as u16;
+        let mut udp = Vec::with_capacity(udp_len as usize);
+        udp.extend_from_slice(&DHCP_CLIENT_PORT.to_be_bytes());
+        udp.extend_from_slice(&DHCP_SERVER_PORT.to_be_bytes());
+        udp.extend_from_slice(&udp_len.to_be_bytes());
+        udp.extend_from_slice(&[0, 0]);
+        udp.extend_from_slice(&bootp);
+
+        let mut frame = ipv4_frame(hw_addr, ciaddr, u32::MAX, IPPROTO_UDP, &udp);
+        frame[..6].copy_from_slice(&[0xff; 6]);
+        frame
+    }
+
+    /// Send what the state asks for and back off until the next try.
+    fn send(&mut self) {
+        let frame = match self.state {
+            DhcpState::Selecting => self.frame(0, &[OPT_MESSAGE_TYPE, 1, DHCPDISCOVER]),
+            DhcpState::Requesting { server, offered } => {
+                let mut options = vec![OPT_MESSAGE_TYPE, 1, DHCPREQUEST, OPT_REQUESTED_IP, 4];
+                options.extend_from_slice(&offered.to_be_bytes());
+                options.extend_from_slice(&[OPT_SERVER_ID, 4]);
+                options.extend_from_slice(&server.to_be_bytes());
+                self.frame(0, &options)
+            }
+            // any server may extend it, so it is broadcast as when rebinding
+            DhcpState::Renewing { .. } => self.frame(
+                self.iface.ip().to_u32(),
+                &[OPT_MESSAGE_TYPE, 1, DHCPREQUEST],
+            ),
+            DhcpState::Bound { .. } => return,
+        };
+        self.iface.transmit(&frame);
+        let backoff = (RETRY_MIN_MS << self.tries.min(4)).min(RETRY_MAX_MS);
+        self.tries += 1;
+        self.next_send = get_time_ms() + backoff;
+    }
+
+    fn enter(&mut self, state: DhcpState) {
+        self.state = state;
+        self.tries = 0;
+        self.send();
+    }
+
+    /// Start over with a fresh transaction.
+    fn restart(&mut self) {
+        self.xid = self.xid.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        self.enter(DhcpState::Selecting);
+    }
+
+    fn bind(&mut self, ip: u32, options: &DhcpOptions) {
+        let now = get_time_ms();
+        let lease_secs = options.lease_secs.unwrap_or(u32::MAX);
+        let renew_secs = options.renew_secs.unwrap_or(lease_secs / 2);
+        let netmask = options.netmask.unwrap_or(0xffff_ff00);
+        self.iface.set_ip(IPv4::from_u32(ip));
+        self.iface.set_netmask(netmask);
+        set_default_gateway(&self.iface, options.router);
+        self.state = DhcpState::Bound {
+            renew_at: deadline(now, renew_secs),
+            lease_end: deadline(now, lease_secs),
+        };
+        info!(
+            "KERN: {}: leased {} netmask {} for {}s",
+            self.iface.name,
+            Dotted(ip),
+            Dotted(netmask),
+            lease_secs
+        );
+    }
+
+    fn unconfigure(&self) {
+        self.iface.set_ip(IPv4::from_u32(0));
+        self.iface.set_netmask(0);
+        set_default_gateway(&self.iface, None);
+    }
+}
+
+/// Lease an address for `iface`, instead of whichever interface had one.
+pub fn start(iface: &Arc<NetInterface>) {
+    let [.., a, b, c, d] = iface.hw_addr();
+    let mut client = DhcpClient {
+        iface: iface.clone(),
+        xid: u32::from_be_bytes([a, b, c, d]) ^ get_time_ms() as u32,
+        state: DhcpState::Selecting,
+        tries: 0,
+        next_send: 0,
+    };
+    client.send();
+    *DHCP_CLIENT.exclusive_access() = Some(client);
+}
+
+/// Leave `iface` alone, its address is set by hand now.
+pub fn stop(iface: &Arc<NetInterface>) {
+    let mut client = DHCP_CLIENT.exclusive_access();
+    if matches!(&*client, Some(c) if Arc::ptr_eq(&c.iface, iface)) {
+        *client = None;
+    }
+}
+
+/// UDP to the client port, which no socket gets.
+pub fn is_dhcp(packet: &Ipv4Packet) -> bool {
+    let udp = packet.payload;
+    packet.protocol == IPPROTO_UDP
+        && udp.len() >= UDP_HEADER_LEN
+        && u16::from_be_bytes([udp[2], udp[3]]) == DHCP_CLIENT_PORT
+}
+
+pub fn dhcp_input(iface: &Arc<NetInterface>, packet: &Ipv4Packet) {
+    let bootp = &packet.payload[UDP_HEADER_LEN..];
+    if bootp.len() < BOOTP_LEN + MAGIC_COOKIE.len()
+        || bootp[0] != BOOTREPLY
+        || bootp[BOOTP_LEN..BOOTP_LEN + 4] != MAGIC_COOKIE
+    {
+        return;
+    }
+    let be32 =
+        |at: usize| u32::from_be_bytes([bootp[at], bootp[at + 1], bootp[at + 2], bootp[at + 3]]);
+    let mut client = DHCP_CLIENT.exclusive_access();
+    let client = match client.as_mut() {
+        Some(client) if Arc::ptr_eq(&client.iface, iface) && client.xid == be32(4) => client,
+        _ => return,
+    };
+    let yiaddr = be32(16);
+    let options = DhcpOptions::parse(&bootp[BOOTP_LEN + 4..]);
+    match (options.message_type, &client.state) {
+        (Some(DHCPOFFER), DhcpState::Selecting) => {
+            if let Some(server) = options.server {
+                client.enter(DhcpState::Requesting {
+                    server,
+                    offered: yiaddr,
+                });
+            }
+        }
+        (Some(DHCPACK), DhcpState::Requesting { .. } | DhcpState::Renewing { .. }) => {
+            client.bind(yiaddr, &options)
+        }
+        (Some(DHCPNAK), DhcpState::Requesting { .. } | DhcpState::Renewing { .. }) => {
+            warn!("KERN: {}: lease refused", client.iface.name);
+            client.unconfigure();
+            client.restart();
+        }
+        _ => {}
+    }
+}
+
+/// Retry unanswered messages and keep the lease going.
+/// When `dhcp_tick` next has something to do.
+pub fn next_timeout() -> Option<usize> {
+    let client = DHCP_CLIENT.exclusive_access();
+    let client = client.as_ref()?;
+    Some(match client.state {
+        DhcpState::Bound { renew_at, .. } => renew_at,
+        DhcpState::Renewing { lease_end } => lease_end.min(client.next_send),
+        _ => client.next_send,
+    })
+}
+
+pub fn dhcp_tick() {
+    let mut client = DHCP_CLIENT.exclusive_access();
+    let client = match client.as_mut() {
+        Some(client) => client,
+        None => return,
+    };
+    let now = get_time_ms();
+    match client.state {
+        DhcpState::Bound {
+            renew_at,
+            lease_end,
+        } if now >= renew_at => client.enter(DhcpState::Renewing { lease_end }),
+        DhcpState::Renewing { lease_end } if now >= lease_end => {
+            warn!("KERN: {}: lease expired", client.iface.name);
+            client.unconfigure();
+            client.restart();
+        }
+        DhcpState::Requesting { .. }
+            if now >= client.next_send && client.tries == REQUEST_TRIES =>
+        {
+            client.restart()
+        }
+        DhcpState::Bound { .. } => {}
+        _ if now >= client.next_send => client.send(),
+        _ => {}
+    }
+}
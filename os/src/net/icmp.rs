@@ -0,0 +1,306 @@
+use alloc::{
+    collections::VecDeque,
+    sync::{
+        Arc,
+        Weak,
+    },
+    vec::Vec,
+};
+use lazy_static::lazy_static;
+use shared_defination::error::{
+    EADDRINUSE,
+    EDESTADDRREQ,
+    EINVAL,
+    EMSGSIZE,
+};
+
+use crate::{
+    fs::{
+        File,
+        PollEvents,
+    },
+    mm::UserBuffer,
+    sync::{
+        UPIntrFreeCell,
+        WaitQueue,
+    },
+};
+
+use super::{
+    iface::interfaces,
+    ipv4::{
+        checksum,
+        ipv4_frame,
+        Ipv4Packet,
+    },
+    net_block_on,
+    net_bottom_half,
+    route_to,
+    socket::{
+        alloc_ephemeral_port,
+        copy_from_user,
+        copy_to_user,
+        InetAddr,
+        Socket,
+        SocketOptions,
+    },
+    IPPROTO_ICMP,
+};
+
+const ICMP_HEADER_LEN: usize = 8;
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_ECHO_REQUEST: u8 = 8;
+/// Largest ICMP message that fits an ethernet frame.
+const ICMP_MAX_LEN: usize = 1480;
+/// Messages queued per socket before new ones are dropped.
+const ICMP_RX_QUEUE: usize = 64;
+
+lazy_static! {
+    static ref ICMP_SOCKETS: UPIntrFreeCell<Vec<Weak<IcmpSocket>>> =
+        unsafe { UPIntrFreeCell::new(Vec::new()) };
+}
+
+/// Send `message` to `dest`, from `src` or the address of the way out.
+fn send_icmp(src: Option<u32>, dest: u32, message: &[u8]) -> Result<(), isize> {
+    let route = route_to(dest)?;
+    let src = src.unwrap_or(route.src.to_u32());
+    route.transmit(ipv4_frame(
+        route.iface.hw_addr(),
+        src,
+        dest,
+        IPPROTO_ICMP,
+        message,
+    ));
+    Ok(())
+}
+
+/// Answer echo requests to our addresses and hand replies to the sockets.
+pub fn icmp_input(packet: &Ipv4Packet) {
+    let message = packet.payload;
+    if message.len() < ICMP_HEADER_LEN || checksum(message) != 0 {
+        return;
+    }
+    let (src, dest) = (packet.src, packet.dest);
+
+    let sockets: Vec<Arc<IcmpSocket>> = ICMP_SOCKETS
+        .exclusive_access()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect();
+    for socket in sockets {
+        socket.push(src, packet.packet, message);
+    }
+
+    if message[0] == ICMP_ECHO_REQUEST
+        && interfaces()
+            .iter()
+            .any(|iface| iface.is_configured() && iface.ip().to_u32() == dest)
+    {
+        let mut reply = message.to_vec();
+        reply[0] = ICMP_ECHO_REPLY;
+        reply[2..4].copy_from_slice(&[0, 0]);
+        let sum = checksum(&reply);
+        reply[2..4].copy_from_slice(&sum.to_be_bytes());
+        let _ = send_icmp(Some(dest), src, &reply);
+    }
+}
+
+fn id_in_use(id: u16) -> bool {
+    ICMP_SOCKETS
+        .exclusive_access()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .any(|socket| !socket.raw && socket.inner.exclusive_access().id == Some(id))
+}
+
+struct IcmpInner {
+    /// Echo identifier of a datagram socket, like a UDP port.
+    id: Option<u16>,
+    rx: VecDeque<(InetAddr, Vec<u8>)>,
+}
+
+/// `SOCK_DGRAM` with `IPPROTO_ICMP` sends echo requests and receives the
+/// replies to them, as Linux ping sockets do. `SOCK_RAW` sends any ICMP
+/// message and receives every one, IP header included.
+pub struct IcmpSocket {
+    raw: bool,
+    inner: UPIntrFreeCell<IcmpInner>,
+    wait_queue: WaitQueue,
+    options: UPIntrFreeCell<SocketOptions>,
+}
+
+impl IcmpSocket {
+    pub fn new(raw: bool) -> Arc<Self> {
+        let socket = Arc::new(Self {
+            raw,
+            inner: unsafe {
+                UPIntrFreeCell::new(IcmpInner {
+                    id: None,
+                    rx: VecDeque::new(),
+                })
+            },
+            wait_queue: WaitQueue::new(),
+            options: unsafe { UPIntrFreeCell::new(SocketOptions::default()) },
+        });
+        ICMP_SOCKETS
+            .exclusive_access()
+            .push(Arc::downgrade(&socket));
+        socket
+    }
+
+    fn id_or_bind(&self) -> Result<u16, isize> {
+        if let Some(id) = self.inner.exclusive_access().id {
+            return Ok(id);
+        }
+        let id = alloc_ephemeral_port(id_in_use).ok_or(-(EADDRINUSE as isize))?;
+        self.inner.exclusive_access().id = Some(id);
+        Ok(id)
+    }
+
+    fn push(&self, src: u32, packet: &[u8], message: &[u8]) {
+        let mut inner = self.inner.exclusive_access();
+        let data = if self.raw {
+            packet
+        } else {
+            let id = u16::from_be_bytes([message[4], message[5]]);
+            if message[0] != ICMP_ECHO_REPLY || inner.id != Some(id) {
+                return;
+            }
+            message
+        };
+        if inner.rx.len() >= ICMP_RX_QUEUE {
+            return;
+        }
+        inner.rx.push_back((InetAddr::new(src, 0), data.to_vec()));
+        self.wait_queue.wake_all();
+    }
+}
+
+impl Socket for IcmpSocket {
+    /// The port is the echo identifier.
+    fn bind(&self, addr: InetAddr) -> isize {
+        if self.raw {
+            return 0;
+        }
+        if self.inner.exclusive_access().id.is_some() {
+            return -(EINVAL as isize);
+        }
+        let id = match addr.port {
+            0 => match alloc_ephemeral_port(id_in_use) {
+                Some(id) => id,
+                None => return -(EADDRINUSE as isize),
+            },
+            id if id_in_use(id) => return -(EADDRINUSE as isize),
+            id => id,
+        };
+        self.inner.exclusive_access().id = Some(id);
+        0
+    }
+
+    /// A datagram socket only sends echo requests, their identifier and
+    /// checksum are filled in.
+    fn sendto(&self, buf: UserBuffer, addr: Option<InetAddr>) -> isize {
+        let dest = match addr {
+            Some(addr) => addr.ip,
+            None => return -(EDESTADDRREQ as isize),
+        };
+        if buf.len() > ICMP_MAX_LEN {
+            return -(EMSGSIZE as isize);
+        }
+        let mut message = copy_from_user(&buf);
+        if message.len() < ICMP_HEADER_LEN {
+            return -(EINVAL as isize);
+        }
+        if !self.raw {
+            if message[0] != ICMP_ECHO_REQUEST || message[1] != 0 {
+                return -(EINVAL as isize);
+            }
+            let id = match self.id_or_bind() {
+                Ok(id) => id,
+                Err(err) => return err,
+            };
+            message[4..6].copy_from_slice(&id.to_be_bytes());
+            message[2..4].copy_from_slice(&[0, 0]);
+            let sum = checksum(&message);
+            message[2..4].copy_from_slice(&sum.to_be_bytes());
+        }
+        if let Err(err) = send_icmp(None, dest, &message) {
+            return err;
+        }
+        net_bottom_half();
+        message.len() as isize
+    }
+
+    /// The rest of a message that does not fit `buf` is dropped.
+    fn recvfrom(&self, buf: UserBuffer) -> (isize, Option<InetAddr>) {
+        let blocking = self.options.exclusive_access().recv_blocking();
+        match net_block_on(&self.wait_queue, blocking, || {
+            self.inner.exclusive_access().rx.pop_front()
+        }) {
+            Ok((source, data)) => (copy_to_user(buf, &data) as isize, Some(source)),
+            Err(err) => (err, None),
+        }
+    }
+
+    fn sockname(&self) -> InetAddr {
+        InetAddr::new(0, self.inner.exclusive_access().id.unwrap_or(0))
+    }
+
+    fn peername(&self) -> Option<InetAddr> {
+        None
+    }
+
+    fn options(&self) -> &UPIntrFreeCell<SocketOptions> {
+        &self.options
+    }
+}
+
+impl File for IcmpSocket {
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn read(&self, buf: UserBuffer) -> usize {
+        self.recvfrom(buf).0 as usize
+    }
+
+    fn write(&self, buf: UserBuffer) -> usize {
+        self.sendto(buf, None) as usize
+    }
+
+    fn poll_ready(&self, events: PollEvents) -> PollEvents {
+        let mut ready = PollEvents::OUT;
+        if !self.inner.exclusive_access().rx.is_empty() {
+            ready |= PollEvents::IN;
+        }
+        ready & events
+    }
+
+    fn wait_queue(&self) -> Option<&WaitQueue> {
+        Some(&self.wait_queue)
+    }
+
+    fn nonblocking(&self) -> bool {
+        self.options.exclusive_access().nonblock
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) {
+        self.options.exclusive_access().nonblock = nonblocking;
+    }
+
+    fn as_socket(&self) -> Option<&dyn Socket> {
+        Some(self)
+    }
+}
+
+impl Drop for IcmpSocket {
+    fn drop(&mut self) {
+        ICMP_SOCKETS
+            .exclusive_access()
+            .retain(|socket| socket.strong_count() > 0);
+    }
+}
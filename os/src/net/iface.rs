@@ -0,0 +1,226 @@
+use alloc::{
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use lazy_static::lazy_static;
+use lose_net_stack::{
+    results::Packet,
+    IPv4,
+    LoseStack,
+    MacAddress,
+};
+
+use super::{
+    arp,
+    pcap,
+};
+use crate::{
+    drivers::{
+        net_device,
+        LoopbackDevice,
+        NetDevice,
+    },
+    sync::UPIntrFreeCell,
+};
+
+struct IfInner {
+    /// Parses the UDP and TCP frames for us.
+    stack: LoseStack,
+    netmask: u32,
+}
+
+/// A device together with the addresses the stack uses on it.
+pub struct NetInterface {
+    pub name: &'static str,
+    device: Arc<dyn NetDevice>,
+    hw_addr: [u8; 6],
+    /// Next hops are resolved with ARP, loopback has no link layer to speak
+    /// of.
+    needs_arp: bool,
+    inner: UPIntrFreeCell<IfInner>,
+}
+
+impl NetInterface {
+    fn new(
+        name: &'static str, device: Arc<dyn NetDevice>, ip: IPv4, netmask: u32, needs_arp: bool,
+    ) -> Arc<Self> {
+        let hw_addr = device.mac();
+        Arc::new(Self {
+            name,
+            device,
+            hw_addr,
+            needs_arp,
+            inner: unsafe {
+                UPIntrFreeCell::new(IfInner {
+                    stack: LoseStack::new(ip, MacAddress::new(hw_addr)),
+                    netmask,
+                })
+            },
+        })
+    }
+
+    pub fn ip(&self) -> IPv4 {
+        self.inner.exclusive_access().stack.ip
+    }
+
+    pub fn mac(&self) -> MacAddress {
+        MacAddress::new(self.hw_addr)
+    }
+
+    pub fn hw_addr(&self) -> [u8; 6] {
+        self.hw_addr
+    }
+
+    pub fn netmask(&self) -> u32 {
+        self.inner.exclusive_access().netmask
+    }
+
+    pub fn set_ip(&self, ip: IPv4) {
+        self.inner.exclusive_access().stack.ip = ip;
+    }
+
+    pub fn set_netmask(&self, netmask: u32) {
+        self.inner.exclusive_access().netmask = netmask;
+    }
+
+    /// Has an address, until then only DHCP uses it.
+    pub fn is_configured(&self) -> bool {
+        self.ip().to_u32() != 0
+    }
+
+    pub fn is_loopback(&self) -> bool {
+        !self.needs_arp
+    }
+
+    pub fn transmit(&self, frame: &[u8]) {
+        pcap::capture(frame);
+        self.device.transmit(frame);
+    }
+
+    pub fn can_recv(&self) -> bool {
+        self.device.can_recv()
+    }
+
+    pub fn receive(&self, buf: &mut [u8]) -> usize {
+        let len = self.device.receive(buf);
+        // loopback hands back what was sent, which is captured already
+        if self.needs_arp {
+            pcap::capture(&buf[..len]);
+        }
+        len
+    }
+
+    pub fn analysis<'a>(&self, frame: &'a [u8]) -> Packet<'a> {
+        self.inner.exclusive_access().stack.analysis(frame)
+    }
+}
+
+/// A network reached through a gateway. Those on an interface's own
+/// subnet need no entry.
+struct RouteEntry {
+    dest: u32,
+    netmask: u32,
+    gateway: u32,
+    iface: Arc<NetInterface>,
+}
+
+/// Where a packet for some address leaves.
+pub struct Route {
+    pub iface: Arc<NetInterface>,
+    /// Source address for packets that are not bound to one.
+    pub src: IPv4,
+    /// The gateway, or the destination itself when it is on the link.
+    pub next_hop: u32,
+}
+
+impl Route {
+    /// Send an ethernet frame whose destination is left to us: broadcasts go
+    /// to everyone, anything else waits for ARP to find the next hop.
+    pub fn transmit(&self, mut frame: Vec<u8>) {
+        let subnet_broadcast = self.iface.ip().to_u32() | !self.iface.netmask();
+        if !self.iface.needs_arp {
+            frame[..6].copy_from_slice(&self.iface.hw_addr);
+            self.iface.transmit(&frame);
+        } else if self.next_hop == u32::MAX || self.next_hop == subnet_broadcast {
+            frame[..6].copy_from_slice(&[0xff; 6]);
+            self.iface.transmit(&frame);
+        } else {
+            arp::transmit(&self.iface, self.next_hop, frame);
+        }
+    }
+}
+
+lazy_static! {
+    pub static ref LOOPBACK: Arc<NetInterface> = NetInterface::new(
+        "lo",
+        Arc::new(LoopbackDevice::new()),
+        IPv4::new(127, 0, 0, 1),
+        0xff00_0000,
+        false,
+    );
+    /// Loopback, and eth0 when there is a network card. It is addressed by
+    /// DHCP or the `ip=` boot argument.
+    static ref INTERFACES: Vec<Arc<NetInterface>> = {
+        let mut interfaces = vec![LOOPBACK.clone()];
+        if let Some(device) = net_device() {
+            interfaces.push(NetInterface::new("eth0", device, IPv4::from_u32(0), 0, true));
+        }
+        interfaces
+    };
+    static ref ROUTES: UPIntrFreeCell<Vec<RouteEntry>> = unsafe { UPIntrFreeCell::new(Vec::new()) };
+}
+
+pub fn interfaces() -> &'static [Arc<NetInterface>] {
+    &INTERFACES
+}
+
+pub fn interface(name: &str) -> Option<&'static Arc<NetInterface>> {
+    INTERFACES.iter().find(|iface| iface.name == name)
+}
+
+/// Replace the default route with one through `gateway` on `iface`, or
+/// drop it.
+pub fn set_default_gateway(iface: &Arc<NetInterface>, gateway: Option<u32>) {
+    let mut routes = ROUTES.exclusive_access();
+    routes.retain(|entry| entry.netmask != 0);
+    if let Some(gateway) = gateway {
+        routes.push(RouteEntry {
+            dest: 0,
+            netmask: 0,
+            gateway,
+            iface: iface.clone(),
+        });
+    }
+}
+
+/// The longest matching prefix among the interfaces' subnets and the
+/// gateways. Our own addresses are reached through loopback whichever
+/// interface they belong to. Interfaces without an address are left out.
+pub fn route(dest: u32) -> Option<Route> {
+    let configured = || INTERFACES.iter().filter(|iface| iface.is_configured());
+    if configured().any(|iface| iface.ip().to_u32() == dest) {
+        return Some(Route {
+            iface: LOOPBACK.clone(),
+            src: IPv4::from_u32(dest),
+            next_hop: dest,
+        });
+    }
+    let on_link = configured().filter_map(|iface| {
+        let netmask = iface.netmask();
+        (dest & netmask == iface.ip().to_u32() & netmask).then(|| (netmask, iface.clone(), dest))
+    });
+    let routes = ROUTES.exclusive_access();
+    let via_gateway = routes
+        .iter()
+        .filter(|entry| dest & entry.netmask == entry.dest && entry.iface.is_configured())
+        .map(|entry| (entry.netmask, entry.iface.clone(), entry.gateway));
+    on_link
+        .chain(via_gateway)
+        .max_by_key(|(netmask, ..)| *netmask)
+        .map(|(_, iface, next_hop)| Route {
+            src: iface.ip(),
+            iface,
+            next_hop,
+        })
+}
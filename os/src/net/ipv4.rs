@@ -0,0 +1,121 @@
+use alloc::vec::Vec;
+use core::fmt;
+use lazy_static::lazy_static;
+
+use crate::sync::UPIntrFreeCell;
+
+use super::{
+    ETHERTYPE_IPV4,
+    ETH_HEADER_LEN,
+};
+
+pub const IPV4_HEADER_LEN: usize = 20;
+const IPV4_TTL: u8 = 64;
+
+lazy_static! {
+    static ref NEXT_IP_ID: UPIntrFreeCell<u16> = unsafe { UPIntrFreeCell::new(0) };
+}
+
+/// The internet checksum of RFC 1071.
+pub fn checksum(data: &[u8]) -> u16 {
+    let mut sum = 0u32;
+    for chunk in data.chunks(2) {
+        let word = match chunk {
+            [hi, lo] => u16::from_be_bytes([*hi, *lo]),
+            [hi] => u16::from_be_bytes([*hi, 0]),
+            _ => unreachable!(),
+        };
+        sum += word as u32;
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// `payload` in an IPv4 packet in an ethernet frame, with the destination
+/// MAC left to whoever sends it.
+pub fn ipv4_frame(src_hw: [u8; 6], src: u32, dest: u32, protocol: u8, payload: &[u8]) -> Vec<u8> {
+    let id = {
+        let mut next = NEXT_IP_ID.exclusive_access();
+        *next = next.wrapping_add(1);
+        *next
+    };
+    let total_len = (IPV4_HEADER_LEN + payload.len()) as u16;
+    let mut header = [0u8; IPV4_HEADER_LEN];
+    header[0] = 0x45;
+    header[2..4].copy_from_slice(&total_len.to_be_bytes());
+    header[4..6].copy_from_slice(&id.to_be_bytes());
+    header[8] = IPV4_TTL;
+    header[9] = protocol;
+    header[12..16].copy_from_slice(&src.to_be_bytes());
+    header[16..20].copy_from_slice(&dest.to_be_bytes());
+    let sum = checksum(&header);
+    header[10..12].copy_from_slice(&sum.to_be_bytes());
+
+    let mut frame = Vec::with_capacity(ETH_HEADER_LEN + total_len as usize);
+    frame.extend_from_slice(&[0; 6]);
+    frame.extend_from_slice(&src_hw);
+    frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+    frame.extend_from_slice(&header);
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// An IPv4 packet out of a received frame.
+pub struct Ipv4Packet<'a> {
+    pub src: u32,
+    pub dest: u32,
+    pub protocol: u8,
+    /// The whole packet, header included.
+    pub packet: &'a [u8],
+    pub payload: &'a [u8],
+}
+
+impl<'a> Ipv4Packet<'a> {
+    /// The packet in an ethernet frame carrying IPv4, if its header holds
+    /// up.
+    pub fn parse(frame: &'a [u8]) -> Option<Self> {
+        let packet = frame.get(ETH_HEADER_LEN..)?;
+        if packet.len() < IPV4_HEADER_LEN {
+            return None;
+        }
+        let header_len = (packet[0] & 0xf) as usize * 4;
+        let total_len = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+        if header_len < IPV4_HEADER_LEN
+            || total_len < header_len
+            || total_len > packet.len()
+            || checksum(&packet[..header_len]) != 0
+        {
+            return None;
+        }
+        let packet = &packet[..total_len];
+        Some(Self {
+            src: u32::from_be_bytes([packet[12], packet[13], packet[14], packet[15]]),
+            dest: u32::from_be_bytes([packet[16], packet[17], packet[18], packet[19]]),
+            protocol: packet[9],
+            packet,
+            payload: &packet[header_len..],
+        })
+    }
+}
+
+/// `a.b.c.d`.
+pub fn parse_addr(s: &str) -> Option<u32> {
+    let mut octets = s.split('.');
+    let mut addr = 0u32;
+    for _ in 0..4 {
+        addr = addr << 8 | octets.next()?.parse::<u8>().ok()? as u32;
+    }
+    octets.next().is_none().then_some(addr)
+}
+
+/// Shows an address as `a.b.c.d`.
+pub struct Dotted(pub u32);
+
+impl fmt::Display for Dotted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [a, b, c, d] = self.0.to_be_bytes();
+        write!(f, "{}.{}.{}.{}", a, b, c, d)
+    }
+}
@@ -1,117 +1,233 @@
-pub mod port_table;
+mod arp;
+mod dhcp;
+pub mod icmp;
+pub mod iface;
+mod ipv4;
+pub mod pcap;
 pub mod socket;
+mod tcb;
 pub mod tcp;
 pub mod udp;
+pub mod unix;
 
+pub use icmp::IcmpSocket;
 pub use lose_net_stack::IPv4;
+pub use socket::{
+    Blocking,
+    InetAddr,
+    Socket,
+    SocketOptions,
+};
+pub use tcp::TcpSocket;
+pub use udp::UdpSocket;
+pub use unix::UnixSocket;
 
 use alloc::{
     sync::Arc,
     vec,
 };
-use lose_net_stack::{
-    results::Packet,
-    LoseStack,
-    MacAddress,
-    TcpFlags,
+use core::sync::atomic::{
+    AtomicBool,
+    Ordering,
+};
+use log::{
+    info,
+    warn,
 };
+use lose_net_stack::results::Packet;
+use shared_defination::error::ENETUNREACH;
 
+use self::{
+    iface::{
+        interface,
+        interfaces,
+        route,
+        set_default_gateway,
+        NetInterface,
+        Route,
+    },
+    ipv4::{
+        parse_addr,
+        Dotted,
+        Ipv4Packet,
+    },
+};
 use crate::{
-    drivers::NET_DEVICE,
-    net::socket::{
-        get_socket,
-        push_data,
+    boot_args::boot_arg,
+    sync::{
+        intr_free,
+        WaitQueue,
     },
-    sync::UPIntrFreeCell,
 };
 
-use self::{
-    port_table::check_accept,
-    socket::set_s_a_by_index,
-};
+const ETH_HEADER_LEN: usize = 14;
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+const IPPROTO_ICMP: u8 = 1;
+const IPPROTO_UDP: u8 = 17;
 
-pub struct NetStack(UPIntrFreeCell<LoseStack>);
+/// Set while the bottom half runs, so an interrupt does not start it again.
+static BOTTOM_HALF_RUNNING: AtomicBool = AtomicBool::new(false);
 
-impl NetStack {
-    pub fn new() -> Self {
-        unsafe {
-            NetStack(UPIntrFreeCell::new(LoseStack::new(
-                IPv4::new(10, 0, 2, 15),
-                MacAddress::new([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]),
-            )))
-        }
-    }
+/// An IP packet for `dest` leaves through its route, `ENETUNREACH` without
+/// one.
+fn route_to(dest: u32) -> Result<Route, isize> {
+    route(dest).ok_or(-(ENETUNREACH as isize))
 }
 
-lazy_static::lazy_static! {
-    static ref LOSE_NET_STACK: Arc<NetStack> = Arc::new(NetStack::new());
-}
+fn net_receive(iface: &Arc<NetInterface>) {
+    // room for a full ethernet frame
+    let mut recv_buf = vec![0u8; 2048];
 
-pub fn net_interrupt_handler() {
-    let mut recv_buf = vec![0u8; 1024];
+    let len = iface.receive(&mut recv_buf);
+    let frame = &recv_buf[..len];
 
-    let len = NET_DEVICE.receive(&mut recv_buf);
+    // println!("[kernel] receive a packet");
+    // hexdump(frame);
 
-    let packet = LOSE_NET_STACK
-        .0
-        .exclusive_access()
-        .analysis(&recv_buf[..len]);
+    if frame.len() < ETH_HEADER_LEN {
+        return;
+    }
+    // ARP, ICMP and DHCP are ours, the stack parses UDP and TCP
+    match u16::from_be_bytes([frame[12], frame[13]]) {
+        ETHERTYPE_ARP => arp::arp_input(iface, frame),
+        ETHERTYPE_IPV4 => match Ipv4Packet::parse(frame) {
+            Some(packet) if packet.protocol == IPPROTO_ICMP => icmp::icmp_input(&packet),
+            Some(packet) if dhcp::is_dhcp(&packet) => dhcp::dhcp_input(iface, &packet),
+            Some(_) => match iface.analysis(frame) {
+                Packet::UDP(udp_packet) => udp::udp_input(&udp_packet),
+                Packet::TCP(tcp_packet) => tcp::tcp_input(&tcp_packet),
+                _ => {}
+            },
+            None => {}
+        },
+        _ => {}
+    }
+}
 
-    // println!("[kernel] receive a packet");
-    // hexdump(&recv_buf[..len]);
-
-    match packet {
-        Packet::ARP(arp_packet) => {
-            let lose_stack = LOSE_NET_STACK.0.exclusive_access();
-            let reply_packet = arp_packet
-                .reply_packet(lose_stack.ip, lose_stack.mac)
-                .expect("can't build reply");
-            let reply_data = reply_packet.build_data();
-            NET_DEVICE.transmit(&reply_data)
+/// `ip=<client>:<server>:<gateway>:<netmask>`, the rest of Linux's fields
+/// are ignored. Empty ones are left out.
+fn parse_static_config(config: &str) -> Option<(u32, Option<u32>, u32)> {
+    let mut fields = config.split(':');
+    let ip = parse_addr(fields.next()?)?;
+    let _server = fields.next();
+    let gateway = match fields.next() {
+        None | Some("") => None,
+        Some(gateway) => Some(parse_addr(gateway)?),
+    };
+    let netmask = match fields.next() {
+        None | Some("") => 0xffff_ff00,
+        Some(netmask) => parse_addr(netmask)?,
+    };
+    Some((ip, gateway, netmask))
+}
+
+/// Configure eth0 as the `ip=` boot argument says: `dhcp`, the default,
+/// `off`, or a static address. `pcap=on` captures from boot on, DHCP
+/// included.
+pub fn init() {
+    if boot_arg("pcap").as_deref() == Some("on") {
+        pcap::set_capturing(true);
+    }
+    let eth0 = match interface("eth0") {
+        Some(eth0) => eth0,
+        None => {
+            info!("KERN: no network card, only loopback");
+            return;
         }
+    };
+    let config = boot_arg("ip");
+    match config.as_deref() {
+        None | Some("dhcp" | "on" | "any") => dhcp::start(eth0),
+        Some("off" | "none") => info!("KERN: eth0 left unconfigured"),
+        Some(config) => match parse_static_config(config) {
+            Some((ip, gateway, netmask)) => {
+                eth0.set_ip(IPv4::from_u32(ip));
+                eth0.set_netmask(netmask);
+                set_default_gateway(eth0, gateway);
+                info!("KERN: eth0 {} netmask {}", Dotted(ip), Dotted(netmask));
+            }
+            None => {
+                warn!("KERN: bad ip={}, using DHCP", config);
+                dhcp::start(eth0);
+            }
+        },
+    }
+}
 
-        Packet::UDP(udp_packet) => {
-            let target = udp_packet.source_ip;
-            let lport = udp_packet.dest_port;
-            let rport = udp_packet.source_port;
+/// An address set by hand, which DHCP does not touch from then on.
+pub fn set_address(iface: &Arc<NetInterface>, ip: u32) {
+    dhcp::stop(iface);
+    iface.set_ip(IPv4::from_u32(ip));
+}
 
-            if let Some(socket_index) = get_socket(target, lport, rport) {
-                push_data(socket_index, udp_packet.data.to_vec());
+/// Handle the frames every interface has, until none are left. Run after
+/// the PLIC got its completion, so other devices are not held up, and
+/// after sending, for what loopback got. Sockets wake their readers
+/// themselves.
+pub fn net_bottom_half() {
+    // an interrupted run picks up the frames itself
+    while !BOTTOM_HALF_RUNNING.swap(true, Ordering::Acquire) {
+        loop {
+            let mut received = false;
+            for iface in interfaces() {
+                while iface.can_recv() {
+                    net_receive(iface);
+                    received = true;
+                }
             }
+            if !received {
+                break;
+            }
+        }
+        BOTTOM_HALF_RUNNING.store(false, Ordering::Release);
+        // a frame that came after the last check, whose interrupt saw the
+        // flag still set, would sit there until the next one
+        if !interfaces().iter().any(|iface| iface.can_recv()) {
+            break;
         }
+    }
+}
 
-        Packet::TCP(tcp_packet) => {
-            let target = tcp_packet.source_ip;
-            let lport = tcp_packet.dest_port;
-            let rport = tcp_packet.source_port;
-            let flags = tcp_packet.flags;
-
-            if flags.contains(TcpFlags::S) {
-                // if it has a port to accept, then response the request
-                if check_accept(lport, &tcp_packet).is_some() {
-                    let mut reply_packet = tcp_packet.ack();
-                    reply_packet.flags = TcpFlags::S | TcpFlags::A;
-                    NET_DEVICE.transmit(&reply_packet.build_data());
-                }
-                return;
-            } else if tcp_packet.flags.contains(TcpFlags::F) {
-                // tcp disconnected
-                let reply_packet = tcp_packet.ack();
-                NET_DEVICE.transmit(&reply_packet.build_data());
-
-                let mut end_packet = reply_packet.ack();
-                end_packet.flags |= TcpFlags::F;
-                NET_DEVICE.transmit(&end_packet.build_data());
-            } else if tcp_packet.flags.contains(TcpFlags::A) && tcp_packet.data_len == 0 {
-                return;
-            }
+/// Called on every timer interrupt for the ARP, DHCP and TCP timers.
+pub fn net_tick() {
+    net_bottom_half();
+    arp::arp_tick();
+    dhcp::dhcp_tick();
+    tcp::tcp_tick();
+}
 
-            if let Some(socket_index) = get_socket(target, lport, rport) {
-                push_data(socket_index, tcp_packet.data.to_vec());
-                set_s_a_by_index(socket_index, tcp_packet.seq, tcp_packet.ack);
-            }
+/// When the earliest of those timers is due, in milliseconds since boot.
+pub fn next_timeout_ms() -> Option<usize> {
+    [
+        arp::next_timeout(),
+        dhcp::next_timeout(),
+        tcp::next_timeout(),
+    ]
+    .into_iter()
+    .flatten()
+    .min()
+}
+
+/// Sleep on `wait_queue` until `ready` has something, for as long as
+/// `blocking` allows. Whoever changes what `ready` looks at wakes the queue.
+pub fn net_block_on<T>(
+    wait_queue: &WaitQueue, blocking: Blocking, mut ready: impl FnMut() -> Option<T>,
+) -> Result<T, isize> {
+    loop {
+        // the caller may just have sent something to loopback
+        net_bottom_half();
+        // masked, so the bottom half can not wake us between the check and
+        // going to sleep
+        let waited = intr_free(|| match ready() {
+            Some(value) => Ok(value),
+            None => Err(blocking.wait_no_sched(wait_queue)),
+        });
+        match waited {
+            Ok(value) => return Ok(value),
+            Err(Ok(task_ctx_ptr)) => blocking.schedule(task_ctx_ptr, wait_queue),
+            Err(Err(err)) => return Err(err),
         }
-        _ => {}
     }
 }
 
@@ -0,0 +1,93 @@
+//! Packet capture in libpcap format. Every frame an interface sends or
+//! receives is recorded while capturing is on, `/dev/pcap` reads the
+//! capture back as a file Wireshark opens.
+
+use alloc::{
+    collections::VecDeque,
+    vec::Vec,
+};
+use lazy_static::lazy_static;
+
+use crate::{
+    config::CLOCK_FREQ,
+    sync::UPIntrFreeCell,
+    timer::get_time,
+};
+
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+const LINKTYPE_ETHERNET: u32 = 1;
+const SNAPLEN: usize = 65535;
+/// The oldest records are dropped once the capture grows past this.
+const CAPTURE_LIMIT: usize = 512 * 1024;
+
+struct Capture {
+    enabled: bool,
+    /// Records with their headers, oldest first.
+    records: VecDeque<Vec<u8>>,
+    size: usize,
+}
+
+lazy_static! {
+    static ref CAPTURE: UPIntrFreeCell<Capture> = unsafe {
+        UPIntrFreeCell::new(Capture {
+            enabled: false,
+            records: VecDeque::new(),
+            size: 0,
+        })
+    };
+}
+
+pub fn capturing() -> bool {
+    CAPTURE.exclusive_access().enabled
+}
+
+/// Starting a capture throws away the previous one.
+pub fn set_capturing(enabled: bool) {
+    let mut capture = CAPTURE.exclusive_access();
+    if enabled && !capture.enabled {
+        capture.records.clear();
+        capture.size = 0;
+    }
+    capture.enabled = enabled;
+}
+
+/// Record `frame` if capturing.
+pub fn capture(frame: &[u8]) {
+    let mut capture = CAPTURE.exclusive_access();
+    if !capture.enabled {
+        return;
+    }
+    let time = get_time();
+    let sec = time / CLOCK_FREQ;
+    let usec = time % CLOCK_FREQ * 1_000_000 / CLOCK_FREQ;
+    let captured = &frame[..frame.len().min(SNAPLEN)];
+    let mut record = Vec::with_capacity(16 + captured.len());
+    for field in [sec, usec, captured.len(), frame.len()] {
+        record.extend_from_slice(&(field as u32).to_le_bytes());
+    }
+    record.extend_from_slice(captured);
+    capture.size += record.len();
+    capture.records.push_back(record);
+    while capture.size > CAPTURE_LIMIT {
+        let oldest = capture.records.pop_front().unwrap();
+        capture.size -= oldest.len();
+    }
+}
+
+/// The file header followed by what has been captured so far.
+pub fn snapshot() -> Vec<u8> {
+    let capture = CAPTURE.exclusive_access();
+    let mut data = Vec::with_capacity(24 + capture.size);
+    data.extend_from_slice(&PCAP_MAGIC.to_le_bytes());
+    // version 2.4
+    data.extend_from_slice(&2u16.to_le_bytes());
+    data.extend_from_slice(&4u16.to_le_bytes());
+    // no time zone offset, no accuracy given
+    data.extend_from_slice(&[0; 8]);
+    data.extend_from_slice(&(SNAPLEN as u32).to_le_bytes());
+    data.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+    for record in capture.records.iter() {
+        data.extend_from_slice(record);
+    }
+    data
+}
@@ -1,125 +1,189 @@
 use alloc::{
-    collections::VecDeque,
+    sync::Arc,
     vec::Vec,
 };
 use lazy_static::lazy_static;
-use lose_net_stack::IPv4;
-
-use crate::sync::UPIntrFreeCell;
-
-// TODO: specify the protocol, TCP or UDP
-pub struct Socket {
-    pub raddr: IPv4,                // remote address
-    pub lport: u16,                 // local port
-    pub rport: u16,                 // rempote port
-    pub buffers: VecDeque<Vec<u8>>, // datas
-    pub seq: u32,
-    pub ack: u32,
-}
+use shared_defination::error::{
+    EAGAIN,
+    ENOPROTOOPT,
+    EOPNOTSUPP,
+};
 
-lazy_static! {
-    static ref SOCKET_TABLE: UPIntrFreeCell<Vec<Option<Socket>>> =
-        unsafe { UPIntrFreeCell::new(Vec::new()) };
-}
+use crate::{
+    fs::File,
+    mm::UserBuffer,
+    sync::{
+        UPIntrFreeCell,
+        WaitQueue,
+    },
+    task::{
+        current_task,
+        schedule,
+        TaskContext,
+    },
+    timer::{
+        add_timer,
+        get_time_ms,
+        remove_timer,
+    },
+};
 
-/// get the seq and ack by socket index
-pub fn get_s_a_by_index(index: usize) -> Option<(u32, u32)> {
-    let socket_table = SOCKET_TABLE.exclusive_access();
+pub const SHUT_RD: usize = 0;
+pub const SHUT_WR: usize = 1;
+pub const SHUT_RDWR: usize = 2;
 
-    assert!(index < socket_table.len());
+/// An IPv4 endpoint in host byte order, `sockaddr_in` is converted at the
+/// syscall boundary.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct InetAddr {
+    pub ip: u32,
+    pub port: u16,
+}
 
-    socket_table.get(index).map_or(None, |x| match x {
-        Some(x) => Some((x.seq, x.ack)),
-        None => None,
-    })
+impl InetAddr {
+    pub const fn new(ip: u32, port: u16) -> Self {
+        Self { ip, port }
+    }
 }
 
-pub fn set_s_a_by_index(index: usize, seq: u32, ack: u32) {
-    let mut socket_table = SOCKET_TABLE.exclusive_access();
+/// What `fcntl` and `setsockopt` set on a socket.
+#[derive(Clone, Copy, Default)]
+pub struct SocketOptions {
+    pub nonblock: bool,
+    /// `SO_RCVTIMEO` and `SO_SNDTIMEO` in ms, 0 waits forever.
+    pub recv_timeout: usize,
+    pub send_timeout: usize,
+    pub reuse_addr: bool,
+    pub no_delay: bool,
+}
+
+impl SocketOptions {
+    fn blocking(&self, timeout: usize) -> Blocking {
+        match (self.nonblock, timeout) {
+            (true, _) => Blocking::Never,
+            (false, 0) => Blocking::Forever,
+            (false, timeout) => Blocking::Until(get_time_ms().saturating_add(timeout)),
+        }
+    }
 
-    assert!(socket_table.len() > index);
-    assert!(socket_table[index].is_some());
+    /// How long a receive, or `accept`, may sleep from now on.
+    pub fn recv_blocking(&self) -> Blocking {
+        self.blocking(self.recv_timeout)
+    }
 
-    let sock = socket_table[index].as_mut().unwrap();
+    /// How long a send, or `connect`, may sleep from now on.
+    pub fn send_blocking(&self) -> Blocking {
+        self.blocking(self.send_timeout)
+    }
+}
 
-    sock.ack = ack;
-    sock.seq = seq;
+/// How long a socket call may sleep.
+#[derive(Clone, Copy)]
+pub enum Blocking {
+    /// `O_NONBLOCK`, fail with `EAGAIN` instead.
+    Never,
+    /// Until a deadline in ms, `EAGAIN` after it.
+    Until(usize),
+    Forever,
 }
 
-pub fn get_socket(raddr: IPv4, lport: u16, rport: u16) -> Option<usize> {
-    let socket_table = SOCKET_TABLE.exclusive_access();
-    for i in 0..socket_table.len() {
-        let sock = &socket_table[i];
-        if sock.is_none() {
-            continue;
+impl Blocking {
+    /// `WaitQueue::wait_no_sched`, unless we may not sleep (any more).
+    pub fn wait_no_sched(self, wait_queue: &WaitQueue) -> Result<*mut TaskContext, isize> {
+        match self {
+            Blocking::Never => Err(-(EAGAIN as isize)),
+            Blocking::Until(deadline) if deadline <= get_time_ms() => Err(-(EAGAIN as isize)),
+            Blocking::Until(deadline) => {
+                add_timer(deadline, current_task().unwrap());
+                Ok(wait_queue.wait_no_sched())
+            }
+            Blocking::Forever => Ok(wait_queue.wait_no_sched()),
         }
+    }
 
-        let sock = sock.as_ref().unwrap();
-        if sock.raddr == raddr && sock.lport == lport && sock.rport == rport {
-            return Some(i);
+    /// Sleep after `wait_no_sched`. Whichever of the timer and `wait_queue`
+    /// did not wake us is cleared, it would cut a later sleep short.
+    pub fn schedule(self, task_ctx_ptr: *mut TaskContext, wait_queue: &WaitQueue) {
+        schedule(task_ctx_ptr);
+        if let Blocking::Until(_) = self {
+            let task = current_task().unwrap();
+            remove_timer(&task);
+            wait_queue.unregister(&task);
         }
     }
-    None
 }
 
-pub fn add_socket(raddr: IPv4, lport: u16, rport: u16) -> Option<usize> {
-    if get_socket(raddr, lport, rport).is_some() {
-        return None;
+/// What a socket fd can do beyond `read` and `write`. Errors are negative
+/// errno values, operations a socket type lacks answer `EOPNOTSUPP`.
+pub trait Socket {
+    fn bind(&self, _addr: InetAddr) -> isize {
+        -(EOPNOTSUPP as isize)
     }
-
-    let mut socket_table = SOCKET_TABLE.exclusive_access();
-    let mut index = usize::MAX;
-    for i in 0..socket_table.len() {
-        if socket_table[i].is_none() {
-            index = i;
-            break;
-        }
+    fn listen(&self, _backlog: usize) -> isize {
+        -(EOPNOTSUPP as isize)
     }
-
-    let socket = Socket {
-        raddr,
-        lport,
-        rport,
-        buffers: VecDeque::new(),
-        seq: 0,
-        ack: 0,
-    };
-
-    if index == usize::MAX {
-        socket_table.push(Some(socket));
-        Some(socket_table.len() - 1)
-    } else {
-        socket_table[index] = Some(socket);
-        Some(index)
+    /// Wait for a connection, returning it with the peer address.
+    fn accept(&self) -> Result<(Arc<dyn File + Send + Sync>, InetAddr), isize> {
+        Err(-(EOPNOTSUPP as isize))
+    }
+    fn connect(&self, _addr: InetAddr) -> isize {
+        -(EOPNOTSUPP as isize)
+    }
+    /// `None` sends to the connected peer.
+    fn sendto(&self, buf: UserBuffer, addr: Option<InetAddr>) -> isize;
+    /// The source address is only known for datagrams.
+    fn recvfrom(&self, buf: UserBuffer) -> (isize, Option<InetAddr>);
+    fn shutdown(&self, _how: usize) -> isize {
+        -(EOPNOTSUPP as isize)
+    }
+    /// The local address, `0.0.0.0:0` while unbound.
+    fn sockname(&self) -> InetAddr;
+    fn peername(&self) -> Option<InetAddr>;
+    fn options(&self) -> &UPIntrFreeCell<SocketOptions>;
+    /// `TCP_NODELAY`, `None` for sockets other than TCP.
+    fn no_delay(&self) -> Option<bool> {
+        None
+    }
+    fn set_no_delay(&self, _no_delay: bool) -> isize {
+        -(ENOPROTOOPT as isize)
     }
 }
 
-pub fn remove_socket(index: usize) {
-    let mut socket_table = SOCKET_TABLE.exclusive_access();
-
-    assert!(socket_table.len() > index);
-
-    socket_table[index] = None;
+lazy_static! {
+    static ref NEXT_EPHEMERAL_PORT: UPIntrFreeCell<u16> = unsafe { UPIntrFreeCell::new(49152) };
 }
 
-pub fn push_data(index: usize, data: Vec<u8>) {
-    let mut socket_table = SOCKET_TABLE.exclusive_access();
-
-    assert!(socket_table.len() > index);
-    assert!(socket_table[index].is_some());
-
-    socket_table[index]
-        .as_mut()
-        .unwrap()
-        .buffers
-        .push_back(data);
+/// A free port from the IANA dynamic range for an unbound socket.
+pub fn alloc_ephemeral_port(in_use: impl Fn(u16) -> bool) -> Option<u16> {
+    let mut next = NEXT_EPHEMERAL_PORT.exclusive_access();
+    for _ in 49152..=u16::MAX {
+        let port = *next;
+        *next = if port == u16::MAX { 49152 } else { port + 1 };
+        if !in_use(port) {
+            return Some(port);
+        }
+    }
+    None
 }
 
-pub fn pop_data(index: usize) -> Option<Vec<u8>> {
-    let mut socket_table = SOCKET_TABLE.exclusive_access();
-
-    assert!(socket_table.len() > index);
-    assert!(socket_table[index].is_some());
+pub fn copy_from_user(buf: &UserBuffer) -> Vec<u8> {
+    let mut data = Vec::with_capacity(buf.len());
+    for buffer in buf.buffers.iter() {
+        data.extend_from_slice(buffer);
+    }
+    data
+}
 
-    socket_table[index].as_mut().unwrap().buffers.pop_front()
+/// Returns how many bytes fit.
+pub fn copy_to_user(buf: UserBuffer, data: &[u8]) -> usize {
+    let mut copied = 0;
+    for buffer in buf.buffers {
+        let len = buffer.len().min(data.len() - copied);
+        buffer[..len].copy_from_slice(&data[copied..copied + len]);
+        copied += len;
+        if copied == data.len() {
+            break;
+        }
+    }
+    copied
 }
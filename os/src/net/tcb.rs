@@ -0,0 +1,756 @@
+use alloc::{
+    collections::{
+        BTreeMap,
+        VecDeque,
+    },
+    sync::{
+        Arc,
+        Weak,
+    },
+    vec::Vec,
+};
+use lose_net_stack::{
+    packets::tcp::TCPPacket,
+    IPv4,
+    MacAddress,
+    TcpFlags,
+};
+use shared_defination::error::{
+    ECONNREFUSED,
+    ECONNRESET,
+    EPIPE,
+    ETIMEDOUT,
+};
+
+use crate::{
+    fs::PollEvents,
+    sync::{
+        UPIntrFreeCell,
+        WaitQueue,
+    },
+    timer::{
+        get_time,
+        get_time_ms,
+    },
+};
+
+use super::{
+    route_to,
+    socket::InetAddr,
+    tcp::{
+        forget_connection,
+        TcpListener,
+    },
+};
+
+/// Largest segment payload that fits an ethernet frame. TCP options are not
+/// parsed, so the peer is assumed to take as much.
+const TCP_MSS: usize = 1460;
+/// Received bytes kept for the reader, advertised as our window.
+const TCP_RX_BUFFER: usize = 65535;
+/// Bytes written but not acknowledged yet.
+const TCP_TX_BUFFER: usize = 65536;
+/// RFC 6298 initial timeout. There is no RTT sampling, it only backs off.
+const TCP_RTO_INIT_MS: usize = 1000;
+const TCP_RTO_MAX_MS: usize = 60_000;
+/// Retransmissions before the connection is given up with `ETIMEDOUT`.
+const TCP_MAX_RETRIES: usize = 8;
+/// Retransmissions of our SYN before `connect` gives up, about 63 seconds.
+const TCP_SYN_RETRIES: usize = 5;
+/// 2 MSL, also how long an orphaned `FIN_WAIT_2` waits for the peer's FIN.
+const TCP_TIME_WAIT_MS: usize = 60_000;
+
+fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+fn seq_le(a: u32, b: u32) -> bool {
+    !seq_lt(b, a)
+}
+
+/// Segments without a route are dropped, retransmission gives up on them.
+pub fn send_segment(
+    local: InetAddr, peer: InetAddr, seq: u32, ack: u32, flags: TcpFlags, win: u16, data: &[u8],
+) {
+    let route = match route_to(peer.ip) {
+        Ok(route) => route,
+        Err(_) => return,
+    };
+    let tcp_packet = TCPPacket {
+        source_ip: IPv4::from_u32(local.ip),
+        source_mac: route.iface.mac(),
+        source_port: local.port,
+        dest_ip: IPv4::from_u32(peer.ip),
+        // filled in by `Route::transmit`
+        dest_mac: MacAddress::new([0; 6]),
+        dest_port: peer.port,
+        data_len: data.len(),
+        seq,
+        ack,
+        flags,
+        win,
+        urg: 0,
+        data,
+    };
+    route.transmit(tcp_packet.build_data());
+}
+
+/// RFC 793 connection states, `LISTEN` is a `TcpListener` instead.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum TcpState {
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+    Closed,
+}
+
+struct TcbInner {
+    state: TcpState,
+    local: InetAddr,
+    peer: InetAddr,
+    /// Told when a passive open becomes established, `None` for an active
+    /// open.
+    listener: Option<Weak<TcpListener>>,
+    iss: u32,
+    snd_una: u32,
+    snd_nxt: u32,
+    snd_wnd: u32,
+    /// Bytes from `snd_una` on, the first `sent` of them are in flight.
+    tx: VecDeque<u8>,
+    sent: usize,
+    /// A FIN goes out once `tx` is sent.
+    fin_queued: bool,
+    rcv_nxt: u32,
+    rx: VecDeque<u8>,
+    /// Segments past a hole, by sequence number.
+    out_of_order: BTreeMap<u32, Vec<u8>>,
+    read_shut: bool,
+    /// Retransmission or `TIME_WAIT` deadline in ms.
+    timer: Option<usize>,
+    rto_ms: usize,
+    retries: usize,
+    /// The fd is closed, nobody will read or write any more.
+    orphan: bool,
+    error: Option<usize>,
+    /// `TCP_NODELAY`, turns off Nagle's algorithm.
+    no_delay: bool,
+}
+
+/// A TCP control block. The connection table owns it, so it outlives its
+/// fd until the close handshake is done.
+pub struct Tcb {
+    me: Weak<Tcb>,
+    inner: UPIntrFreeCell<TcbInner>,
+    /// Shared with the socket, woken whenever a segment arrives or the
+    /// connection goes away.
+    wait_queue: Arc<WaitQueue>,
+}
+
+impl TcbInner {
+    fn rcv_wnd(&self) -> u16 {
+        (TCP_RX_BUFFER - self.rx.len()).min(u16::MAX as usize) as u16
+    }
+
+    fn transmit(&self, seq: u32, flags: TcpFlags, data: &[u8]) {
+        send_segment(
+            self.local,
+            self.peer,
+            seq,
+            self.rcv_nxt,
+            flags,
+            self.rcv_wnd(),
+            data,
+        );
+    }
+
+    fn send_ack(&self) {
+        self.transmit(self.snd_nxt, TcpFlags::A, &[]);
+    }
+
+    fn fin_sent(&self) -> bool {
+        matches!(
+            self.state,
+            TcpState::FinWait1
+                | TcpState::FinWait2
+                | TcpState::Closing
+                | TcpState::LastAck
+                | TcpState::TimeWait
+        )
+    }
+
+    /// The peer's FIN has arrived.
+    fn fin_received(&self) -> bool {
+        matches!(
+            self.state,
+            TcpState::CloseWait | TcpState::Closing | TcpState::LastAck | TcpState::TimeWait
+        )
+    }
+
+    /// RFC 793 segment acceptability test against the receive window.
+    fn acceptable(&self, seq: u32, seg_len: u32) -> bool {
+        let wnd = self.rcv_wnd() as u32;
+        let in_window =
+            |s: u32| seq_le(self.rcv_nxt, s) && seq_lt(s, self.rcv_nxt.wrapping_add(wnd));
+        match (seg_len, wnd) {
+            (0, 0) => seq == self.rcv_nxt,
+            (0, _) => in_window(seq),
+            (_, 0) => false,
+            _ => in_window(seq) || in_window(seq.wrapping_add(seg_len - 1)),
+        }
+    }
+
+    /// Anything that has to be retransmitted if it is not acknowledged, or a
+    /// zero window to probe.
+    fn outstanding(&self) -> bool {
+        self.snd_una != self.snd_nxt || (self.sent < self.tx.len() && self.snd_wnd == 0)
+    }
+
+    fn arm_timer(&mut self) {
+        match self.state {
+            TcpState::TimeWait | TcpState::Closed => {}
+            TcpState::FinWait2 if self.orphan => {}
+            _ if self.outstanding() => {
+                if self.timer.is_none() {
+                    self.timer = Some(get_time_ms() + self.rto_ms);
+                }
+            }
+            _ => self.timer = None,
+        }
+    }
+
+    /// Send what the peer's window allows, then the FIN once nothing is left.
+    fn output(&mut self) {
+        if matches!(self.state, TcpState::Established | TcpState::CloseWait) {
+            loop {
+                let window = (self.snd_wnd as usize).saturating_sub(self.sent);
+                let len = (self.tx.len() - self.sent).min(window).min(TCP_MSS);
+                if len == 0 {
+                    break;
+                }
+                // Nagle: a small segment waits while anything is unacknowledged
+                if len < TCP_MSS && self.sent > 0 && !self.no_delay {
+                    break;
+                }
+                let data: Vec<u8> = self.tx.range(self.sent..self.sent + len).copied().collect();
+                self.transmit(self.snd_nxt, TcpFlags::A | TcpFlags::P, &data);
+                self.snd_nxt = self.snd_nxt.wrapping_add(len as u32);
+                self.sent += len;
+            }
+            if self.fin_queued && self.sent == self.tx.len() {
+                self.transmit(self.snd_nxt, TcpFlags::F | TcpFlags::A, &[]);
+                self.snd_nxt = self.snd_nxt.wrapping_add(1);
+                self.state = match self.state {
+                    TcpState::Established => TcpState::FinWait1,
+                    _ => TcpState::LastAck,
+                };
+            }
+        }
+        self.arm_timer();
+    }
+
+    /// Resend the oldest unacknowledged thing.
+    fn retransmit(&mut self) {
+        if self.state == TcpState::SynSent {
+            self.transmit(self.iss, TcpFlags::S, &[]);
+        } else if self.state == TcpState::SynReceived {
+            self.transmit(self.iss, TcpFlags::S | TcpFlags::A, &[]);
+        } else if self.sent > 0 {
+            let len = self.sent.min(TCP_MSS);
+            let data: Vec<u8> = self.tx.range(..len).copied().collect();
+            self.transmit(self.snd_una, TcpFlags::A | TcpFlags::P, &data);
+        } else if self.snd_una != self.snd_nxt {
+            self.transmit(self.snd_una, TcpFlags::F | TcpFlags::A, &[]);
+        } else if !self.tx.is_empty() {
+            // zero window probe, one byte past the window
+            let byte = [self.tx[0]];
+            self.transmit(self.snd_nxt, TcpFlags::A | TcpFlags::P, &byte);
+            self.snd_nxt = self.snd_nxt.wrapping_add(1);
+            self.sent = 1;
+        }
+    }
+
+    fn deliver(&mut self, data: &[u8]) {
+        if !self.read_shut {
+            self.rx.extend(data.iter());
+        }
+        self.rcv_nxt = self.rcv_nxt.wrapping_add(data.len() as u32);
+    }
+
+    /// Take in data, holding segments after a hole until it is filled.
+    fn receive(&mut self, seq: u32, data: &[u8]) {
+        let room = self.rcv_wnd() as usize;
+        let ahead = seq.wrapping_sub(self.rcv_nxt) as i32;
+        if ahead > 0 {
+            let ahead = ahead as usize;
+            if ahead < room {
+                let len = data.len().min(room - ahead);
+                self.out_of_order.insert(seq, data[..len].to_vec());
+            }
+            return;
+        }
+        // a retransmission may overlap what we have
+        let skip = (-ahead) as usize;
+        if skip >= data.len() {
+            return;
+        }
+        let data = &data[skip..];
+        self.deliver(&data[..data.len().min(room)]);
+        while let Some(seq) = self
+            .out_of_order
+            .keys()
+            .copied()
+            .find(|seq| seq_le(*seq, self.rcv_nxt))
+        {
+            let data = self.out_of_order.remove(&seq).unwrap();
+            let skip = self.rcv_nxt.wrapping_sub(seq) as usize;
+            if skip < data.len() {
+                let room = self.rcv_wnd() as usize;
+                let data = &data[skip..];
+                self.deliver(&data[..data.len().min(room)]);
+            }
+        }
+    }
+}
+
+impl Tcb {
+    fn new(
+        state: TcpState, local: InetAddr, peer: InetAddr, listener: Option<Weak<TcpListener>>,
+        rcv_nxt: u32, snd_wnd: u32, wait_queue: Arc<WaitQueue>,
+    ) -> Arc<Self> {
+        // clock driven, as RFC 793 suggests
+        let iss = get_time() as u32;
+        let tcb = Arc::new_cyclic(|me| Self {
+            me: me.clone(),
+            inner: unsafe {
+                UPIntrFreeCell::new(TcbInner {
+                    state,
+                    local,
+                    peer,
+                    listener,
+                    iss,
+                    snd_una: iss,
+                    snd_nxt: iss.wrapping_add(1),
+                    snd_wnd,
+                    tx: VecDeque::new(),
+                    sent: 0,
+                    fin_queued: false,
+                    rcv_nxt,
+                    rx: VecDeque::new(),
+                    out_of_order: BTreeMap::new(),
+                    read_shut: false,
+                    timer: None,
+                    rto_ms: TCP_RTO_INIT_MS,
+                    retries: 0,
+                    orphan: false,
+                    error: None,
+                    no_delay: false,
+                })
+            },
+            wait_queue,
+        });
+        let mut inner = tcb.inner.exclusive_access();
+        inner.retransmit();
+        inner.arm_timer();
+        drop(inner);
+        tcb
+    }
+
+    /// Answer a SYN to a listening port.
+    pub fn passive_open(
+        local: InetAddr, peer: InetAddr, irs: u32, win: u16, listener: Weak<TcpListener>,
+    ) -> Arc<Self> {
+        Self::new(
+            TcpState::SynReceived,
+            local,
+            peer,
+            Some(listener),
+            irs.wrapping_add(1),
+            win as u32,
+            Arc::new(WaitQueue::new()),
+        )
+    }
+
+    /// Send a SYN to `peer`, see `connected` for the outcome.
+    pub fn active_open(local: InetAddr, peer: InetAddr, wait_queue: Arc<WaitQueue>) -> Arc<Self> {
+        Self::new(TcpState::SynSent, local, peer, None, 0, 0, wait_queue)
+    }
+
+    /// `None` while the handshake is going on.
+    pub fn connected(&self) -> Option<Result<(), isize>> {
+        let inner = self.inner.exclusive_access();
+        match inner.state {
+            TcpState::SynSent | TcpState::SynReceived => None,
+            _ => match inner.error {
+                Some(error) => Some(Err(-(error as isize))),
+                None => Some(Ok(())),
+            },
+        }
+    }
+
+    pub fn local(&self) -> InetAddr {
+        self.inner.exclusive_access().local
+    }
+
+    /// Turning Nagle off sends what it held back.
+    pub fn set_no_delay(&self, no_delay: bool) {
+        let mut inner = self.inner.exclusive_access();
+        inner.no_delay = no_delay;
+        inner.output();
+    }
+
+    pub fn peer(&self) -> InetAddr {
+        self.inner.exclusive_access().peer
+    }
+
+    pub fn wait_queue(&self) -> &Arc<WaitQueue> {
+        &self.wait_queue
+    }
+
+    /// Leave the connection table. A half-open connection gives its place in
+    /// the listener's backlog back.
+    fn close_with(&self, inner: &mut TcbInner, error: Option<usize>) {
+        if inner.state == TcpState::SynReceived {
+            if let Some(listener) = inner.listener.as_ref().and_then(Weak::upgrade) {
+                listener.half_open_done(None);
+            }
+        }
+        inner.state = TcpState::Closed;
+        inner.error = error;
+        inner.timer = None;
+        inner.tx.clear();
+        inner.sent = 0;
+        inner.out_of_order.clear();
+        forget_connection(inner.local.port, inner.peer, &self.me);
+        self.wait_queue.wake_all();
+    }
+
+    /// Tear the connection down with a RST.
+    pub fn abort(&self) {
+        let mut inner = self.inner.exclusive_access();
+        if inner.state != TcpState::Closed {
+            inner.transmit(inner.snd_nxt, TcpFlags::R, &[]);
+            self.close_with(&mut inner, Some(ECONNRESET));
+        }
+    }
+
+    /// RFC 793 processing of a segment in `SYN_SENT`.
+    fn syn_sent_segment(&self, inner: &mut TcbInner, packet: &TCPPacket) {
+        let flags = packet.flags;
+        let ack = packet.ack;
+        let ack_ok =
+            flags.contains(TcpFlags::A) && seq_lt(inner.iss, ack) && seq_le(ack, inner.snd_nxt);
+        if flags.contains(TcpFlags::A) && !ack_ok {
+            if !flags.contains(TcpFlags::R) {
+                inner.transmit(ack, TcpFlags::R, &[]);
+            }
+            return;
+        }
+        if flags.contains(TcpFlags::R) {
+            if ack_ok {
+                self.close_with(inner, Some(ECONNREFUSED));
+            }
+            return;
+        }
+        if !flags.contains(TcpFlags::S) {
+            return;
+        }
+        inner.rcv_nxt = packet.seq.wrapping_add(1);
+        inner.snd_wnd = packet.win as u32;
+        inner.timer = None;
+        inner.retries = 0;
+        inner.rto_ms = TCP_RTO_INIT_MS;
+        if ack_ok {
+            inner.snd_una = ack;
+            inner.state = TcpState::Established;
+            inner.send_ack();
+            inner.output();
+        } else {
+            // both sides opened at once
+            inner.state = TcpState::SynReceived;
+            inner.retransmit();
+            inner.arm_timer();
+        }
+    }
+
+    /// Anything may have changed for the reader or writer after a segment.
+    pub fn segment_arrived(&self, packet: &TCPPacket) {
+        self.input(packet);
+        self.wait_queue.wake_all();
+    }
+
+    fn input(&self, packet: &TCPPacket) {
+        let mut inner = self.inner.exclusive_access();
+        if inner.state == TcpState::SynSent {
+            self.syn_sent_segment(&mut inner, packet);
+            return;
+        }
+        let flags = packet.flags;
+        let data = packet.data;
+        let mut seg_len = data.len() as u32;
+        if flags.contains(TcpFlags::S) {
+            seg_len += 1;
+        }
+        if flags.contains(TcpFlags::F) {
+            seg_len += 1;
+        }
+        // our SYN|ACK got lost, the peer repeats its SYN
+        if inner.state == TcpState::SynReceived
+            && flags.contains(TcpFlags::S)
+            && !flags.contains(TcpFlags::A)
+            && packet.seq == inner.rcv_nxt.wrapping_sub(1)
+        {
+            inner.transmit(inner.iss, TcpFlags::S | TcpFlags::A, &[]);
+            return;
+        }
+        if !inner.acceptable(packet.seq, seg_len) {
+            if !flags.contains(TcpFlags::R) {
+                inner.send_ack();
+            }
+            return;
+        }
+        if flags.contains(TcpFlags::R) {
+            // a reset passive open just goes away
+            let error = match (inner.state, &inner.listener) {
+                (TcpState::SynReceived, Some(_)) => None,
+                (TcpState::SynReceived, None) => Some(ECONNREFUSED),
+                _ => Some(ECONNRESET),
+            };
+            self.close_with(&mut inner, error);
+            return;
+        }
+        if flags.contains(TcpFlags::S) {
+            inner.transmit(inner.snd_nxt, TcpFlags::R, &[]);
+            self.close_with(&mut inner, Some(ECONNRESET));
+            return;
+        }
+        if !flags.contains(TcpFlags::A) {
+            return;
+        }
+
+        let ack = packet.ack;
+        if inner.state == TcpState::SynReceived {
+            if !(seq_lt(inner.snd_una, ack) && seq_le(ack, inner.snd_nxt)) {
+                inner.transmit(ack, TcpFlags::R, &[]);
+                return;
+            }
+            inner.state = TcpState::Established;
+            match inner.listener.as_ref().map(Weak::upgrade) {
+                Some(Some(listener)) => listener.half_open_done(Some(self.me.upgrade().unwrap())),
+                None => {}
+                Some(None) => {
+                    // nobody listens any more
+                    inner.transmit(ack, TcpFlags::R, &[]);
+                    self.close_with(&mut inner, Some(ECONNRESET));
+                    return;
+                }
+            }
+        }
+        if seq_lt(inner.snd_nxt, ack) {
+            // acknowledges something we never sent
+            inner.send_ack();
+            return;
+        }
+        if seq_lt(inner.snd_una, ack) {
+            let acked = ack.wrapping_sub(inner.snd_una) as usize;
+            let data_acked = acked.min(inner.sent);
+            inner.tx.drain(..data_acked);
+            inner.sent -= data_acked;
+            inner.snd_una = ack;
+            inner.retries = 0;
+            inner.rto_ms = TCP_RTO_INIT_MS;
+            inner.timer = None;
+        }
+        if seq_le(inner.snd_una, ack) {
+            inner.snd_wnd = packet.win as u32;
+        }
+        let fin_acked = inner.fin_sent() && inner.snd_una == inner.snd_nxt;
+        match inner.state {
+            TcpState::FinWait1 if fin_acked => {
+                inner.state = TcpState::FinWait2;
+                if inner.orphan {
+                    inner.timer = Some(get_time_ms() + TCP_TIME_WAIT_MS);
+                }
+            }
+            TcpState::Closing if fin_acked => {
+                inner.state = TcpState::TimeWait;
+                inner.timer = Some(get_time_ms() + TCP_TIME_WAIT_MS);
+            }
+            TcpState::LastAck if fin_acked => {
+                self.close_with(&mut inner, None);
+                return;
+            }
+            _ => {}
+        }
+
+        let mut need_ack = false;
+        if !data.is_empty()
+            && matches!(
+                inner.state,
+                TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2
+            )
+        {
+            inner.receive(packet.seq, data);
+            need_ack = true;
+        }
+        if flags.contains(TcpFlags::F)
+            && !inner.fin_received()
+            && packet.seq.wrapping_add(data.len() as u32) == inner.rcv_nxt
+        {
+            inner.rcv_nxt = inner.rcv_nxt.wrapping_add(1);
+            need_ack = true;
+            inner.state = match inner.state {
+                TcpState::Established => TcpState::CloseWait,
+                TcpState::FinWait1 => TcpState::Closing,
+                _ => {
+                    inner.timer = Some(get_time_ms() + TCP_TIME_WAIT_MS);
+                    TcpState::TimeWait
+                }
+            };
+        }
+        if need_ack {
+            inner.send_ack();
+        }
+        inner.output();
+    }
+
+    /// When the retransmission or `TIME_WAIT` timer is due.
+    pub fn timer(&self) -> Option<usize> {
+        self.inner.exclusive_access().timer
+    }
+
+    /// Retransmit, or finish `TIME_WAIT`, once the timer is due.
+    pub fn on_timer(&self, now: usize) {
+        let mut inner = self.inner.exclusive_access();
+        match inner.timer {
+            Some(deadline) if deadline <= now => inner.timer = None,
+            _ => return,
+        }
+        match inner.state {
+            TcpState::TimeWait | TcpState::FinWait2 => {
+                self.close_with(&mut inner, None);
+                return;
+            }
+            _ => {}
+        }
+        let max_retries = match inner.state {
+            TcpState::SynSent => TCP_SYN_RETRIES,
+            _ => TCP_MAX_RETRIES,
+        };
+        if inner.retries == max_retries {
+            inner.transmit(inner.snd_nxt, TcpFlags::R, &[]);
+            self.close_with(&mut inner, Some(ETIMEDOUT));
+            return;
+        }
+        inner.retries += 1;
+        inner.rto_ms = (inner.rto_ms * 2).min(TCP_RTO_MAX_MS);
+        inner.retransmit();
+        inner.arm_timer();
+    }
+
+    /// Queue as much of `data` as there is room for. `None` while the send
+    /// buffer is full.
+    pub fn send(&self, data: &[u8]) -> Option<Result<usize, isize>> {
+        let mut inner = self.inner.exclusive_access();
+        if let Some(error) = inner.error {
+            return Some(Err(-(error as isize)));
+        }
+        match inner.state {
+            TcpState::Established | TcpState::CloseWait if !inner.fin_queued => {}
+            _ => return Some(Err(-(EPIPE as isize))),
+        }
+        let len = data.len().min(TCP_TX_BUFFER - inner.tx.len());
+        if len == 0 {
+            return None;
+        }
+        inner.tx.extend(data[..len].iter());
+        inner.output();
+        Some(Ok(len))
+    }
+
+    /// Up to `max` received bytes, empty at end of stream. `None` while there
+    /// is nothing to read yet.
+    pub fn recv(&self, max: usize) -> Option<Result<Vec<u8>, isize>> {
+        let mut inner = self.inner.exclusive_access();
+        if !inner.rx.is_empty() {
+            let window = inner.rcv_wnd() as usize;
+            let len = max.min(inner.rx.len());
+            let data = inner.rx.drain(..len).collect();
+            // the peer may be waiting for the window to open
+            if window < TCP_MSS && inner.rcv_wnd() as usize >= TCP_MSS {
+                inner.send_ack();
+            }
+            return Some(Ok(data));
+        }
+        if let Some(error) = inner.error {
+            return Some(Err(-(error as isize)));
+        }
+        if inner.fin_received() || inner.read_shut || inner.state == TcpState::Closed {
+            return Some(Ok(Vec::new()));
+        }
+        None
+    }
+
+    pub fn shutdown(&self, read: bool, write: bool) {
+        let mut inner = self.inner.exclusive_access();
+        if read {
+            inner.read_shut = true;
+            inner.rx.clear();
+        }
+        if write {
+            inner.fin_queued = true;
+            inner.output();
+        }
+    }
+
+    /// The fd is gone: finish sending and close gracefully, or reset the
+    /// connection if unread data would be lost, as Linux does.
+    pub fn close(&self) {
+        let mut inner = self.inner.exclusive_access();
+        inner.orphan = true;
+        match inner.state {
+            TcpState::Closed => {}
+            // nothing was sent to the peer yet
+            TcpState::SynSent => self.close_with(&mut inner, None),
+            _ if !inner.rx.is_empty() => {
+                inner.transmit(inner.snd_nxt, TcpFlags::R, &[]);
+                self.close_with(&mut inner, Some(ECONNRESET));
+            }
+            TcpState::FinWait2 => inner.timer = Some(get_time_ms() + TCP_TIME_WAIT_MS),
+            _ => {
+                inner.fin_queued = true;
+                inner.output();
+            }
+        }
+    }
+
+    pub fn poll_ready(&self, events: PollEvents) -> PollEvents {
+        let inner = self.inner.exclusive_access();
+        let mut ready = PollEvents::empty();
+        if !inner.rx.is_empty()
+            || inner.fin_received()
+            || inner.read_shut
+            || inner.state == TcpState::Closed
+        {
+            ready |= PollEvents::IN;
+        }
+        if matches!(inner.state, TcpState::Established | TcpState::CloseWait)
+            && !inner.fin_queued
+            && inner.tx.len() < TCP_TX_BUFFER
+        {
+            ready |= PollEvents::OUT;
+        }
+        if inner.state == TcpState::Closed || (inner.fin_sent() && inner.fin_received()) {
+            ready |= PollEvents::HUP;
+        }
+        if inner.error.is_some() {
+            ready |= PollEvents::ERR;
+        }
+        ready & events
+    }
+}
@@ -1,55 +1,491 @@
-use alloc::vec;
+use alloc::{
+    collections::{
+        BTreeMap,
+        VecDeque,
+    },
+    sync::{
+        Arc,
+        Weak,
+    },
+    vec::Vec,
+};
+use lazy_static::lazy_static;
 use lose_net_stack::{
     packets::tcp::TCPPacket,
-    IPv4,
-    MacAddress,
     TcpFlags,
 };
+use shared_defination::error::{
+    EADDRINUSE,
+    EADDRNOTAVAIL,
+    EAGAIN,
+    EALREADY,
+    EINPROGRESS,
+    EINVAL,
+    EISCONN,
+    ENOTCONN,
+};
 
 use crate::{
-    drivers::NET_DEVICE,
-    fs::File,
+    fs::{
+        File,
+        PollEvents,
+    },
+    mm::UserBuffer,
+    sync::{
+        UPIntrFreeCell,
+        WaitQueue,
+    },
+    timer::get_time_ms,
 };
 
 use super::{
-    net_interrupt_handler,
+    net_block_on,
+    net_bottom_half,
+    route_to,
     socket::{
-        add_socket,
-        get_s_a_by_index,
-        pop_data,
-        remove_socket,
+        alloc_ephemeral_port,
+        copy_from_user,
+        copy_to_user,
+        InetAddr,
+        Socket,
+        SocketOptions,
+        SHUT_RD,
+        SHUT_RDWR,
+        SHUT_WR,
+    },
+    tcb::{
+        send_segment,
+        Tcb,
     },
-    LOSE_NET_STACK,
 };
 
-// add tcp packet info to this structure
-pub struct TCP {
-    pub target: IPv4,
-    pub sport: u16,
-    pub dport: u16,
-    #[allow(unused)]
-    pub seq: u32,
-    #[allow(unused)]
-    pub ack: u32,
-    pub socket_index: usize,
+lazy_static! {
+    /// Listening sockets by local port.
+    static ref TCP_LISTENERS: UPIntrFreeCell<BTreeMap<u16, Weak<TcpListener>>> =
+        unsafe { UPIntrFreeCell::new(BTreeMap::new()) };
+    /// Connections by local port and peer, including those whose fd is
+    /// closed but which are still shutting down.
+    static ref TCP_CONNECTIONS: UPIntrFreeCell<BTreeMap<(u16, InetAddr), Arc<Tcb>>> =
+        unsafe { UPIntrFreeCell::new(BTreeMap::new()) };
+}
+
+fn listening(port: u16) -> bool {
+    TCP_LISTENERS
+        .exclusive_access()
+        .get(&port)
+        .map_or(false, |listener| listener.strong_count() > 0)
+}
+
+/// Whether a new connection can use `port` as its local port.
+fn port_in_use(port: u16) -> bool {
+    listening(port)
+        || TCP_CONNECTIONS
+            .exclusive_access()
+            .range((port, InetAddr::new(0, 0))..)
+            .next()
+            .map_or(false, |((local_port, _), _)| *local_port == port)
+}
+
+/// Drop the table entry of `tcb`, unless a newer connection took it over.
+pub fn forget_connection(port: u16, peer: InetAddr, tcb: &Weak<Tcb>) {
+    let mut connections = TCP_CONNECTIONS.exclusive_access();
+    if connections
+        .get(&(port, peer))
+        .map_or(false, |entry| Arc::as_ptr(entry) == tcb.as_ptr())
+    {
+        connections.remove(&(port, peer));
+    }
+}
+
+struct ListenerInner {
+    /// Established connections waiting for `accept`.
+    accept_queue: VecDeque<Arc<Tcb>>,
+    /// Connections still in the handshake.
+    half_open: usize,
+    max_backlog: usize,
+}
+
+pub struct TcpListener {
+    local: InetAddr,
+    inner: UPIntrFreeCell<ListenerInner>,
+    /// The listening socket's, woken when a connection can be accepted.
+    wait_queue: Arc<WaitQueue>,
 }
 
-impl TCP {
-    pub fn new(target: IPv4, sport: u16, dport: u16, seq: u32, ack: u32) -> Self {
-        let index = add_socket(target, sport, dport).expect("can't add socket");
+impl TcpListener {
+    /// A SYN for our port: start a connection unless the backlog is full.
+    fn syn_arrived(self: &Arc<Self>, packet: &TCPPacket, peer: InetAddr) {
+        let mut inner = self.inner.exclusive_access();
+        if inner.accept_queue.len() + inner.half_open >= inner.max_backlog {
+            // the peer will try again
+            return;
+        }
+        inner.half_open += 1;
+        let local = InetAddr::new(packet.dest_ip.to_u32(), self.local.port);
+        let tcb = Tcb::passive_open(local, peer, packet.seq, packet.win, Arc::downgrade(self));
+        TCP_CONNECTIONS
+            .exclusive_access()
+            .insert((local.port, peer), tcb);
+    }
 
+    /// A handshake finished, with the connection if it got established.
+    pub fn half_open_done(&self, tcb: Option<Arc<Tcb>>) {
+        let mut inner = self.inner.exclusive_access();
+        inner.half_open -= 1;
+        if let Some(tcb) = tcb {
+            inner.accept_queue.push_back(tcb);
+            self.wait_queue.wake_all();
+        }
+    }
+}
+
+/// Nobody wants this segment, tell the peer so.
+fn send_reset(packet: &TCPPacket, local: InetAddr, peer: InetAddr) {
+    if packet.flags.contains(TcpFlags::A) {
+        send_segment(local, peer, packet.ack, 0, TcpFlags::R, 0, &[]);
+    } else {
+        let mut len = packet.data_len as u32;
+        if packet.flags.contains(TcpFlags::S) {
+            len += 1;
+        }
+        if packet.flags.contains(TcpFlags::F) {
+            len += 1;
+        }
+        send_segment(
+            local,
+            peer,
+            0,
+            packet.seq.wrapping_add(len),
+            TcpFlags::R | TcpFlags::A,
+            0,
+            &[],
+        );
+    }
+}
+
+pub fn tcp_input(packet: &TCPPacket) {
+    let local = InetAddr::new(packet.dest_ip.to_u32(), packet.dest_port);
+    let peer = InetAddr::new(packet.source_ip.to_u32(), packet.source_port);
+    let tcb = TCP_CONNECTIONS
+        .exclusive_access()
+        .get(&(local.port, peer))
+        .cloned();
+    if let Some(tcb) = tcb {
+        tcb.segment_arrived(packet);
+        return;
+    }
+    if packet.flags.contains(TcpFlags::S)
+        && !packet.flags.contains(TcpFlags::A)
+        && !packet.flags.contains(TcpFlags::R)
+    {
+        let listener = TCP_LISTENERS
+            .exclusive_access()
+            .get(&local.port)
+            .and_then(Weak::upgrade);
+        if let Some(listener) = listener {
+            listener.syn_arrived(packet, peer);
+            return;
+        }
+    }
+    if !packet.flags.contains(TcpFlags::R) {
+        send_reset(packet, local, peer);
+    }
+}
+
+/// When `tcp_tick` next has something to do.
+pub fn next_timeout() -> Option<usize> {
+    TCP_CONNECTIONS
+        .exclusive_access()
+        .values()
+        .filter_map(|tcb| tcb.timer())
+        .min()
+}
+
+/// Run the retransmission and `TIME_WAIT` timers.
+pub fn tcp_tick() {
+    let now = get_time_ms();
+    let connections: Vec<Arc<Tcb>> = TCP_CONNECTIONS
+        .exclusive_access()
+        .values()
+        .cloned()
+        .collect();
+    for tcb in connections {
+        tcb.on_timer(now);
+    }
+}
+
+enum TcpSocketState {
+    Closed { local: Option<InetAddr> },
+    Listening(Arc<TcpListener>),
+    Connected(Arc<Tcb>),
+}
+
+/// A TCP socket fd, what it does depends on whether it listens or carries
+/// a connection.
+pub struct TcpSocket {
+    inner: UPIntrFreeCell<TcpSocketState>,
+    /// Handed to the listener or connection, which wake it.
+    wait_queue: Arc<WaitQueue>,
+    options: UPIntrFreeCell<SocketOptions>,
+}
+
+impl TcpSocket {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::with_state(
+            TcpSocketState::Closed { local: None },
+            Arc::new(WaitQueue::new()),
+            SocketOptions::default(),
+        ))
+    }
+
+    fn with_state(
+        state: TcpSocketState, wait_queue: Arc<WaitQueue>, options: SocketOptions,
+    ) -> Self {
         Self {
-            target,
-            sport,
-            dport,
-            seq,
-            ack,
-            socket_index: index,
+            inner: unsafe { UPIntrFreeCell::new(state) },
+            wait_queue,
+            options: unsafe { UPIntrFreeCell::new(options) },
+        }
+    }
+
+    fn tcb(&self) -> Option<Arc<Tcb>> {
+        match &*self.inner.exclusive_access() {
+            TcpSocketState::Connected(tcb) => Some(tcb.clone()),
+            _ => None,
         }
     }
 }
 
-impl File for TCP {
+impl Socket for TcpSocket {
+    fn bind(&self, addr: InetAddr) -> isize {
+        let mut state = self.inner.exclusive_access();
+        match &*state {
+            TcpSocketState::Closed { local: None } => {}
+            _ => return -(EINVAL as isize),
+        }
+        // connections left on the port, in TIME_WAIT say, only let
+        // SO_REUSEADDR sockets bind it
+        let in_use = if self.options.exclusive_access().reuse_addr {
+            listening(addr.port)
+        } else {
+            port_in_use(addr.port)
+        };
+        let port = if addr.port == 0 {
+            match alloc_ephemeral_port(port_in_use) {
+                Some(port) => port,
+                None => return -(EADDRINUSE as isize),
+            }
+        } else if in_use {
+            return -(EADDRINUSE as isize);
+        } else {
+            addr.port
+        };
+        *state = TcpSocketState::Closed {
+            local: Some(InetAddr::new(addr.ip, port)),
+        };
+        0
+    }
+
+    fn listen(&self, backlog: usize) -> isize {
+        let mut state = self.inner.exclusive_access();
+        let local = match &*state {
+            TcpSocketState::Closed { local } => *local,
+            TcpSocketState::Listening(listener) => {
+                listener.inner.exclusive_access().max_backlog = backlog.max(1);
+                return 0;
+            }
+            TcpSocketState::Connected(_) => return -(EISCONN as isize),
+        };
+        let local = match local {
+            Some(local) => local,
+            None => match alloc_ephemeral_port(port_in_use) {
+                Some(port) => InetAddr::new(0, port),
+                None => return -(EADDRINUSE as isize),
+            },
+        };
+        // two sockets may be bound to a port, only one can listen on it
+        if listening(local.port) {
+            return -(EADDRINUSE as isize);
+        }
+        let listener = Arc::new(TcpListener {
+            local,
+            inner: unsafe {
+                UPIntrFreeCell::new(ListenerInner {
+                    accept_queue: VecDeque::new(),
+                    half_open: 0,
+                    max_backlog: backlog.max(1),
+                })
+            },
+            wait_queue: self.wait_queue.clone(),
+        });
+        TCP_LISTENERS
+            .exclusive_access()
+            .insert(local.port, Arc::downgrade(&listener));
+        *state = TcpSocketState::Listening(listener);
+        0
+    }
+
+    fn accept(&self) -> Result<(Arc<dyn File + Send + Sync>, InetAddr), isize> {
+        let listener = match &*self.inner.exclusive_access() {
+            TcpSocketState::Listening(listener) => listener.clone(),
+            _ => return Err(-(EINVAL as isize)),
+        };
+        let options = *self.options.exclusive_access();
+        let tcb = net_block_on(&self.wait_queue, options.recv_blocking(), || {
+            listener.inner.exclusive_access().accept_queue.pop_front()
+        })?;
+        // the connection takes the listener's options, but not O_NONBLOCK
+        tcb.set_no_delay(options.no_delay);
+        let peer = tcb.peer();
+        let wait_queue = tcb.wait_queue().clone();
+        let options = SocketOptions {
+            nonblock: false,
+            ..options
+        };
+        let socket = Arc::new(Self::with_state(
+            TcpSocketState::Connected(tcb),
+            wait_queue,
+            options,
+        ));
+        Ok((socket, peer))
+    }
+
+    /// Active open, blocks until the handshake is done. A failed socket can
+    /// try again. Without blocking, or once `SO_SNDTIMEO` runs out, the
+    /// handshake goes on and `EINPROGRESS` is returned: the socket becomes
+    /// writable when it is done, and the next `connect` tells how it went.
+    fn connect(&self, addr: InetAddr) -> isize {
+        let mut state = self.inner.exclusive_access();
+        let local = match &*state {
+            TcpSocketState::Closed { local } => *local,
+            TcpSocketState::Listening(_) => return -(EINVAL as isize),
+            TcpSocketState::Connected(tcb) => {
+                return match tcb.connected() {
+                    None => -(EALREADY as isize),
+                    Some(Ok(())) => -(EISCONN as isize),
+                    Some(Err(err)) => {
+                        let local = tcb.local();
+                        *state = TcpSocketState::Closed { local: Some(local) };
+                        err
+                    }
+                };
+            }
+        };
+        let port = match local {
+            Some(local) => local.port,
+            None => match alloc_ephemeral_port(port_in_use) {
+                Some(port) => port,
+                None => return -(EADDRNOTAVAIL as isize),
+            },
+        };
+        let route = match route_to(addr.ip) {
+            Ok(route) => route,
+            Err(err) => return err,
+        };
+        let ip = match local {
+            Some(local) if local.ip != 0 => local.ip,
+            _ => route.src.to_u32(),
+        };
+        let local = InetAddr::new(ip, port);
+        let mut connections = TCP_CONNECTIONS.exclusive_access();
+        if connections.contains_key(&(port, addr)) {
+            return -(EADDRINUSE as isize);
+        }
+        let tcb = Tcb::active_open(local, addr, self.wait_queue.clone());
+        connections.insert((port, addr), tcb.clone());
+        drop(connections);
+        *state = TcpSocketState::Connected(tcb.clone());
+        drop(state);
+        let options = *self.options.exclusive_access();
+        tcb.set_no_delay(options.no_delay);
+        match net_block_on(&self.wait_queue, options.send_blocking(), || {
+            tcb.connected()
+        }) {
+            Ok(Ok(())) => 0,
+            Err(err) if err == -(EAGAIN as isize) => -(EINPROGRESS as isize),
+            Ok(Err(err)) | Err(err) => {
+                *self.inner.exclusive_access() = TcpSocketState::Closed { local: Some(local) };
+                err
+            }
+        }
+    }
+
+    fn sendto(&self, buf: UserBuffer, _addr: Option<InetAddr>) -> isize {
+        let tcb = match self.tcb() {
+            Some(tcb) => tcb,
+            None => return -(ENOTCONN as isize),
+        };
+        let data = copy_from_user(&buf);
+        let blocking = self.options.exclusive_access().send_blocking();
+        let mut queued = 0;
+        while queued < data.len() {
+            match net_block_on(&self.wait_queue, blocking, || tcb.send(&data[queued..])) {
+                Ok(Ok(len)) => queued += len,
+                Ok(Err(err)) | Err(err) if queued == 0 => return err,
+                _ => break,
+            }
+        }
+        net_bottom_half();
+        queued as isize
+    }
+
+    fn recvfrom(&self, buf: UserBuffer) -> (isize, Option<InetAddr>) {
+        let tcb = match self.tcb() {
+            Some(tcb) => tcb,
+            None => return (-(ENOTCONN as isize), None),
+        };
+        let max = buf.len();
+        let blocking = self.options.exclusive_access().recv_blocking();
+        match net_block_on(&self.wait_queue, blocking, || tcb.recv(max)) {
+            Ok(Ok(data)) => (copy_to_user(buf, &data) as isize, None),
+            Ok(Err(err)) | Err(err) => (err, None),
+        }
+    }
+
+    fn shutdown(&self, how: usize) -> isize {
+        let tcb = match self.tcb() {
+            Some(tcb) => tcb,
+            None => return -(ENOTCONN as isize),
+        };
+        match how {
+            SHUT_RD => tcb.shutdown(true, false),
+            SHUT_WR => tcb.shutdown(false, true),
+            SHUT_RDWR => tcb.shutdown(true, true),
+            _ => return -(EINVAL as isize),
+        }
+        0
+    }
+
+    fn sockname(&self) -> InetAddr {
+        match &*self.inner.exclusive_access() {
+            TcpSocketState::Closed { local } => local.unwrap_or(InetAddr::new(0, 0)),
+            TcpSocketState::Listening(listener) => listener.local,
+            TcpSocketState::Connected(tcb) => tcb.local(),
+        }
+    }
+
+    fn peername(&self) -> Option<InetAddr> {
+        self.tcb().map(|tcb| tcb.peer())
+    }
+
+    fn options(&self) -> &UPIntrFreeCell<SocketOptions> {
+        &self.options
+    }
+
+    fn no_delay(&self) -> Option<bool> {
+        Some(self.options.exclusive_access().no_delay)
+    }
+
+    fn set_no_delay(&self, no_delay: bool) -> isize {
+        self.options.exclusive_access().no_delay = no_delay;
+        if let Some(tcb) = self.tcb() {
+            tcb.set_no_delay(no_delay);
+        }
+        0
+    }
+}
+
+impl File for TcpSocket {
     fn readable(&self) -> bool {
         true
     }
@@ -58,67 +494,72 @@ impl File for TCP {
         true
     }
 
-    fn read(&self, mut buf: crate::mm::UserBuffer) -> usize {
-        loop {
-            if let Some(data) = pop_data(self.socket_index) {
-                let data_len = data.len();
-                let mut left = 0;
-                for i in 0..buf.buffers.len() {
-                    let buffer_i_len = buf.buffers[i].len().min(data_len - left);
+    fn read(&self, buf: UserBuffer) -> usize {
+        self.recvfrom(buf).0 as usize
+    }
 
-                    buf.buffers[i][..buffer_i_len]
-                        .copy_from_slice(&data[left..(left + buffer_i_len)]);
+    fn write(&self, buf: UserBuffer) -> usize {
+        self.sendto(buf, None) as usize
+    }
 
-                    left += buffer_i_len;
-                    if left == data_len {
-                        break;
-                    }
+    /// A listener is readable while a connection waits to be accepted.
+    fn poll_ready(&self, events: PollEvents) -> PollEvents {
+        match &*self.inner.exclusive_access() {
+            TcpSocketState::Closed { .. } => (PollEvents::OUT | PollEvents::HUP) & events,
+            TcpSocketState::Listening(listener) => {
+                if listener.inner.exclusive_access().accept_queue.is_empty() {
+                    PollEvents::empty()
+                } else {
+                    PollEvents::IN & events
                 }
-                return left;
-            } else {
-                net_interrupt_handler();
             }
+            TcpSocketState::Connected(tcb) => tcb.poll_ready(events),
         }
     }
 
-    fn write(&self, buf: crate::mm::UserBuffer) -> usize {
-        let lose_net_stack = LOSE_NET_STACK.0.exclusive_access();
-
-        let mut data = vec![0u8; buf.len()];
-
-        let mut left = 0;
-        for i in 0..buf.buffers.len() {
-            data[left..(left + buf.buffers[i].len())].copy_from_slice(buf.buffers[i]);
-            left += buf.buffers[i].len();
-        }
+    fn wait_queue(&self) -> Option<&WaitQueue> {
+        Some(&self.wait_queue)
+    }
 
-        let len = data.len();
+    fn nonblocking(&self) -> bool {
+        self.options.exclusive_access().nonblock
+    }
 
-        // get sock and sequence
-        let (ack, seq) = get_s_a_by_index(self.socket_index).map_or((0, 0), |x| x);
+    fn set_nonblocking(&self, nonblocking: bool) {
+        self.options.exclusive_access().nonblock = nonblocking;
+    }
 
-        let tcp_packet = TCPPacket {
-            source_ip: lose_net_stack.ip,
-            source_mac: lose_net_stack.mac,
-            source_port: self.sport,
-            dest_ip: self.target,
-            dest_mac: MacAddress::new([0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
-            dest_port: self.dport,
-            data_len: len,
-            seq,
-            ack,
-            flags: TcpFlags::A,
-            win: 65535,
-            urg: 0,
-            data: data.as_ref(),
-        };
-        NET_DEVICE.transmit(&tcp_packet.build_data());
-        len
+    fn as_socket(&self) -> Option<&dyn Socket> {
+        Some(self)
     }
 }
 
-impl Drop for TCP {
+impl Drop for TcpSocket {
     fn drop(&mut self) {
-        remove_socket(self.socket_index)
+        match &*self.inner.exclusive_access() {
+            TcpSocketState::Closed { .. } => {}
+            TcpSocketState::Listening(listener) => {
+                let mut listeners = TCP_LISTENERS.exclusive_access();
+                if listeners
+                    .get(&listener.local.port)
+                    .map_or(false, |entry| entry.as_ptr() == Arc::as_ptr(listener))
+                {
+                    listeners.remove(&listener.local.port);
+                }
+                drop(listeners);
+                // connections nobody accepted are reset, half-open ones are
+                // reset when their handshake completes
+                let queued: Vec<Arc<Tcb>> = listener
+                    .inner
+                    .exclusive_access()
+                    .accept_queue
+                    .drain(..)
+                    .collect();
+                for tcb in queued {
+                    tcb.abort();
+                }
+            }
+            TcpSocketState::Connected(tcb) => tcb.close(),
+        }
     }
 }
@@ -1,42 +1,274 @@
 use super::{
-    net_interrupt_handler,
+    net_block_on,
+    net_bottom_half,
+    route_to,
     socket::{
-        add_socket,
-        pop_data,
-        remove_socket,
+        alloc_ephemeral_port,
+        copy_from_user,
+        copy_to_user,
+        InetAddr,
+        Socket,
+        SocketOptions,
+        SHUT_RD,
+        SHUT_RDWR,
+        SHUT_WR,
     },
-    LOSE_NET_STACK,
-    NET_DEVICE,
+    IPv4,
 };
-use crate::fs::File;
-use alloc::vec;
+use crate::{
+    fs::{
+        File,
+        PollEvents,
+    },
+    mm::UserBuffer,
+    sync::{
+        UPIntrFreeCell,
+        WaitQueue,
+    },
+};
+use alloc::{
+    collections::{
+        BTreeMap,
+        VecDeque,
+    },
+    sync::{
+        Arc,
+        Weak,
+    },
+    vec::Vec,
+};
+use lazy_static::lazy_static;
 use lose_net_stack::{
     packets::udp::UDPPacket,
-    IPv4,
     MacAddress,
 };
+use shared_defination::error::{
+    EADDRINUSE,
+    EDESTADDRREQ,
+    EINVAL,
+    EMSGSIZE,
+    EPIPE,
+};
 
-pub struct UDP {
-    pub target: IPv4,
-    pub sport: u16,
-    pub dport: u16,
-    pub socket_index: usize,
+/// Largest payload that fits an ethernet frame without fragmentation.
+const UDP_MAX_PAYLOAD: usize = 1472;
+/// Datagrams queued per socket before new ones are dropped.
+const UDP_RX_QUEUE: usize = 64;
+
+lazy_static! {
+    /// Bound sockets by local port.
+    static ref UDP_PORTS: UPIntrFreeCell<BTreeMap<u16, Weak<UdpSocket>>> =
+        unsafe { UPIntrFreeCell::new(BTreeMap::new()) };
 }
 
-impl UDP {
-    pub fn new(target: IPv4, sport: u16, dport: u16) -> Self {
-        let index = add_socket(target, sport, dport).expect("can't add socket");
+fn port_in_use(port: u16) -> bool {
+    UDP_PORTS
+        .exclusive_access()
+        .get(&port)
+        .map_or(false, |socket| socket.strong_count() > 0)
+}
 
-        Self {
-            target,
-            sport,
-            dport,
-            socket_index: index,
+struct UdpInner {
+    local: Option<InetAddr>,
+    /// Set by `connect`, only datagrams from it are received then.
+    peer: Option<InetAddr>,
+    rx: VecDeque<(InetAddr, Vec<u8>)>,
+    read_shut: bool,
+    write_shut: bool,
+}
+
+pub struct UdpSocket {
+    me: Weak<UdpSocket>,
+    inner: UPIntrFreeCell<UdpInner>,
+    /// Woken when a datagram arrives or receiving is shut down.
+    wait_queue: WaitQueue,
+    options: UPIntrFreeCell<SocketOptions>,
+}
+
+impl UdpSocket {
+    pub fn new() -> Arc<Self> {
+        Arc::new_cyclic(|me| Self {
+            me: me.clone(),
+            inner: unsafe {
+                UPIntrFreeCell::new(UdpInner {
+                    local: None,
+                    peer: None,
+                    rx: VecDeque::new(),
+                    read_shut: false,
+                    write_shut: false,
+                })
+            },
+            wait_queue: WaitQueue::new(),
+            options: unsafe { UPIntrFreeCell::new(SocketOptions::default()) },
+        })
+    }
+
+    /// Bind to an ephemeral port unless bound already.
+    fn local_or_bind(&self) -> Result<InetAddr, isize> {
+        if let Some(local) = self.inner.exclusive_access().local {
+            return Ok(local);
+        }
+        let port = alloc_ephemeral_port(port_in_use).ok_or(-(EADDRINUSE as isize))?;
+        let local = InetAddr::new(0, port);
+        match self.bind(local) {
+            0 => Ok(local),
+            err => Err(err),
         }
     }
+
+    fn push(&self, source: InetAddr, data: &[u8]) {
+        let mut inner = self.inner.exclusive_access();
+        if inner.read_shut
+            || inner.rx.len() >= UDP_RX_QUEUE
+            || inner.peer.map_or(false, |peer| peer != source)
+        {
+            return;
+        }
+        inner.rx.push_back((source, data.to_vec()));
+        self.wait_queue.wake_all();
+    }
 }
 
-impl File for UDP {
+pub fn udp_input(packet: &UDPPacket) {
+    let socket = UDP_PORTS
+        .exclusive_access()
+        .get(&packet.dest_port)
+        .and_then(Weak::upgrade);
+    if let Some(socket) = socket {
+        let source = InetAddr::new(packet.source_ip.to_u32(), packet.source_port);
+        socket.push(source, packet.data);
+    }
+}
+
+impl Socket for UdpSocket {
+    fn bind(&self, addr: InetAddr) -> isize {
+        let mut inner = self.inner.exclusive_access();
+        if inner.local.is_some() {
+            return -(EINVAL as isize);
+        }
+        let port = if addr.port == 0 {
+            match alloc_ephemeral_port(port_in_use) {
+                Some(port) => port,
+                None => return -(EADDRINUSE as isize),
+            }
+        } else if port_in_use(addr.port) {
+            return -(EADDRINUSE as isize);
+        } else {
+            addr.port
+        };
+        UDP_PORTS.exclusive_access().insert(port, self.me.clone());
+        inner.local = Some(InetAddr::new(addr.ip, port));
+        0
+    }
+
+    fn connect(&self, addr: InetAddr) -> isize {
+        if let Err(err) = self.local_or_bind() {
+            return err;
+        }
+        let mut inner = self.inner.exclusive_access();
+        inner.peer = Some(addr);
+        // whatever came from elsewhere is not for us any more
+        inner.rx.retain(|(source, _)| *source == addr);
+        0
+    }
+
+    fn sendto(&self, buf: UserBuffer, addr: Option<InetAddr>) -> isize {
+        let local = match self.local_or_bind() {
+            Ok(local) => local,
+            Err(err) => return err,
+        };
+        let (peer, write_shut) = {
+            let inner = self.inner.exclusive_access();
+            (addr.or(inner.peer), inner.write_shut)
+        };
+        if write_shut {
+            return -(EPIPE as isize);
+        }
+        let peer = match peer {
+            Some(peer) => peer,
+            None => return -(EDESTADDRREQ as isize),
+        };
+        if buf.len() > UDP_MAX_PAYLOAD {
+            return -(EMSGSIZE as isize);
+        }
+        let route = match route_to(peer.ip) {
+            Ok(route) => route,
+            Err(err) => return err,
+        };
+        let ip = match local.ip {
+            0 => route.src,
+            ip => IPv4::from_u32(ip),
+        };
+        let data = copy_from_user(&buf);
+        let udp_packet = UDPPacket::new(
+            ip,
+            route.iface.mac(),
+            local.port,
+            IPv4::from_u32(peer.ip),
+            MacAddress::new([0; 6]),
+            peer.port,
+            data.len(),
+            data.as_ref(),
+        );
+        route.transmit(udp_packet.build_data());
+        net_bottom_half();
+        data.len() as isize
+    }
+
+    /// The rest of a datagram that does not fit `buf` is dropped.
+    fn recvfrom(&self, buf: UserBuffer) -> (isize, Option<InetAddr>) {
+        let blocking = self.options.exclusive_access().recv_blocking();
+        let datagram = net_block_on(&self.wait_queue, blocking, || {
+            let mut inner = self.inner.exclusive_access();
+            match inner.rx.pop_front() {
+                Some(datagram) => Some(Some(datagram)),
+                None if inner.read_shut => Some(None),
+                None => None,
+            }
+        });
+        match datagram {
+            Ok(Some((source, data))) => (copy_to_user(buf, &data) as isize, Some(source)),
+            Ok(None) => (0, None),
+            Err(err) => (err, None),
+        }
+    }
+
+    fn shutdown(&self, how: usize) -> isize {
+        let mut inner = self.inner.exclusive_access();
+        match how {
+            SHUT_RD => inner.read_shut = true,
+            SHUT_WR => inner.write_shut = true,
+            SHUT_RDWR => {
+                inner.read_shut = true;
+                inner.write_shut = true;
+            }
+            _ => return -(EINVAL as isize),
+        }
+        if inner.read_shut {
+            inner.rx.clear();
+            // blocked readers see end of file
+            self.wait_queue.wake_all();
+        }
+        0
+    }
+
+    fn sockname(&self) -> InetAddr {
+        self.inner
+            .exclusive_access()
+            .local
+            .unwrap_or(InetAddr::new(0, 0))
+    }
+
+    fn peername(&self) -> Option<InetAddr> {
+        self.inner.exclusive_access().peer
+    }
+
+    fn options(&self) -> &UPIntrFreeCell<SocketOptions> {
+        &self.options
+    }
+}
+
+impl File for UdpSocket {
     fn readable(&self) -> bool {
         true
     }
@@ -45,59 +277,50 @@ impl File for UDP {
         true
     }
 
-    fn read(&self, mut buf: crate::mm::UserBuffer) -> usize {
-        loop {
-            if let Some(data) = pop_data(self.socket_index) {
-                let data_len = data.len();
-                let mut left = 0;
-                for i in 0..buf.buffers.len() {
-                    let buffer_i_len = buf.buffers[i].len().min(data_len - left);
-
-                    buf.buffers[i][..buffer_i_len]
-                        .copy_from_slice(&data[left..(left + buffer_i_len)]);
-
-                    left += buffer_i_len;
-                    if left == data_len {
-                        break;
-                    }
-                }
-                return left;
-            } else {
-                net_interrupt_handler();
-            }
-        }
+    fn read(&self, buf: UserBuffer) -> usize {
+        self.recvfrom(buf).0 as usize
     }
 
-    fn write(&self, buf: crate::mm::UserBuffer) -> usize {
-        let lose_net_stack = LOSE_NET_STACK.0.exclusive_access();
-
-        let mut data = vec![0u8; buf.len()];
+    fn write(&self, buf: UserBuffer) -> usize {
+        self.sendto(buf, None) as usize
+    }
 
-        let mut left = 0;
-        for i in 0..buf.buffers.len() {
-            data[left..(left + buf.buffers[i].len())].copy_from_slice(buf.buffers[i]);
-            left += buf.buffers[i].len();
+    fn poll_ready(&self, events: PollEvents) -> PollEvents {
+        let inner = self.inner.exclusive_access();
+        let mut ready = PollEvents::OUT;
+        if !inner.rx.is_empty() || inner.read_shut {
+            ready |= PollEvents::IN;
         }
+        ready & events
+    }
 
-        let len = data.len();
+    fn wait_queue(&self) -> Option<&WaitQueue> {
+        Some(&self.wait_queue)
+    }
 
-        let udp_packet = UDPPacket::new(
-            lose_net_stack.ip,
-            lose_net_stack.mac,
-            self.sport,
-            self.target,
-            MacAddress::new([0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
-            self.dport,
-            len,
-            data.as_ref(),
-        );
-        NET_DEVICE.transmit(&udp_packet.build_data());
-        len
+    fn nonblocking(&self) -> bool {
+        self.options.exclusive_access().nonblock
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) {
+        self.options.exclusive_access().nonblock = nonblocking;
+    }
+
+    fn as_socket(&self) -> Option<&dyn Socket> {
+        Some(self)
     }
 }
 
-impl Drop for UDP {
+impl Drop for UdpSocket {
     fn drop(&mut self) {
-        remove_socket(self.socket_index)
+        if let Some(local) = self.inner.exclusive_access().local {
+            let mut ports = UDP_PORTS.exclusive_access();
+            if ports
+                .get(&local.port)
+                .map_or(false, |socket| Weak::ptr_eq(socket, &self.me))
+            {
+                ports.remove(&local.port);
+            }
+        }
     }
 }
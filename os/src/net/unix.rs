@@ -0,0 +1,630 @@
+//! `AF_UNIX` sockets, for processes on this machine to talk to each other
+//! and pass open files along.
+
+use alloc::{
+    collections::{
+        BTreeMap,
+        VecDeque,
+    },
+    string::String,
+    sync::{
+        Arc,
+        Weak,
+    },
+    vec::Vec,
+};
+use core::mem;
+use lazy_static::lazy_static;
+use shared_defination::error::{
+    EADDRINUSE,
+    ECONNREFUSED,
+    EINVAL,
+    EISCONN,
+    EMSGSIZE,
+    ENOENT,
+    ENOTCONN,
+    EOPNOTSUPP,
+    EPIPE,
+    EPROTOTYPE,
+};
+
+use super::socket::{
+    copy_to_user,
+    Blocking,
+    SocketOptions,
+    SHUT_RD,
+    SHUT_RDWR,
+    SHUT_WR,
+};
+use crate::{
+    fs::{
+        File,
+        PollEvents,
+    },
+    mm::UserBuffer,
+    sync::{
+        UPIntrFreeCell,
+        WaitQueue,
+    },
+};
+
+/// Bytes a stream takes before its writers block.
+const UNIX_STREAM_BUF: usize = 64 * 1024;
+/// Datagrams a socket queues before its senders block.
+const UNIX_DGRAM_QUEUE: usize = 64;
+const UNIX_DGRAM_MAX: usize = 64 * 1024;
+
+lazy_static! {
+    /// Bound sockets by name: an absolute path, or an abstract name starting
+    /// with a NUL. Unlike Linux a name goes away with its socket, and no
+    /// file is made for it.
+    static ref UNIX_NAMES: UPIntrFreeCell<BTreeMap<String, Weak<UnixSocket>>> =
+        unsafe { UPIntrFreeCell::new(BTreeMap::new()) };
+}
+
+fn lookup(name: &str) -> Result<Arc<UnixSocket>, isize> {
+    match UNIX_NAMES.exclusive_access().get(name).map(Weak::upgrade) {
+        Some(Some(socket)) => Ok(socket),
+        _ if name.starts_with('\0') => Err(-(ECONNREFUSED as isize)),
+        _ => Err(-(ENOENT as isize)),
+    }
+}
+
+/// Drop the name `path`, as `unlink` does. `false` if no socket has it.
+pub fn unbind(path: &str) -> bool {
+    UNIX_NAMES.exclusive_access().remove(path).is_some()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum UnixType {
+    Stream,
+    Dgram,
+}
+
+pub type FileRef = Arc<dyn File + Send + Sync>;
+
+/// What one send queued. A stream reads through the data of several, but
+/// not past files sent along with a later one.
+struct Message {
+    data: Vec<u8>,
+    /// `SCM_RIGHTS`, installed as new fds by whoever receives them.
+    files: Vec<FileRef>,
+    /// Name of the sending datagram socket.
+    from: Option<String>,
+}
+
+enum UnixState {
+    Unconnected,
+    Listening {
+        backlog: usize,
+        /// Connections made by `connect`, waiting for `accept`.
+        pending: VecDeque<Arc<UnixSocket>>,
+    },
+    /// The other end of a stream, or where a datagram socket sends by
+    /// default.
+    Connected(Weak<UnixSocket>),
+}
+
+struct UnixInner {
+    name: Option<String>,
+    state: UnixState,
+    rx: VecDeque<Message>,
+    /// Bytes of data in `rx`.
+    rx_len: usize,
+    shut_rd: bool,
+    shut_wr: bool,
+    /// The peer of a stream closed or shut its writing down.
+    eof: bool,
+}
+
+/// What a receive got.
+pub struct Received {
+    pub len: usize,
+    pub files: Vec<FileRef>,
+    pub from: Option<String>,
+    /// A datagram did not fit and lost its tail.
+    pub truncated: bool,
+}
+
+pub struct UnixSocket {
+    ty: UnixType,
+    this: Weak<UnixSocket>,
+    inner: UPIntrFreeCell<UnixInner>,
+    /// Woken when something arrives and when room is made in `rx`.
+    wait_queue: Arc<WaitQueue>,
+    options: UPIntrFreeCell<SocketOptions>,
+}
+
+impl UnixSocket {
+    pub fn new(ty: UnixType) -> Arc<Self> {
+        Self::with_state(ty, None, UnixState::Unconnected)
+    }
+
+    fn with_state(ty: UnixType, name: Option<String>, state: UnixState) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self {
+            ty,
+            this: this.clone(),
+            inner: unsafe {
+                UPIntrFreeCell::new(UnixInner {
+                    name,
+                    state,
+                    rx: VecDeque::new(),
+                    rx_len: 0,
+                    shut_rd: false,
+                    shut_wr: false,
+                    eof: false,
+                })
+            },
+            wait_queue: Arc::new(WaitQueue::new()),
+            options: unsafe { UPIntrFreeCell::new(SocketOptions::default()) },
+        })
+    }
+
+    /// Two sockets connected to each other, for `socketpair`.
+    pub fn pair(ty: UnixType) -> (Arc<Self>, Arc<Self>) {
+        let a = Self::new(ty);
+        let b = Self::with_state(ty, None, UnixState::Connected(Arc::downgrade(&a)));
+        a.inner.exclusive_access().state = UnixState::Connected(Arc::downgrade(&b));
+        (a, b)
+    }
+
+    pub fn is_dgram(&self) -> bool {
+        self.ty == UnixType::Dgram
+    }
+
+    fn peer(&self) -> Option<Weak<UnixSocket>> {
+        match &self.inner.exclusive_access().state {
+            UnixState::Connected(peer) => Some(peer.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn bind(&self, name: String) -> isize {
+        let mut inner = self.inner.exclusive_access();
+        if inner.name.is_some() {
+            return -(EINVAL as isize);
+        }
+        let mut names = UNIX_NAMES.exclusive_access();
+        if names
+            .get(&name)
+            .map_or(false, |socket| socket.strong_count() > 0)
+        {
+            return -(EADDRINUSE as isize);
+        }
+        names.insert(name.clone(), self.this.clone());
+        inner.name = Some(name);
+        0
+    }
+
+    pub fn listen(&self, backlog: usize) -> isize {
+        if self.ty != UnixType::Stream {
+            return -(EOPNOTSUPP as isize);
+        }
+        let mut inner = self.inner.exclusive_access();
+        if inner.name.is_none() {
+            return -(EINVAL as isize);
+        }
+        let backlog = backlog.max(1);
+        match &mut inner.state {
+            state @ UnixState::Unconnected => {
+                *state = UnixState::Listening {
+                    backlog,
+                    pending: VecDeque::new(),
+                }
+            }
+            UnixState::Listening { backlog: old, .. } => *old = backlog,
+            UnixState::Connected(_) => return -(EINVAL as isize),
+        }
+        0
+    }
+
+    pub fn accept(&self) -> Result<Arc<UnixSocket>, isize> {
+        let blocking = self.options.exclusive_access().recv_blocking();
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            let pending = match &mut inner.state {
+                UnixState::Listening { pending, .. } => pending,
+                _ => return Err(-(EINVAL as isize)),
+            };
+            if let Some(connection) = pending.pop_front() {
+                drop(inner);
+                // connectors waiting for room in the backlog
+                self.wait_queue.wake_all();
+                return Ok(connection);
+            }
+            let task_ctx_ptr = blocking.wait_no_sched(&self.wait_queue)?;
+            drop(inner);
+            blocking.schedule(task_ctx_ptr, &self.wait_queue);
+        }
+    }
+
+    /// A stream is connected once the listener has it in its backlog, a
+    /// datagram socket only remembers where to send.
+    pub fn connect(&self, name: &str) -> isize {
+        match &self.inner.exclusive_access().state {
+            UnixState::Unconnected => {}
+            UnixState::Connected(_) if self.ty == UnixType::Dgram => {}
+            UnixState::Connected(_) => return -(EISCONN as isize),
+            UnixState::Listening { .. } => return -(EINVAL as isize),
+        }
+        let blocking = self.options.exclusive_access().send_blocking();
+        loop {
+            let target = match lookup(name) {
+                Ok(target) => target,
+                Err(err) => return err,
+            };
+            if target.ty != self.ty {
+                return -(EPROTOTYPE as isize);
+            }
+            if self.ty == UnixType::Dgram {
+                self.inner.exclusive_access().state = UnixState::Connected(Arc::downgrade(&target));
+                return 0;
+            }
+            let mut target_inner = target.inner.exclusive_access();
+            let target_name = target_inner.name.clone();
+            let (backlog, pending) = match &mut target_inner.state {
+                UnixState::Listening { backlog, pending } => (*backlog, pending),
+                _ => return -(ECONNREFUSED as isize),
+            };
+            if pending.len() >= backlog {
+                let task_ctx_ptr = match blocking.wait_no_sched(&target.wait_queue) {
+                    Ok(task_ctx_ptr) => task_ctx_ptr,
+                    Err(err) => return err,
+                };
+                let wait_queue = target.wait_queue.clone();
+                drop(target_inner);
+                drop(target);
+                blocking.schedule(task_ctx_ptr, &wait_queue);
+                continue;
+            }
+            // the accepted end goes by the listener's name
+            let server_end = Self::with_state(
+                UnixType::Stream,
+                target_name,
+                UnixState::Connected(self.this.clone()),
+            );
+            self.inner.exclusive_access().state = UnixState::Connected(Arc::downgrade(&server_end));
+            pending.push_back(server_end);
+            drop(target_inner);
+            target.wait_queue.wake_all();
+            return 0;
+        }
+    }
+
+    /// Queue `data` with `files` on the peer, or on the socket named `dest`
+    /// for a datagram. A stream blocks until all of it is queued.
+    pub fn send(&self, data: &[u8], files: Vec<FileRef>, dest: Option<&str>) -> isize {
+        if self.inner.exclusive_access().shut_wr {
+            return -(EPIPE as isize);
+        }
+        match self.ty {
+            UnixType::Stream => {
+                if dest.is_some() {
+                    return -(EISCONN as isize);
+                }
+                match self.peer() {
+                    Some(peer) => self.send_stream(&peer, data, files),
+                    None => -(ENOTCONN as isize),
+                }
+            }
+            UnixType::Dgram => {
+                let target = match dest {
+                    Some(name) => lookup(name),
+                    None => match self.peer() {
+                        Some(peer) => peer.upgrade().ok_or(-(ECONNREFUSED as isize)),
+                        None => Err(-(ENOTCONN as isize)),
+                    },
+                };
+                match target {
+                    Ok(target) if target.ty != UnixType::Dgram => -(EPROTOTYPE as isize),
+                    Ok(target) => self.send_dgram(target, data, files),
+                    Err(err) => err,
+                }
+            }
+        }
+    }
+
+    fn send_stream(&self, peer: &Weak<UnixSocket>, data: &[u8], files: Vec<FileRef>) -> isize {
+        let blocking = self.options.exclusive_access().send_blocking();
+        let mut files = Some(files);
+        let mut sent = 0;
+        while sent < data.len() {
+            // the peer is not held while asleep, so closing it wakes us
+            let peer = match peer.upgrade() {
+                Some(peer) => peer,
+                None if sent > 0 => break,
+                None => return -(EPIPE as isize),
+            };
+            let mut peer_inner = peer.inner.exclusive_access();
+            if peer_inner.shut_rd {
+                return -(EPIPE as isize);
+            }
+            let room = UNIX_STREAM_BUF - peer_inner.rx_len;
+            if room == 0 {
+                let task_ctx_ptr = match blocking.wait_no_sched(&peer.wait_queue) {
+                    Ok(task_ctx_ptr) => task_ctx_ptr,
+                    Err(_) if sent > 0 => break,
+                    Err(err) => return err,
+                };
+                let wait_queue = peer.wait_queue.clone();
+                drop(peer_inner);
+                drop(peer);
+                blocking.schedule(task_ctx_ptr, &wait_queue);
+                continue;
+            }
+            let len = room.min(data.len() - sent);
+            peer_inner.rx.push_back(Message {
+                data: data[sent..sent + len].to_vec(),
+                files: files.take().unwrap_or_default(),
+                from: None,
+            });
+            peer_inner.rx_len += len;
+            sent += len;
+            drop(peer_inner);
+            peer.wait_queue.wake_all();
+        }
+        sent as isize
+    }
+
+    fn send_dgram(&self, target: Arc<UnixSocket>, data: &[u8], files: Vec<FileRef>) -> isize {
+        if data.len() > UNIX_DGRAM_MAX {
+            return -(EMSGSIZE as isize);
+        }
+        let from = self.inner.exclusive_access().name.clone();
+        let blocking = self.options.exclusive_access().send_blocking();
+        let weak = Arc::downgrade(&target);
+        let mut target = Some(target);
+        loop {
+            let target = match target.take().or_else(|| weak.upgrade()) {
+                Some(target) => target,
+                None => return -(ECONNREFUSED as isize),
+            };
+            let mut target_inner = target.inner.exclusive_access();
+            if target_inner.shut_rd {
+                return -(ECONNREFUSED as isize);
+            }
+            if target_inner.rx.len() >= UNIX_DGRAM_QUEUE {
+                let task_ctx_ptr = match blocking.wait_no_sched(&target.wait_queue) {
+                    Ok(task_ctx_ptr) => task_ctx_ptr,
+                    Err(err) => return err,
+                };
+                let wait_queue = target.wait_queue.clone();
+                drop(target_inner);
+                drop(target);
+                blocking.schedule(task_ctx_ptr, &wait_queue);
+                continue;
+            }
+            target_inner.rx.push_back(Message {
+                data: data.to_vec(),
+                files,
+                from,
+            });
+            target_inner.rx_len += data.len();
+            drop(target_inner);
+            target.wait_queue.wake_all();
+            return data.len() as isize;
+        }
+    }
+
+    /// Wait for data, a stream returns 0 at its end.
+    pub fn recv(&self, buf: UserBuffer) -> Result<Received, isize> {
+        let blocking = self.options.exclusive_access().recv_blocking();
+        let mut inner = loop {
+            let inner = self.inner.exclusive_access();
+            if !inner.rx.is_empty() {
+                break inner;
+            }
+            if self.ty == UnixType::Stream {
+                match inner.state {
+                    UnixState::Unconnected => return Err(-(ENOTCONN as isize)),
+                    UnixState::Listening { .. } => return Err(-(EINVAL as isize)),
+                    UnixState::Connected(_) => {}
+                }
+            }
+            if inner.eof || inner.shut_rd || buf.len() == 0 {
+                return Ok(Received {
+                    len: 0,
+                    files: Vec::new(),
+                    from: None,
+                    truncated: false,
+                });
+            }
+            let task_ctx_ptr = blocking.wait_no_sched(&self.wait_queue)?;
+            drop(inner);
+            blocking.schedule(task_ctx_ptr, &self.wait_queue);
+        };
+        let received = match self.ty {
+            UnixType::Stream => {
+                let mut data = Vec::with_capacity(buf.len().min(inner.rx_len));
+                let mut files = Vec::new();
+                while data.len() < buf.len() {
+                    let message = match inner.rx.front_mut() {
+                        Some(message) if data.is_empty() || message.files.is_empty() => message,
+                        _ => break,
+                    };
+                    files.append(&mut message.files);
+                    let len = message.data.len().min(buf.len() - data.len());
+                    data.extend(message.data.drain(..len));
+                    if message.data.is_empty() {
+                        inner.rx.pop_front();
+                    }
+                }
+                inner.rx_len -= data.len();
+                drop(inner);
+                Received {
+                    len: copy_to_user(buf, &data),
+                    files,
+                    from: None,
+                    truncated: false,
+                }
+            }
+            UnixType::Dgram => {
+                let message = inner.rx.pop_front().unwrap();
+                inner.rx_len -= message.data.len();
+                drop(inner);
+                Received {
+                    truncated: message.data.len() > buf.len(),
+                    len: copy_to_user(buf, &message.data),
+                    files: message.files,
+                    from: message.from,
+                }
+            }
+        };
+        // room for whoever sends to us, and for the peer polling for it
+        self.wait_queue.wake_all();
+        if let Some(peer) = self.peer().and_then(|peer| peer.upgrade()) {
+            peer.wait_queue.wake_all();
+        }
+        Ok(received)
+    }
+
+    pub fn shutdown(&self, how: usize) -> isize {
+        let mut inner = self.inner.exclusive_access();
+        if !matches!(inner.state, UnixState::Connected(_)) {
+            return -(ENOTCONN as isize);
+        }
+        match how {
+            SHUT_RD => inner.shut_rd = true,
+            SHUT_WR => inner.shut_wr = true,
+            SHUT_RDWR => {
+                inner.shut_rd = true;
+                inner.shut_wr = true;
+            }
+            _ => return -(EINVAL as isize),
+        }
+        let shut_wr = inner.shut_wr;
+        drop(inner);
+        self.wait_queue.wake_all();
+        if let Some(peer) = self.peer().and_then(|peer| peer.upgrade()) {
+            if shut_wr && self.ty == UnixType::Stream {
+                peer.inner.exclusive_access().eof = true;
+            }
+            peer.wait_queue.wake_all();
+        }
+        0
+    }
+
+    pub fn name(&self) -> Option<String> {
+        self.inner.exclusive_access().name.clone()
+    }
+
+    pub fn options(&self) -> &UPIntrFreeCell<SocketOptions> {
+        &self.options
+    }
+
+    pub fn peer_name(&self) -> Result<Option<String>, isize> {
+        match self.peer().and_then(|peer| peer.upgrade()) {
+            Some(peer) => Ok(peer.name()),
+            None => Err(-(ENOTCONN as isize)),
+        }
+    }
+}
+
+impl File for UnixSocket {
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn read(&self, buf: UserBuffer) -> usize {
+        match self.recv(buf) {
+            Ok(received) => received.len,
+            Err(err) => err as usize,
+        }
+    }
+
+    fn write(&self, buf: UserBuffer) -> usize {
+        let data = super::socket::copy_from_user(&buf);
+        self.send(&data, Vec::new(), None) as usize
+    }
+
+    fn poll_ready(&self, events: PollEvents) -> PollEvents {
+        let inner = self.inner.exclusive_access();
+        let mut ready = PollEvents::empty();
+        match &inner.state {
+            UnixState::Listening { pending, .. } => {
+                if !pending.is_empty() {
+                    ready |= PollEvents::IN;
+                }
+                return ready & events;
+            }
+            UnixState::Unconnected if self.ty == UnixType::Stream => {
+                return (PollEvents::OUT | PollEvents::HUP) & events;
+            }
+            _ => {}
+        }
+        if !inner.rx.is_empty() || inner.eof || inner.shut_rd {
+            ready |= PollEvents::IN;
+        }
+        if inner.eof && self.ty == UnixType::Stream {
+            ready |= PollEvents::HUP;
+        }
+        drop(inner);
+        // a stream can be written once its peer has room, or is gone
+        let writable = self.ty == UnixType::Dgram
+            || self
+                .peer()
+                .and_then(|peer| peer.upgrade())
+                .map_or(true, |peer| {
+                    peer.inner.exclusive_access().rx_len < UNIX_STREAM_BUF
+                });
+        if writable {
+            ready |= PollEvents::OUT;
+        }
+        ready & events
+    }
+
+    fn wait_queue(&self) -> Option<&WaitQueue> {
+        Some(&self.wait_queue)
+    }
+
+    fn nonblocking(&self) -> bool {
+        self.options.exclusive_access().nonblock
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) {
+        self.options.exclusive_access().nonblock = nonblocking;
+    }
+
+    fn as_unix_socket(&self) -> Option<&UnixSocket> {
+        Some(self)
+    }
+}
+
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        // taken out first, files in flight may be sockets that look at us
+        // while they go
+        let (name, rx, state) = {
+            let mut inner = self.inner.exclusive_access();
+            (
+                inner.name.take(),
+                mem::take(&mut inner.rx),
+                mem::replace(&mut inner.state, UnixState::Unconnected),
+            )
+        };
+        if let Some(name) = name {
+            let mut names = UNIX_NAMES.exclusive_access();
+            if names
+                .get(&name)
+                .map_or(false, |socket| socket.ptr_eq(&self.this))
+            {
+                names.remove(&name);
+            }
+        }
+        drop(rx);
+        if let UnixState::Connected(peer) = state {
+            if let Some(peer) = peer.upgrade() {
+                if self.ty == UnixType::Stream {
+                    peer.inner.exclusive_access().eof = true;
+                }
+                peer.wait_queue.wake_all();
+            }
+        }
+        // senders asleep on our queue find us gone
+        self.wait_queue.wake_all();
+    }
+}
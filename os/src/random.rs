@@ -0,0 +1,167 @@
+//! Kernel random numbers: ChaCha20 run as a fast key erasure generator.
+//! Every request also replaces the key, so what was handed out before can
+//! not be worked back out of the state. The key is seeded at boot from the
+//! virtio entropy device, or from timer jitter on machines without one.
+
+use crate::{
+    drivers::rng_device,
+    sync::UPIntrFreeCell,
+    timer::get_time,
+};
+use core::hint::spin_loop;
+use lazy_static::*;
+use log::info;
+
+/// "expand 32-byte k"
+const CHACHA_CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+const SEED_BYTES: usize = 64;
+/// Output after which fresh entropy is taken from the device.
+const RESEED_BYTES: usize = 1 << 20;
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// Block `counter` of the ChaCha20 stream of `key`, with a zero nonce.
+fn chacha20_block(key: &[u32; 8], counter: u64) -> [u8; 64] {
+    let mut input = [0u32; 16];
+    input[..4].copy_from_slice(&CHACHA_CONSTANTS);
+    input[4..12].copy_from_slice(key);
+    input[12] = counter as u32;
+    input[13] = (counter >> 32) as u32;
+    let mut state = input;
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+    let mut block = [0u8; 64];
+    for (i, word) in block.chunks_exact_mut(4).enumerate() {
+        word.copy_from_slice(&state[i].wrapping_add(input[i]).to_le_bytes());
+    }
+    block
+}
+
+struct Generator {
+    key: [u32; 8],
+    /// Bytes handed out since entropy was last mixed in.
+    handed_out: usize,
+}
+
+impl Generator {
+    fn set_key(&mut self, bytes: &[u8]) {
+        for (word, bytes) in self.key.iter_mut().zip(bytes.chunks_exact(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+    }
+
+    /// Each 32 bytes of `entropy` are xored into the key, which is then
+    /// replaced by the first block under it.
+    fn mix(&mut self, entropy: &[u8]) {
+        for piece in entropy.chunks(32) {
+            for (i, byte) in piece.iter().enumerate() {
+                self.key[i / 4] ^= (*byte as u32) << (i % 4 * 8);
+            }
+            let block = chacha20_block(&self.key, 0);
+            self.set_key(&block[..32]);
+        }
+        self.handed_out = 0;
+    }
+
+    /// The first half of block 0 becomes the next key, the rest of the
+    /// stream is the output.
+    fn fill(&mut self, buf: &mut [u8]) {
+        let first = chacha20_block(&self.key, 0);
+        let head = buf.len().min(32);
+        buf[..head].copy_from_slice(&first[32..32 + head]);
+        for (counter, chunk) in buf[head..].chunks_mut(64).enumerate() {
+            let block = chacha20_block(&self.key, counter as u64 + 1);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+        self.set_key(&first[..32]);
+        self.handed_out += buf.len();
+    }
+}
+
+lazy_static! {
+    static ref GENERATOR: UPIntrFreeCell<Generator> = unsafe {
+        UPIntrFreeCell::new(Generator {
+            key: [0; 8],
+            handed_out: 0,
+        })
+    };
+}
+
+/// Fill `buf` from the entropy device, false without one or when it
+/// stops giving.
+fn device_entropy(buf: &mut [u8]) -> bool {
+    let device = match rng_device() {
+        Some(device) => device,
+        None => return false,
+    };
+    let mut filled = 0;
+    while filled < buf.len() {
+        match device.read(&mut buf[filled..]) {
+            0 => return false,
+            len => filled += len,
+        }
+    }
+    true
+}
+
+/// How long a little busy work takes in timer ticks varies with caches,
+/// interrupts and, under QEMU, the host. A sample carries a bit or so, so
+/// each byte folds in many of them.
+fn timer_jitter(buf: &mut [u8]) {
+    for byte in buf.iter_mut() {
+        let mut folded = 0u8;
+        for _ in 0..64 {
+            let start = get_time();
+            for _ in 0..(64 + (start & 0xff)) {
+                spin_loop();
+            }
+            folded = folded.rotate_left(3) ^ (get_time() - start) as u8;
+        }
+        *byte = folded;
+    }
+}
+
+/// Seed the generator. Runs once the drivers are up.
+pub fn init() {
+    let mut seed = [0u8; SEED_BYTES];
+    if device_entropy(&mut seed) {
+        info!("KERN: random seeded from virtio-rng");
+    } else {
+        timer_jitter(&mut seed);
+        info!("KERN: random seeded from timer jitter");
+    }
+    GENERATOR.exclusive_access().mix(&seed);
+}
+
+/// Stir `entropy` into the generator, like bytes written to `/dev/random`.
+pub fn mix(entropy: &[u8]) {
+    GENERATOR.exclusive_access().mix(entropy);
+}
+
+pub fn fill(buf: &mut [u8]) {
+    let mut generator = GENERATOR.exclusive_access();
+    if generator.handed_out >= RESEED_BYTES {
+        let mut entropy = [0u8; 32];
+        if device_entropy(&mut entropy) {
+            generator.mix(&entropy);
+        }
+    }
+    generator.fill(buf);
+}
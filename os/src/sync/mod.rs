@@ -2,6 +2,7 @@ mod condvar;
 mod mutex;
 mod semaphore;
 mod up;
+mod wait_queue;
 
 pub use condvar::Condvar;
 pub use mutex::{
@@ -11,6 +12,8 @@ pub use mutex::{
 };
 pub use semaphore::Semaphore;
 pub use up::{
+    intr_free,
     UPIntrFreeCell,
     UPIntrRefMut,
 };
+pub use wait_queue::WaitQueue;
@@ -98,6 +98,15 @@ impl IntrMaskingInfo {
     }
 }
 
+/// Run `f` with interrupts masked, so that checking a condition and going to
+/// sleep on it can not race an interrupt handler that changes it.
+pub fn intr_free<V>(f: impl FnOnce() -> V) -> V {
+    INTR_MASKING_INFO.get_mut().enter();
+    let ret = f();
+    INTR_MASKING_INFO.get_mut().exit();
+    ret
+}
+
 pub struct UPIntrFreeCell<T> {
     /// inner data
     inner: RefCell<T>,
@@ -120,6 +129,19 @@ impl<T> UPIntrFreeCell<T> {
         UPIntrRefMut(Some(self.inner.borrow_mut()))
     }
 
+    /// `None` instead of a panic if the data is borrowed, for the panic
+    /// handler.
+    pub fn try_exclusive_access<'a>(&'a self) -> Option<UPIntrRefMut<'a, T>> {
+        INTR_MASKING_INFO.get_mut().enter();
+        match self.inner.try_borrow_mut() {
+            Ok(inner) => Some(UPIntrRefMut(Some(inner))),
+            Err(_) => {
+                INTR_MASKING_INFO.get_mut().exit();
+                None
+            }
+        }
+    }
+
     pub fn exclusive_session<F, V>(&self, f: F) -> V
     where
         F: FnOnce(&mut T) -> V,
@@ -0,0 +1,58 @@
+use crate::{
+    sync::UPIntrFreeCell,
+    task::{
+        block_current_task,
+        current_task,
+        wakeup_blocked_task,
+        TaskContext,
+        TaskStruct,
+    },
+};
+use alloc::{
+    collections::VecDeque,
+    sync::Arc,
+};
+
+/// Tasks waiting for something (a file becoming readable, ...) to change.
+/// Unlike `Condvar` every waiter is woken and checks again, and a task may
+/// sit in several queues at once, as `poll` does.
+pub struct WaitQueue {
+    tasks: UPIntrFreeCell<VecDeque<Arc<TaskStruct>>>,
+}
+
+impl WaitQueue {
+    pub fn new() -> Self {
+        Self {
+            tasks: unsafe { UPIntrFreeCell::new(VecDeque::new()) },
+        }
+    }
+
+    pub fn register(&self, task: Arc<TaskStruct>) {
+        let mut tasks = self.tasks.exclusive_access();
+        if !tasks.iter().any(|t| Arc::ptr_eq(t, &task)) {
+            tasks.push_back(task);
+        }
+    }
+
+    pub fn unregister(&self, task: &Arc<TaskStruct>) {
+        self.tasks
+            .exclusive_access()
+            .retain(|t| !Arc::ptr_eq(t, task));
+    }
+
+    /// Sleep on this queue. Must be followed by a schedule, and the caller
+    /// should hold the lock protecting the condition until then.
+    pub fn wait_no_sched(&self) -> *mut TaskContext {
+        self.register(current_task().unwrap());
+        block_current_task()
+    }
+
+    pub fn wake_all(&self) {
+        let tasks: VecDeque<Arc<TaskStruct>> = self
+            .tasks
+            .exclusive_session(|tasks| tasks.drain(..).collect());
+        for task in tasks {
+            wakeup_blocked_task(task);
+        }
+    }
+}
@@ -1,8 +1,13 @@
 use crate::{
     fs::{
+        absolute_path,
         make_pipe,
-        open_file,
+        mknod,
+        mount,
+        open,
+        File,
         OpenFlags,
+        TmpFs,
     },
     mm::{
         translated_byte_buffer,
@@ -15,7 +20,15 @@ use crate::{
         current_user_token,
     },
 };
-use alloc::sync::Arc;
+use alloc::{
+    string::String,
+    sync::Arc,
+};
+use shared_defination::error::{
+    EBADF,
+    EINVAL,
+    ENODEV,
+};
 
 use super::user_space::__user;
 
@@ -24,18 +37,18 @@ pub fn sys_write(fd: usize, buf: __user<*const u8>, len: usize) -> isize {
     let process = current_process();
     let inner = process.inner_exclusive_access();
     if fd >= inner.fd_table.len() {
-        return -1;
+        return -(EBADF as isize);
     }
     if let Some(file) = &inner.fd_table[fd] {
         if !file.writable() {
-            return -1;
+            return -(EBADF as isize);
         }
         let file = file.clone();
         // release current task TCB manually to avoid multi-borrow
         drop(inner);
         file.write(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
     } else {
-        -1
+        -(EBADF as isize)
     }
 }
 
@@ -44,29 +57,36 @@ pub fn sys_read(fd: usize, buf: __user<*const u8>, len: usize) -> isize {
     let process = current_process();
     let inner = process.inner_exclusive_access();
     if fd >= inner.fd_table.len() {
-        return -1;
+        return -(EBADF as isize);
     }
     if let Some(file) = &inner.fd_table[fd] {
         let file = file.clone();
         if !file.readable() {
-            return -1;
+            return -(EBADF as isize);
         }
         // release current task TCB manually to avoid multi-borrow
         drop(inner);
         file.read(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
     } else {
-        -1
+        -(EBADF as isize)
     }
 }
 
 pub fn sys_open(path:__user< *const u8>, flags: u32) -> isize {
     let process = current_process();
     let token = current_user_token();
-    let path = translated_str(token, path);
-    if let Some(inode) = open_file(path.as_str(), OpenFlags::from_bits(flags).unwrap()) {
+    let path = absolute_path(&process.getcwd(), &translated_str(token, path));
+    let flags = OpenFlags::from_bits(flags).unwrap();
+    if let Some(inode) = open(path.as_str(), flags) {
+        if flags.contains(OpenFlags::NONBLOCK) {
+            inode.set_nonblocking(true);
+        }
         let mut inner = process.inner_exclusive_access();
         let fd = inner.alloc_fd();
         inner.fd_table[fd] = Some(inode);
+        if flags.contains(OpenFlags::CLOEXEC) {
+            inner.close_on_exec.insert(fd);
+        }
         fd as isize
     } else {
         -1
@@ -86,15 +106,28 @@ pub fn sys_close(fd: usize) -> isize {
     0
 }
 
-pub fn sys_pipe(pipe: __user<*mut usize>) -> isize {
+/// `flags` may hold `O_NONBLOCK` and `O_CLOEXEC`, for both ends.
+pub fn sys_pipe(pipe: __user<*mut usize>, flags: u32) -> isize {
+    let flags = match OpenFlags::from_bits(flags) {
+        Some(flags) if (flags - (OpenFlags::NONBLOCK | OpenFlags::CLOEXEC)).is_empty() => flags,
+        _ => return -(EINVAL as isize),
+    };
     let process = current_process();
     let token = current_user_token();
     let mut inner = process.inner_exclusive_access();
     let (pipe_read, pipe_write) = make_pipe();
+    if flags.contains(OpenFlags::NONBLOCK) {
+        pipe_read.set_nonblocking(true);
+        pipe_write.set_nonblocking(true);
+    }
     let read_fd = inner.alloc_fd();
     inner.fd_table[read_fd] = Some(pipe_read);
     let write_fd = inner.alloc_fd();
     inner.fd_table[write_fd] = Some(pipe_write);
+    if flags.contains(OpenFlags::CLOEXEC) {
+        inner.close_on_exec.insert(read_fd);
+        inner.close_on_exec.insert(write_fd);
+    }
     *translated_refmut(token, pipe) = read_fd;
     *translated_refmut(token, unsafe { pipe.inner().add(1).into() }) = write_fd;
     0
@@ -113,3 +146,145 @@ pub fn sys_dup(fd: usize) -> isize {
     inner.fd_table[new_fd] = Some(Arc::clone(inner.fd_table[fd].as_ref().unwrap()));
     new_fd as isize
 }
+
+const F_DUPFD: usize = 0;
+const F_GETFD: usize = 1;
+const F_SETFD: usize = 2;
+const F_GETFL: usize = 3;
+const F_SETFL: usize = 4;
+const F_DUPFD_CLOEXEC: usize = 1030;
+const FD_CLOEXEC: usize = 1;
+/// `F_DUPFD` refuses to go past this, like `RLIMIT_NOFILE` on Linux.
+const FD_LIMIT: usize = 1024;
+
+/// Of the status flags only `O_NONBLOCK` can be changed, the rest of
+/// `F_SETFL` is ignored.
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -(EBADF as isize),
+    };
+    match cmd {
+        F_DUPFD | F_DUPFD_CLOEXEC => {
+            if arg >= FD_LIMIT {
+                return -(EINVAL as isize);
+            }
+            let new_fd = inner.alloc_fd_from(arg);
+            inner.fd_table[new_fd] = Some(file);
+            if cmd == F_DUPFD_CLOEXEC {
+                inner.close_on_exec.insert(new_fd);
+            }
+            new_fd as isize
+        }
+        F_GETFD => {
+            if inner.close_on_exec.contains(&fd) {
+                FD_CLOEXEC as isize
+            } else {
+                0
+            }
+        }
+        F_SETFD => {
+            if arg & FD_CLOEXEC != 0 {
+                inner.close_on_exec.insert(fd);
+            } else {
+                inner.close_on_exec.remove(&fd);
+            }
+            0
+        }
+        F_GETFL => {
+            drop(inner);
+            let mut flags = match (file.readable(), file.writable()) {
+                (true, true) => OpenFlags::RDWR,
+                (false, true) => OpenFlags::WRONLY,
+                _ => OpenFlags::RDONLY,
+            };
+            if file.nonblocking() {
+                flags |= OpenFlags::NONBLOCK;
+            }
+            flags.bits() as isize
+        }
+        F_SETFL => {
+            drop(inner);
+            file.set_nonblocking(arg & OpenFlags::NONBLOCK.bits() as usize != 0);
+            0
+        }
+        _ => -(EINVAL as isize),
+    }
+}
+
+pub fn sys_ioctl(fd: usize, cmd: u32, arg: usize) -> isize {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    if let Some(Some(file)) = inner.fd_table.get(fd) {
+        let file = file.clone();
+        drop(inner);
+        if file.as_socket().is_some() {
+            if let Some(ret) = super::net::interface_ioctl(cmd, arg) {
+                return ret;
+            }
+        }
+        file.ioctl(cmd, arg)
+    } else {
+        -(EBADF as isize)
+    }
+}
+
+/// `dirfd` is ignored, relative paths start from the working directory.
+pub fn sys_mknodat(_dirfd: isize, path: __user<*const u8>, mode: u32, dev: u64) -> isize {
+    let process = current_process();
+    let path = absolute_path(&process.getcwd(), &translated_str(current_user_token(), path));
+    mknod(path.as_str(), mode, dev)
+}
+
+pub fn sys_getdents64(fd: usize, buf: __user<*const u8>, len: usize) -> isize {
+    let token = current_user_token();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    if let Some(Some(file)) = inner.fd_table.get(fd) {
+        let file = file.clone();
+        drop(inner);
+        file.getdents(UserBuffer::new(translated_byte_buffer(token, buf, len)))
+    } else {
+        -(EBADF as isize)
+    }
+}
+
+pub fn sys_ftruncate(fd: usize, len: usize) -> isize {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    if let Some(Some(file)) = inner.fd_table.get(fd) {
+        let file = file.clone();
+        drop(inner);
+        if !file.writable() {
+            return -(EBADF as isize);
+        }
+        file.truncate(len)
+    } else {
+        -(EBADF as isize)
+    }
+}
+
+/// Only `tmpfs` can be mounted, `source` and `flags` are ignored. `data`,
+/// when not null, is the option string, like `size=16m`.
+pub fn sys_mount(
+    _source: __user<*const u8>, target: __user<*const u8>, fstype: __user<*const u8>,
+    _flags: usize, data: __user<*const u8>,
+) -> isize {
+    let token = current_user_token();
+    let process = current_process();
+    let target = absolute_path(&process.getcwd(), &translated_str(token, target));
+    let options = if data.inner().is_null() {
+        String::new()
+    } else {
+        translated_str(token, data)
+    };
+    match translated_str(token, fstype).as_str() {
+        "tmpfs" => match TmpFs::with_options(&options) {
+            Some(fs) => mount(target.as_str(), Arc::new(fs)),
+            None => -(EINVAL as isize),
+        },
+        _ => -(ENODEV as isize),
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    drivers::GPU_DEVICE,
+    drivers::gpu_device,
     mm::{
         MapArea,
         MapPermission,
@@ -9,11 +9,16 @@ use crate::{
     },
     task::current_process,
 };
+use shared_defination::error::ENODEV;
 
 const FB_VADDR: usize = 0x10000000;
 
 pub fn sys_framebuffer() -> isize {
-    let fb = GPU_DEVICE.get_framebuffer();
+    let gpu = match gpu_device() {
+        Some(gpu) => gpu,
+        None => return -(ENODEV as isize),
+    };
+    let fb = gpu.get_framebuffer();
     let len = fb.len();
     // println!("[kernel] FrameBuffer: addr 0x{:X}, len {}", fb.as_ptr() as usize , len);
     let fb_start_pa = PhysAddr::from(fb.as_ptr() as usize);
@@ -37,6 +42,11 @@ pub fn sys_framebuffer() -> isize {
 }
 
 pub fn sys_framebuffer_flush() -> isize {
-    GPU_DEVICE.flush();
-    0
+    match gpu_device() {
+        Some(gpu) => {
+            gpu.flush();
+            0
+        }
+        None => -(ENODEV as isize),
+    }
 }
@@ -1,28 +1,24 @@
-//use crate::drivers::{KEYBOARD_DEVICE,MOUSE_DEVICE,INPUT_CONDVAR,read_input_event};
 use crate::drivers::{
-    KEYBOARD_DEVICE,
-    MOUSE_DEVICE,
+    input_device,
+    InputKind,
 };
 
+/// An event from the keyboard, else from the mouse, 0 if neither has one.
 pub fn sys_event_get() -> isize {
-    let kb = KEYBOARD_DEVICE.clone();
-    let mouse = MOUSE_DEVICE.clone();
-    //let input=INPUT_CONDVAR.clone();
-    //read_input_event() as isize
-    if !kb.is_empty() {
-        kb.read_event() as isize
-    } else if !mouse.is_empty() {
-        mouse.read_event() as isize
-    } else {
-        0
+    for kind in [InputKind::Keyboard, InputKind::Mouse] {
+        match input_device(kind) {
+            Some(device) if !device.is_empty() => return device.read_event() as isize,
+            _ => {}
+        }
     }
+    0
 }
 
-use crate::drivers::chardev::UART;
+use crate::fs::console;
 
-/// check UART's read-buffer is empty or not
+/// check whether the console has input to read
 pub fn sys_key_pressed() -> isize {
-    let res = !UART.read_buffer_is_empty();
+    let res = console().input_ready();
     if res {
         1
     } else {
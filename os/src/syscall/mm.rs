@@ -1,4 +1,9 @@
 use crate::task::current_process;
+use shared_defination::error::{
+    EACCES,
+    EBADF,
+    EINVAL,
+};
 
 pub fn sys_brk(size: isize) -> isize {
     let process = current_process();
@@ -10,9 +15,38 @@ pub fn sys_brk(size: isize) -> isize {
     }
 }
 
-pub fn sys_mmap(start: usize, len: usize, prot: usize) -> isize {
+const PROT_READ: usize = 0x1;
+const PROT_WRITE: usize = 0x2;
+const PROT_EXEC: usize = 0x4;
+
+const MAP_SHARED: usize = 0x01;
+const MAP_ANONYMOUS: usize = 0x20;
+
+pub fn sys_mmap(
+    start: usize, len: usize, prot: usize, flags: usize, fd: isize, offset: usize,
+) -> isize {
+    if prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+        return -(EINVAL as isize);
+    }
     let process = current_process();
-    process.current_task_mmap(start, len, prot)
+    if flags & MAP_ANONYMOUS != 0 || fd < 0 {
+        return process.current_task_mmap(start, len, prot);
+    }
+    // file pages are only ever mapped shared, there are no private copies
+    if flags & MAP_SHARED == 0 {
+        return -(EINVAL as isize);
+    }
+    let inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd as usize) {
+        Some(Some(file)) => file.clone(),
+        _ => return -(EBADF as isize),
+    };
+    drop(inner);
+    // the pages are the file's own, so the mapping can do no more than the fd
+    if !file.readable() || prot & PROT_WRITE != 0 && !file.writable() {
+        return -(EACCES as isize);
+    }
+    process.current_task_mmap_file(start, len, prot, file, offset)
 }
 
 pub fn sys_munmap(start: usize) -> isize {
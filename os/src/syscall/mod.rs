@@ -3,9 +3,12 @@ mod gui;
 mod input;
 mod mm;
 mod net;
+mod poll;
 pub mod process;
+mod random;
 mod sync;
 mod thread;
+pub mod time;
 pub mod user_space;
 
 use fs::*;
@@ -17,33 +20,138 @@ use mm::{
     sys_munmap,
 };
 use net::*;
+use poll::*;
 use process::*;
+use random::sys_getrandom;
 use sync::*;
 use thread::*;
+use time::*;
 #[allow(unused)]
 extern crate shared_defination;
 use shared_defination::syscall_nr::call;
 use user_space::__user;
 
+#[repr(C)]
 pub struct TimeVal {
     sec: u64,  // 自 Unix 纪元起的秒数
-    #[allow(unused)]
     usec: u64, // 微秒数
 }
 
+#[repr(C)]
+pub struct TimeSpec {
+    sec: u64,
+    nsec: u64,
+}
+
 pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
     match syscall_id {
         // net
         call::DUP3 => sys_dup(args[0]),
-        call::CONNECT => sys_connect(args[0] as _, args[1] as _, args[2] as _),
-        call::LISTEN => sys_listen(args[0] as _),
-        call::ACCEPT => sys_accept(args[0] as _),
+        call::SOCKET => sys_socket(args[0], args[1], args[2]),
+        call::SOCKETPAIR => sys_socketpair(
+            args[0],
+            args[1],
+            args[2],
+            __user::new(args[3] as *mut i32),
+        ),
+        call::BIND => sys_bind(args[0], __user::new(args[1] as *const SockAddrIn), args[2]),
+        call::LISTEN => sys_listen(args[0], args[1]),
+        call::ACCEPT => sys_accept4(
+            args[0],
+            __user::new(args[1] as *mut SockAddrIn),
+            __user::new(args[2] as *mut u32),
+            0,
+        ),
+        call::ACCEPT4 => sys_accept4(
+            args[0],
+            __user::new(args[1] as *mut SockAddrIn),
+            __user::new(args[2] as *mut u32),
+            args[3],
+        ),
+        call::CONNECT => sys_connect(args[0], __user::new(args[1] as *const SockAddrIn), args[2]),
+        call::SENDTO => sys_sendto(
+            args[0],
+            __user::new(args[1] as *const u8),
+            args[2],
+            args[3],
+            __user::new(args[4] as *const SockAddrIn),
+            args[5],
+        ),
+        call::RECVFROM => sys_recvfrom(
+            args[0],
+            __user::new(args[1] as *const u8),
+            args[2],
+            args[3],
+            __user::new(args[4] as *mut SockAddrIn),
+            __user::new(args[5] as *mut u32),
+        ),
+        call::SHUTDOWN => sys_shutdown(args[0], args[1]),
+        call::GETSOCKNAME => sys_getsockname(
+            args[0],
+            __user::new(args[1] as *mut SockAddrIn),
+            __user::new(args[2] as *mut u32),
+        ),
+        call::GETPEERNAME => sys_getpeername(
+            args[0],
+            __user::new(args[1] as *mut SockAddrIn),
+            __user::new(args[2] as *mut u32),
+        ),
+        call::SETSOCKOPT => sys_setsockopt(
+            args[0],
+            args[1],
+            args[2],
+            __user::new(args[3] as *const u8),
+            args[4],
+        ),
+        call::GETSOCKOPT => sys_getsockopt(
+            args[0],
+            args[1],
+            args[2],
+            __user::new(args[3] as *mut u8),
+            __user::new(args[4] as *mut u32),
+        ),
+        call::SENDMSG => sys_sendmsg(args[0], __user::new(args[1] as *const MsgHdr), args[2]),
+        call::RECVMSG => sys_recvmsg(args[0], __user::new(args[1] as *mut MsgHdr), args[2]),
 
         // Process
         call::EXIT => sys_exit(args[0] as i32),
-        call::NANOSLEEP => sys_nanosleep(__user::new(args[0] as *mut TimeVal)),
+        call::NANOSLEEP => sys_nanosleep(__user::new(args[0] as *const TimeSpec)),
+        call::CLOCK_GETTIME => sys_clock_gettime(args[0], __user::new(args[1] as *mut TimeSpec)),
+        call::CLOCK_GETRES => sys_clock_getres(args[0], __user::new(args[1] as *mut TimeSpec)),
+        call::CLOCK_NANOSLEEP => sys_clock_nanosleep(
+            args[0],
+            args[1],
+            __user::new(args[2] as *const TimeSpec),
+            __user::new(args[3] as *mut TimeSpec),
+        ),
+        call::GETITIMER => sys_getitimer(args[0], __user::new(args[1] as *mut ITimerVal)),
+        call::SETITIMER => sys_setitimer(
+            args[0],
+            __user::new(args[1] as *const ITimerVal),
+            __user::new(args[2] as *mut ITimerVal),
+        ),
+        call::TIMER_CREATE => sys_timer_create(
+            args[0],
+            __user::new(args[1] as *const SigEvent),
+            __user::new(args[2] as *mut i32),
+        ),
+        call::TIMER_SETTIME => sys_timer_settime(
+            args[0],
+            args[1],
+            __user::new(args[2] as *const ITimerSpec),
+            __user::new(args[3] as *mut ITimerSpec),
+        ),
+        call::TIMER_GETTIME => sys_timer_gettime(args[0], __user::new(args[1] as *mut ITimerSpec)),
+        call::TIMER_DELETE => sys_timer_delete(args[0]),
         call::SCHED_YIELD => sys_yield(),
         call::KILL => sys_kill(args[0], args[1] as u32),
+        call::RT_SIGACTION => sys_sigaction(
+            args[0],
+            __user::new(args[1] as *const SigAction),
+            __user::new(args[2] as *mut SigAction),
+        ),
+        call::SETPGID => sys_setpgid(args[0], args[1]),
+        call::GETPGID => sys_getpgid(args[0]),
         call::GETTIMEOFDAY => sys_get_time(__user::new(args[0] as *mut TimeVal), args[1] as i32),
         call::GETPID => sys_getpid(),
         call::CLONE => sys_fork(),
@@ -56,6 +164,11 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
         call::GETTID => sys_gettid(),
         call::WAITID => sys_waittid(args[0]) as isize,
         call::GETPPID => sys_getppid(),
+        call::GETRANDOM => sys_getrandom(
+            __user::new(args[0] as *const u8),
+            args[1],
+            args[2] as u32,
+        ),
 
         call::MUTEX_CREATE => sys_mutex_create(args[0] == 1),
         call::MUTEX_LOCK => sys_mutex_lock(args[0]),
@@ -74,7 +187,59 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
         // fs
         call::OPENAT => sys_open(__user::new(args[0] as *const u8), args[1] as u32),
         call::CLOSE => sys_close(args[0]),
-        call::PIPE2 => sys_pipe(__user::new(args[0] as *mut usize)),
+        call::GETDENTS64 => sys_getdents64(args[0], __user::new(args[1] as *const u8), args[2]),
+        call::IOCTL => sys_ioctl(args[0], args[1] as u32, args[2]),
+        call::FCNTL => sys_fcntl(args[0], args[1], args[2]),
+        call::FTRUNCATE => sys_ftruncate(args[0], args[1]),
+        call::PPOLL => sys_ppoll(
+            __user::new(args[0] as *mut PollFd),
+            args[1],
+            __user::new(args[2] as *const TimeSpec),
+        ),
+        call::PSELECT6 => sys_pselect6(
+            args[0],
+            __user::new(args[1] as *mut u64),
+            __user::new(args[2] as *mut u64),
+            __user::new(args[3] as *mut u64),
+            __user::new(args[4] as *const TimeSpec),
+        ),
+        call::EPOLL_CREATE1 => sys_epoll_create1(args[0]),
+        call::EPOLL_CTL => sys_epoll_ctl(
+            args[0],
+            args[1],
+            args[2],
+            __user::new(args[3] as *const EpollEvent),
+        ),
+        call::EPOLL_PWAIT => sys_epoll_pwait(
+            args[0],
+            __user::new(args[1] as *mut EpollEvent),
+            args[2] as i32,
+            args[3] as i32,
+        ),
+        call::TIMERFD_CREATE => sys_timerfd_create(args[0], args[1] as u32),
+        call::TIMERFD_SETTIME => sys_timerfd_settime(
+            args[0],
+            args[1],
+            __user::new(args[2] as *const ITimerSpec),
+            __user::new(args[3] as *mut ITimerSpec),
+        ),
+        call::TIMERFD_GETTIME => {
+            sys_timerfd_gettime(args[0], __user::new(args[1] as *mut ITimerSpec))
+        }
+        call::MOUNT => sys_mount(
+            __user::new(args[0] as *const u8),
+            __user::new(args[1] as *const u8),
+            __user::new(args[2] as *const u8),
+            args[3],
+            __user::new(args[4] as *const u8),
+        ),
+        call::MKNODAT => sys_mknodat(
+            args[0] as isize,
+            __user::new(args[1] as *const u8),
+            args[2] as u32,
+            args[3] as u64,
+        ),
+        call::PIPE2 => sys_pipe(__user::new(args[0] as *mut usize), args[1] as u32),
         call::READ => sys_read(args[0], __user::new(args[1] as *const u8), args[2]),
         call::WRITE => sys_write(args[0], __user::new(args[1] as *const u8), args[2]),
         call::GETCWD => sys_getcwd(__user::new(args[0] as *const u8), args[1]),
@@ -82,7 +247,9 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
         call::CHDIR => sys_chdir(__user::new(args[0] as *const u8)),
         call::FCHDIR => sys_fchdir(args[0]),
         call::MKDIRAT => sys_mkdirat(args[0] as isize, __user::new(args[1] as *const u8), args[2]),
-        call::UNLINKAT => sys_unlinkat(args[0] as isize, __user::new(args[1] as *const u8)),
+        call::UNLINKAT => {
+            sys_unlinkat(args[0] as isize, __user::new(args[1] as *const u8), args[2])
+        }
         call::SYMLINKAT => sys_symlinkat(
             __user::new(args[0] as *const u8),
             args[1] as isize,
@@ -98,7 +265,14 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
 
         // Mem
         call::BRK => sys_brk(args[0] as isize),
-        call::MMAP => sys_mmap(args[0], args[1], args[2]),
+        call::MMAP => sys_mmap(
+            args[0],
+            args[1],
+            args[2],
+            args[3],
+            args[4] as isize,
+            args[5],
+        ),
         call::MUNMAP => sys_munmap(args[0]),
 
         _ => panic!("Unsupported syscall_id: {}", syscall_id),
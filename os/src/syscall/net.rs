@@ -1,67 +1,873 @@
 use crate::{
+    fs::{
+        absolute_path,
+        File,
+    },
+    mm::{
+        translated_byte_buffer,
+        translated_ref,
+        translated_refmut,
+        UserBuffer,
+    },
     net::{
-        net_interrupt_handler,
-        port_table::{
-            accept,
-            listen,
-            port_acceptable,
-            PortFd,
+        iface::{
+            interface,
+            interfaces,
+            NetInterface,
+        },
+        set_address,
+        socket::{
+            copy_from_user,
+            copy_to_user,
         },
-        udp::UDP,
-        IPv4,
+        unix::{
+            FileRef,
+            UnixType,
+        },
+        IcmpSocket,
+        InetAddr,
+        SocketOptions,
+        TcpSocket,
+        UdpSocket,
+        UnixSocket,
     },
+    sync::UPIntrFreeCell,
     task::{
         current_process,
-        current_task,
-        current_trap_ctx,
+        current_user_token,
     },
 };
-use alloc::sync::Arc;
+use alloc::{
+    string::String,
+    sync::Arc,
+    vec::Vec,
+};
+use core::mem::size_of;
+use shared_defination::error::{
+    EAFNOSUPPORT,
+    EBADF,
+    EDOM,
+    EINVAL,
+    ENODEV,
+    ENOPROTOOPT,
+    ENOTCONN,
+    ENOTSOCK,
+    EOPNOTSUPP,
+    EPROTONOSUPPORT,
+    ESOCKTNOSUPPORT,
+};
 
-// just support udp
-pub fn sys_connect(raddr: u32, lport: u16, rport: u16) -> isize {
+use super::user_space::__user;
+
+const AF_UNIX: u16 = 1;
+const AF_INET: u16 = 2;
+const SOCK_STREAM: usize = 1;
+const SOCK_DGRAM: usize = 2;
+const SOCK_RAW: usize = 3;
+/// The rest of `type` are flags such as `SOCK_NONBLOCK` and `SOCK_CLOEXEC`.
+const SOCK_TYPE_MASK: usize = 0xf;
+const SOCK_NONBLOCK: usize = 0o4000;
+const SOCK_CLOEXEC: usize = 0o2000000;
+const IPPROTO_ICMP: usize = 1;
+const IPPROTO_TCP: usize = 6;
+const IPPROTO_UDP: usize = 17;
+/// `sun_family` and the 108 bytes of `sun_path`.
+const UNIX_ADDR_LEN: usize = 110;
+
+const SOL_SOCKET: usize = 1;
+const SO_REUSEADDR: usize = 2;
+const SO_RCVTIMEO: usize = 20;
+const SO_SNDTIMEO: usize = 21;
+const TCP_NODELAY: usize = 1;
+const SCM_RIGHTS: i32 = 1;
+/// Files one `SCM_RIGHTS` message carries at most, as on Linux.
+const SCM_MAX_FD: usize = 253;
+const MSG_CTRUNC: i32 = 0x8;
+const MSG_TRUNC: i32 = 0x20;
+
+const SIOCGIFCONF: u32 = 0x8912;
+const SIOCGIFFLAGS: u32 = 0x8913;
+const SIOCGIFADDR: u32 = 0x8915;
+const SIOCSIFADDR: u32 = 0x8916;
+const SIOCGIFNETMASK: u32 = 0x891b;
+const SIOCSIFNETMASK: u32 = 0x891c;
+const SIOCGIFHWADDR: u32 = 0x8927;
+const IFF_UP: u16 = 0x1;
+const IFF_BROADCAST: u16 = 0x2;
+const IFF_LOOPBACK: u16 = 0x8;
+const IFF_RUNNING: u16 = 0x40;
+const ARPHRD_ETHER: u16 = 1;
+const ARPHRD_LOOPBACK: u16 = 772;
+const IFNAMSIZ: usize = 16;
+
+/// `struct sockaddr_in`, port and address in network byte order.
+#[repr(C)]
+pub struct SockAddrIn {
+    family: u16,
+    port: u16,
+    addr: u32,
+    zero: [u8; 8],
+}
+
+fn read_sockaddr(addr: __user<*const SockAddrIn>, addrlen: usize) -> Result<InetAddr, isize> {
+    if addr.inner().is_null() || addrlen < size_of::<SockAddrIn>() {
+        return Err(-(EINVAL as isize));
+    }
+    let addr = translated_ref(current_user_token(), addr);
+    if addr.family != AF_INET {
+        return Err(-(EAFNOSUPPORT as isize));
+    }
+    Ok(InetAddr::new(
+        u32::from_be(addr.addr),
+        u16::from_be(addr.port),
+    ))
+}
+
+/// `struct sockaddr_in` as bytes.
+fn inet_bytes(inet: InetAddr) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(size_of::<SockAddrIn>());
+    bytes.extend_from_slice(&AF_INET.to_ne_bytes());
+    bytes.extend_from_slice(&inet.port.to_be_bytes());
+    bytes.extend_from_slice(&inet.ip.to_be_bytes());
+    bytes.extend_from_slice(&[0; 8]);
+    bytes
+}
+
+/// `struct sockaddr_un` as bytes, only as long as the name. An unbound
+/// socket has just the family.
+fn unix_bytes(name: Option<&str>) -> Vec<u8> {
+    let mut bytes = Vec::from(AF_UNIX.to_ne_bytes());
+    if let Some(name) = name {
+        bytes.extend_from_slice(name.as_bytes());
+        // abstract names are not terminated
+        if !name.starts_with('\0') {
+            bytes.push(0);
+        }
+    }
+    bytes
+}
+
+/// Copy as much of `bytes` as fits `len` to user memory at `addr`.
+fn copy_out(bytes: &[u8], addr: usize, len: usize) {
+    let len = len.min(bytes.len());
+    let buf = UserBuffer::new(translated_byte_buffer(
+        current_user_token(),
+        __user::new(addr as *const u8),
+        len,
+    ));
+    copy_to_user(buf, &bytes[..len]);
+}
+
+/// Store an address like Linux does: truncated to the caller's `*addrlen`,
+/// which is set to the full size. A null `addr` stores nothing.
+fn write_addr(bytes: &[u8], addr: __user<*mut SockAddrIn>, addrlen: __user<*mut u32>) {
+    if addr.inner().is_null() {
+        return;
+    }
+    let addrlen = translated_refmut(current_user_token(), addrlen);
+    copy_out(bytes, addr.inner() as usize, *addrlen as usize);
+    *addrlen = bytes.len() as u32;
+}
+
+fn write_sockaddr(inet: InetAddr, addr: __user<*mut SockAddrIn>, addrlen: __user<*mut u32>) {
+    write_addr(&inet_bytes(inet), addr, addrlen);
+}
+
+/// The name in a `struct sockaddr_un`: a path made absolute, or an abstract
+/// name that starts with a NUL and takes all of `addrlen`.
+fn read_unix_addr(addr: usize, addrlen: usize) -> Result<String, isize> {
+    let len = addrlen.min(UNIX_ADDR_LEN);
+    if addr == 0 || len <= size_of::<u16>() {
+        return Err(-(EINVAL as isize));
+    }
+    let token = current_user_token();
+    let bytes = copy_from_user(&UserBuffer::new(translated_byte_buffer(
+        token,
+        __user::new(addr as *const u8),
+        len,
+    )));
+    if u16::from_ne_bytes([bytes[0], bytes[1]]) != AF_UNIX {
+        return Err(-(EINVAL as isize));
+    }
+    let path = &bytes[2..];
+    if path[0] == 0 {
+        return String::from_utf8(path.to_vec()).map_err(|_| -(EINVAL as isize));
+    }
+    let end = path.iter().position(|&c| c == 0).unwrap_or(path.len());
+    let path = core::str::from_utf8(&path[..end]).map_err(|_| -(EINVAL as isize))?;
+    Ok(absolute_path(&current_process().getcwd(), path))
+}
+
+fn socket_file(fd: usize) -> Result<Arc<dyn File + Send + Sync>, isize> {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    match inner.fd_table.get(fd).cloned().flatten() {
+        Some(file) if file.as_socket().is_some() || file.as_unix_socket().is_some() => Ok(file),
+        Some(_) => Err(-(ENOTSOCK as isize)),
+        None => Err(-(EBADF as isize)),
+    }
+}
+
+/// A new fd for `file`, with the `SOCK_NONBLOCK` and `SOCK_CLOEXEC` of
+/// `flags`.
+fn install_fd(file: Arc<dyn File + Send + Sync>, flags: usize) -> isize {
+    if flags & SOCK_NONBLOCK != 0 {
+        file.set_nonblocking(true);
+    }
     let process = current_process();
     let mut inner = process.inner_exclusive_access();
     let fd = inner.alloc_fd();
-    let udp_node = UDP::new(IPv4::from_u32(raddr), lport, rport);
-    inner.fd_table[fd] = Some(Arc::new(udp_node));
+    inner.fd_table[fd] = Some(file);
+    if flags & SOCK_CLOEXEC != 0 {
+        inner.close_on_exec.insert(fd);
+    }
     fd as isize
 }
 
-// listen a port
-pub fn sys_listen(port: u16) -> isize {
-    match listen(port) {
-        Some(port_index) => {
-            let process = current_process();
-            let mut inner = process.inner_exclusive_access();
-            let fd = inner.alloc_fd();
-            let port_fd = PortFd::new(port_index);
-            inner.fd_table[fd] = Some(Arc::new(port_fd));
+fn unix_type(ty: usize) -> Result<UnixType, isize> {
+    match ty & SOCK_TYPE_MASK {
+        SOCK_STREAM => Ok(UnixType::Stream),
+        SOCK_DGRAM => Ok(UnixType::Dgram),
+        _ => Err(-(ESOCKTNOSUPPORT as isize)),
+    }
+}
+
+pub fn sys_socket(domain: usize, ty: usize, protocol: usize) -> isize {
+    if domain == AF_UNIX as usize {
+        return match (unix_type(ty), protocol) {
+            (Ok(unix_ty), 0) => install_fd(UnixSocket::new(unix_ty), ty),
+            (Ok(_), _) => -(EPROTONOSUPPORT as isize),
+            (Err(err), _) => err,
+        };
+    }
+    if domain != AF_INET as usize {
+        return -(EAFNOSUPPORT as isize);
+    }
+    let file: Arc<dyn File + Send + Sync> = match (ty & SOCK_TYPE_MASK, protocol) {
+        (SOCK_STREAM, 0 | IPPROTO_TCP) => TcpSocket::new(),
+        (SOCK_DGRAM, 0 | IPPROTO_UDP) => UdpSocket::new(),
+        (SOCK_DGRAM, IPPROTO_ICMP) => IcmpSocket::new(false),
+        (SOCK_RAW, IPPROTO_ICMP) => IcmpSocket::new(true),
+        (SOCK_STREAM | SOCK_DGRAM | SOCK_RAW, _) => return -(EPROTONOSUPPORT as isize),
+        _ => return -(ESOCKTNOSUPPORT as isize),
+    };
+    install_fd(file, ty)
+}
+
+/// Only `AF_UNIX` has pairs, the fds are stored as two `int`s.
+pub fn sys_socketpair(domain: usize, ty: usize, protocol: usize, sv: __user<*mut i32>) -> isize {
+    if domain == AF_INET as usize {
+        return -(EOPNOTSUPP as isize);
+    }
+    if domain != AF_UNIX as usize {
+        return -(EAFNOSUPPORT as isize);
+    }
+    let unix_ty = match (unix_type(ty), protocol) {
+        (Ok(unix_ty), 0) => unix_ty,
+        (Ok(_), _) => return -(EPROTONOSUPPORT as isize),
+        (Err(err), _) => return err,
+    };
+    let (a, b) = UnixSocket::pair(unix_ty);
+    let token = current_user_token();
+    *translated_refmut(token, sv) = install_fd(a, ty) as i32;
+    *translated_refmut(token, unsafe { sv.inner().add(1).into() }) = install_fd(b, ty) as i32;
+    0
+}
+
+pub fn sys_bind(fd: usize, addr: __user<*const SockAddrIn>, addrlen: usize) -> isize {
+    let file = match socket_file(fd) {
+        Ok(file) => file,
+        Err(err) => return err,
+    };
+    if let Some(unix) = file.as_unix_socket() {
+        return match read_unix_addr(addr.inner() as usize, addrlen) {
+            Ok(name) => unix.bind(name),
+            Err(err) => err,
+        };
+    }
+    match read_sockaddr(addr, addrlen) {
+        Ok(addr) => file.as_socket().unwrap().bind(addr),
+        Err(err) => err,
+    }
+}
+
+pub fn sys_listen(fd: usize, backlog: usize) -> isize {
+    match socket_file(fd) {
+        Ok(file) => match file.as_unix_socket() {
+            Some(unix) => unix.listen(backlog),
+            None => file.as_socket().unwrap().listen(backlog),
+        },
+        Err(err) => err,
+    }
+}
+
+/// `flags` are `SOCK_NONBLOCK` and `SOCK_CLOEXEC` for the new fd.
+pub fn sys_accept4(
+    fd: usize, addr: __user<*mut SockAddrIn>, addrlen: __user<*mut u32>, flags: usize,
+) -> isize {
+    let file = match socket_file(fd) {
+        Ok(file) => file,
+        Err(err) => return err,
+    };
+    if let Some(unix) = file.as_unix_socket() {
+        return match unix.accept() {
+            Ok(connection) => {
+                let peer = connection.peer_name().ok().flatten();
+                write_addr(&unix_bytes(peer.as_deref()), addr, addrlen);
+                install_fd(connection, flags)
+            }
+            Err(err) => err,
+        };
+    }
+    match file.as_socket().unwrap().accept() {
+        Ok((connection, peer)) => {
+            write_sockaddr(peer, addr, addrlen);
+            install_fd(connection, flags)
+        }
+        Err(err) => err,
+    }
+}
+
+pub fn sys_connect(fd: usize, addr: __user<*const SockAddrIn>, addrlen: usize) -> isize {
+    let file = match socket_file(fd) {
+        Ok(file) => file,
+        Err(err) => return err,
+    };
+    if let Some(unix) = file.as_unix_socket() {
+        return match read_unix_addr(addr.inner() as usize, addrlen) {
+            Ok(name) => unix.connect(&name),
+            Err(err) => err,
+        };
+    }
+    match read_sockaddr(addr, addrlen) {
+        Ok(addr) => file.as_socket().unwrap().connect(addr),
+        Err(err) => err,
+    }
+}
+
+/// Send `buf` to `addr` or the peer, with `files` for a unix socket.
+fn send_to(
+    file: &Arc<dyn File + Send + Sync>, buf: UserBuffer, addr: usize, addrlen: usize,
+    files: Vec<FileRef>,
+) -> isize {
+    if let Some(unix) = file.as_unix_socket() {
+        let dest = match addr {
+            0 => None,
+            _ => match read_unix_addr(addr, addrlen) {
+                Ok(name) => Some(name),
+                Err(err) => return err,
+            },
+        };
+        return unix.send(&copy_from_user(&buf), files, dest.as_deref());
+    }
+    if !files.is_empty() {
+        return -(EINVAL as isize);
+    }
+    let addr = match addr {
+        0 => None,
+        _ => match read_sockaddr(__user::new(addr as *const SockAddrIn), addrlen) {
+            Ok(addr) => Some(addr),
+            Err(err) => return err,
+        },
+    };
+    file.as_socket().unwrap().sendto(buf, addr)
+}
+
+/// A null `addr` sends to the connected peer. `flags` are ignored.
+pub fn sys_sendto(
+    fd: usize, buf: __user<*const u8>, len: usize, _flags: usize, addr: __user<*const SockAddrIn>,
+    addrlen: usize,
+) -> isize {
+    let file = match socket_file(fd) {
+        Ok(file) => file,
+        Err(err) => return err,
+    };
+    let buf = UserBuffer::new(translated_byte_buffer(current_user_token(), buf, len));
+    send_to(&file, buf, addr.inner() as usize, addrlen, Vec::new())
+}
+
+/// Received data with the address of the sender as `sockaddr` bytes, and
+/// the files that came along.
+struct RecvResult {
+    len: isize,
+    source: Option<Vec<u8>>,
+    files: Vec<FileRef>,
+    truncated: bool,
+}
+
+fn recv_from(file: &Arc<dyn File + Send + Sync>, buf: UserBuffer) -> RecvResult {
+    if let Some(unix) = file.as_unix_socket() {
+        let datagram = unix.is_dgram();
+        return match unix.recv(buf) {
+            Ok(received) => RecvResult {
+                len: received.len as isize,
+                source: datagram.then(|| unix_bytes(received.from.as_deref())),
+                files: received.files,
+                truncated: received.truncated,
+            },
+            Err(err) => RecvResult {
+                len: err,
+                source: None,
+                files: Vec::new(),
+                truncated: false,
+            },
+        };
+    }
+    let (len, source) = file.as_socket().unwrap().recvfrom(buf);
+    RecvResult {
+        len,
+        source: source.map(inet_bytes),
+        files: Vec::new(),
+        truncated: false,
+    }
+}
+
+/// `flags` are ignored.
+pub fn sys_recvfrom(
+    fd: usize, buf: __user<*const u8>, len: usize, _flags: usize, addr: __user<*mut SockAddrIn>,
+    addrlen: __user<*mut u32>,
+) -> isize {
+    let file = match socket_file(fd) {
+        Ok(file) => file,
+        Err(err) => return err,
+    };
+    let buf = UserBuffer::new(translated_byte_buffer(current_user_token(), buf, len));
+    // files sent along are closed, only recvmsg takes them
+    let received = recv_from(&file, buf);
+    if let Some(source) = received.source {
+        write_addr(&source, addr, addrlen);
+    }
+    received.len
+}
+
+/// `struct msghdr`.
+#[repr(C)]
+pub struct MsgHdr {
+    name: usize,
+    namelen: u32,
+    iov: usize,
+    iovlen: usize,
+    control: usize,
+    controllen: usize,
+    flags: i32,
+}
+
+/// `struct iovec`.
+#[repr(C)]
+struct IoVec {
+    base: usize,
+    len: usize,
+}
 
-            // NOTICE: this return the port index, not the fd
-            port_index as isize
+/// `struct cmsghdr`, the data follows aligned to 8 bytes.
+#[repr(C)]
+struct CmsgHdr {
+    len: usize,
+    level: i32,
+    ty: i32,
+}
+
+fn cmsg_align(len: usize) -> usize {
+    (len + size_of::<usize>() - 1) & !(size_of::<usize>() - 1)
+}
+
+/// The buffers of all of `msg`'s iovecs as one.
+fn iov_buffer(msg: &MsgHdr) -> UserBuffer {
+    let token = current_user_token();
+    let mut buffers = Vec::new();
+    for i in 0..msg.iovlen {
+        let iov = translated_ref(
+            token,
+            __user::new((msg.iov + i * size_of::<IoVec>()) as *const IoVec),
+        );
+        buffers.extend(translated_byte_buffer(
+            token,
+            __user::new(iov.base as *const u8),
+            iov.len,
+        ));
+    }
+    UserBuffer::new(buffers)
+}
+
+/// The files of the `SCM_RIGHTS` messages in `msg`'s control data, other
+/// kinds are skipped.
+fn control_files(msg: &MsgHdr) -> Result<Vec<FileRef>, isize> {
+    if msg.control == 0 || msg.controllen == 0 {
+        return Ok(Vec::new());
+    }
+    let control = copy_from_user(&UserBuffer::new(translated_byte_buffer(
+        current_user_token(),
+        __user::new(msg.control as *const u8),
+        msg.controllen,
+    )));
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let mut files = Vec::new();
+    let mut offset = 0;
+    while offset + size_of::<CmsgHdr>() <= control.len() {
+        let header =
+            unsafe { core::ptr::read_unaligned(control[offset..].as_ptr() as *const CmsgHdr) };
+        if header.len < size_of::<CmsgHdr>() || offset + header.len > control.len() {
+            return Err(-(EINVAL as isize));
+        }
+        if header.level == SOL_SOCKET as i32 && header.ty == SCM_RIGHTS {
+            let data = &control[offset + size_of::<CmsgHdr>()..offset + header.len];
+            for fd in data.chunks_exact(size_of::<i32>()) {
+                let fd = i32::from_ne_bytes([fd[0], fd[1], fd[2], fd[3]]);
+                match inner.fd_table.get(fd as usize).cloned().flatten() {
+                    Some(_) if files.len() == SCM_MAX_FD => return Err(-(EINVAL as isize)),
+                    Some(file) => files.push(file),
+                    None => return Err(-(EBADF as isize)),
+                }
+            }
         }
-        None => -1,
+        offset += cmsg_align(header.len);
     }
+    Ok(files)
 }
 
-// accept a tcp connection
-pub fn sys_accept(port_index: usize) -> isize {
-    println!("accepting port {}", port_index);
+/// `flags` are ignored. Files in `SCM_RIGHTS` only go over unix sockets.
+pub fn sys_sendmsg(fd: usize, msg: __user<*const MsgHdr>, _flags: usize) -> isize {
+    let file = match socket_file(fd) {
+        Ok(file) => file,
+        Err(err) => return err,
+    };
+    let msg = translated_ref(current_user_token(), msg);
+    let files = match control_files(msg) {
+        Ok(files) => files,
+        Err(err) => return err,
+    };
+    send_to(
+        &file,
+        iov_buffer(msg),
+        msg.name,
+        msg.namelen as usize,
+        files,
+    )
+}
 
-    let task = current_task().unwrap();
-    accept(port_index, task);
-    // block_current_and_run_next();
+/// Received files become new fds in one `SCM_RIGHTS` message, those that
+/// do not fit the control buffer are closed and `MSG_CTRUNC` is set.
+/// `flags` are ignored.
+pub fn sys_recvmsg(fd: usize, msg: __user<*mut MsgHdr>, _flags: usize) -> isize {
+    let file = match socket_file(fd) {
+        Ok(file) => file,
+        Err(err) => return err,
+    };
+    let msg = translated_refmut(current_user_token(), msg);
+    let received = recv_from(&file, iov_buffer(msg));
+    if received.len < 0 {
+        return received.len;
+    }
+    msg.flags = 0;
+    if received.truncated {
+        msg.flags |= MSG_TRUNC;
+    }
+    if msg.name != 0 {
+        let source = received.source.unwrap_or_default();
+        copy_out(&source, msg.name, msg.namelen as usize);
+        msg.namelen = source.len() as u32;
+    }
 
-    // NOTICE: There does not have interrupt handler, just call it munually.
-    loop {
-        net_interrupt_handler();
+    let room = if msg.control == 0 {
+        0
+    } else {
+        msg.controllen.saturating_sub(size_of::<CmsgHdr>()) / size_of::<i32>()
+    };
+    let mut files = received.files;
+    if files.len() > room {
+        files.truncate(room);
+        msg.flags |= MSG_CTRUNC;
+    }
+    if files.is_empty() {
+        msg.controllen = 0;
+        return received.len;
+    }
+    let len = size_of::<CmsgHdr>() + files.len() * size_of::<i32>();
+    let mut control = Vec::with_capacity(len);
+    control.extend_from_slice(&len.to_ne_bytes());
+    control.extend_from_slice(&(SOL_SOCKET as i32).to_ne_bytes());
+    control.extend_from_slice(&SCM_RIGHTS.to_ne_bytes());
+    for file in files {
+        control.extend_from_slice(&(install_fd(file, 0) as i32).to_ne_bytes());
+    }
+    copy_out(&control, msg.control, len);
+    msg.controllen = cmsg_align(len).min(msg.controllen);
+    received.len
+}
+
+pub fn sys_shutdown(fd: usize, how: usize) -> isize {
+    match socket_file(fd) {
+        Ok(file) => match file.as_unix_socket() {
+            Some(unix) => unix.shutdown(how),
+            None => file.as_socket().unwrap().shutdown(how),
+        },
+        Err(err) => err,
+    }
+}
+
+pub fn sys_getsockname(
+    fd: usize, addr: __user<*mut SockAddrIn>, addrlen: __user<*mut u32>,
+) -> isize {
+    match socket_file(fd) {
+        Ok(file) => {
+            let bytes = match file.as_unix_socket() {
+                Some(unix) => unix_bytes(unix.name().as_deref()),
+                None => inet_bytes(file.as_socket().unwrap().sockname()),
+            };
+            write_addr(&bytes, addr, addrlen);
+            0
+        }
+        Err(err) => err,
+    }
+}
 
-        if !port_acceptable(port_index) {
-            break;
+pub fn sys_getpeername(
+    fd: usize, addr: __user<*mut SockAddrIn>, addrlen: __user<*mut u32>,
+) -> isize {
+    let file = match socket_file(fd) {
+        Ok(file) => file,
+        Err(err) => return err,
+    };
+    let peer = match file.as_unix_socket() {
+        Some(unix) => unix.peer_name().map(|name| unix_bytes(name.as_deref())),
+        None => file
+            .as_socket()
+            .unwrap()
+            .peername()
+            .map(inet_bytes)
+            .ok_or(-(ENOTCONN as isize)),
+    };
+    match peer {
+        Ok(bytes) => {
+            write_addr(&bytes, addr, addrlen);
+            0
         }
+        Err(err) => err,
+    }
+}
+
+fn socket_options(file: &Arc<dyn File + Send + Sync>) -> &UPIntrFreeCell<SocketOptions> {
+    match file.as_unix_socket() {
+        Some(unix) => unix.options(),
+        None => file.as_socket().unwrap().options(),
+    }
+}
+
+/// The `int` most options take, anything but 0 turns a flag on.
+fn int_option(value: &[u8]) -> Result<i32, isize> {
+    match value.get(..size_of::<i32>()) {
+        Some(bytes) => Ok(i32::from_ne_bytes(bytes.try_into().unwrap())),
+        None => Err(-(EINVAL as isize)),
     }
+}
 
-    let cx = current_trap_ctx();
-    cx.x[10] as isize
+/// A `struct timeval` timeout in ms, 0 for none. Anything shorter than a
+/// millisecond is rounded up to one.
+fn timeout_option(value: &[u8]) -> Result<usize, isize> {
+    if value.len() < 2 * size_of::<i64>() {
+        return Err(-(EINVAL as isize));
+    }
+    let sec = i64::from_ne_bytes(value[..8].try_into().unwrap());
+    let usec = i64::from_ne_bytes(value[8..16].try_into().unwrap());
+    if sec < 0 || !(0..1_000_000).contains(&usec) {
+        return Err(-(EDOM as isize));
+    }
+    Ok((sec as usize).saturating_mul(1000) + (usec as usize + 999) / 1000)
+}
+
+fn timeout_bytes(ms: usize) -> Vec<u8> {
+    let mut bytes = Vec::from(((ms / 1000) as i64).to_ne_bytes());
+    bytes.extend_from_slice(&((ms % 1000 * 1000) as i64).to_ne_bytes());
+    bytes
+}
+
+/// `SO_REUSEADDR`, `SO_RCVTIMEO` and `SO_SNDTIMEO`, and `TCP_NODELAY` on TCP
+/// sockets. Only TCP looks at `SO_REUSEADDR`.
+pub fn sys_setsockopt(
+    fd: usize, level: usize, name: usize, optval: __user<*const u8>, optlen: usize,
+) -> isize {
+    let file = match socket_file(fd) {
+        Ok(file) => file,
+        Err(err) => return err,
+    };
+    if optval.inner().is_null() {
+        return -(EINVAL as isize);
+    }
+    let value = copy_from_user(&UserBuffer::new(translated_byte_buffer(
+        current_user_token(),
+        optval,
+        optlen,
+    )));
+    let options = socket_options(&file);
+    let result = match (level, name) {
+        (SOL_SOCKET, SO_REUSEADDR) => {
+            int_option(&value).map(|on| options.exclusive_access().reuse_addr = on != 0)
+        }
+        (SOL_SOCKET, SO_RCVTIMEO) => {
+            timeout_option(&value).map(|ms| options.exclusive_access().recv_timeout = ms)
+        }
+        (SOL_SOCKET, SO_SNDTIMEO) => {
+            timeout_option(&value).map(|ms| options.exclusive_access().send_timeout = ms)
+        }
+        (IPPROTO_TCP, TCP_NODELAY) => match file.as_socket() {
+            Some(socket) => int_option(&value).and_then(|on| match socket.set_no_delay(on != 0) {
+                0 => Ok(()),
+                err => Err(err),
+            }),
+            None => Err(-(EOPNOTSUPP as isize)),
+        },
+        _ => Err(-(ENOPROTOOPT as isize)),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(ret) => ret,
+    }
+}
+
+/// The options `setsockopt` takes. The value is cut to `*optlen`, which is
+/// set to how much was stored.
+pub fn sys_getsockopt(
+    fd: usize, level: usize, name: usize, optval: __user<*mut u8>, optlen: __user<*mut u32>,
+) -> isize {
+    let file = match socket_file(fd) {
+        Ok(file) => file,
+        Err(err) => return err,
+    };
+    let options = *socket_options(&file).exclusive_access();
+    let value = match (level, name) {
+        (SOL_SOCKET, SO_REUSEADDR) => Vec::from((options.reuse_addr as i32).to_ne_bytes()),
+        (SOL_SOCKET, SO_RCVTIMEO) => timeout_bytes(options.recv_timeout),
+        (SOL_SOCKET, SO_SNDTIMEO) => timeout_bytes(options.send_timeout),
+        (IPPROTO_TCP, TCP_NODELAY) => match file.as_socket().map(|socket| socket.no_delay()) {
+            Some(Some(no_delay)) => Vec::from((no_delay as i32).to_ne_bytes()),
+            Some(None) => return -(ENOPROTOOPT as isize),
+            None => return -(EOPNOTSUPP as isize),
+        },
+        _ => return -(ENOPROTOOPT as isize),
+    };
+    if optval.inner().is_null() || optlen.inner().is_null() {
+        return -(EINVAL as isize);
+    }
+    let optlen = translated_refmut(current_user_token(), optlen);
+    let len = (*optlen as usize).min(value.len());
+    copy_out(&value, optval.inner() as usize, len);
+    *optlen = len as u32;
+    0
+}
+
+/// `struct ifreq`: an interface name and a union of what is asked about it,
+/// mostly a `sockaddr`.
+#[repr(C)]
+struct IfReq {
+    name: [u8; IFNAMSIZ],
+    data: [u8; 24],
+}
+
+impl IfReq {
+    fn new(iface: &NetInterface) -> Self {
+        let mut name = [0; IFNAMSIZ];
+        name[..iface.name.len()].copy_from_slice(iface.name.as_bytes());
+        Self {
+            name,
+            data: [0; 24],
+        }
+    }
+
+    fn iface(&self) -> Option<&'static Arc<NetInterface>> {
+        let len = self.name.iter().position(|&c| c == 0).unwrap_or(IFNAMSIZ);
+        interface(core::str::from_utf8(&self.name[..len]).ok()?)
+    }
+
+    fn set_inet(&mut self, addr: u32) {
+        self.data[..2].copy_from_slice(&AF_INET.to_ne_bytes());
+        self.data[4..8].copy_from_slice(&addr.to_be_bytes());
+    }
+
+    fn inet(&self) -> Result<u32, isize> {
+        if u16::from_ne_bytes([self.data[0], self.data[1]]) != AF_INET {
+            return Err(-(EINVAL as isize));
+        }
+        Ok(u32::from_be_bytes([
+            self.data[4],
+            self.data[5],
+            self.data[6],
+            self.data[7],
+        ]))
+    }
+}
+
+/// `struct ifconf`, room for `len` bytes of `ifreq` at `buf`.
+#[repr(C)]
+struct IfConf {
+    len: i32,
+    buf: usize,
+}
+
+/// Each interface with its address. A null `buf` only asks for the length.
+fn get_if_conf(arg: usize) -> isize {
+    let token = current_user_token();
+    let conf = translated_refmut(token, __user::new(arg as *mut IfConf));
+    let size = size_of::<IfReq>();
+    if conf.buf == 0 {
+        conf.len = (interfaces().len() * size) as i32;
+        return 0;
+    }
+    let room = conf.len.max(0) as usize / size;
+    let mut len = 0;
+    for (i, iface) in interfaces().iter().take(room).enumerate() {
+        let mut req = IfReq::new(iface);
+        req.set_inet(iface.ip().to_u32());
+        *translated_refmut(token, __user::new((conf.buf + i * size) as *mut IfReq)) = req;
+        len += size;
+    }
+    conf.len = len as i32;
+    0
+}
+
+/// Interface requests, which any socket takes as on Linux. `None` for
+/// other commands.
+pub fn interface_ioctl(cmd: u32, arg: usize) -> Option<isize> {
+    let req = match cmd {
+        SIOCGIFCONF => return Some(get_if_conf(arg)),
+        SIOCGIFFLAGS | SIOCGIFADDR | SIOCSIFADDR | SIOCGIFNETMASK | SIOCSIFNETMASK
+        | SIOCGIFHWADDR => translated_refmut(current_user_token(), __user::new(arg as *mut IfReq)),
+        _ => return None,
+    };
+    let iface = match req.iface() {
+        Some(iface) => iface,
+        None => return Some(-(ENODEV as isize)),
+    };
+    match cmd {
+        SIOCGIFFLAGS => {
+            let mut flags = IFF_UP | IFF_RUNNING;
+            flags |= if iface.is_loopback() {
+                IFF_LOOPBACK
+            } else {
+                IFF_BROADCAST
+            };
+            req.data[..2].copy_from_slice(&flags.to_ne_bytes());
+        }
+        SIOCGIFADDR => req.set_inet(iface.ip().to_u32()),
+        SIOCGIFNETMASK => req.set_inet(iface.netmask()),
+        SIOCSIFADDR => match req.inet() {
+            Ok(ip) => set_address(iface, ip),
+            Err(err) => return Some(err),
+        },
+        SIOCSIFNETMASK => match req.inet() {
+            Ok(netmask) => iface.set_netmask(netmask),
+            Err(err) => return Some(err),
+        },
+        SIOCGIFHWADDR => {
+            let family = if iface.is_loopback() {
+                ARPHRD_LOOPBACK
+            } else {
+                ARPHRD_ETHER
+            };
+            req.data[..2].copy_from_slice(&family.to_ne_bytes());
+            req.data[2..8].copy_from_slice(&iface.hw_addr());
+        }
+        _ => unreachable!(),
+    }
+    Some(0)
 }
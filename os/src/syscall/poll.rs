@@ -0,0 +1,257 @@
+use crate::{
+    fs::{
+        poll_wait,
+        Epoll,
+        File,
+        PollEvents,
+    },
+    mm::{
+        translated_ref,
+        translated_refmut,
+    },
+    task::{
+        current_process,
+        current_user_token,
+    },
+    timer::get_time_ms,
+};
+use alloc::{
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use shared_defination::error::{
+    EBADF,
+    EINVAL,
+};
+
+use super::{
+    user_space::__user,
+    TimeSpec,
+};
+
+#[repr(C)]
+pub struct PollFd {
+    fd: i32,
+    events: i16,
+    revents: i16,
+}
+
+/// `struct epoll_event`, only x86_64 packs it, here `data` is at offset 8.
+#[repr(C)]
+pub struct EpollEvent {
+    events: u32,
+    data: u64,
+}
+
+fn fd_file(fd: usize) -> Option<Arc<dyn File + Send + Sync>> {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    inner.fd_table.get(fd).cloned().flatten()
+}
+
+/// A null timeout waits forever.
+fn timespec_deadline(token: usize, timeout: __user<*const TimeSpec>) -> Option<usize> {
+    if timeout.inner().is_null() {
+        return None;
+    }
+    let timeout = translated_ref(token, timeout);
+    Some(get_time_ms() + timeout.sec as usize * 1000 + timeout.nsec as usize / 1_000_000)
+}
+
+/// The signal mask is ignored, there is nothing to block yet.
+pub fn sys_ppoll(fds: __user<*mut PollFd>, nfds: usize, timeout: __user<*const TimeSpec>) -> isize {
+    let token = current_user_token();
+    let deadline = timespec_deadline(token, timeout);
+    let pollfds: Vec<&'static mut PollFd> = (0..nfds)
+        .map(|i| translated_refmut(token, __user::new(fds.inner().wrapping_add(i))))
+        .collect();
+    // negative fds are skipped, as the caller's way to switch an entry off
+    let files: Vec<Option<Arc<dyn File + Send + Sync>>> = pollfds
+        .iter()
+        .map(|pollfd| {
+            if pollfd.fd < 0 {
+                None
+            } else {
+                fd_file(pollfd.fd as usize)
+            }
+        })
+        .collect();
+    let waited: Vec<Arc<dyn File + Send + Sync>> = files.iter().flatten().cloned().collect();
+    let revents = poll_wait(&waited, deadline, || {
+        let revents: Vec<PollEvents> = pollfds
+            .iter()
+            .zip(files.iter())
+            .map(|(pollfd, file)| match file {
+                _ if pollfd.fd < 0 => PollEvents::empty(),
+                None => PollEvents::NVAL,
+                Some(file) => file.poll_ready(
+                    PollEvents::from_bits_truncate(pollfd.events as u16)
+                        | PollEvents::ERR
+                        | PollEvents::HUP,
+                ),
+            })
+            .collect();
+        if revents.iter().any(|events| !events.is_empty()) {
+            Some(revents)
+        } else {
+            None
+        }
+    })
+    .unwrap_or_else(|| vec![PollEvents::empty(); nfds]);
+    let mut count = 0;
+    for (pollfd, events) in pollfds.into_iter().zip(revents) {
+        pollfd.revents = events.bits() as i16;
+        if !events.is_empty() {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// An `fd_set` as `u64` words, `None` for a null pointer.
+fn read_fd_set(token: usize, set: __user<*mut u64>, nfds: usize) -> Option<Vec<&'static mut u64>> {
+    if set.inner().is_null() {
+        return None;
+    }
+    Some(
+        (0..(nfds + 63) / 64)
+            .map(|i| translated_refmut(token, __user::new(set.inner().wrapping_add(i))))
+            .collect(),
+    )
+}
+
+fn fd_isset(set: &Option<Vec<&'static mut u64>>, fd: usize) -> bool {
+    set.as_ref()
+        .map_or(false, |words| *words[fd / 64] & (1 << (fd % 64)) != 0)
+}
+
+/// Built on the same readiness as `ppoll`, exceptional conditions are
+/// `POLLPRI`. The signal mask is ignored.
+pub fn sys_pselect6(
+    nfds: usize, readfds: __user<*mut u64>, writefds: __user<*mut u64>,
+    exceptfds: __user<*mut u64>, timeout: __user<*const TimeSpec>,
+) -> isize {
+    let token = current_user_token();
+    let deadline = timespec_deadline(token, timeout);
+    let mut sets = [
+        read_fd_set(token, readfds, nfds),
+        read_fd_set(token, writefds, nfds),
+        read_fd_set(token, exceptfds, nfds),
+    ];
+    let wanted = [
+        PollEvents::IN | PollEvents::HUP | PollEvents::ERR,
+        PollEvents::OUT | PollEvents::ERR,
+        PollEvents::PRI,
+    ];
+    let mut files: Vec<(usize, Arc<dyn File + Send + Sync>)> = Vec::new();
+    for fd in 0..nfds {
+        if sets.iter().any(|set| fd_isset(set, fd)) {
+            match fd_file(fd) {
+                Some(file) => files.push((fd, file)),
+                None => return -(EBADF as isize),
+            }
+        }
+    }
+    let waited: Vec<Arc<dyn File + Send + Sync>> =
+        files.iter().map(|(_, file)| file.clone()).collect();
+    // (fd, index of the set) pairs that are ready
+    let ready = poll_wait(&waited, deadline, || {
+        let mut ready = Vec::new();
+        for (fd, file) in files.iter() {
+            let events = file.poll_ready(PollEvents::all());
+            for (i, set) in sets.iter().enumerate() {
+                if fd_isset(set, *fd) && events.intersects(wanted[i]) {
+                    ready.push((*fd, i));
+                }
+            }
+        }
+        if ready.is_empty() {
+            None
+        } else {
+            Some(ready)
+        }
+    })
+    .unwrap_or_default();
+    for words in sets.iter_mut().flatten() {
+        for word in words.iter_mut() {
+            **word = 0;
+        }
+    }
+    for (fd, i) in ready.iter() {
+        if let Some(words) = sets[*i].as_mut() {
+            *words[fd / 64] |= 1 << (fd % 64);
+        }
+    }
+    ready.len() as isize
+}
+
+pub fn sys_epoll_create1(_flags: usize) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let fd = inner.alloc_fd();
+    inner.fd_table[fd] = Some(Arc::new(Epoll::new()));
+    fd as isize
+}
+
+pub fn sys_epoll_ctl(epfd: usize, op: usize, fd: usize, event: __user<*const EpollEvent>) -> isize {
+    let epoll_file = match fd_file(epfd) {
+        Some(file) => file,
+        None => return -(EBADF as isize),
+    };
+    let file = match fd_file(fd) {
+        Some(file) => file,
+        None => return -(EBADF as isize),
+    };
+    let epoll = match epoll_file.as_epoll() {
+        Some(epoll) if fd != epfd => epoll,
+        _ => return -(EINVAL as isize),
+    };
+    // EPOLL_CTL_DEL may pass a null event
+    let (events, data) = if event.inner().is_null() {
+        (0, 0)
+    } else {
+        let event = translated_ref(current_user_token(), event);
+        (event.events, event.data)
+    };
+    epoll.ctl(op, fd, &file, events, data)
+}
+
+/// `timeout` in milliseconds, negative waits forever. The signal mask is
+/// ignored.
+pub fn sys_epoll_pwait(
+    epfd: usize, events: __user<*mut EpollEvent>, maxevents: i32, timeout: i32,
+) -> isize {
+    if maxevents <= 0 {
+        return -(EINVAL as isize);
+    }
+    let epoll_file = match fd_file(epfd) {
+        Some(file) => file,
+        None => return -(EBADF as isize),
+    };
+    let epoll = match epoll_file.as_epoll() {
+        Some(epoll) => epoll,
+        None => return -(EINVAL as isize),
+    };
+    let deadline = if timeout < 0 {
+        None
+    } else {
+        Some(get_time_ms() + timeout as usize)
+    };
+    let ready = poll_wait(&epoll.files(), deadline, || {
+        let ready = epoll.ready(maxevents as usize);
+        if ready.is_empty() {
+            None
+        } else {
+            Some(ready)
+        }
+    })
+    .unwrap_or_default();
+    let token = current_user_token();
+    for (i, (revents, data)) in ready.iter().enumerate() {
+        let event = translated_refmut(token, __user::new(events.inner().wrapping_add(i)));
+        event.events = *revents;
+        event.data = *data;
+    }
+    ready.len() as isize
+}
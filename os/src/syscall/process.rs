@@ -16,18 +16,36 @@ use crate::{
         current_user_token,
         exit_current_and_run_next,
         pid2process,
+        pid_list,
+        schedule,
         suspend_current_and_run_next,
         SignalFlags,
     },
-    timer::get_time_ms,
 };
 use alloc::{
     string::String,
     sync::Arc,
     vec::Vec,
 };
+use shared_defination::error::{
+    EINVAL,
+    EPERM,
+    ESRCH,
+};
+
+use super::user_space::__user;
 
-use super::{user_space::__user, TimeVal};
+const SIG_DFL: usize = 0;
+const SIG_IGN: usize = 1;
+
+/// `struct sigaction` of `rt_sigaction`.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SigAction {
+    pub handler: usize,
+    pub flags: usize,
+    pub mask: u64,
+}
 
 pub fn sys_exit(exit_code: i32) -> ! {
     exit_current_and_run_next(exit_code);
@@ -39,16 +57,6 @@ pub fn sys_yield() -> isize {
     0
 }
 
-pub fn sys_get_time(ts: __user<*mut TimeVal>, _tz: i32) -> isize {
-    let ptr = ts.inner() as *mut u64;
-    let sec = translated_refmut(current_user_token(), __user::new(ptr));
-    let usec = translated_refmut(current_user_token(), __user::new(unsafe { ptr.add(8) }));
-    let t = get_time_ms();
-    *sec = (t / 1000) as u64;
-    *usec = 0;
-    0
-}
-
 pub fn sys_getpid() -> isize {
     current_task().unwrap().process.upgrade().unwrap().getpid() as isize
 }
@@ -82,15 +90,16 @@ pub fn sys_fchdir(fd: usize) -> isize {
     current_process().fchdir(fd)
 }
 
-// TODO
 pub fn sys_mkdirat(_dfd: isize, name: __user<*const u8>, _mode: usize) -> isize {
     let new = translated_str(current_user_token(), name);
     current_process().mkdirat(new.as_str())
 }
 
-pub fn sys_unlinkat(_dfd: isize, name: __user<*const u8>) -> isize {
+const AT_REMOVEDIR: usize = 0x200;
+
+pub fn sys_unlinkat(_dfd: isize, name: __user<*const u8>, flags: usize) -> isize {
     let new = translated_str(current_user_token(), name);
-    current_process().unlinkat(new.as_str())
+    current_process().unlinkat(new.as_str(), flags & AT_REMOVEDIR != 0)
 }
 
 #[allow(unused)]
@@ -150,13 +159,15 @@ pub fn sys_exec(path: __user<*const u8>, mut args: __user<*const usize>) -> isiz
     }
 }
 
+/// Sleep until a child to reap exits, the exiting child wakes us.
 pub fn sys_wait4(pid: isize, exit_code_ptr: __user<*mut i32>) -> isize {
-    let mut ret = waitpid(pid, exit_code_ptr);
-    while ret == -2 {
-        sys_yield();
-        ret = waitpid(pid, exit_code_ptr);
+    let process = current_process();
+    loop {
+        match waitpid(pid, exit_code_ptr) {
+            -2 => schedule(process.wait_children.wait_no_sched()),
+            ret => return ret,
+        }
     }
-    ret
 }
 
 /// If there is not a child process whose pid is same as given, return -1.
@@ -209,3 +220,93 @@ pub fn sys_kill(pid: usize, signal: u32) -> isize {
         -1
     }
 }
+
+/// There are no signal handlers, so a signal can only be set to `SIG_DFL`
+/// or `SIG_IGN`.
+pub fn sys_sigaction(
+    signum: usize, act: __user<*const SigAction>, oldact: __user<*mut SigAction>,
+) -> isize {
+    let signal = match signum {
+        1..=31 => SignalFlags::from_bits(1 << signum),
+        _ => None,
+    };
+    let signal = match signal {
+        Some(signal) => signal,
+        None => return -(EINVAL as isize),
+    };
+    let token = current_user_token();
+    let ignore = if act.inner().is_null() {
+        None
+    } else {
+        match translated_ref(token, act).handler {
+            SIG_DFL => Some(false),
+            SIG_IGN => Some(true),
+            _ => return -(EINVAL as isize),
+        }
+    };
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if !oldact.inner().is_null() {
+        let handler = if inner.ignored_signals.contains(signal) {
+            SIG_IGN
+        } else {
+            SIG_DFL
+        };
+        *translated_refmut(token, oldact) = SigAction {
+            handler,
+            flags: 0,
+            mask: 0,
+        };
+    }
+    if let Some(ignore) = ignore {
+        inner.ignored_signals.set(signal, ignore);
+    }
+    0
+}
+
+/// Move process `pid`, the caller or one of its children, to group `pgid`.
+/// 0 stands for the caller and for a group of its own respectively.
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    let current = current_process();
+    let process = if pid == 0 || pid == current.getpid() {
+        current
+    } else {
+        let child = current
+            .inner_exclusive_access()
+            .children
+            .iter()
+            .find(|child| child.getpid() == pid)
+            .cloned();
+        match child {
+            Some(child) => child,
+            None => return -(ESRCH as isize),
+        }
+    };
+    let pgid = match pgid {
+        0 => process.getpid(),
+        pgid => pgid,
+    };
+    // joining a group needs somebody in it already
+    if pgid != process.getpid()
+        && !pid_list()
+            .into_iter()
+            .filter_map(pid2process)
+            .any(|other| other.inner_exclusive_access().pgid == pgid)
+    {
+        return -(EPERM as isize);
+    }
+    process.inner_exclusive_access().pgid = pgid;
+    0
+}
+
+pub fn sys_getpgid(pid: usize) -> isize {
+    let process = match pid {
+        0 => current_process(),
+        pid => match pid2process(pid) {
+            Some(process) => process,
+            None => return -(ESRCH as isize),
+        },
+    };
+    let pgid = process.inner_exclusive_access().pgid;
+    pgid as isize
+}
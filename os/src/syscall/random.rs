@@ -0,0 +1,25 @@
+use super::user_space::__user;
+use crate::{
+    mm::translated_byte_buffer,
+    random,
+    task::current_user_token,
+};
+use shared_defination::error::EINVAL;
+
+const GRND_NONBLOCK: u32 = 1;
+const GRND_RANDOM: u32 = 2;
+const GRND_INSECURE: u32 = 4;
+
+/// The generator is seeded before any process runs, so there is never
+/// anything to wait for and every flag reads the same stream.
+pub fn sys_getrandom(buf: __user<*const u8>, len: usize, flags: u32) -> isize {
+    if flags & !(GRND_NONBLOCK | GRND_RANDOM | GRND_INSECURE) != 0
+        || flags & (GRND_RANDOM | GRND_INSECURE) == GRND_RANDOM | GRND_INSECURE
+    {
+        return -(EINVAL as isize);
+    }
+    for slice in translated_byte_buffer(current_user_token(), buf, len) {
+        random::fill(slice);
+    }
+    len as isize
+}
@@ -1,5 +1,4 @@
 use crate::{
-    mm::translated_ref,
     sync::{
         Condvar,
         Mutex,
@@ -7,34 +6,11 @@ use crate::{
         MutexSpin,
         Semaphore,
     },
-    task::{
-        block_current_and_run_next,
-        current_process,
-        current_task,
-        current_user_token,
-    },
-    timer::{
-        add_timer,
-        get_time_ms,
-    },
+    task::current_process,
 };
 use alloc::sync::Arc;
 use log::info;
 
-use super::{
-    user_space::__user,
-    TimeVal,
-};
-
-pub fn sys_nanosleep(time: __user<*const TimeVal>) -> isize {
-    let time = translated_ref(current_user_token(), time);
-    let expire_ms = get_time_ms() + (time.sec as usize) * 1000 + (time.usec as usize) / 1000;
-    let task = current_task().unwrap();
-    add_timer(expire_ms, task);
-    block_current_and_run_next();
-    0
-}
-
 pub fn sys_mutex_create(blocking: bool) -> isize {
     let process = current_process();
     let mutex: Option<Arc<dyn Mutex>> = if !blocking {
@@ -0,0 +1,467 @@
+use super::{
+    user_space::__user,
+    TimeSpec,
+    TimeVal,
+};
+use crate::{
+    config::CLOCK_FREQ,
+    fs::{
+        OpenFlags,
+        TimerFd,
+    },
+    mm::{
+        translated_ref,
+        translated_refmut,
+    },
+    sync::intr_free,
+    task::{
+        block_current_task,
+        current_process,
+        current_run_time,
+        current_task,
+        current_user_token,
+        schedule,
+        SignalFlags,
+    },
+    timer::{
+        add_timer_ns,
+        boot_realtime_ns,
+        get_realtime_ns,
+        get_time_ns,
+        ns_to_ticks,
+        ticks_to_ns,
+        IntervalTimer,
+        TimerNotify,
+        NSEC_PER_SEC,
+    },
+};
+use alloc::sync::Arc;
+use shared_defination::error::{
+    EBADF,
+    EINVAL,
+};
+
+const CLOCK_REALTIME: usize = 0;
+const CLOCK_MONOTONIC: usize = 1;
+const CLOCK_PROCESS_CPUTIME_ID: usize = 2;
+const CLOCK_THREAD_CPUTIME_ID: usize = 3;
+const CLOCK_MONOTONIC_RAW: usize = 4;
+const CLOCK_REALTIME_COARSE: usize = 5;
+const CLOCK_MONOTONIC_COARSE: usize = 6;
+const CLOCK_BOOTTIME: usize = 7;
+
+const TIMER_ABSTIME: usize = 1;
+
+const ITIMER_REAL: usize = 0;
+const ITIMER_VIRTUAL: usize = 1;
+const ITIMER_PROF: usize = 2;
+
+const SIGEV_SIGNAL: i32 = 0;
+const SIGEV_NONE: i32 = 1;
+
+#[repr(C)]
+pub struct ITimerVal {
+    interval: TimeVal,
+    value: TimeVal,
+}
+
+#[repr(C)]
+pub struct ITimerSpec {
+    interval: TimeSpec,
+    value: TimeSpec,
+}
+
+/// The start of `struct sigevent`, the rest is only used by
+/// `SIGEV_THREAD`.
+#[repr(C)]
+pub struct SigEvent {
+    value: u64,
+    signo: i32,
+    notify: i32,
+}
+
+/// Time since boot stands in for every monotonic clock, nothing here
+/// suspends or slews.
+fn clock_ns(clock: usize) -> Option<usize> {
+    let ns = match clock {
+        CLOCK_REALTIME | CLOCK_REALTIME_COARSE => get_realtime_ns(),
+        CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW | CLOCK_MONOTONIC_COARSE | CLOCK_BOOTTIME => {
+            get_time_ns()
+        }
+        CLOCK_PROCESS_CPUTIME_ID => {
+            let ticks = current_process().inner_exclusive_access().cpu_time;
+            ticks_to_ns(ticks + current_run_time())
+        }
+        CLOCK_THREAD_CPUTIME_ID => {
+            let ticks = current_task().unwrap().inner_exclusive_access().cpu_time;
+            ticks_to_ns(ticks + current_run_time())
+        }
+        _ => return None,
+    };
+    Some(ns)
+}
+
+fn to_timespec(ns: usize) -> TimeSpec {
+    TimeSpec {
+        sec: (ns / NSEC_PER_SEC) as u64,
+        nsec: (ns % NSEC_PER_SEC) as u64,
+    }
+}
+
+fn to_timeval(ns: usize) -> TimeVal {
+    TimeVal {
+        sec: (ns / NSEC_PER_SEC) as u64,
+        usec: (ns % NSEC_PER_SEC / 1000) as u64,
+    }
+}
+
+fn timespec_ns(ts: &TimeSpec) -> Option<usize> {
+    if ts.nsec >= NSEC_PER_SEC as u64 {
+        return None;
+    }
+    Some(
+        (ts.sec as usize)
+            .saturating_mul(NSEC_PER_SEC)
+            .saturating_add(ts.nsec as usize),
+    )
+}
+
+fn timeval_ns(tv: &TimeVal) -> Option<usize> {
+    if tv.usec >= 1_000_000 {
+        return None;
+    }
+    Some(
+        (tv.sec as usize)
+            .saturating_mul(NSEC_PER_SEC)
+            .saturating_add(tv.usec as usize * 1000),
+    )
+}
+
+/// The nanoseconds in `ts`, `None` when it is not a valid timespec.
+fn read_timespec(ts: __user<*const TimeSpec>) -> Option<usize> {
+    timespec_ns(translated_ref(current_user_token(), ts))
+}
+
+/// Clocks that can be waited on, the others count CPU time.
+fn is_timer_clock(clock: usize) -> bool {
+    matches!(clock, CLOCK_REALTIME | CLOCK_MONOTONIC | CLOCK_BOOTTIME)
+}
+
+/// When `ns` on `clock` is in nanoseconds since boot, taking `ns` as a
+/// point in time if `absolute` and as a duration from now otherwise.
+/// `None` for clocks that can not be waited on.
+fn deadline_on(clock: usize, absolute: bool, ns: usize) -> Option<usize> {
+    let deadline = match clock {
+        CLOCK_REALTIME if absolute => ns.saturating_sub(boot_realtime_ns()),
+        CLOCK_MONOTONIC | CLOCK_BOOTTIME if absolute => ns,
+        CLOCK_REALTIME | CLOCK_MONOTONIC | CLOCK_BOOTTIME => get_time_ns().saturating_add(ns),
+        _ => return None,
+    };
+    Some(deadline)
+}
+
+/// Block until `deadline` in nanoseconds since boot, rounded up to the next
+/// timer tick.
+pub fn sleep_until(deadline: usize) {
+    if deadline <= get_time_ns() {
+        return;
+    }
+    let task = current_task().unwrap();
+    // the timer must not fire before the task is blocked
+    let task_ctx_ptr = intr_free(|| {
+        add_timer_ns(deadline, task);
+        block_current_task()
+    });
+    schedule(task_ctx_ptr);
+}
+
+pub fn sys_get_time(tv: __user<*mut TimeVal>, _tz: i32) -> isize {
+    *translated_refmut(current_user_token(), tv) = to_timeval(get_realtime_ns());
+    0
+}
+
+pub fn sys_clock_gettime(clock: usize, tp: __user<*mut TimeSpec>) -> isize {
+    match clock_ns(clock) {
+        Some(ns) => {
+            *translated_refmut(current_user_token(), tp) = to_timespec(ns);
+            0
+        }
+        None => -(EINVAL as isize),
+    }
+}
+
+/// Every clock counts in timer ticks.
+pub fn sys_clock_getres(clock: usize, res: __user<*mut TimeSpec>) -> isize {
+    if clock_ns(clock).is_none() {
+        return -(EINVAL as isize);
+    }
+    if !res.inner().is_null() {
+        *translated_refmut(current_user_token(), res) = to_timespec(NSEC_PER_SEC / CLOCK_FREQ);
+    }
+    0
+}
+
+pub fn sys_nanosleep(req: __user<*const TimeSpec>) -> isize {
+    match read_timespec(req) {
+        Some(ns) => {
+            sleep_until(get_time_ns().saturating_add(ns));
+            0
+        }
+        None => -(EINVAL as isize),
+    }
+}
+
+/// Sleeping on a CPU clock is not supported. Nothing cuts a sleep short,
+/// so `rem` is never written.
+pub fn sys_clock_nanosleep(
+    clock: usize, flags: usize, req: __user<*const TimeSpec>, _rem: __user<*mut TimeSpec>,
+) -> isize {
+    let ns = match read_timespec(req) {
+        Some(ns) => ns,
+        None => return -(EINVAL as isize),
+    };
+    match deadline_on(clock, flags & TIMER_ABSTIME != 0, ns) {
+        Some(deadline) => {
+            sleep_until(deadline);
+            0
+        }
+        None => -(EINVAL as isize),
+    }
+}
+
+/// The interval timer `which`, as time left and interval in nanoseconds.
+fn itimer(which: usize) -> (usize, usize) {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    match which {
+        ITIMER_REAL => inner
+            .real_timer
+            .as_ref()
+            .map_or((0, 0), |timer| timer.get()),
+        _ => {
+            let timer = inner.cpu_timers[which - ITIMER_VIRTUAL];
+            let left = match timer.deadline {
+                0 => 0,
+                deadline => deadline
+                    .saturating_sub(inner.cpu_time + current_run_time())
+                    .max(1),
+            };
+            (ticks_to_ns(left), ticks_to_ns(timer.interval))
+        }
+    }
+}
+
+pub fn sys_getitimer(which: usize, curr: __user<*mut ITimerVal>) -> isize {
+    if which > ITIMER_PROF {
+        return -(EINVAL as isize);
+    }
+    let (left, interval) = itimer(which);
+    *translated_refmut(current_user_token(), curr) = ITimerVal {
+        interval: to_timeval(interval),
+        value: to_timeval(left),
+    };
+    0
+}
+
+/// `ITIMER_REAL` raises SIGALRM, which is how `alarm` is done. The CPU
+/// time timers go off at the first switch after their time is used up.
+pub fn sys_setitimer(
+    which: usize, new: __user<*const ITimerVal>, old: __user<*mut ITimerVal>,
+) -> isize {
+    let token = current_user_token();
+    let new = translated_ref(token, new);
+    let (value, interval) = match (timeval_ns(&new.value), timeval_ns(&new.interval)) {
+        (Some(value), Some(interval)) if which <= ITIMER_PROF => (value, interval),
+        _ => return -(EINVAL as isize),
+    };
+    let (old_left, old_interval) = itimer(which);
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if which == ITIMER_REAL {
+        let notify = TimerNotify::Signal(Arc::downgrade(&process), SignalFlags::SIGALRM);
+        let timer = inner
+            .real_timer
+            .get_or_insert_with(|| IntervalTimer::new(notify))
+            .clone();
+        drop(inner);
+        let deadline = match value {
+            0 => 0,
+            value => get_time_ns().saturating_add(value),
+        };
+        timer.set(deadline, interval);
+    } else {
+        let cpu_time = inner.cpu_time + current_run_time();
+        let timer = &mut inner.cpu_timers[which - ITIMER_VIRTUAL];
+        timer.deadline = match value {
+            0 => 0,
+            value => cpu_time + ns_to_ticks(value),
+        };
+        timer.interval = ns_to_ticks(interval);
+    }
+    if !old.inner().is_null() {
+        *translated_refmut(token, old) = ITimerVal {
+            interval: to_timeval(old_interval),
+            value: to_timeval(old_left),
+        };
+    }
+    0
+}
+
+/// Only `SIGEV_SIGNAL` and `SIGEV_NONE` are supported, on the clocks
+/// `clock_nanosleep` can wait on. The id is written to `timerid`.
+pub fn sys_timer_create(
+    clock: usize, sevp: __user<*const SigEvent>, timerid: __user<*mut i32>,
+) -> isize {
+    if !is_timer_clock(clock) {
+        return -(EINVAL as isize);
+    }
+    let token = current_user_token();
+    let process = current_process();
+    let notify = if sevp.inner().is_null() {
+        TimerNotify::Signal(Arc::downgrade(&process), SignalFlags::SIGALRM)
+    } else {
+        let sev = translated_ref(token, sevp);
+        let signal = 1u32
+            .checked_shl(sev.signo as u32)
+            .and_then(SignalFlags::from_bits);
+        match (sev.notify, signal) {
+            (SIGEV_NONE, _) => TimerNotify::None,
+            (SIGEV_SIGNAL, Some(signal)) => TimerNotify::Signal(Arc::downgrade(&process), signal),
+            _ => return -(EINVAL as isize),
+        }
+    };
+    let timer = Some((clock, IntervalTimer::new(notify)));
+    let mut inner = process.inner_exclusive_access();
+    let id = match inner.timers.iter().position(|timer| timer.is_none()) {
+        Some(id) => {
+            inner.timers[id] = timer;
+            id
+        }
+        None => {
+            inner.timers.push(timer);
+            inner.timers.len() - 1
+        }
+    };
+    drop(inner);
+    *translated_refmut(token, timerid) = id as i32;
+    0
+}
+
+fn posix_timer(id: usize) -> Option<(usize, Arc<IntervalTimer>)> {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    inner.timers.get(id).cloned().flatten()
+}
+
+/// Arm or disarm `timer`, whose deadlines are on `clock`, from `new`, and
+/// write what it was set to before to `old` unless that is null.
+fn settime(
+    clock: usize, timer: &Arc<IntervalTimer>, flags: usize, new: __user<*const ITimerSpec>,
+    old: __user<*mut ITimerSpec>,
+) -> isize {
+    let token = current_user_token();
+    let new = translated_ref(token, new);
+    let (value, interval) = match (timespec_ns(&new.value), timespec_ns(&new.interval)) {
+        (Some(value), Some(interval)) => (value, interval),
+        _ => return -(EINVAL as isize),
+    };
+    let deadline = match value {
+        0 => 0,
+        // a deadline already passed goes off at the next tick
+        value => deadline_on(clock, flags & TIMER_ABSTIME != 0, value)
+            .unwrap()
+            .max(1),
+    };
+    let (old_left, old_interval) = timer.set(deadline, interval);
+    if !old.inner().is_null() {
+        *translated_refmut(token, old) = ITimerSpec {
+            interval: to_timespec(old_interval),
+            value: to_timespec(old_left),
+        };
+    }
+    0
+}
+
+fn gettime(timer: &IntervalTimer, curr: __user<*mut ITimerSpec>) -> isize {
+    let (left, interval) = timer.get();
+    *translated_refmut(current_user_token(), curr) = ITimerSpec {
+        interval: to_timespec(interval),
+        value: to_timespec(left),
+    };
+    0
+}
+
+pub fn sys_timer_settime(
+    timerid: usize, flags: usize, new: __user<*const ITimerSpec>, old: __user<*mut ITimerSpec>,
+) -> isize {
+    match posix_timer(timerid) {
+        Some((clock, timer)) => settime(clock, &timer, flags, new, old),
+        None => -(EINVAL as isize),
+    }
+}
+
+pub fn sys_timer_gettime(timerid: usize, curr: __user<*mut ITimerSpec>) -> isize {
+    match posix_timer(timerid) {
+        Some((_, timer)) => gettime(&timer, curr),
+        None => -(EINVAL as isize),
+    }
+}
+
+pub fn sys_timer_delete(timerid: usize) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    match inner.timers.get_mut(timerid).and_then(Option::take) {
+        Some(_) => 0,
+        None => -(EINVAL as isize),
+    }
+}
+
+/// `flags` may hold `TFD_NONBLOCK` and `TFD_CLOEXEC`, which are
+/// `O_NONBLOCK` and `O_CLOEXEC`.
+pub fn sys_timerfd_create(clock: usize, flags: u32) -> isize {
+    let flags = match OpenFlags::from_bits(flags) {
+        Some(flags) if (flags - (OpenFlags::NONBLOCK | OpenFlags::CLOEXEC)).is_empty() => flags,
+        _ => return -(EINVAL as isize),
+    };
+    if !is_timer_clock(clock) {
+        return -(EINVAL as isize);
+    }
+    let timerfd = TimerFd::new(clock, flags.contains(OpenFlags::NONBLOCK));
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let fd = inner.alloc_fd();
+    inner.fd_table[fd] = Some(Arc::new(timerfd));
+    if flags.contains(OpenFlags::CLOEXEC) {
+        inner.close_on_exec.insert(fd);
+    }
+    fd as isize
+}
+
+/// The clock and timer of timerfd `fd`.
+fn timerfd(fd: usize) -> Result<(usize, Arc<IntervalTimer>), isize> {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd).cloned().flatten() {
+        Some(file) => file,
+        None => return Err(-(EBADF as isize)),
+    };
+    file.as_timerfd()
+        .map(|timerfd| (timerfd.clock(), timerfd.timer().clone()))
+        .ok_or(-(EINVAL as isize))
+}
+
+pub fn sys_timerfd_settime(
+    fd: usize, flags: usize, new: __user<*const ITimerSpec>, old: __user<*mut ITimerSpec>,
+) -> isize {
+    match timerfd(fd) {
+        Ok((clock, timer)) => settime(clock, &timer, flags, new, old),
+        Err(errno) => errno,
+    }
+}
+
+pub fn sys_timerfd_gettime(fd: usize, curr: __user<*mut ITimerSpec>) -> isize {
+    match timerfd(fd) {
+        Ok((_, timer)) => gettime(&timer, curr),
+        Err(errno) => errno,
+    }
+}
@@ -1,5 +1,6 @@
 use super::{
     ProcessControlBlock,
+    SignalFlags,
     TaskStatus,
     TaskStruct,
 };
@@ -10,6 +11,7 @@ use alloc::{
         VecDeque,
     },
     sync::Arc,
+    vec::Vec,
 };
 use lazy_static::*;
 
@@ -50,6 +52,18 @@ pub fn wakeup_task(task: Arc<TaskStruct>) {
     add_task(task);
 }
 
+/// Wake `task` only if it is still blocked. A task sleeping on several
+/// queues at once (or a queue and a timer) must not be queued twice.
+pub fn wakeup_blocked_task(task: Arc<TaskStruct>) {
+    let mut task_inner = task.inner_exclusive_access();
+    if task_inner.task_status != TaskStatus::Blocked {
+        return;
+    }
+    task_inner.task_status = TaskStatus::Ready;
+    drop(task_inner);
+    add_task(task);
+}
+
 pub fn fetch_task() -> Option<Arc<TaskStruct>> {
     TASK_MANAGER.exclusive_access().fetch()
 }
@@ -59,6 +73,20 @@ pub fn pid2process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
     map.get(&pid).map(Arc::clone)
 }
 
+/// Raise `signal` in every process of group `pgid`.
+pub fn signal_process_group(pgid: usize, signal: SignalFlags) {
+    for process in PID2PCB.exclusive_access().values() {
+        let mut process_inner = process.inner_exclusive_access();
+        if process_inner.pgid == pgid {
+            process_inner.signals |= signal;
+        }
+    }
+}
+
+pub fn pid_list() -> Vec<usize> {
+    PID2PCB.exclusive_access().keys().copied().collect()
+}
+
 pub fn insert_into_pid2process(pid: usize, process: Arc<ProcessControlBlock>) {
     PID2PCB.exclusive_access().insert(pid, process);
 }
@@ -18,13 +18,17 @@ use crate::{
     sbi::shutdown,
 };
 use alloc::{
-    sync::Arc,
+    string::String,
+    sync::{
+        Arc,
+        Weak,
+    },
+    vec,
     vec::Vec,
 };
 use lazy_static::*;
 use log::trace;
 use manager::fetch_task;
-use process::ProcessControlBlock;
 use switch::__switch;
 
 pub use context::TaskContext;
@@ -38,12 +42,16 @@ pub use id::{
 pub use manager::{
     add_task,
     pid2process,
+    pid_list,
     remove_from_pid2process,
+    signal_process_group,
+    wakeup_blocked_task,
     wakeup_task,
 };
 pub use processor::{
     current_kstack_top,
     current_process,
+    current_run_time,
     current_task,
     current_trap_ctx,
     current_trap_ctx_user_va,
@@ -52,6 +60,7 @@ pub use processor::{
     schedule,
     take_current_task,
 };
+pub use process::ProcessControlBlock;
 pub use signal::SignalFlags;
 pub use task::{
     TaskStatus,
@@ -158,6 +167,9 @@ pub fn exit_current_and_run_next(exit_code: i32) {
         process_inner.memory_set.recycle_data_pages();
         // drop file descriptors
         process_inner.fd_table.clear();
+        // a zombie gets no more signals from its timers
+        process_inner.real_timer = None;
+        process_inner.timers.clear();
         // Remove all tasks except for the main thread itself.
         // This is because we are still using the kstack under the TCB
         // of the main thread. This TCB, including its kstack, will be
@@ -165,6 +177,13 @@ pub fn exit_current_and_run_next(exit_code: i32) {
         while process_inner.tasks.len() > 1 {
             process_inner.tasks.pop();
         }
+        let parent = process_inner.parent.as_ref().and_then(Weak::upgrade);
+        drop(process_inner);
+        // initproc may have been handed zombies to reap
+        INITPROC.wait_children.wake_all();
+        if let Some(parent) = parent {
+            parent.wait_children.wake_all();
+        }
     }
     drop(process);
     // we do not have to save task context
@@ -176,7 +195,9 @@ lazy_static! {
     pub static ref INITPROC: Arc<ProcessControlBlock> = {
         let inode = open_file("initproc", OpenFlags::RDONLY).unwrap();
         let v = inode.read_all();
-        ProcessControlBlock::new(v.as_slice())
+        let process = ProcessControlBlock::new(v.as_slice());
+        process.inner_exclusive_access().cmdline = vec![String::from("initproc")];
+        process
     };
 }
 
@@ -186,10 +207,23 @@ pub fn add_initproc() {
 
 pub fn check_signals_of_current() -> Option<(i32, &'static str)> {
     let process = current_process();
-    let process_inner = process.inner_exclusive_access();
+    let mut process_inner = process.inner_exclusive_access();
+    // jobs can not be stopped, so SIGTSTP is dropped like ignored signals
+    let ignored = process_inner.ignored_signals | SignalFlags::SIGTSTP;
+    process_inner.signals.remove(ignored);
     process_inner.signals.check_error()
 }
 
+/// Whether the current process has a signal that will end it, so that a
+/// blocking call should give up with `EINTR`.
+pub fn current_signal_pending() -> bool {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    (process_inner.signals - process_inner.ignored_signals)
+        .check_error()
+        .is_some()
+}
+
 pub fn current_add_signal(signal: SignalFlags) {
     let process = current_process();
     let mut process_inner = process.inner_exclusive_access();
@@ -10,14 +10,17 @@ use super::{
 };
 use crate::{
     config::{
+        MMAP_BASE,
         PAGE_SIZE,
         USER_STACK_TOP,
     },
     fs::{
+        self,
+        absolute_path,
+        console,
         File,
         OSInode,
-        Stdin,
-        Stdout,
+        TtyFile,
         ROOT_INODE,
     },
     mm::{
@@ -33,6 +36,11 @@ use crate::{
         Semaphore,
         UPIntrFreeCell,
         UPIntrRefMut,
+        WaitQueue,
+    },
+    timer::{
+        CpuTimer,
+        IntervalTimer,
     },
     trap::{
         trap_handler,
@@ -40,6 +48,7 @@ use crate::{
     },
 };
 use alloc::{
+    collections::BTreeSet,
     string::String,
     sync::{
         Arc,
@@ -48,12 +57,15 @@ use alloc::{
     vec,
     vec::Vec,
 };
+use shared_defination::error::EINVAL;
 
 pub struct ProcessControlBlock {
     // immutable
     pub pid_handle: PidHandle,
     // mutable
     inner: UPIntrFreeCell<ProcessControlBlockInner>,
+    /// Woken when a child exits, for `wait4`.
+    pub wait_children: WaitQueue,
 }
 
 #[derive(Copy, Clone, PartialEq)]
@@ -69,6 +81,7 @@ pub struct ProcessControlBlockInner {
     // =====================================================
     pub is_zombie: bool,                                    // is_zombie process
     pub exit_code: i32,                                     // exit code
+    pub cpu_time: usize,                                    // ticks run by its threads
 
 
     // =====================================================
@@ -76,6 +89,7 @@ pub struct ProcessControlBlockInner {
     // =====================================================
     pub parent: Option<Weak<ProcessControlBlock>>,          // parent process
     pub children: Vec<Arc<ProcessControlBlock>>,            // children processes array
+    pub pgid: usize,                                        // process group, for job control
 
     pub dir_struct: Arc<DirStruct>,                         // Process Session. Aslo Process Group.
 
@@ -93,7 +107,12 @@ pub struct ProcessControlBlockInner {
     // =====================================================
     pub memory_set: MemorySet,                              // memory space
     pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>, // file description
+    pub close_on_exec: BTreeSet<usize>,                     // fds with FD_CLOEXEC
     pub signals: SignalFlags,                               // signals
+    pub ignored_signals: SignalFlags,                       // signals set to SIG_IGN
+    pub real_timer: Option<Arc<IntervalTimer>>,             // ITIMER_REAL, made on first use
+    pub cpu_timers: [CpuTimer; 2],                          // ITIMER_VIRTUAL and ITIMER_PROF
+    pub timers: Vec<Option<(usize, Arc<IntervalTimer>)>>,   // timer_create clocks and timers
     pub mutex_list: Vec<Option<Arc<dyn Mutex>>>,
     pub semaphore_list: Vec<Option<Arc<Semaphore>>>,
     pub condvar_list: Vec<Option<Arc<Condvar>>>,
@@ -101,6 +120,7 @@ pub struct ProcessControlBlockInner {
     pub program_brk_bottom: usize,                          // user heap lowerbound.
     pub current_heap_top: usize,                            // current upperbound os heap.
     pub privilege: Privilege,                               // U-Mode Process or K-Mode Thread.
+    pub cmdline: Vec<String>,                               // argv of the last exec.
 }
 
 impl ProcessControlBlockInner {
@@ -110,12 +130,22 @@ impl ProcessControlBlockInner {
     }
 
     pub fn alloc_fd(&mut self) -> usize {
-        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
-            fd
-        } else {
-            self.fd_table.push(None);
-            self.fd_table.len() - 1
-        }
+        self.alloc_fd_from(0)
+    }
+
+    /// The lowest free fd that is at least `min`, as `F_DUPFD` wants.
+    pub fn alloc_fd_from(&mut self, min: usize) -> usize {
+        let fd = match (min..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
+            Some(fd) => fd,
+            None => {
+                let fd = self.fd_table.len().max(min);
+                self.fd_table.resize(fd + 1, None);
+                fd
+            }
+        };
+        // a new fd never inherits the flag of a closed one
+        self.close_on_exec.remove(&fd);
+        fd
     }
 
     pub fn alloc_tid(&mut self) -> usize {
@@ -166,7 +196,7 @@ impl ProcessControlBlockInner {
         }
     }
 
-    pub fn current_task_mmap(&mut self, addr: usize, len: usize, prot: usize) -> isize {
+    fn mmap_pte_flags(&self, prot: usize) -> PTEFlags {
         bitflags! {
             pub struct MmapProtect: u8{
                 const R = 1 << 0;
@@ -190,6 +220,11 @@ impl ProcessControlBlockInner {
             pte_flags |= PTEFlags::X;
         }
         assert_eq!(prot >> 3, 0);
+        pte_flags
+    }
+
+    pub fn current_task_mmap(&mut self, addr: usize, len: usize, prot: usize) -> isize {
+        let pte_flags = self.mmap_pte_flags(prot);
         assert_eq!(addr & (PAGE_SIZE - 1), 0);
         assert_eq!(len & (PAGE_SIZE - 1), 0);
 
@@ -199,6 +234,30 @@ impl ProcessControlBlockInner {
         self.memory_set.mmap(start_vaddr, end_vaddr, pte_flags)
     }
 
+    /// Map `len` bytes of `file` from `offset`. With `addr` 0 the kernel picks the place,
+    /// otherwise it has to be unmapped. Returns the start address.
+    pub fn current_task_mmap_file(
+        &mut self, addr: usize, len: usize, prot: usize, file: Arc<dyn File + Send + Sync>,
+        offset: usize,
+    ) -> isize {
+        let pte_flags = self.mmap_pte_flags(prot);
+        if addr & (PAGE_SIZE - 1) != 0 || offset & (PAGE_SIZE - 1) != 0 || len == 0 {
+            return -(EINVAL as isize);
+        }
+        let start_vaddr = if addr == 0 {
+            self.memory_set.find_free_area(MMAP_BASE, len)
+        } else {
+            addr.into()
+        };
+        let end_vaddr: VirtAddr = (start_vaddr.0 + len).into();
+        if !self.memory_set.is_free(start_vaddr, end_vaddr) {
+            return -(EINVAL as isize);
+        }
+        self.memory_set
+            .mmap_file(start_vaddr, end_vaddr, pte_flags, file, offset);
+        start_vaddr.0 as isize
+    }
+
     pub fn current_task_munmap(&mut self, addr: usize) -> isize {
         assert_eq!(addr & (PAGE_SIZE - 1), 0);
         self.memory_set.munmap(addr.into())
@@ -217,6 +276,9 @@ impl ProcessControlBlock {
         // allocate a pid
         let pid_handle = pid_alloc();
         let root_os_inode = Arc::new(OSInode::new(true, true, ROOT_INODE.clone()));
+        // a process starts its own group
+        let pgid = pid_handle.0;
+        let tty: Arc<dyn File + Send + Sync> = Arc::new(TtyFile::new(console()));
 
         let process = Arc::new(Self {
             pid_handle,
@@ -226,17 +288,18 @@ impl ProcessControlBlock {
                     memory_set,
                     parent: None,
                     children: Vec::new(),
+                    pgid,
                     exit_code: 0,
-                    fd_table: vec![
-                        // 0 -> stdin
-                        Some(Arc::new(Stdin)),
-                        // 1 -> stdout
-                        Some(Arc::new(Stdout)),
-                        // 2 -> stderr
-                        Some(Arc::new(Stdout)),
-                    ],
+                    cpu_time: 0,
+                    // stdin, stdout and stderr, all the console
+                    fd_table: vec![Some(tty.clone()), Some(tty.clone()), Some(tty)],
+                    close_on_exec: BTreeSet::new(),
                     dir_struct: Arc::new(DirStruct::new(&root_os_inode)),
                     signals: SignalFlags::empty(),
+                    ignored_signals: SignalFlags::empty(),
+                    real_timer: None,
+                    cpu_timers: [CpuTimer::default(); 2],
+                    timers: Vec::new(),
                     tasks: Vec::new(),
                     task_res_allocator: RecycleAllocator::new(),
                     mutex_list: Vec::new(),
@@ -245,8 +308,10 @@ impl ProcessControlBlock {
                     program_brk_bottom: program_brk,
                     current_heap_top: program_brk,
                     privilege: Privilege::User,
+                    cmdline: Vec::new(),
                 })
             },
+            wait_children: WaitQueue::new(),
         });
         // create a main thread, we should allocate ustack and trap_ctx here
         let task = Arc::new(TaskStruct::new(Arc::clone(&process), ustack_base, true));
@@ -278,6 +343,8 @@ impl ProcessControlBlock {
         let pid_handle = pid_alloc();
         let ustack_base = user_stack_upper_bound;
         let root_os_inode = Arc::new(OSInode::new(true, true, ROOT_INODE.clone()));
+        let pgid = pid_handle.0;
+        let tty: Arc<dyn File + Send + Sync> = Arc::new(TtyFile::new(console()));
 
         let process = Arc::new(Self {
             pid_handle,
@@ -287,17 +354,18 @@ impl ProcessControlBlock {
                     memory_set: MemorySet::new_bare(),
                     parent: None,
                     children: Vec::new(),
+                    pgid,
                     exit_code: 0,
-                    fd_table: vec![
-                        // 0 -> stdin
-                        Some(Arc::new(Stdin)),
-                        // 1 -> stdout
-                        Some(Arc::new(Stdout)),
-                        // 2 -> stderr
-                        Some(Arc::new(Stdout)),
-                    ],
+                    cpu_time: 0,
+                    // stdin, stdout and stderr, all the console
+                    fd_table: vec![Some(tty.clone()), Some(tty.clone()), Some(tty)],
+                    close_on_exec: BTreeSet::new(),
                     dir_struct: Arc::new(DirStruct::new(&root_os_inode)),
                     signals: SignalFlags::empty(),
+                    ignored_signals: SignalFlags::empty(),
+                    real_timer: None,
+                    cpu_timers: [CpuTimer::default(); 2],
+                    timers: Vec::new(),
                     tasks: Vec::new(),
                     task_res_allocator: RecycleAllocator::new(),
                     mutex_list: Vec::new(),
@@ -306,8 +374,10 @@ impl ProcessControlBlock {
                     program_brk_bottom: 0,
                     current_heap_top: 0,
                     privilege: Privilege::Kernel,
+                    cmdline: vec![String::from("kpthread")],
                 })
             },
+            wait_children: WaitQueue::new(),
         });
         // create a main thread, we should allocate ustack and trap_ctx here
         let task = Arc::new(TaskStruct::new_kpthread(Arc::clone(&process), ustack_base));
@@ -344,6 +414,14 @@ impl ProcessControlBlock {
         self.inner_exclusive_access().memory_set = memory_set;
         self.inner_exclusive_access().program_brk_bottom = program_brk;
         self.inner_exclusive_access().current_heap_top = program_brk;
+        self.inner_exclusive_access().cmdline = args.clone();
+        let mut inner = self.inner_exclusive_access();
+        for fd in core::mem::take(&mut inner.close_on_exec) {
+            inner.fd_table[fd] = None;
+        }
+        // interval timers carry on, POSIX ones are deleted
+        inner.timers.clear();
+        drop(inner);
 
         // then we alloc user resource for main thread again
         // since memory_set has been changed
@@ -420,10 +498,17 @@ impl ProcessControlBlock {
                     memory_set,
                     parent: Some(Arc::downgrade(self)),
                     children: Vec::new(),
+                    pgid: parent.pgid,
                     exit_code: 0,
+                    cpu_time: 0,
                     fd_table: new_fd_table,
+                    close_on_exec: parent.close_on_exec.clone(),
                     dir_struct: Arc::new(dir),
                     signals: SignalFlags::empty(),
+                    ignored_signals: parent.ignored_signals,
+                    real_timer: None,
+                    cpu_timers: [CpuTimer::default(); 2],
+                    timers: Vec::new(),
                     tasks: Vec::new(),
                     task_res_allocator: RecycleAllocator::new(),
                     mutex_list: Vec::new(),
@@ -432,8 +517,10 @@ impl ProcessControlBlock {
                     program_brk_bottom: parent.program_brk_bottom,
                     current_heap_top: parent.current_heap_top,
                     privilege: Privilege::User,
+                    cmdline: parent.cmdline.clone(),
                 })
             },
+            wait_children: WaitQueue::new(),
         });
         // add child
         parent.children.push(Arc::clone(&child));
@@ -471,8 +558,9 @@ impl ProcessControlBlock {
     }
 
     pub fn chdir(&self, path: &str) -> isize {
+        let path = absolute_path(&self.getcwd(), path);
         self.inner.exclusive_session(|inner| {
-            inner.dir_struct.chdir(path);
+            inner.dir_struct.chdir(path.as_str());
         });
         0
     }
@@ -486,14 +574,18 @@ impl ProcessControlBlock {
         0
     }
 
-    // TODO
-    pub fn mkdirat(&self, _path: &str) -> isize {
-        0
+    pub fn mkdirat(&self, path: &str) -> isize {
+        fs::mkdir(absolute_path(&self.getcwd(), path).as_str())
     }
 
-    // TODO
-    pub fn unlinkat(&self, _path: &str) -> isize {
-        0
+    /// Remove a file, or an empty directory when `rmdir` is set.
+    pub fn unlinkat(&self, path: &str, rmdir: bool) -> isize {
+        let path = absolute_path(&self.getcwd(), path);
+        // bound socket names have no file of their own
+        if !rmdir && crate::net::unix::unbind(&path) {
+            return 0;
+        }
+        fs::unlink(path.as_str(), rmdir)
     }
 
     // memory syscall.
@@ -505,6 +597,13 @@ impl ProcessControlBlock {
         let mut inner = self.inner_exclusive_access();
         inner.current_task_mmap(addr, len, prot)
     }
+    pub fn current_task_mmap_file(
+        &self, addr: usize, len: usize, prot: usize, file: Arc<dyn File + Send + Sync>,
+        offset: usize,
+    ) -> isize {
+        let mut inner = self.inner_exclusive_access();
+        inner.current_task_mmap_file(addr, len, prot, file, offset)
+    }
     pub fn current_task_munmap(&self, addr: usize) -> isize {
         let mut inner = self.inner_exclusive_access();
         inner.current_task_munmap(addr)
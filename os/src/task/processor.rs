@@ -2,13 +2,24 @@ use super::{
     __switch,
     fetch_task,
     ProcessControlBlock,
+    SignalFlags,
     TaskContext,
     TaskStatus,
     TaskStruct,
 };
 use crate::{
     sync::UPIntrFreeCell,
-    trap::TrapContext,
+    timer::{
+        get_time,
+        set_next_trigger,
+        start_time_slice,
+        stop_time_slice,
+    },
+    trap::{
+        take_pending_interrupts,
+        wait_for_interrupt,
+        TrapContext,
+    },
 };
 use alloc::sync::Arc;
 use core::arch::asm;
@@ -17,6 +28,8 @@ use lazy_static::*;
 pub struct Processor {
     current: Option<Arc<TaskStruct>>,
     idle_task_ctx: TaskContext,
+    /// When the current task was switched to.
+    switched_in: usize,
 }
 
 impl Processor {
@@ -24,6 +37,7 @@ impl Processor {
         Self {
             current: None,
             idle_task_ctx: TaskContext::zero_init(),
+            switched_in: 0,
         }
     }
     fn get_idle_task_ctx_ptr(&mut self) -> *mut TaskContext {
@@ -52,14 +66,48 @@ pub fn run_tasks() {
                 task_inner.task_status = TaskStatus::Running;
                 &task_inner.task_ctx as *const TaskContext
             });
-            processor.current = Some(task);
+            processor.current = Some(task.clone());
+            processor.switched_in = get_time();
             // release processor manually
             drop(processor);
+            start_time_slice();
             unsafe {
                 __switch(idle_task_ctx_ptr, next_task_ctx_ptr);
             }
+            charge_cpu_time(&task, current_run_time());
         } else {
-            println!("no tasks available in run_tasks");
+            // nothing to run until an interrupt, the timer is left set only
+            // for the next timer due
+            stop_time_slice();
+            set_next_trigger();
+            wait_for_interrupt();
+            drop(processor);
+            take_pending_interrupts();
+        }
+    }
+}
+
+/// Timer ticks since the current task was switched to.
+pub fn current_run_time() -> usize {
+    get_time() - PROCESSOR.exclusive_access().switched_in
+}
+
+/// Add a run of `ticks` to `task` and its process, which outlives its
+/// threads and keeps their time when they exit. User and kernel time are
+/// not told apart, so `ITIMER_VIRTUAL` counts the same as `ITIMER_PROF`.
+fn charge_cpu_time(task: &Arc<TaskStruct>, ticks: usize) {
+    task.inner_exclusive_access().cpu_time += ticks;
+    if let Some(process) = task.process.upgrade() {
+        let mut process_inner = process.inner_exclusive_access();
+        process_inner.cpu_time += ticks;
+        let cpu_time = process_inner.cpu_time;
+        for (timer, signal) in [SignalFlags::SIGVTALRM, SignalFlags::SIGPROF]
+            .into_iter()
+            .enumerate()
+        {
+            if process_inner.cpu_timers[timer].expire(cpu_time) {
+                process_inner.signals |= signal;
+            }
         }
     }
 }
@@ -2,18 +2,30 @@ use bitflags::*;
 
 bitflags! {
     pub struct SignalFlags: u32 {
+        const SIGHUP    = 1 << 1;
         const SIGINT    = 1 << 2;
+        const SIGQUIT   = 1 << 3;
         const SIGILL    = 1 << 4;
         const SIGABRT   = 1 << 6;
         const SIGFPE    = 1 << 8;
         const SIGSEGV   = 1 << 11;
+        const SIGALRM   = 1 << 14;
+        const SIGTSTP   = 1 << 20;
+        const SIGVTALRM = 1 << 26;
+        const SIGPROF   = 1 << 27;
+        /// Ignored by default, so it never ends a process.
+        const SIGWINCH  = 1 << 28;
     }
 }
 
 impl SignalFlags {
     pub fn check_error(&self) -> Option<(i32, &'static str)> {
-        if self.contains(Self::SIGINT) {
+        if self.contains(Self::SIGHUP) {
+            Some((-1, "Hangup, SIGHUP=1"))
+        } else if self.contains(Self::SIGINT) {
             Some((-2, "Killed, SIGINT=2"))
+        } else if self.contains(Self::SIGQUIT) {
+            Some((-3, "Quit, SIGQUIT=3"))
         } else if self.contains(Self::SIGILL) {
             Some((-4, "Illegal Instruction, SIGILL=4"))
         } else if self.contains(Self::SIGABRT) {
@@ -22,6 +34,12 @@ impl SignalFlags {
             Some((-8, "Erroneous Arithmetic Operation, SIGFPE=8"))
         } else if self.contains(Self::SIGSEGV) {
             Some((-11, "Segmentation Fault, SIGSEGV=11"))
+        } else if self.contains(Self::SIGALRM) {
+            Some((-14, "Alarm clock, SIGALRM=14"))
+        } else if self.contains(Self::SIGVTALRM) {
+            Some((-26, "Virtual timer expired, SIGVTALRM=26"))
+        } else if self.contains(Self::SIGPROF) {
+            Some((-27, "Profiling timer expired, SIGPROF=27"))
         } else {
             None
         }
@@ -46,6 +46,8 @@ pub struct TaskControlBlockInner {
     pub task_ctx: TaskContext,
     pub task_status: TaskStatus,
     pub exit_code: Option<i32>,
+    /// Timer ticks it has run for, not counting the current run.
+    pub cpu_time: usize,
 }
 
 impl TaskControlBlockInner {
@@ -77,6 +79,7 @@ impl TaskStruct {
                     task_ctx: TaskContext::goto_trap_return(kstack_top),
                     task_status: TaskStatus::Ready,
                     exit_code: None,
+                    cpu_time: 0,
                 })
             },
         }
@@ -99,6 +102,7 @@ impl TaskStruct {
                     ),
                     task_status: TaskStatus::Ready,
                     exit_code: None,
+                    cpu_time: 0,
                 })
             },
         }
@@ -1,23 +1,52 @@
 use core::cmp::Ordering;
 
 use crate::{
+    board::rtc_base,
     config::CLOCK_FREQ,
+    drivers::rtc::GoldfishRtc,
+    net::next_timeout_ms,
     sbi::set_timer,
-    sync::UPIntrFreeCell,
+    sync::{
+        UPIntrFreeCell,
+        WaitQueue,
+    },
     task::{
-        wakeup_task,
+        wakeup_blocked_task,
+        ProcessControlBlock,
+        SignalFlags,
         TaskStruct,
     },
 };
 use alloc::{
     collections::BinaryHeap,
-    sync::Arc,
+    sync::{
+        Arc,
+        Weak,
+    },
+    vec::Vec,
+};
+use core::sync::atomic::{
+    AtomicUsize,
+    Ordering as AtomicOrdering,
 };
 use lazy_static::*;
 use riscv::register::time;
 
 const TICKS_PER_SEC: usize = 100;
 const MSEC_PER_SEC: usize = 1000;
+pub const TICK_MS: usize = MSEC_PER_SEC / TICKS_PER_SEC;
+pub const NSEC_PER_SEC: usize = 1_000_000_000;
+/// Timer ticks a task may run before another gets the CPU.
+const TIME_SLICE: usize = CLOCK_FREQ / TICKS_PER_SEC;
+
+/// Wall-clock time at boot in nanoseconds since the epoch, 0 without an
+/// RTC.
+static BOOT_REALTIME_NS: AtomicUsize = AtomicUsize::new(0);
+/// When the running task has used up its time slice, `usize::MAX` while
+/// there is none.
+static SLICE_END: AtomicUsize = AtomicUsize::new(usize::MAX);
+/// What the timer interrupt is set for.
+static NEXT_TRIGGER: AtomicUsize = AtomicUsize::new(usize::MAX);
 
 pub fn get_time() -> usize {
     time::read()
@@ -27,26 +56,108 @@ pub fn get_time_ms() -> usize {
     time::read() / (CLOCK_FREQ / MSEC_PER_SEC)
 }
 
+pub fn ticks_to_ns(ticks: usize) -> usize {
+    ticks / CLOCK_FREQ * NSEC_PER_SEC + ticks % CLOCK_FREQ * NSEC_PER_SEC / CLOCK_FREQ
+}
+
+/// Timer ticks in `ns` nanoseconds, rounded up.
+pub fn ns_to_ticks(ns: usize) -> usize {
+    ns / NSEC_PER_SEC * CLOCK_FREQ
+        + (ns % NSEC_PER_SEC * CLOCK_FREQ + NSEC_PER_SEC - 1) / NSEC_PER_SEC
+}
+
+/// Nanoseconds since boot, in steps of one timer tick.
+pub fn get_time_ns() -> usize {
+    ticks_to_ns(get_time())
+}
+
+/// Take the wall-clock time from the RTC. The timer keeps it from then on.
+pub fn init_realtime() {
+    if let Some(base) = rtc_base() {
+        let now = GoldfishRtc::new(base).read_ns() as usize;
+        BOOT_REALTIME_NS.store(now.saturating_sub(get_time_ns()), AtomicOrdering::Relaxed);
+    }
+}
+
+/// Nanoseconds since the epoch, or since boot on a machine without an RTC.
+pub fn get_realtime_ns() -> usize {
+    boot_realtime_ns() + get_time_ns()
+}
+
+pub fn boot_realtime_ns() -> usize {
+    BOOT_REALTIME_NS.load(AtomicOrdering::Relaxed)
+}
+
+fn ms_to_ticks(ms: usize) -> usize {
+    ms.saturating_mul(CLOCK_FREQ / MSEC_PER_SEC)
+}
+
+/// Give the task being switched to a time slice from now.
+pub fn start_time_slice() {
+    SLICE_END.store(get_time() + TIME_SLICE, AtomicOrdering::Relaxed);
+    set_next_trigger();
+}
+
+/// Nothing runs, so only the timers need the interrupt.
+pub fn stop_time_slice() {
+    SLICE_END.store(usize::MAX, AtomicOrdering::Relaxed);
+}
+
+pub fn time_slice_over() -> bool {
+    get_time() >= SLICE_END.load(AtomicOrdering::Relaxed)
+}
+
+/// Set the timer interrupt for the end of the time slice or the first
+/// timer due, whichever comes first. With neither it stays off.
 pub fn set_next_trigger() {
-    set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
+    let now = get_time();
+    let mut next = SLICE_END.load(AtomicOrdering::Relaxed);
+    // a slice that ran out while in the kernel ends at the next interrupt
+    if next <= now {
+        next = now + TIME_SLICE;
+    }
+    if let Some(timer) = TIMERS.exclusive_access().peek() {
+        next = next.min(timer.expire);
+    }
+    // the network timers count in milliseconds, one that is already due
+    // is looked at again on the next slice rather than in a loop
+    if let Some(timeout) = next_timeout_ms().map(ms_to_ticks) {
+        next = next.min(if timeout > now {
+            timeout
+        } else {
+            now + TIME_SLICE
+        });
+    }
+    trigger_at(next);
+}
+
+fn trigger_at(time: usize) {
+    NEXT_TRIGGER.store(time, AtomicOrdering::Relaxed);
+    set_timer(time);
 }
 
 pub struct TimerCondVar {
-    pub expire_ms: usize,
-    pub task: Arc<TaskStruct>,
+    /// When to go off, in timer ticks since boot.
+    pub expire: usize,
+    pub target: TimerTarget,
+}
+
+pub enum TimerTarget {
+    /// A sleeping task to wake up.
+    Task(Arc<TaskStruct>),
+    /// An interval timer, unless it was set again since this generation.
+    Interval(Weak<IntervalTimer>, usize),
 }
 
 impl PartialEq for TimerCondVar {
     fn eq(&self, other: &Self) -> bool {
-        self.expire_ms == other.expire_ms
+        self.expire == other.expire
     }
 }
 impl Eq for TimerCondVar {}
 impl PartialOrd for TimerCondVar {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        let a = -(self.expire_ms as isize);
-        let b = -(other.expire_ms as isize);
-        Some(a.cmp(&b))
+        Some(other.expire.cmp(&self.expire))
     }
 }
 
@@ -61,21 +172,209 @@ lazy_static! {
         unsafe { UPIntrFreeCell::new(BinaryHeap::<TimerCondVar>::new()) };
 }
 
+/// Queue `timer`, moving the timer interrupt up if it is due before.
+fn push_timer(timer: TimerCondVar) {
+    let expire = timer.expire;
+    TIMERS.exclusive_access().push(timer);
+    if expire < NEXT_TRIGGER.load(AtomicOrdering::Relaxed) {
+        trigger_at(expire);
+    }
+}
+
+/// Wake `task` at `expire_ms` milliseconds since boot.
 pub fn add_timer(expire_ms: usize, task: Arc<TaskStruct>) {
-    let mut timers = TIMERS.exclusive_access();
-    timers.push(TimerCondVar { expire_ms, task });
+    push_timer(TimerCondVar {
+        expire: ms_to_ticks(expire_ms),
+        target: TimerTarget::Task(task),
+    });
 }
 
-pub fn check_timer() {
-    let current_ms = get_time_ms();
+/// Wake `task` at `deadline` nanoseconds since boot.
+pub fn add_timer_ns(deadline: usize, task: Arc<TaskStruct>) {
+    push_timer(TimerCondVar {
+        expire: ns_to_ticks(deadline),
+        target: TimerTarget::Task(task),
+    });
+}
+
+/// Drop the timers of `task`, for a sleep that ended early.
+pub fn remove_timer(task: &Arc<TaskStruct>) {
+    TIMERS
+        .exclusive_access()
+        .retain(|timer| match &timer.target {
+            TimerTarget::Task(sleeper) => !Arc::ptr_eq(sleeper, task),
+            TimerTarget::Interval(..) => true,
+        });
+}
+
+/// Fire the timers that are due, true if there were any.
+pub fn check_timer() -> bool {
+    let now = get_time();
+    let mut expired = Vec::new();
     TIMERS.exclusive_session(|timers| {
         while let Some(timer) = timers.peek() {
-            if timer.expire_ms <= current_ms {
-                wakeup_task(Arc::clone(&timer.task));
-                timers.pop();
+            if timer.expire <= now {
+                expired.push(timers.pop().unwrap().target);
             } else {
                 break;
             }
         }
     });
+    let fired = !expired.is_empty();
+    // interval timers queue their next expiry, so they go off outside
+    for target in expired {
+        match target {
+            TimerTarget::Task(task) => wakeup_blocked_task(task),
+            TimerTarget::Interval(timer, generation) => {
+                if let Some(timer) = timer.upgrade() {
+                    timer.expire(generation);
+                }
+            }
+        }
+    }
+    fired
+}
+
+/// What an `IntervalTimer` does when it goes off, besides counting.
+pub enum TimerNotify {
+    /// Nothing, the owner only looks at the count (`SIGEV_NONE`).
+    None,
+    /// Raise a signal in a process.
+    Signal(Weak<ProcessControlBlock>, SignalFlags),
+    /// Wake whoever waits for the count, as a timerfd does.
+    Wake(WaitQueue),
+}
+
+struct IntervalTimerInner {
+    /// Next expiry in nanoseconds since boot, 0 while disarmed.
+    deadline: usize,
+    interval: usize,
+    /// Expirations since the count was last taken.
+    expirations: u64,
+    /// Bumped whenever the timer is set, so entries queued before are
+    /// ignored.
+    generation: usize,
+}
+
+/// A one-shot or periodic timer on the monotonic clock, behind
+/// `ITIMER_REAL`, `timer_create` and `timerfd_create`.
+pub struct IntervalTimer {
+    inner: UPIntrFreeCell<IntervalTimerInner>,
+    notify: TimerNotify,
+}
+
+impl IntervalTimer {
+    pub fn new(notify: TimerNotify) -> Arc<Self> {
+        Arc::new(Self {
+            inner: unsafe {
+                UPIntrFreeCell::new(IntervalTimerInner {
+                    deadline: 0,
+                    interval: 0,
+                    expirations: 0,
+                    generation: 0,
+                })
+            },
+            notify,
+        })
+    }
+
+    /// Go off at `deadline` and every `interval` nanoseconds after, a
+    /// `deadline` of 0 disarms. Returns what `get` did before.
+    pub fn set(self: &Arc<Self>, deadline: usize, interval: usize) -> (usize, usize) {
+        let old = self.get();
+        let mut inner = self.inner.exclusive_access();
+        inner.generation += 1;
+        inner.deadline = deadline;
+        inner.interval = interval;
+        inner.expirations = 0;
+        if deadline != 0 {
+            self.queue(deadline, inner.generation);
+        }
+        old
+    }
+
+    /// Nanoseconds until the next expiry, 0 when disarmed, and the
+    /// interval.
+    pub fn get(&self) -> (usize, usize) {
+        let inner = self.inner.exclusive_access();
+        let left = match inner.deadline {
+            0 => 0,
+            // one that is due but has not gone off yet is about to
+            deadline => deadline.saturating_sub(get_time_ns()).max(1),
+        };
+        (left, inner.interval)
+    }
+
+    pub fn expirations(&self) -> u64 {
+        self.inner.exclusive_access().expirations
+    }
+
+    pub fn take_expirations(&self) -> u64 {
+        core::mem::take(&mut self.inner.exclusive_access().expirations)
+    }
+
+    pub fn wait_queue(&self) -> Option<&WaitQueue> {
+        match &self.notify {
+            TimerNotify::Wake(wait_queue) => Some(wait_queue),
+            _ => None,
+        }
+    }
+
+    /// Timers go off on whole timer ticks, so a deadline is rounded up.
+    fn queue(self: &Arc<Self>, deadline: usize, generation: usize) {
+        push_timer(TimerCondVar {
+            expire: ns_to_ticks(deadline),
+            target: TimerTarget::Interval(Arc::downgrade(self), generation),
+        });
+    }
+
+    fn expire(self: &Arc<Self>, generation: usize) {
+        let mut inner = self.inner.exclusive_access();
+        if inner.generation != generation || inner.deadline == 0 {
+            return;
+        }
+        let mut count = 1;
+        if inner.interval == 0 {
+            inner.deadline = 0;
+        } else {
+            // periods that passed while interrupts were off count as well
+            count += get_time_ns().saturating_sub(inner.deadline) / inner.interval;
+            inner.deadline += count * inner.interval;
+            self.queue(inner.deadline, generation);
+        }
+        inner.expirations += count as u64;
+        drop(inner);
+        match &self.notify {
+            TimerNotify::None => {}
+            TimerNotify::Signal(process, signal) => {
+                if let Some(process) = process.upgrade() {
+                    process.inner_exclusive_access().signals |= *signal;
+                }
+            }
+            TimerNotify::Wake(wait_queue) => wait_queue.wake_all(),
+        }
+    }
+}
+
+/// `ITIMER_VIRTUAL` or `ITIMER_PROF`, in timer ticks of process CPU time.
+#[derive(Copy, Clone, Default)]
+pub struct CpuTimer {
+    /// CPU time to go off at, 0 while disarmed.
+    pub deadline: usize,
+    pub interval: usize,
+}
+
+impl CpuTimer {
+    /// Whether the timer went off by `cpu_time`. A periodic one is set
+    /// for its next period.
+    pub fn expire(&mut self, cpu_time: usize) -> bool {
+        if self.deadline == 0 || cpu_time < self.deadline {
+            return false;
+        }
+        self.deadline = match self.interval {
+            0 => 0,
+            interval => cpu_time + interval,
+        };
+        true
+    }
 }
@@ -2,6 +2,8 @@ mod context;
 
 use crate::{
     config::TRAMPOLINE,
+    net::net_tick,
+    sync::UPIntrFreeCell,
     syscall::syscall,
     task::{
         check_signals_of_current,
@@ -17,9 +19,15 @@ use crate::{
     timer::{
         check_timer,
         set_next_trigger,
+        time_slice_over,
     },
 };
+use alloc::{
+    collections::BTreeMap,
+    vec::Vec,
+};
 use core::arch::asm;
+use lazy_static::*;
 use riscv::register::{
     mtvec::TrapMode,
     scause::{
@@ -35,6 +43,28 @@ use riscv::register::{
     stvec,
 };
 
+/// Pseudo IRQ number the timer is counted under, PLIC sources start from 1.
+pub const TIMER_IRQ: usize = 0;
+
+lazy_static! {
+    /// irq -> times taken, for `/proc/interrupts`.
+    static ref IRQ_COUNTS: UPIntrFreeCell<BTreeMap<usize, usize>> =
+        unsafe { UPIntrFreeCell::new(BTreeMap::new()) };
+}
+
+pub fn count_interrupt(irq: usize) {
+    *IRQ_COUNTS.exclusive_access().entry(irq).or_insert(0) += 1;
+}
+
+/// (irq, count) pairs in irq order.
+pub fn interrupt_counts() -> Vec<(usize, usize)> {
+    IRQ_COUNTS
+        .exclusive_access()
+        .iter()
+        .map(|(irq, count)| (*irq, *count))
+        .collect()
+}
+
 pub fn init() {
     set_kernel_trap_entry();
 }
@@ -84,6 +114,24 @@ fn disable_supervisor_interrupt() {
     }
 }
 
+/// Sleep until an interrupt is pending, for the idle loop. Interrupts must
+/// be masked, so that one coming in just before is not missed.
+pub fn wait_for_interrupt() {
+    set_kernel_trap_entry();
+    unsafe {
+        asm!("wfi");
+    }
+}
+
+/// Handle the interrupts that are pending, leaving them masked if they
+/// were.
+pub fn take_pending_interrupts() {
+    if !sstatus::read().sie() {
+        enable_supervisor_interrupt();
+        disable_supervisor_interrupt();
+    }
+}
+
 #[no_mangle]
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
@@ -127,9 +175,15 @@ pub fn trap_handler() -> ! {
             current_add_signal(SignalFlags::SIGILL);
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
-            set_next_trigger();
-            check_timer();
-            suspend_current_and_run_next();
+            count_interrupt(TIMER_IRQ);
+            let woke = check_timer();
+            net_tick();
+            // a woken sleeper gets to run at once
+            if woke || time_slice_over() {
+                suspend_current_and_run_next();
+            } else {
+                set_next_trigger();
+            }
         }
         Trap::Interrupt(Interrupt::SupervisorExternal) => {
             crate::board::irq_handler();
@@ -183,8 +237,10 @@ pub fn trap_from_kernel(_trap_ctx: &TrapContext) {
             crate::board::irq_handler();
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
-            set_next_trigger();
+            count_interrupt(TIMER_IRQ);
             check_timer();
+            net_tick();
+            set_next_trigger();
             // do not schedule now
         }
         _ => {
@@ -230,8 +286,14 @@ pub fn kpthread_trap_handler(ctx: &mut TrapContext) -> ! {
             crate::board::irq_handler();
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
-            set_next_trigger();
-            suspend_current_and_run_next();
+            count_interrupt(TIMER_IRQ);
+            let woke = check_timer();
+            net_tick();
+            if woke || time_slice_over() {
+                suspend_current_and_run_next();
+            } else {
+                set_next_trigger();
+            }
         }
         _ => {
             panic!(
@@ -33,3 +33,28 @@ pub const EMLINK: usize = 31; /* Too many links */
 pub const EPIPE: usize = 32; /* Broken pipe */
 pub const EDOM: usize = 33; /* Math argument out of domain of func */
 pub const ERANGE: usize = 34; /* Math result not representable */
+pub const EDEADLK: usize = 35; /* Resource deadlock would occur */
+pub const ENAMETOOLONG: usize = 36; /* File name too long */
+pub const ENOLCK: usize = 37; /* No record locks available */
+pub const ENOSYS: usize = 38; /* Invalid system call number */
+pub const ENOTEMPTY: usize = 39; /* Directory not empty */
+pub const ELOOP: usize = 40; /* Too many symbolic links encountered */
+pub const ENOTSOCK: usize = 88; /* Socket operation on non-socket */
+pub const EDESTADDRREQ: usize = 89; /* Destination address required */
+pub const EMSGSIZE: usize = 90; /* Message too long */
+pub const EPROTOTYPE: usize = 91; /* Protocol wrong type for socket */
+pub const ENOPROTOOPT: usize = 92; /* Protocol not available */
+pub const EPROTONOSUPPORT: usize = 93; /* Protocol not supported */
+pub const ESOCKTNOSUPPORT: usize = 94; /* Socket type not supported */
+pub const EOPNOTSUPP: usize = 95; /* Operation not supported on transport endpoint */
+pub const EAFNOSUPPORT: usize = 97; /* Address family not supported by protocol */
+pub const EADDRINUSE: usize = 98; /* Address already in use */
+pub const EADDRNOTAVAIL: usize = 99; /* Cannot assign requested address */
+pub const ENETUNREACH: usize = 101; /* Network is unreachable */
+pub const ECONNRESET: usize = 104; /* Connection reset by peer */
+pub const EISCONN: usize = 106; /* Transport endpoint is already connected */
+pub const ENOTCONN: usize = 107; /* Transport endpoint is not connected */
+pub const ETIMEDOUT: usize = 110; /* Connection timed out */
+pub const ECONNREFUSED: usize = 111; /* Connection refused */
+pub const EALREADY: usize = 114; /* Operation already in progress */
+pub const EINPROGRESS: usize = 115; /* Operation now in progress */
@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    clock_getres, clock_gettime, clock_nanosleep, get_time, TimeSpec, CLOCK_MONOTONIC,
+    CLOCK_PROCESS_CPUTIME_ID, CLOCK_REALTIME, CLOCK_THREAD_CPUTIME_ID, TIMER_ABSTIME,
+};
+
+const EINVAL: isize = 22;
+/// 2020-01-01, any RTC reads later than that
+const Y2020: u64 = 1_577_836_800;
+
+fn now(clock: usize) -> u64 {
+    let mut time = TimeSpec { sec: 0, nsec: 0 };
+    assert_eq!(clock_gettime(clock, &mut time), 0);
+    assert!(time.nsec < 1_000_000_000);
+    time.sec * 1_000_000_000 + time.nsec
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert!(now(CLOCK_REALTIME) / 1_000_000_000 > Y2020);
+    assert!(get_time() as u64 / 1000 > Y2020);
+
+    let mut res = TimeSpec { sec: 0, nsec: 0 };
+    for clock in [
+        CLOCK_REALTIME,
+        CLOCK_MONOTONIC,
+        CLOCK_PROCESS_CPUTIME_ID,
+        CLOCK_THREAD_CPUTIME_ID,
+    ] {
+        assert_eq!(clock_getres(clock, &mut res), 0);
+        assert!(res.sec == 0 && res.nsec > 0 && res.nsec <= 1_000_000);
+    }
+    assert_eq!(clock_gettime(100, &mut res), -EINVAL);
+
+    // a relative and an absolute sleep of 20ms each
+    let start = now(CLOCK_MONOTONIC);
+    let nap = TimeSpec {
+        sec: 0,
+        nsec: 20_000_000,
+    };
+    assert_eq!(clock_nanosleep(CLOCK_MONOTONIC, 0, &nap), 0);
+    let woken = now(CLOCK_MONOTONIC);
+    assert!(woken - start >= 20_000_000);
+    let wake_at = woken + 20_000_000;
+    let until = TimeSpec {
+        sec: wake_at / 1_000_000_000,
+        nsec: wake_at % 1_000_000_000,
+    };
+    assert_eq!(clock_nanosleep(CLOCK_MONOTONIC, TIMER_ABSTIME, &until), 0);
+    assert!(now(CLOCK_MONOTONIC) >= wake_at);
+    let bad = TimeSpec {
+        sec: 0,
+        nsec: 1_000_000_000,
+    };
+    assert_eq!(clock_nanosleep(CLOCK_MONOTONIC, 0, &bad), -EINVAL);
+
+    // short sleeps are not rounded up to a scheduler tick
+    let start = now(CLOCK_MONOTONIC);
+    let nap = TimeSpec {
+        sec: 0,
+        nsec: 100_000,
+    };
+    for _ in 0..20 {
+        assert_eq!(clock_nanosleep(CLOCK_MONOTONIC, 0, &nap), 0);
+    }
+    let slept = now(CLOCK_MONOTONIC) - start;
+    assert!(slept >= 2_000_000 && slept < 20_000_000);
+
+    // sleeping took no CPU time, spinning does
+    let cpu_start = now(CLOCK_THREAD_CPUTIME_ID);
+    let spin_start = now(CLOCK_MONOTONIC);
+    while now(CLOCK_MONOTONIC) - spin_start < 30_000_000 {}
+    let cpu_spent = now(CLOCK_THREAD_CPUTIME_ID) - cpu_start;
+    assert!(cpu_spent > 0 && cpu_spent <= now(CLOCK_MONOTONIC) - spin_start);
+    assert!(now(CLOCK_PROCESS_CPUTIME_ID) >= now(CLOCK_THREAD_CPUTIME_ID) - 1_000_000);
+    println!("clock_test passed!");
+    0
+}
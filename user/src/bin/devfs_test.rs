@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, ioctl, makedev, mknod, mmap_file, open, read, write, MapProtect, OpenFlags, S_IFCHR,
+};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("/dev/null\0", OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut buffer = [0xffu8; 64];
+    assert_eq!(write(fd, b"discarded"), 9);
+    assert_eq!(read(fd, &mut buffer), 0);
+    // not a terminal
+    assert!(ioctl(fd, 0x5401, 0) < 0);
+    close(fd);
+
+    let fd = open("/dev/zero\0", OpenFlags::RDONLY) as usize;
+    assert_eq!(read(fd, &mut buffer), 64);
+    assert!(buffer.iter().all(|b| *b == 0));
+    // the fd is read only even if the device takes writes
+    assert!(write(fd, b"dropped") < 0);
+    close(fd);
+
+    let fd = open("/dev/urandom\0", OpenFlags::RDONLY) as usize;
+    assert_eq!(read(fd, &mut buffer), 64);
+    assert!(buffer.iter().any(|b| *b != 0));
+    close(fd);
+
+    // a second name for /dev/null
+    assert_eq!(mknod("/dev/null2\0", S_IFCHR | 0o666, makedev(1, 3)), 0);
+    assert!(mknod("/dev/null2\0", S_IFCHR | 0o666, makedev(1, 3)) < 0);
+    let fd = open("/dev/null2\0", OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, b"gone"), 4);
+    assert!(read(fd as usize, &mut buffer) < 0);
+    close(fd as usize);
+
+    // only there with a virtio-gpu
+    let fd = open("/dev/fb0\0", OpenFlags::RDWR);
+    if fd > 0 {
+        let addr = mmap_file(0, 4096, MapProtect::R | MapProtect::W, fd as usize, 0);
+        assert!(addr > 0);
+        let pixel = addr as *mut u32;
+        unsafe {
+            pixel.write_volatile(0x00ff_ffff);
+            assert_eq!(pixel.read_volatile(), 0x00ff_ffff);
+        }
+        close(fd as usize);
+    }
+
+    println!("devfs_test passed!");
+    0
+}
@@ -0,0 +1,39 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, getrandom, open, read, write, OpenFlags, GRND_NONBLOCK, GRND_RANDOM};
+
+const EINVAL: isize = 22;
+
+fn read_device(path: &str, buf: &mut [u8]) {
+    let fd = open(path, OpenFlags::RDWR);
+    assert!(fd >= 0);
+    assert_eq!(read(fd as usize, buf), buf.len() as isize);
+    // written bytes are mixed in, not taken as they are
+    assert_eq!(write(fd as usize, b"some entropy"), 12);
+    close(fd as usize);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut first = [0u8; 64];
+    let mut second = [0u8; 64];
+    assert_eq!(getrandom(&mut first, 0), 64);
+    assert_eq!(getrandom(&mut second, GRND_NONBLOCK), 64);
+    assert!(first != second);
+    assert!(first.iter().any(|byte| *byte != 0));
+    // longer than a page, so the buffer is split across pages
+    let mut large = [0u8; 5000];
+    assert_eq!(getrandom(&mut large, GRND_RANDOM), 5000);
+    assert!(large[4096..].iter().any(|byte| *byte != 0));
+    assert_eq!(getrandom(&mut first, 0x80), -EINVAL);
+
+    read_device("/dev/random\0", &mut first);
+    read_device("/dev/urandom\0", &mut second);
+    assert!(first != second);
+    println!("getrandom_test passed!");
+    0
+}
@@ -5,7 +5,7 @@ extern crate user_lib;
 extern crate alloc;
 
 use user_lib::console::getchar;
-use user_lib::{Display, VIRTGPU_XRES, VIRTGPU_YRES};
+use user_lib::{set_raw_mode, tcsetattr, Display, TCSANOW, VIRTGPU_XRES, VIRTGPU_YRES};
 
 use embedded_graphics::pixelcolor::Rgb888;
 use embedded_graphics::prelude::{Drawable, Point, RgbColor, Size};
@@ -61,6 +61,8 @@ pub fn main() -> i32 {
     let mut board = DrawingBoard::new();
     let _ = board.disp.clear(Rgb888::BLACK).unwrap();
     board.disp.flush();
+    // keys take effect without waiting for Enter
+    let saved = set_raw_mode(0);
     loop {
         let c = getchar();
         if c == LF || c == CR {
@@ -78,5 +80,6 @@ pub fn main() -> i32 {
             board.disp.flush();
         }
     }
+    tcsetattr(0, TCSANOW, &saved);
     0
 }
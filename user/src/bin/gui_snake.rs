@@ -5,7 +5,9 @@ extern crate alloc;
 extern crate user_lib;
 
 use user_lib::console::getchar;
-use user_lib::{key_pressed, sleep, Display, VIRTGPU_XRES, VIRTGPU_YRES};
+use user_lib::{
+    key_pressed, set_raw_mode, sleep, tcsetattr, Display, TCSANOW, VIRTGPU_XRES, VIRTGPU_YRES,
+};
 
 use embedded_graphics::pixelcolor::*;
 use embedded_graphics::prelude::{Drawable, Point, RgbColor, Size};
@@ -330,6 +332,8 @@ pub fn main() -> i32 {
     let mut disp = Display::new(Size::new(VIRTGPU_XRES, VIRTGPU_YRES));
     let mut game = SnakeGame::<20, Rgb888>::new(1280, 800, 20, 20, Rgb888::RED, Rgb888::YELLOW, 200);
     let _ = disp.clear(Rgb888::BLACK).unwrap();
+    // keys take effect without waiting for Enter
+    let saved = set_raw_mode(0);
     loop {
         if key_pressed() {
             let c = getchar();
@@ -348,5 +352,6 @@ pub fn main() -> i32 {
         disp.flush();
         sleep(40);
     }
+    tcsetattr(0, TCSANOW, &saved);
     0
 }
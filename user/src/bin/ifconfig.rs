@@ -0,0 +1,139 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, interfaces, ioctl, socket, IfReq, AF_INET, IFF_BROADCAST, IFF_LOOPBACK, IFF_RUNNING,
+    IFF_UP, SIOCGIFADDR, SIOCGIFFLAGS, SIOCGIFHWADDR, SIOCGIFNETMASK, SIOCSIFADDR, SIOCSIFNETMASK,
+    SOCK_DGRAM,
+};
+
+// usage: ifconfig [interface [address [netmask mask]]]
+// without an address shows the interfaces, with one sets it
+
+const MAX_INTERFACES: usize = 8;
+
+fn parse_ip(s: &str) -> Option<[u8; 4]> {
+    let mut ip = [0u8; 4];
+    let mut parts = s.split('.');
+    for byte in ip.iter_mut() {
+        *byte = parts.next()?.parse().ok()?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(ip)
+}
+
+fn query(fd: usize, name: &str, cmd: u32) -> Option<IfReq> {
+    let mut req = IfReq::new(name);
+    if ioctl(fd, cmd, &mut req as *mut IfReq as usize) < 0 {
+        return None;
+    }
+    Some(req)
+}
+
+fn show(fd: usize, name: &str) -> bool {
+    let flags = match query(fd, name, SIOCGIFFLAGS) {
+        Some(req) => req.flags(),
+        None => {
+            println!("{}: no such interface", name);
+            return false;
+        }
+    };
+    print!("{}: flags={:x}<", name, flags);
+    let names = [
+        (IFF_UP, "UP"),
+        (IFF_BROADCAST, "BROADCAST"),
+        (IFF_LOOPBACK, "LOOPBACK"),
+        (IFF_RUNNING, "RUNNING"),
+    ];
+    let mut first = true;
+    for (flag, flag_name) in names {
+        if flags & flag != 0 {
+            print!("{}{}", if first { "" } else { "," }, flag_name);
+            first = false;
+        }
+    }
+    println!(">");
+
+    let addr = query(fd, name, SIOCGIFADDR)
+        .map(|req| req.addr())
+        .unwrap_or_default();
+    let netmask = query(fd, name, SIOCGIFNETMASK)
+        .map(|req| req.addr())
+        .unwrap_or_default();
+    println!(
+        "        inet {}.{}.{}.{}  netmask {}.{}.{}.{}",
+        addr[0], addr[1], addr[2], addr[3], netmask[0], netmask[1], netmask[2], netmask[3]
+    );
+    if flags & IFF_LOOPBACK == 0 {
+        if let Some(req) = query(fd, name, SIOCGIFHWADDR) {
+            let mac = req.hw_addr();
+            println!(
+                "        ether {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+                mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
+            );
+        }
+    }
+    true
+}
+
+fn set(fd: usize, name: &str, cmd: u32, value: &str) -> bool {
+    let ip = match parse_ip(value) {
+        Some(ip) => ip,
+        None => {
+            println!("bad address {}", value);
+            return false;
+        }
+    };
+    let mut req = IfReq::new(name);
+    req.set_addr(ip);
+    let ret = ioctl(fd, cmd, &mut req as *mut IfReq as usize);
+    if ret < 0 {
+        println!("{}: can't set {} ({})", name, value, ret);
+        return false;
+    }
+    true
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let fd = socket(AF_INET, SOCK_DGRAM);
+    if fd < 0 {
+        println!("socket failed: {}", fd);
+        return -1;
+    }
+    let fd = fd as usize;
+    let ok = match argc {
+        1 => {
+            let mut reqs = [IfReq::new(""); MAX_INTERFACES];
+            let count = interfaces(fd, &mut reqs);
+            if count < 0 {
+                println!("can't list interfaces: {}", count);
+                false
+            } else {
+                reqs[..count as usize]
+                    .iter()
+                    .all(|req| show(fd, req.name()))
+            }
+        }
+        2 => show(fd, argv[1]),
+        3 => set(fd, argv[1], SIOCSIFADDR, argv[2]),
+        5 if argv[3] == "netmask" => {
+            set(fd, argv[1], SIOCSIFADDR, argv[2]) && set(fd, argv[1], SIOCSIFNETMASK, argv[4])
+        }
+        _ => {
+            println!("usage: ifconfig [interface [address [netmask mask]]]");
+            false
+        }
+    };
+    close(fd);
+    if ok {
+        0
+    } else {
+        -1
+    }
+}
@@ -3,20 +3,21 @@
 
 extern crate user_lib;
 
-use user_lib::{exec, fork, wait, yield_};
+use user_lib::{exec, fork, mount, wait, yield_};
 
 #[no_mangle]
 fn main() -> i32 {
+    mount("tmpfs\0", "/tmp\0", "tmpfs\0", 0, None);
     if fork() == 0 {
         exec("user_shell\0", &[core::ptr::null::<u8>()]);
     } else {
         loop {
             let mut exit_code: i32 = 0;
-            // let pid = wait(&mut exit_code);
-            // if pid == -1 {
-            yield_();
-            //     continue;
-            // }
+            let pid = wait(&mut exit_code);
+            if pid == -1 {
+                yield_();
+                continue;
+            }
             /*
             println!(
                 "[initproc] Released a zombie process, pid={}, exit_code={}",
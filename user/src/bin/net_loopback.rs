@@ -0,0 +1,89 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    accept, bind, close, connect, exit, fork, getpeername, listen, read, recvfrom, sendto,
+    shutdown, socket, waitpid, write, SockAddrIn, AF_INET, SHUT_WR, SOCK_DGRAM, SOCK_STREAM,
+};
+
+const LOCALHOST: [u8; 4] = [127, 0, 0, 1];
+const MESSAGE: &[u8] = b"hello over loopback";
+
+fn udp_test() {
+    let receiver = socket(AF_INET, SOCK_DGRAM) as usize;
+    assert_eq!(bind(receiver, &SockAddrIn::new(LOCALHOST, 7001)), 0);
+    let sender = socket(AF_INET, SOCK_DGRAM) as usize;
+    assert_eq!(
+        sendto(sender, MESSAGE, &SockAddrIn::new(LOCALHOST, 7001)),
+        MESSAGE.len() as isize
+    );
+    let mut buf = [0u8; 64];
+    let mut source = SockAddrIn::default();
+    let len = recvfrom(receiver, &mut buf, Some(&mut source));
+    assert_eq!(&buf[..len as usize], MESSAGE);
+    assert_eq!(source.ip(), LOCALHOST);
+    close(sender);
+    close(receiver);
+}
+
+/// The child connects and checks that it gets back what it sent.
+fn tcp_test() {
+    let listener = socket(AF_INET, SOCK_STREAM) as usize;
+    assert_eq!(bind(listener, &SockAddrIn::new(LOCALHOST, 7000)), 0);
+    assert_eq!(listen(listener, 1), 0);
+
+    let pid = fork();
+    if pid == 0 {
+        let fd = socket(AF_INET, SOCK_STREAM) as usize;
+        assert_eq!(connect(fd, &SockAddrIn::new(LOCALHOST, 7000)), 0);
+        let mut peer = SockAddrIn::default();
+        assert_eq!(getpeername(fd, &mut peer), 0);
+        assert_eq!(peer.port(), 7000);
+        assert_eq!(write(fd, MESSAGE), MESSAGE.len() as isize);
+        shutdown(fd, SHUT_WR);
+        let mut buf = [0u8; 64];
+        let mut len = 0;
+        loop {
+            let n = read(fd, &mut buf[len..]);
+            if n <= 0 {
+                break;
+            }
+            len += n as usize;
+        }
+        assert_eq!(&buf[..len], MESSAGE);
+        close(fd);
+        exit(0);
+    }
+
+    let mut peer = SockAddrIn::default();
+    let fd = accept(listener, Some(&mut peer));
+    assert!(fd >= 0);
+    assert_eq!(peer.ip(), LOCALHOST);
+    let fd = fd as usize;
+    // echo until the client is done sending
+    let mut buf = [0u8; 64];
+    loop {
+        let n = read(fd, &mut buf);
+        if n <= 0 {
+            break;
+        }
+        write(fd, &buf[..n as usize]);
+    }
+    close(fd);
+    close(listener);
+
+    let mut exit_code = 0;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    udp_test();
+    tcp_test();
+    println!("net_loopback passed!");
+    0
+}
@@ -0,0 +1,103 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    bind, close, fcntl, get_time, getsockopt, pipe2, read, recv, set_socket_timeout, setsockopt,
+    socket, socketpair, write, OpenFlags, SockAddrIn, AF_INET, AF_UNIX, FD_CLOEXEC, F_DUPFD,
+    F_GETFD, F_GETFL, F_SETFD, F_SETFL, IPPROTO_TCP, SOCK_DGRAM, SOCK_NONBLOCK, SOCK_STREAM,
+    SOL_SOCKET, SO_RCVTIMEO, SO_REUSEADDR, TCP_NODELAY,
+};
+
+const EAGAIN: isize = -11;
+
+fn pipe_test() {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe2(&mut fds, OpenFlags::NONBLOCK), 0);
+    let mut buf = [0u8; 4];
+    assert_eq!(read(fds[0], &mut buf), EAGAIN);
+    assert!(fcntl(fds[0], F_GETFL, 0) & OpenFlags::NONBLOCK.bits() as isize != 0);
+    assert_eq!(write(fds[1], b"hi"), 2);
+    assert_eq!(read(fds[0], &mut buf), 2);
+    assert_eq!(&buf[..2], b"hi");
+
+    // back to blocking, the data is there so it returns at once
+    assert_eq!(fcntl(fds[0], F_SETFL, 0), 0);
+    assert_eq!(
+        fcntl(fds[0], F_GETFL, 0) & OpenFlags::NONBLOCK.bits() as isize,
+        0
+    );
+    assert_eq!(write(fds[1], b"x"), 1);
+    assert_eq!(read(fds[0], &mut buf), 1);
+
+    // a full pipe takes what fits, then nothing
+    let chunk = [0u8; 256];
+    let mut written = 0;
+    loop {
+        let ret = write(fds[1], &chunk);
+        if ret == EAGAIN {
+            break;
+        }
+        assert!(ret > 0);
+        written += ret;
+    }
+    assert!(written > 0);
+    close(fds[0]);
+    close(fds[1]);
+}
+
+fn fd_flags_test() {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe2(&mut fds, OpenFlags::CLOEXEC), 0);
+    assert_eq!(fcntl(fds[0], F_GETFD, 0), FD_CLOEXEC as isize);
+    assert_eq!(fcntl(fds[0], F_SETFD, 0), 0);
+    assert_eq!(fcntl(fds[0], F_GETFD, 0), 0);
+
+    // the lowest free fd at or above the argument, without FD_CLOEXEC
+    let dup = fcntl(fds[1], F_DUPFD, 20);
+    assert_eq!(dup, 20);
+    assert_eq!(fcntl(dup as usize, F_GETFD, 0), 0);
+    assert_eq!(write(dup as usize, b"d"), 1);
+    let mut buf = [0u8; 1];
+    assert_eq!(read(fds[0], &mut buf), 1);
+    close(dup as usize);
+    close(fds[0]);
+    close(fds[1]);
+}
+
+fn socket_test() {
+    let mut sv = [0i32; 2];
+    assert_eq!(socketpair(AF_UNIX, SOCK_STREAM | SOCK_NONBLOCK, &mut sv), 0);
+    let mut buf = [0u8; 8];
+    assert_eq!(recv(sv[0] as usize, &mut buf), EAGAIN);
+    close(sv[0] as usize);
+    close(sv[1] as usize);
+
+    // a receive timeout gives up with EAGAIN
+    let fd = socket(AF_INET, SOCK_DGRAM) as usize;
+    assert_eq!(bind(fd, &SockAddrIn::new([127, 0, 0, 1], 2039)), 0);
+    assert_eq!(set_socket_timeout(fd, SO_RCVTIMEO, 50), 0);
+    let start = get_time();
+    assert_eq!(recv(fd, &mut buf), EAGAIN);
+    assert!(get_time() - start >= 50);
+    close(fd);
+
+    let fd = socket(AF_INET, SOCK_STREAM) as usize;
+    assert_eq!(getsockopt(fd, SOL_SOCKET as usize, SO_REUSEADDR), 0);
+    assert_eq!(setsockopt(fd, SOL_SOCKET as usize, SO_REUSEADDR, 1), 0);
+    assert_eq!(getsockopt(fd, SOL_SOCKET as usize, SO_REUSEADDR), 1);
+    assert_eq!(setsockopt(fd, IPPROTO_TCP, TCP_NODELAY, 1), 0);
+    assert_eq!(getsockopt(fd, IPPROTO_TCP, TCP_NODELAY), 1);
+    close(fd);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    pipe_test();
+    fd_flags_test();
+    socket_test();
+    println!("nonblock passed!");
+    0
+}
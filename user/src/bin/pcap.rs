@@ -0,0 +1,63 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, open, read, write, OpenFlags};
+
+// usage: pcap on|off|dump
+// dump prints the capture as hex, `xxd -r -p` on the host turns the
+// console output back into a file Wireshark opens
+
+const PCAP_DEV: &str = "/dev/pcap\0";
+const BYTES_PER_LINE: usize = 32;
+
+fn toggle(on: bool) -> i32 {
+    let fd = open(PCAP_DEV, OpenFlags::WRONLY);
+    if fd < 0 {
+        println!("can not open {}", PCAP_DEV.trim_end_matches('\0'));
+        return -1;
+    }
+    let ret = write(fd as usize, if on { b"1" } else { b"0" });
+    close(fd as usize);
+    if ret < 0 {
+        -1
+    } else {
+        0
+    }
+}
+
+fn dump() -> i32 {
+    let fd = open(PCAP_DEV, OpenFlags::RDONLY);
+    if fd < 0 {
+        println!("can not open {}", PCAP_DEV.trim_end_matches('\0'));
+        return -1;
+    }
+    let mut buf = [0u8; BYTES_PER_LINE];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        for byte in buf[..len as usize].iter() {
+            print!("{:02x}", byte);
+        }
+        println!("");
+    }
+    close(fd as usize);
+    0
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    match argv.get(1).copied() {
+        Some("on") if argc == 2 => toggle(true),
+        Some("off") if argc == 2 => toggle(false),
+        Some("dump") if argc == 2 => dump(),
+        _ => {
+            println!("usage: pcap on|off|dump");
+            -1
+        }
+    }
+}
@@ -0,0 +1,76 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::convert::TryInto;
+use user_lib::{
+    bind, close, open, read, recv, sendto, socket, write, OpenFlags, SockAddrIn, AF_INET,
+    SOCK_DGRAM,
+};
+
+const LOCALHOST: [u8; 4] = [127, 0, 0, 1];
+const MESSAGE: &[u8] = b"captured on loopback";
+/// ethernet, IPv4 and UDP headers
+const HEADERS_LEN: usize = 14 + 20 + 8;
+
+fn set_capturing(on: bool) {
+    let fd = open("/dev/pcap\0", OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, if on { b"1" } else { b"0" }), 1);
+    close(fd as usize);
+}
+
+fn u32_at(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    set_capturing(true);
+    let fd = socket(AF_INET, SOCK_DGRAM) as usize;
+    assert_eq!(bind(fd, &SockAddrIn::new(LOCALHOST, 7040)), 0);
+    assert_eq!(
+        sendto(fd, MESSAGE, &SockAddrIn::new(LOCALHOST, 7040)),
+        MESSAGE.len() as isize
+    );
+    let mut buf = [0u8; 64];
+    assert_eq!(recv(fd, &mut buf), MESSAGE.len() as isize);
+    close(fd);
+    set_capturing(false);
+
+    let fd = open("/dev/pcap\0", OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut data = [0u8; 4096];
+    let mut len = 0;
+    loop {
+        let n = read(fd as usize, &mut data[len..]);
+        if n <= 0 {
+            break;
+        }
+        len += n as usize;
+    }
+    close(fd as usize);
+
+    // the file header: magic, version 2.4 and ethernet frames
+    assert!(len >= 24);
+    assert_eq!(u32_at(&data, 0), 0xa1b2_c3d4);
+    assert_eq!(&data[4..8], &[2, 0, 4, 0]);
+    assert_eq!(u32_at(&data, 20), 1);
+    // DHCP may have sent something meanwhile, look for the datagram
+    let frame_len = HEADERS_LEN + MESSAGE.len();
+    let mut at = 24;
+    let mut found = false;
+    while at + 16 <= len {
+        let captured = u32_at(&data, at + 8) as usize;
+        let frame = &data[at + 16..(at + 16 + captured).min(len)];
+        if captured == frame_len && u32_at(&data, at + 12) as usize == frame_len {
+            found |= frame.len() == frame_len && &frame[HEADERS_LEN..] == MESSAGE;
+        }
+        at += 16 + captured;
+    }
+    assert!(found);
+    println!("pcap_test passed!");
+    0
+}
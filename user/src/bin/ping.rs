@@ -0,0 +1,141 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, get_time, poll, recvfrom, sendto, sleep, socket_with_protocol, PollEvents, PollFd,
+    SockAddrIn, AF_INET, IPPROTO_ICMP, SOCK_DGRAM,
+};
+
+// usage: ping [ip] [count]
+// sends echo requests through an ICMP datagram socket, one a second
+
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_ECHO_REQUEST: u8 = 8;
+const PAYLOAD_LEN: usize = 56;
+const TIMEOUT_MS: isize = 1000;
+
+fn parse_ip(s: &str) -> Option<[u8; 4]> {
+    let mut ip = [0u8; 4];
+    let mut parts = s.split('.');
+    for byte in ip.iter_mut() {
+        *byte = parts.next()?.parse().ok()?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(ip)
+}
+
+/// Echo request with the send time in the payload. The kernel fills in the
+/// identifier and checksum.
+fn echo_request(seq: u16, now: isize) -> [u8; 8 + PAYLOAD_LEN] {
+    let mut message = [0u8; 8 + PAYLOAD_LEN];
+    message[0] = ICMP_ECHO_REQUEST;
+    message[6..8].copy_from_slice(&seq.to_be_bytes());
+    message[8..16].copy_from_slice(&(now as u64).to_be_bytes());
+    for (i, byte) in message[16..].iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    message
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let ip = if argc > 1 {
+        match parse_ip(argv[1]) {
+            Some(ip) => ip,
+            None => {
+                println!("bad address {}", argv[1]);
+                return -1;
+            }
+        }
+    } else {
+        [10, 0, 2, 2]
+    };
+    let count: u16 = if argc > 2 {
+        match argv[2].parse() {
+            Ok(count) => count,
+            Err(_) => {
+                println!("bad count {}", argv[2]);
+                return -1;
+            }
+        }
+    } else {
+        4
+    };
+
+    let fd = socket_with_protocol(AF_INET, SOCK_DGRAM, IPPROTO_ICMP);
+    if fd < 0 {
+        println!("failed to create an icmp socket: {}", fd);
+        return -1;
+    }
+    let fd = fd as usize;
+    let dest = SockAddrIn::new(ip, 0);
+    println!(
+        "PING {}.{}.{}.{} {} data bytes",
+        ip[0], ip[1], ip[2], ip[3], PAYLOAD_LEN
+    );
+
+    let mut received = 0;
+    for seq in 1..=count {
+        let sent_at = get_time();
+        let request = echo_request(seq, sent_at);
+        let ret = sendto(fd, &request, &dest);
+        if ret < 0 {
+            println!("sendto failed: {}", ret);
+            break;
+        }
+        // wait for our reply, skipping late ones for earlier requests
+        loop {
+            let left = TIMEOUT_MS - (get_time() - sent_at);
+            let mut fds = [PollFd::new(fd, PollEvents::IN)];
+            if left <= 0 || poll(&mut fds, left) <= 0 {
+                println!("request timeout for icmp_seq={}", seq);
+                break;
+            }
+            let mut buf = [0u8; 128];
+            let mut source = SockAddrIn::default();
+            let len = recvfrom(fd, &mut buf, Some(&mut source));
+            if len < 8 || buf[0] != ICMP_ECHO_REPLY || u16::from_be_bytes([buf[6], buf[7]]) != seq {
+                continue;
+            }
+            let from = source.ip();
+            println!(
+                "{} bytes from {}.{}.{}.{}: icmp_seq={} time={} ms",
+                len,
+                from[0],
+                from[1],
+                from[2],
+                from[3],
+                seq,
+                get_time() - sent_at
+            );
+            received += 1;
+            break;
+        }
+        let elapsed = get_time() - sent_at;
+        if seq != count && elapsed < 1000 {
+            sleep((1000 - elapsed) as usize);
+        }
+    }
+    close(fd);
+
+    println!(
+        "{} packets transmitted, {} received, {}% packet loss",
+        count,
+        received,
+        if count == 0 {
+            0
+        } else {
+            (count - received) as usize * 100 / count as usize
+        }
+    );
+    if received == count {
+        0
+    } else {
+        1
+    }
+}
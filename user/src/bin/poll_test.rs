@@ -0,0 +1,96 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, epoll_create, epoll_ctl, epoll_wait, exit, fork, pipe, poll, read, select, sleep,
+    waitpid, write, EpollEvent, FdSet, PollEvents, PollFd, EPOLLHUP, EPOLLIN, EPOLL_CTL_ADD,
+    EPOLL_CTL_DEL,
+};
+
+const ELOOP: isize = 40;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut a = [0usize; 2];
+    let mut b = [0usize; 2];
+    pipe(&mut a);
+    pipe(&mut b);
+
+    // nothing written yet: times out
+    let mut fds = [
+        PollFd::new(a[0], PollEvents::IN),
+        PollFd::new(b[0], PollEvents::IN),
+    ];
+    assert_eq!(poll(&mut fds, 50), 0);
+    // an empty pipe can be written
+    let mut out = [PollFd::new(a[1], PollEvents::OUT)];
+    assert_eq!(poll(&mut out, 0), 1);
+    assert!(out[0].revents().contains(PollEvents::OUT));
+
+    let pid = fork();
+    if pid == 0 {
+        sleep(20);
+        write(b[1], b"b");
+        sleep(20);
+        write(a[1], b"a");
+        exit(0);
+    }
+
+    // the child wakes us up through pipe b first
+    assert_eq!(poll(&mut fds, -1), 1);
+    assert!(fds[0].revents().is_empty());
+    assert!(fds[1].revents().contains(PollEvents::IN));
+    let mut byte = [0u8; 1];
+    assert_eq!(read(b[0], &mut byte), 1);
+
+    let mut readfds = FdSet::default();
+    readfds.set(a[0]);
+    readfds.set(b[0]);
+    assert_eq!(
+        select(a[0].max(b[0]) + 1, Some(&mut readfds), None, None, -1),
+        1
+    );
+    assert!(readfds.is_set(a[0]));
+    assert!(!readfds.is_set(b[0]));
+    assert_eq!(read(a[0], &mut byte), 1);
+    assert_eq!(byte[0], b'a');
+
+    let mut exit_code = 0;
+    waitpid(pid as usize, &mut exit_code);
+
+    let epfd = epoll_create();
+    assert!(epfd > 0);
+    let epfd = epfd as usize;
+    let event = EpollEvent {
+        events: EPOLLIN,
+        data: 42,
+    };
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, a[0], Some(&event)), 0);
+    assert!(epoll_ctl(epfd, EPOLL_CTL_ADD, a[0], Some(&event)) < 0);
+    let mut events = [EpollEvent::default(); 4];
+    assert_eq!(epoll_wait(epfd, &mut events, 10), 0);
+    write(a[1], b"x");
+    assert_eq!(epoll_wait(epfd, &mut events, -1), 1);
+    assert_eq!(events[0].data, 42);
+    assert_eq!(events[0].events & EPOLLIN, EPOLLIN);
+    // level-triggered: still there until read
+    assert_eq!(epoll_wait(epfd, &mut events, 0), 1);
+    assert_eq!(read(a[0], &mut byte), 1);
+    // the last write end going away is a hang-up
+    close(a[1]);
+    assert_eq!(epoll_wait(epfd, &mut events, -1), 1);
+    assert_ne!(events[0].events & EPOLLHUP, 0);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_DEL, a[0], None), 0);
+    // sets nest, but not in a loop
+    let outer = epoll_create() as usize;
+    assert_eq!(epoll_ctl(outer, EPOLL_CTL_ADD, epfd, Some(&event)), 0);
+    assert_eq!(epoll_ctl(epfd, EPOLL_CTL_ADD, outer, Some(&event)), -ELOOP);
+    close(outer);
+    close(epfd);
+
+    println!("poll_test passed!");
+    0
+}
@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::{format, string::String, vec::Vec};
+use user_lib::{close, getdents, open, read, DirEntries, OpenFlags};
+
+/// Whole content of a small file, empty if it can not be opened.
+fn read_file(path: &str) -> String {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return String::new();
+    }
+    let fd = fd as usize;
+    let mut content = String::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let size = read(fd, &mut buf);
+        if size <= 0 {
+            break;
+        }
+        content.push_str(core::str::from_utf8(&buf[..size as usize]).unwrap_or("?"));
+    }
+    close(fd);
+    content
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("/proc\0", OpenFlags::RDONLY);
+    if fd < 0 {
+        println!("ps: cannot open /proc");
+        return -1;
+    }
+    let fd = fd as usize;
+    println!("  PID  PPID S  RSS CMD");
+    let mut buf = [0u8; 512];
+    loop {
+        let size = getdents(fd, &mut buf);
+        if size <= 0 {
+            break;
+        }
+        for (_, name) in DirEntries::new(&buf[..size as usize]) {
+            if name.parse::<usize>().is_err() {
+                continue;
+            }
+            let stat = read_file(format!("/proc/{}/stat\0", name).as_str());
+            // pid (comm) state ppid ... rss is the 24th field
+            let (Some(open), Some(close)) = (stat.find('('), stat.rfind(')')) else {
+                continue;
+            };
+            let comm = &stat[open + 1..close];
+            let fields: Vec<&str> = stat[close + 1..].split_whitespace().collect();
+            if fields.len() < 22 {
+                continue;
+            }
+            println!(
+                "{:>5} {:>5} {} {:>4} {}",
+                name, fields[1], fields[0], fields[21], comm
+            );
+        }
+    }
+    close(fd);
+    0
+}
@@ -0,0 +1,84 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use user_lib::{
+    close, exit, fork, getpid, open, posix_openpt, ptsnum, read, setpgid, tcgetpgrp, tcgetwinsize,
+    tcsetpgrp, tcsetwinsize, unlockpt, waitpid, write, yield_, OpenFlags, WinSize,
+};
+
+const EIO: isize = 5;
+const EAGAIN: isize = 11;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let master = posix_openpt(OpenFlags::RDWR | OpenFlags::NONBLOCK);
+    assert!(master > 0);
+    let master = master as usize;
+    let index = ptsnum(master);
+    assert!(index >= 0);
+    let path = format!("/dev/pts/{}\0", index);
+    // locked until unlockpt
+    assert!(open(path.as_str(), OpenFlags::RDWR) < 0);
+    assert_eq!(unlockpt(master), 0);
+    let slave = open(path.as_str(), OpenFlags::RDWR);
+    assert!(slave > 0);
+    let slave = slave as usize;
+    let mut buf = [0u8; 64];
+    assert_eq!(read(master, &mut buf), -EAGAIN);
+
+    // what the master writes is typed into the terminal, and echoed
+    assert_eq!(write(master, b"hello\n"), 6);
+    assert_eq!(read(slave, &mut buf), 6);
+    assert_eq!(&buf[..6], b"hello\n");
+    assert_eq!(read(master, &mut buf), 7);
+    assert_eq!(&buf[..7], b"hello\r\n");
+    assert_eq!(write(slave, b"out\n"), 4);
+    assert_eq!(read(master, &mut buf), 5);
+    assert_eq!(&buf[..5], b"out\r\n");
+
+    let winsize = WinSize {
+        row: 24,
+        col: 80,
+        xpixel: 0,
+        ypixel: 0,
+    };
+    assert_eq!(tcsetwinsize(master, &winsize), 0);
+    let mut got = WinSize::default();
+    assert_eq!(tcgetwinsize(slave, &mut got), 0);
+    assert_eq!(got, winsize);
+
+    // Ctrl-C from the master ends the foreground group of the terminal
+    let child = fork();
+    if child == 0 {
+        setpgid(0, 0);
+        tcsetpgrp(slave, getpid() as usize);
+        read(slave, &mut buf);
+        exit(0);
+    }
+    while tcgetpgrp(master) != child {
+        yield_();
+    }
+    assert_eq!(write(master, b"\x03"), 1);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    assert_eq!(exit_code, -2);
+    assert_eq!(read(master, &mut buf), 2);
+    assert_eq!(&buf[..2], b"^C");
+
+    // with the terminal end closed the master reads EIO
+    close(slave);
+    assert_eq!(read(master, &mut buf), -EIO);
+    close(master);
+    // and the number is free again
+    let master = posix_openpt(OpenFlags::RDWR) as usize;
+    assert_eq!(ptsnum(master), index);
+    close(master);
+
+    println!("pty_test passed!");
+    0
+}
@@ -0,0 +1,92 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use user_lib::{
+    close, connect, read, shutdown, socket, write, SockAddrIn, AF_INET, SHUT_WR, SOCK_STREAM,
+};
+
+// usage: tcp_client [ip] [port] [message]
+// by default talks to port 6202 on the host side of qemu user networking,
+// e.g. `nc -l 6202` or `python3 -m http.server 6202` there
+
+fn parse_ip(s: &str) -> Option<[u8; 4]> {
+    let mut ip = [0u8; 4];
+    let mut parts = s.split('.');
+    for byte in ip.iter_mut() {
+        *byte = parts.next()?.parse().ok()?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(ip)
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let ip = if argc > 1 {
+        match parse_ip(argv[1]) {
+            Some(ip) => ip,
+            None => {
+                println!("bad address {}", argv[1]);
+                return -1;
+            }
+        }
+    } else {
+        [10, 0, 2, 2]
+    };
+    let port = if argc > 2 {
+        match argv[2].parse() {
+            Ok(port) => port,
+            Err(_) => {
+                println!("bad port {}", argv[2]);
+                return -1;
+            }
+        }
+    } else {
+        6202
+    };
+    let message = if argc > 3 {
+        argv[3]
+    } else {
+        "GET / HTTP/1.0\r\n\r\n"
+    };
+
+    let fd = socket(AF_INET, SOCK_STREAM);
+    if fd < 0 {
+        println!("failed to create a tcp socket");
+        return -1;
+    }
+    let fd = fd as usize;
+    let ret = connect(fd, &SockAddrIn::new(ip, port));
+    if ret < 0 {
+        println!("failed to connect: {}", ret);
+        return -1;
+    }
+    println!(
+        "connected to {}.{}.{}.{}:{}",
+        ip[0], ip[1], ip[2], ip[3], port
+    );
+
+    write(fd, message.as_bytes());
+    // nothing more to send, the server sees EOF
+    shutdown(fd, SHUT_WR);
+
+    let mut buf = [0u8; 1024];
+    loop {
+        let len = read(fd, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        print!(
+            "{}",
+            core::str::from_utf8(&buf[..len as usize]).unwrap_or("?")
+        );
+    }
+    println!("");
+    close(fd);
+    0
+}
@@ -10,7 +10,9 @@ extern crate alloc;
 
 // use http://localhost:6201/ to access the http server
 
-use user_lib::{accept, listen, read, write};
+use user_lib::{
+    accept, bind, close, listen, read, socket, write, SockAddrIn, AF_INET, SOCK_STREAM,
+};
 
 // get url from the tcp request list.
 fn get_url_from_tcp_request(req: &[u8]) -> String {
@@ -126,27 +128,38 @@ fn handle_tcp_client(client_fd: usize) -> bool {
 pub fn main() -> i32 {
     println!("This is a very simple http server");
 
-    let tcp_fd = listen(80);
+    let tcp_fd = socket(AF_INET, SOCK_STREAM);
 
     if tcp_fd < 0 {
+        println!("Failed to create a tcp socket");
+        return -1;
+    }
+    let tcp_fd = tcp_fd as usize;
+
+    if bind(tcp_fd, &SockAddrIn::new([0, 0, 0, 0], 80)) < 0 || listen(tcp_fd, 4) < 0 {
         println!("Failed to listen on port 80");
         return -1;
     }
 
     loop {
-        let client = accept(tcp_fd as usize);
-        println!("client connected: {}", client);
+        let mut peer = SockAddrIn::default();
+        let client = accept(tcp_fd, Some(&mut peer));
 
-        if client < 1 {
+        if client < 0 {
             println!("Failed to accept a client on port 80");
             return -1;
         }
+        let [a, b, c, d] = peer.ip();
+        println!("client connected: {}.{}.{}.{}:{}", a, b, c, d, peer.port());
 
-        if handle_tcp_client(client as usize) {
+        let done = handle_tcp_client(client as usize);
+        close(client as usize);
+        if done {
             break;
         }
     }
 
+    close(tcp_fd);
     println!("finish tcp test");
 
     // String::from_utf8_lossy(&buf[..len as usize])
@@ -0,0 +1,165 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::hint::spin_loop;
+use user_lib::{
+    alarm, close, fork, getitimer, poll, read, setitimer, sleep, timer_create, timer_delete,
+    timer_gettime, timer_settime, timerfd_create, timerfd_gettime, timerfd_settime, waitpid,
+    ITimerSpec, ITimerVal, PollEvents, PollFd, SigEvent, TimeSpec, TimeVal, CLOCK_MONOTONIC,
+    CLOCK_PROCESS_CPUTIME_ID, ITIMER_REAL, ITIMER_VIRTUAL, SIGEV_NONE, TFD_NONBLOCK,
+};
+
+const EAGAIN: isize = 11;
+const EINVAL: isize = 22;
+
+fn ms(ms: u64) -> TimeSpec {
+    TimeSpec {
+        sec: ms / 1000,
+        nsec: ms % 1000 * 1_000_000,
+    }
+}
+
+fn timeval_ms(ms: u64) -> TimeVal {
+    TimeVal {
+        sec: ms / 1000,
+        usec: ms % 1000 * 1000,
+    }
+}
+
+/// What the child `f` runs in exits with, given that it never returns.
+fn killed_by(f: fn()) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        f();
+        panic!("the timer never went off");
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code >> 8
+}
+
+fn timerfd() {
+    let fd = timerfd_create(CLOCK_MONOTONIC, TFD_NONBLOCK);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    let mut count = [0u8; 8];
+    assert_eq!(read(fd, &mut count), -EAGAIN);
+    assert_eq!(read(fd, &mut count[..4]), -EINVAL);
+
+    // every 20ms, starting in 20ms
+    let every_20ms = ITimerSpec {
+        interval: ms(20),
+        value: ms(20),
+    };
+    assert_eq!(timerfd_settime(fd, 0, &every_20ms, None), 0);
+    let mut curr = ITimerSpec::default();
+    assert_eq!(timerfd_gettime(fd, &mut curr), 0);
+    assert!(curr.value.nsec > 0 && curr.value.nsec <= 20_000_000);
+    assert_eq!(curr.interval.nsec, 20_000_000);
+
+    let mut fds = [PollFd::new(fd, PollEvents::IN)];
+    assert_eq!(poll(&mut fds, 1000), 1);
+    assert!(fds[0].revents().contains(PollEvents::IN));
+    assert_eq!(read(fd, &mut count), 8);
+    assert!(u64::from_ne_bytes(count) >= 1);
+    // missed periods add up
+    sleep(70);
+    assert_eq!(read(fd, &mut count), 8);
+    assert!(u64::from_ne_bytes(count) >= 2);
+
+    let disarm = ITimerSpec::default();
+    let mut old = ITimerSpec::default();
+    assert_eq!(timerfd_settime(fd, 0, &disarm, Some(&mut old)), 0);
+    assert_eq!(old.interval.nsec, 20_000_000);
+    assert_eq!(poll(&mut fds, 50), 0);
+    close(fd);
+    assert_eq!(timerfd_create(CLOCK_PROCESS_CPUTIME_ID, 0), -EINVAL);
+}
+
+fn posix_timer() {
+    let sev = SigEvent::new(SIGEV_NONE, 0);
+    let id = timer_create(CLOCK_MONOTONIC, Some(&sev));
+    assert!(id >= 0);
+    let id = id as usize;
+    let in_1s = ITimerSpec {
+        interval: TimeSpec::default(),
+        value: ms(1000),
+    };
+    assert_eq!(timer_settime(id, 0, &in_1s, None), 0);
+    let mut curr = ITimerSpec::default();
+    assert_eq!(timer_gettime(id, &mut curr), 0);
+    assert!(curr.value.sec == 0 && curr.value.nsec > 900_000_000);
+    // a one-shot timer is disarmed once it went off
+    assert_eq!(
+        timer_settime(
+            id,
+            0,
+            &ITimerSpec {
+                value: ms(10),
+                ..in_1s
+            },
+            None
+        ),
+        0
+    );
+    sleep(30);
+    assert_eq!(timer_gettime(id, &mut curr), 0);
+    assert!(curr.value.sec == 0 && curr.value.nsec == 0);
+    assert_eq!(timer_delete(id), 0);
+    assert_eq!(timer_delete(id), -EINVAL);
+    assert_eq!(timer_settime(id, 0, &in_1s, None), -EINVAL);
+}
+
+fn interval_timers() {
+    let mut curr = ITimerVal::default();
+    assert_eq!(getitimer(ITIMER_REAL, &mut curr), 0);
+    assert!(curr.value.sec == 0 && curr.value.usec == 0);
+    assert_eq!(alarm(5), 0);
+    assert_eq!(getitimer(ITIMER_REAL, &mut curr), 0);
+    assert!(curr.value.sec <= 5 && curr.value.sec + curr.value.usec > 0);
+    assert_eq!(alarm(0), 5);
+    let new = ITimerVal::default();
+    assert_eq!(setitimer(3, &new, None), -EINVAL);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    timerfd();
+    posix_timer();
+    interval_timers();
+    // the signals end the process, there are no handlers
+    assert_eq!(
+        killed_by(|| {
+            let in_20ms = ITimerVal {
+                interval: TimeVal::default(),
+                value: timeval_ms(20),
+            };
+            setitimer(ITIMER_REAL, &in_20ms, None);
+            loop {
+                spin_loop();
+            }
+        }),
+        -14
+    );
+    assert_eq!(
+        killed_by(|| {
+            let in_30ms = ITimerVal {
+                interval: TimeVal::default(),
+                value: timeval_ms(30),
+            };
+            setitimer(ITIMER_VIRTUAL, &in_30ms, None);
+            loop {
+                spin_loop();
+            }
+        }),
+        -26
+    );
+    let sev_default = timer_create(CLOCK_MONOTONIC, None);
+    assert!(sev_default >= 0);
+    assert_eq!(timer_delete(sev_default as usize), 0);
+    println!("timer_test passed!");
+    0
+}
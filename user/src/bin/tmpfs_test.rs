@@ -0,0 +1,86 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, ftruncate, getdents, mkdir, mmap_file, mount, munmap, open, read, rmdir, unlink, write,
+    DirEntries, MapProtect, OpenFlags,
+};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mkdir("/tmp/tmpfs_test\0"), 0);
+    assert!(mkdir("/tmp/tmpfs_test\0") < 0);
+
+    let fd = open("/tmp/tmpfs_test/data\0", OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, b"Hello, tmpfs!"), 13);
+    close(fd);
+
+    let fd = open("/tmp/tmpfs_test/data\0", OpenFlags::RDWR) as usize;
+    let mut buffer = [0u8; 32];
+    assert_eq!(read(fd, &mut buffer), 13);
+    assert_eq!(&buffer[..13], b"Hello, tmpfs!");
+
+    // shrink, then grow again: the cut tail must read back as zeros
+    assert_eq!(ftruncate(fd, 5), 0);
+    assert_eq!(ftruncate(fd, 8192), 0);
+    let addr = mmap_file(0, 8192, MapProtect::R | MapProtect::W, fd, 0);
+    assert!(addr > 0);
+    let bytes = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, 8192) };
+    assert_eq!(&bytes[..5], b"Hello");
+    assert!(bytes[5..].iter().all(|b| *b == 0));
+    // the mapping shares pages with the file
+    bytes[4096] = b'!';
+    // not over a mapping that is there already
+    assert!(mmap_file(addr as usize + 4096, 4096, MapProtect::R, fd, 0) < 0);
+    munmap(addr as usize);
+    close(fd);
+
+    let fd = open("/tmp/tmpfs_test/data\0", OpenFlags::RDONLY) as usize;
+    let mut page = [0u8; 4097];
+    assert_eq!(read(fd, &mut page), 4097);
+    assert_eq!(page[4096], b'!');
+    // read-only fd, read-only mapping
+    assert!(mmap_file(0, 4096, MapProtect::R | MapProtect::W, fd, 0) < 0);
+    close(fd);
+
+    let dir = open("/tmp/tmpfs_test\0", OpenFlags::RDONLY);
+    assert!(dir > 0);
+    let mut dents = [0u8; 256];
+    let len = getdents(dir as usize, &mut dents);
+    assert!(len > 0);
+    let mut entries = DirEntries::new(&dents[..len as usize]);
+    assert_eq!(entries.next().map(|(_, name)| name), Some("data"));
+    close(dir as usize);
+
+    assert!(rmdir("/tmp/tmpfs_test\0") < 0);
+    assert_eq!(unlink("/tmp/tmpfs_test/data\0"), 0);
+    assert!(open("/tmp/tmpfs_test/data\0", OpenFlags::RDONLY) < 0);
+    assert_eq!(rmdir("/tmp/tmpfs_test\0"), 0);
+
+    // a two page mount fills up
+    assert!(mount("tmpfs\0", "/tmpfs_test\0", "tmpfs\0", 0, Some("size=1q\0")) < 0);
+    assert_eq!(
+        mount("tmpfs\0", "/tmpfs_test\0", "tmpfs\0", 0, Some("size=8k\0")),
+        0
+    );
+    let fd = open("/tmpfs_test/full\0", OpenFlags::CREATE | OpenFlags::RDWR) as usize;
+    let chunk = [b'x'; 3000];
+    assert_eq!(write(fd, &chunk), 3000);
+    assert_eq!(write(fd, &chunk), 3000);
+    assert_eq!(write(fd, &chunk), 2192);
+    assert!(write(fd, &chunk) < 0);
+    assert!(ftruncate(fd, 16384) < 0);
+    // giving a page back makes room again
+    assert_eq!(ftruncate(fd, 4096), 0);
+    assert_eq!(write(fd, &chunk), 3000);
+    close(fd);
+    assert_eq!(unlink("/tmpfs_test/full\0"), 0);
+
+    println!("tmpfs_test passed!");
+    0
+}
@@ -0,0 +1,80 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, getpgid, getpid, kill, open, setpgid, signal, tcgetattr, tcgetpgrp,
+    tcsetattr, waitpid, OpenFlags, SignalFlags, Termios, ECHO, ICANON, ISIG, SIG_DFL, SIG_IGN,
+    TCSANOW, VERASE, VINTR,
+};
+
+const EPERM: isize = 1;
+const ESRCH: isize = 3;
+const EINVAL: isize = 22;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // the console starts out canonical, with echo and Ctrl-C
+    let mut saved = Termios::default();
+    assert_eq!(tcgetattr(0, &mut saved), 0);
+    assert_eq!(saved.lflag & (ICANON | ECHO | ISIG), ICANON | ECHO | ISIG);
+    assert_eq!(saved.cc[VINTR], 3);
+    assert_eq!(saved.cc[VERASE], 0x7f);
+    let mut raw = saved;
+    raw.lflag &= !(ICANON | ECHO);
+    assert_eq!(tcsetattr(0, TCSANOW, &raw), 0);
+    let mut termios = Termios::default();
+    tcgetattr(0, &mut termios);
+    assert_eq!(termios.lflag & (ICANON | ECHO), 0);
+    assert_eq!(tcsetattr(0, 7, &saved), -EINVAL);
+    assert_eq!(tcsetattr(0, TCSANOW, &saved), 0);
+    assert!(tcgetpgrp(0) >= 0);
+
+    // not a terminal
+    let fd = open("/dev/null\0", OpenFlags::RDWR) as usize;
+    assert!(tcgetattr(fd, &mut termios) < 0);
+    close(fd);
+
+    // process groups
+    let pid = getpid() as usize;
+    assert_eq!(setpgid(0, 0), 0);
+    assert_eq!(getpgid(0), pid as isize);
+    assert_eq!(setpgid(0, 0x7fff_0000), -EPERM);
+    assert_eq!(setpgid(1, 0), -ESRCH);
+    let child = fork();
+    if child == 0 {
+        assert_eq!(getpgid(0), pid as isize);
+        // a group of its own, then back in the parent's
+        assert_eq!(setpgid(0, 0), 0);
+        assert_eq!(setpgid(0, pid), 0);
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    assert_eq!(exit_code, 0);
+
+    // an ignored SIGINT does nothing, a default one ends the process
+    assert_eq!(signal(SignalFlags::SIGINT, SIG_IGN), SIG_DFL as isize);
+    assert_eq!(signal(SignalFlags::SIGINT, SIG_DFL), SIG_IGN as isize);
+    let child = fork();
+    if child == 0 {
+        signal(SignalFlags::SIGINT, SIG_IGN);
+        kill(getpid() as usize, SignalFlags::SIGINT.bits());
+        exit(0);
+    }
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    assert_eq!(exit_code, 0);
+    let child = fork();
+    if child == 0 {
+        kill(getpid() as usize, SignalFlags::SIGINT.bits());
+        exit(0);
+    }
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    assert_eq!(exit_code, -2);
+    assert_eq!(signal(SignalFlags::SIGINT, 0x1000), -EINVAL);
+
+    println!("tty_test passed!");
+    0
+}
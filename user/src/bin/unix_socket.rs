@@ -0,0 +1,120 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    accept, bind_unix, close, connect_unix, exit, fork, listen, pipe, read, recv, recv_fds, send,
+    send_fds, socket, socketpair, unlink, waitpid, write, AF_UNIX, SOCK_DGRAM, SOCK_STREAM,
+};
+
+const SOCKET_PATH: &str = "unix_socket_test\0";
+const MESSAGE: &[u8] = b"hello over a unix socket";
+
+fn pair_test() {
+    let mut sv = [0i32; 2];
+    assert_eq!(socketpair(AF_UNIX, SOCK_STREAM, &mut sv), 0);
+    let (a, b) = (sv[0] as usize, sv[1] as usize);
+    assert_eq!(write(a, MESSAGE), MESSAGE.len() as isize);
+    let mut buf = [0u8; 64];
+    let len = read(b, &mut buf);
+    assert_eq!(&buf[..len as usize], MESSAGE);
+    close(a);
+    // the peer is gone, so the other end reads end of file
+    assert_eq!(read(b, &mut buf), 0);
+    close(b);
+}
+
+/// Datagrams keep their boundaries.
+fn dgram_test() {
+    let mut sv = [0i32; 2];
+    assert_eq!(socketpair(AF_UNIX, SOCK_DGRAM, &mut sv), 0);
+    let (a, b) = (sv[0] as usize, sv[1] as usize);
+    assert_eq!(send(a, b"one"), 3);
+    assert_eq!(send(a, b"two!"), 4);
+    let mut buf = [0u8; 64];
+    assert_eq!(recv(b, &mut buf), 3);
+    assert_eq!(&buf[..3], b"one");
+    assert_eq!(recv(b, &mut buf), 4);
+    assert_eq!(&buf[..4], b"two!");
+    close(a);
+    close(b);
+}
+
+/// Passes the write end of a pipe and writes through the received copy.
+fn rights_test() {
+    let mut sv = [0i32; 2];
+    assert_eq!(socketpair(AF_UNIX, SOCK_STREAM, &mut sv), 0);
+    let (a, b) = (sv[0] as usize, sv[1] as usize);
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(send_fds(a, b"x", &[pipe_fd[1] as i32]), 1);
+    close(pipe_fd[1]);
+
+    let mut buf = [0u8; 64];
+    let mut fds = [-1i32; 4];
+    let (len, count) = recv_fds(b, &mut buf, &mut fds);
+    assert_eq!(len, 1);
+    assert_eq!(count, 1);
+    let writer = fds[0] as usize;
+    assert_eq!(write(writer, MESSAGE), MESSAGE.len() as isize);
+    close(writer);
+    let len = read(pipe_fd[0], &mut buf);
+    assert_eq!(&buf[..len as usize], MESSAGE);
+    close(pipe_fd[0]);
+    close(a);
+    close(b);
+}
+
+/// The child connects by name and checks that it gets back what it sent.
+fn named_test() {
+    let listener = socket(AF_UNIX, SOCK_STREAM) as usize;
+    assert_eq!(bind_unix(listener, SOCKET_PATH), 0);
+    assert_eq!(listen(listener, 1), 0);
+
+    let pid = fork();
+    if pid == 0 {
+        let fd = socket(AF_UNIX, SOCK_STREAM) as usize;
+        assert_eq!(connect_unix(fd, SOCKET_PATH), 0);
+        assert_eq!(write(fd, MESSAGE), MESSAGE.len() as isize);
+        let mut buf = [0u8; 64];
+        let mut len = 0;
+        while len < MESSAGE.len() {
+            let n = read(fd, &mut buf[len..]);
+            assert!(n > 0);
+            len += n as usize;
+        }
+        assert_eq!(&buf[..len], MESSAGE);
+        close(fd);
+        exit(0);
+    }
+
+    let conn = accept(listener, None);
+    assert!(conn >= 0);
+    let conn = conn as usize;
+    let mut buf = [0u8; 64];
+    let mut len = 0;
+    while len < MESSAGE.len() {
+        let n = read(conn, &mut buf[len..]);
+        assert!(n > 0);
+        len += n as usize;
+    }
+    assert_eq!(write(conn, &buf[..len]), len as isize);
+    let mut exit_code = 0;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, 0);
+    close(conn);
+    close(listener);
+    assert!(unlink(SOCKET_PATH) < 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    pair_test();
+    dgram_test();
+    rights_test();
+    named_test();
+    println!("unix_socket passed!");
+    0
+}
@@ -4,42 +4,43 @@
 #[macro_use]
 extern crate user_lib;
 
-use user_lib::{exec, fork, get_time, kill, waitpid, waitpid_nb, SignalFlags};
+use user_lib::{exec, fork, get_time, setitimer, waitpid, ITimerVal, TimeVal, ITIMER_REAL};
+
+/// What a child ended by SIGALRM exits with.
+const SIGALRM_EXIT: i32 = -14;
 
 #[no_mangle]
 pub fn main(argc: usize, argv: &[&str]) -> i32 {
     assert_eq!(argc, 3, "argc must be 3!");
-    let timeout_ms = argv[2]
-        .parse::<isize>()
-        .expect("Error when parsing timeout!");
+    let timeout_ms = argv[2].parse::<u64>().expect("Error when parsing timeout!");
+    let start_time = get_time();
     let pid = fork() as usize;
     if pid == 0 {
+        // the alarm is kept across exec and ends the child once it is due
+        let timeout = ITimerVal {
+            interval: TimeVal::default(),
+            value: TimeVal {
+                sec: timeout_ms / 1000,
+                usec: timeout_ms % 1000 * 1000,
+            },
+        };
+        setitimer(ITIMER_REAL, &timeout, None);
         if exec(argv[1], &[core::ptr::null::<u8>()]) != 0 {
             println!("Error when executing '{}'", argv[1]);
             return -4;
         }
     } else {
-        let start_time = get_time();
-        let mut child_exited = false;
         let mut exit_code: i32 = 0;
-        loop {
-            if get_time() - start_time > timeout_ms {
-                break;
-            }
-            if waitpid_nb(pid, &mut exit_code) as usize == pid {
-                child_exited = true;
-                println!(
-                    "child exited in {}ms, exit_code = {}",
-                    get_time() - start_time,
-                    exit_code,
-                );
-            }
-        }
-        if !child_exited {
-            println!("child has run for {}ms, kill it!", timeout_ms);
-            kill(pid, SignalFlags::SIGINT.bits());
-            assert_eq!(waitpid(pid, &mut exit_code) as usize, pid);
-            println!("exit code of the child is {}", exit_code);
+        assert_eq!(waitpid(pid, &mut exit_code) as usize, pid);
+        // the exit code is kept in the upper bits of the status
+        if exit_code >> 8 == SIGALRM_EXIT {
+            println!("child has run for {}ms, killed by SIGALRM", timeout_ms);
+        } else {
+            println!(
+                "child exited in {}ms, exit_code = {}",
+                get_time() - start_time,
+                exit_code >> 8,
+            );
         }
     }
     0
@@ -1,6 +1,5 @@
 #![no_std]
 #![no_main]
-#![allow(clippy::println_empty_string)]
 
 extern crate alloc;
 
@@ -9,14 +8,15 @@ extern crate user_lib;
 
 const LF: u8 = 0x0au8;
 const CR: u8 = 0x0du8;
-const DL: u8 = 0x7fu8;
-const BS: u8 = 0x08u8;
 const LINE_START: &str = ">> ";
 
 use alloc::string::String;
 use alloc::vec::Vec;
 use user_lib::console::getchar;
-use user_lib::{close, dup, exec, fork, open, pipe, waitpid, OpenFlags};
+use user_lib::{
+    close, dup, exec, fork, getpid, open, pipe, setpgid, signal, tcsetpgrp, waitpid, OpenFlags,
+    SignalFlags, SIG_DFL, SIG_IGN,
+};
 
 #[derive(Debug)]
 struct ProcessArguments {
@@ -77,13 +77,19 @@ impl ProcessArguments {
 #[no_mangle]
 pub fn main() -> i32 {
     println!("Rust user shell");
+    // the shell owns the terminal between jobs and outlives Ctrl-C
+    let shell_pgid = getpid() as usize;
+    setpgid(0, 0);
+    tcsetpgrp(0, shell_pgid);
+    signal(SignalFlags::SIGINT, SIG_IGN);
+    signal(SignalFlags::SIGQUIT, SIG_IGN);
+    // the terminal echoes and edits the line, it arrives once Enter is hit
     let mut line: String = String::new();
     print!("{}", LINE_START);
     loop {
         let c = getchar();
         match c {
             LF | CR => {
-                println!("");
                 if !line.is_empty() {
                     let splited: Vec<_> = line.as_str().split('|').collect();
                     let process_arguments_list: Vec<_> = splited
@@ -122,8 +128,13 @@ pub fn main() -> i32 {
                         }
                         let mut children: Vec<_> = Vec::new();
                         for (i, process_argument) in process_arguments_list.iter().enumerate() {
+                            // a job is a process group, led by its first process
+                            let job_pgid = children.first().copied().unwrap_or(0) as usize;
                             let pid = fork();
                             if pid == 0 {
+                                setpgid(0, job_pgid);
+                                signal(SignalFlags::SIGINT, SIG_DFL);
+                                signal(SignalFlags::SIGQUIT, SIG_DFL);
                                 let input = &process_argument.input;
                                 let output = &process_argument.output;
                                 let args_copy = &process_argument.args_copy;
@@ -179,6 +190,8 @@ pub fn main() -> i32 {
                                 }
                                 unreachable!();
                             } else {
+                                // set here as well, whichever runs first
+                                setpgid(pid as usize, job_pgid);
                                 children.push(pid);
                             }
                         }
@@ -186,29 +199,24 @@ pub fn main() -> i32 {
                             close(pipe_fd[0]);
                             close(pipe_fd[1]);
                         }
+                        if let Some(&job_pgid) = children.first() {
+                            tcsetpgrp(0, job_pgid as usize);
+                        }
                         let mut exit_code: i32 = 0;
                         for pid in children.into_iter() {
                             let exit_pid = waitpid(pid as usize, &mut exit_code);
                             assert_eq!(pid, exit_pid);
                             //println!("Shell: Process {} exited with code {}", pid, exit_code);
                         }
+                        tcsetpgrp(0, shell_pgid);
                     }
                     line.clear();
                 }
                 print!("{}", LINE_START);
             }
-            BS | DL => {
-                if !line.is_empty() {
-                    print!("{}", BS as char);
-                    print!(" ");
-                    print!("{}", BS as char);
-                    line.pop();
-                }
-            }
-            _ => {
-                print!("{}", c as char);
-                line.push(c as char);
-            }
+            // end of file, there is nothing to run
+            0 => {}
+            _ => line.push(c as char),
         }
     }
 }
@@ -11,6 +11,20 @@ extern crate user_lib;
 static SUCC_TESTS: &[(&str, &str, &str, &str, i32)] = &[
     ("filetest_simple\0", "\0", "\0", "\0", 0),
     ("cat\0", "filea\0", "\0", "\0", 0),
+    ("devfs_test\0", "\0", "\0", "\0", 0),
+    ("tmpfs_test\0", "\0", "\0", "\0", 0),
+    ("poll_test\0", "\0", "\0", "\0", 0),
+    ("net_loopback\0", "\0", "\0", "\0", 0),
+    ("ping\0", "127.0.0.1\0", "2\0", "\0", 0),
+    ("ifconfig\0", "lo\0", "\0", "\0", 0),
+    ("unix_socket\0", "\0", "\0", "\0", 0),
+    ("nonblock\0", "\0", "\0", "\0", 0),
+    ("pcap_test\0", "\0", "\0", "\0", 0),
+    ("getrandom_test\0", "\0", "\0", "\0", 0),
+    ("tty_test\0", "\0", "\0", "\0", 0),
+    ("pty_test\0", "\0", "\0", "\0", 0),
+    ("clock_test\0", "\0", "\0", "\0", 0),
+    ("timer_test\0", "\0", "\0", "\0", 0),
     ("cmdline_args\0", "1\0", "2\0", "3\0", 0),
     ("eisenberg\0", "\0", "\0", "\0", 0),
     ("exit\0", "\0", "\0", "\0", 0),
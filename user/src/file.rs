@@ -7,20 +7,74 @@ bitflags! {
         const RDWR = 1 << 1;
         const CREATE = 1 << 9;
         const TRUNC = 1 << 10;
+        const NONBLOCK = 1 << 11;
+        const CLOEXEC = 1 << 19;
     }
 }
 
+pub const AT_FDCWD: isize = -100;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFBLK: u32 = 0o060000;
+pub const AT_REMOVEDIR: u32 = 0x200;
+
+pub const F_DUPFD: usize = 0;
+pub const F_GETFD: usize = 1;
+pub const F_SETFD: usize = 2;
+pub const F_GETFL: usize = 3;
+pub const F_SETFL: usize = 4;
+pub const F_DUPFD_CLOEXEC: usize = 1030;
+pub const FD_CLOEXEC: usize = 1;
+
+/// Linux `dev_t` encoding of a device number.
+pub fn makedev(major: u32, minor: u32) -> u64 {
+    let (major, minor) = (major as u64, minor as u64);
+    (minor & 0xff) | ((major & 0xfff) << 8) | ((minor & !0xff) << 12)
+}
+
 pub fn dup(fd: usize) -> isize {
     sys_dup(fd)
 }
 pub fn open(path: &str, flags: OpenFlags) -> isize {
     sys_open(path, flags.bits)
 }
+pub fn ioctl(fd: usize, cmd: u32, arg: usize) -> isize {
+    sys_ioctl(fd, cmd, arg)
+}
+pub fn mknod(path: &str, mode: u32, dev: u64) -> isize {
+    sys_mknodat(AT_FDCWD, path, mode, dev)
+}
+/// Fill `buf` with `linux_dirent64` records of directory `fd`, see `DirEntries`.
+pub fn getdents(fd: usize, buf: &mut [u8]) -> isize {
+    sys_getdents64(fd, buf)
+}
+pub fn mkdir(path: &str) -> isize {
+    sys_mkdirat(AT_FDCWD, path, 0o755)
+}
+pub fn unlink(path: &str) -> isize {
+    sys_unlinkat(AT_FDCWD, path, 0)
+}
+pub fn rmdir(path: &str) -> isize {
+    sys_unlinkat(AT_FDCWD, path, AT_REMOVEDIR)
+}
+pub fn ftruncate(fd: usize, len: usize) -> isize {
+    sys_ftruncate(fd, len)
+}
+/// `data` is the option string, nul terminated like the paths.
+pub fn mount(source: &str, target: &str, fstype: &str, flags: usize, data: Option<&str>) -> isize {
+    sys_mount(source, target, fstype, flags, data)
+}
 pub fn close(fd: usize) -> isize {
     sys_close(fd)
 }
 pub fn pipe(pipe_fd: &mut [usize]) -> isize {
-    sys_pipe(pipe_fd)
+    sys_pipe(pipe_fd, 0)
+}
+/// `pipe` taking `NONBLOCK` and `CLOEXEC`.
+pub fn pipe2(pipe_fd: &mut [usize], flags: OpenFlags) -> isize {
+    sys_pipe(pipe_fd, flags.bits)
+}
+pub fn fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    sys_fcntl(fd, cmd, arg)
 }
 pub fn read(fd: usize, buf: &mut [u8]) -> isize {
     sys_read(fd, buf)
@@ -28,3 +82,31 @@ pub fn read(fd: usize, buf: &mut [u8]) -> isize {
 pub fn write(fd: usize, buf: &[u8]) -> isize {
     sys_write(fd, buf)
 }
+
+/// Walks the `linux_dirent64` records `getdents` put into a buffer,
+/// yielding `(d_type, name)`.
+pub struct DirEntries<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> DirEntries<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+}
+
+impl<'a> Iterator for DirEntries<'a> {
+    type Item = (u8, &'a str);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.len() < 19 {
+            return None;
+        }
+        let reclen = u16::from_le_bytes([self.buf[16], self.buf[17]]) as usize;
+        let d_type = self.buf[18];
+        let name = &self.buf[19..reclen];
+        let name_len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+        let name = core::str::from_utf8(&name[..name_len]).unwrap();
+        self.buf = &self.buf[reclen..];
+        Some((d_type, name))
+    }
+}
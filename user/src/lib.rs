@@ -9,9 +9,11 @@ mod file;
 mod io;
 mod lang_items;
 mod net;
+mod poll;
 mod sync;
 mod syscall;
 mod task;
+mod tty;
 mod mm;
 
 extern crate alloc;
@@ -23,12 +25,14 @@ use buddy_system_allocator::LockedHeap;
 pub use file::*;
 pub use io::*;
 pub use net::*;
+pub use poll::*;
 pub use sync::*;
 use syscall::*;
 pub use task::*;
+pub use tty::*;
 pub use mm::*;
 
-pub use syscall::MapProtect;
+pub use syscall::{ITimerSpec, ITimerVal, MapProtect, SigAction, SigEvent, TimeSpec, TimeVal};
 
 const USER_HEAP_SIZE: usize = 32768;
 
@@ -5,8 +5,18 @@ pub fn sbrk(size: isize) -> isize {
     sys_sbrk(size)
 }
 
+const MAP_SHARED: usize = 0x01;
+const MAP_PRIVATE: usize = 0x02;
+const MAP_ANONYMOUS: usize = 0x20;
+
 pub fn mmap(addr: usize, len: usize, prot: MapProtect) -> isize {
-    sys_mmap(addr, len, prot)
+    sys_mmap(addr, len, prot, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0)
+}
+
+/// Map `len` bytes of `fd` from `offset`, `addr` 0 lets the kernel choose.
+/// Returns the mapped address.
+pub fn mmap_file(addr: usize, len: usize, prot: MapProtect, fd: usize, offset: usize) -> isize {
+    sys_mmap(addr, len, prot, MAP_SHARED, fd as isize, offset)
 }
 
 pub fn munmap(addr: usize) -> isize {
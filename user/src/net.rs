@@ -1,13 +1,329 @@
 use super::*;
+use core::convert::TryInto;
 
-pub fn connect(ip: u32, sport: u16, dport: u16) -> isize {
-    sys_connect(ip, sport, dport)
+pub const AF_UNIX: usize = 1;
+pub const AF_INET: usize = 2;
+pub const SOCK_STREAM: usize = 1;
+pub const SOCK_DGRAM: usize = 2;
+pub const SOCK_RAW: usize = 3;
+/// Or'ed into the type of `socket` and `socketpair`.
+pub const SOCK_NONBLOCK: usize = 0o4000;
+pub const SOCK_CLOEXEC: usize = 0o2000000;
+
+pub const IPPROTO_ICMP: usize = 1;
+pub const IPPROTO_TCP: usize = 6;
+
+pub const SO_REUSEADDR: usize = 2;
+pub const SO_RCVTIMEO: usize = 20;
+pub const SO_SNDTIMEO: usize = 21;
+pub const TCP_NODELAY: usize = 1;
+
+pub const SHUT_RD: usize = 0;
+pub const SHUT_WR: usize = 1;
+pub const SHUT_RDWR: usize = 2;
+
+pub const SIOCGIFCONF: u32 = 0x8912;
+pub const SIOCGIFFLAGS: u32 = 0x8913;
+pub const SIOCGIFADDR: u32 = 0x8915;
+pub const SIOCSIFADDR: u32 = 0x8916;
+pub const SIOCGIFNETMASK: u32 = 0x891b;
+pub const SIOCSIFNETMASK: u32 = 0x891c;
+pub const SIOCGIFHWADDR: u32 = 0x8927;
+
+pub const IFF_UP: u16 = 0x1;
+pub const IFF_BROADCAST: u16 = 0x2;
+pub const IFF_LOOPBACK: u16 = 0x8;
+pub const IFF_RUNNING: u16 = 0x40;
+
+/// `struct sockaddr_in`, port and address are kept in network byte order.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SockAddrIn {
+    family: u16,
+    port: u16,
+    addr: u32,
+    zero: [u8; 8],
+}
+
+impl SockAddrIn {
+    pub fn new(ip: [u8; 4], port: u16) -> Self {
+        Self {
+            family: AF_INET as u16,
+            port: port.to_be(),
+            addr: u32::from_ne_bytes(ip),
+            zero: [0; 8],
+        }
+    }
+
+    pub fn ip(&self) -> [u8; 4] {
+        self.addr.to_ne_bytes()
+    }
+
+    pub fn port(&self) -> u16 {
+        u16::from_be(self.port)
+    }
+}
+
+/// `struct sockaddr_un` with a path name.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SockAddrUn {
+    family: u16,
+    path: [u8; 108],
+}
+
+impl SockAddrUn {
+    pub fn new(path: &str) -> Self {
+        let mut addr = Self {
+            family: AF_UNIX as u16,
+            path: [0; 108],
+        };
+        let len = path.len().min(107);
+        addr.path[..len].copy_from_slice(&path.as_bytes()[..len]);
+        addr
+    }
+}
+
+/// `struct iovec`.
+#[repr(C)]
+pub struct IoVec {
+    pub base: usize,
+    pub len: usize,
+}
+
+/// `struct msghdr`.
+#[repr(C)]
+pub struct MsgHdr {
+    pub name: usize,
+    pub namelen: u32,
+    pub iov: *const IoVec,
+    pub iovlen: usize,
+    pub control: usize,
+    pub controllen: usize,
+    pub flags: i32,
+}
+
+pub const SOL_SOCKET: i32 = 1;
+pub const SCM_RIGHTS: i32 = 1;
+pub const MSG_CTRUNC: i32 = 0x8;
+pub const MSG_TRUNC: i32 = 0x20;
+/// Size of `struct cmsghdr`, the data follows it.
+const CMSG_HDR_LEN: usize = 16;
+/// Room for this many fds in the control data of `send_fds` and `recv_fds`.
+const MAX_FDS: usize = 16;
+
+pub fn socket(domain: usize, ty: usize) -> isize {
+    sys_socket(domain, ty, 0)
+}
+
+/// `socket` with a protocol other than the default of its type.
+pub fn socket_with_protocol(domain: usize, ty: usize, protocol: usize) -> isize {
+    sys_socket(domain, ty, protocol)
+}
+
+pub fn bind(fd: usize, addr: &SockAddrIn) -> isize {
+    sys_bind(fd, addr)
+}
+
+/// Bind a unix socket to `path`.
+pub fn bind_unix(fd: usize, path: &str) -> isize {
+    sys_bind_un(fd, &SockAddrUn::new(path))
+}
+
+/// Two connected unix sockets.
+pub fn socketpair(domain: usize, ty: usize, sv: &mut [i32; 2]) -> isize {
+    sys_socketpair(domain, ty, sv)
+}
+
+pub fn listen(fd: usize, backlog: usize) -> isize {
+    sys_listen(fd, backlog)
+}
+
+/// Returns the fd of the new connection, the peer is stored in `addr`.
+pub fn accept(fd: usize, addr: Option<&mut SockAddrIn>) -> isize {
+    sys_accept4(fd, addr, 0)
+}
+
+pub fn connect(fd: usize, addr: &SockAddrIn) -> isize {
+    sys_connect(fd, addr)
+}
+
+/// Connect a unix socket to the one bound to `path`.
+pub fn connect_unix(fd: usize, path: &str) -> isize {
+    sys_connect_un(fd, &SockAddrUn::new(path))
+}
+
+pub fn send(fd: usize, buf: &[u8]) -> isize {
+    sys_sendto(fd, buf, 0, None)
+}
+
+pub fn sendto(fd: usize, buf: &[u8], addr: &SockAddrIn) -> isize {
+    sys_sendto(fd, buf, 0, Some(addr))
+}
+
+pub fn recv(fd: usize, buf: &mut [u8]) -> isize {
+    sys_recvfrom(fd, buf, 0, None)
+}
+
+pub fn recvfrom(fd: usize, buf: &mut [u8], addr: Option<&mut SockAddrIn>) -> isize {
+    sys_recvfrom(fd, buf, 0, addr)
+}
+
+pub fn shutdown(fd: usize, how: usize) -> isize {
+    sys_shutdown(fd, how)
+}
+
+/// Send `buf` with copies of the open files `fds` through a unix socket.
+pub fn send_fds(fd: usize, buf: &[u8], fds: &[i32]) -> isize {
+    let fds = &fds[..fds.len().min(MAX_FDS)];
+    let mut control = [0u8; CMSG_HDR_LEN + MAX_FDS * 4];
+    let len = CMSG_HDR_LEN + fds.len() * 4;
+    control[..8].copy_from_slice(&len.to_ne_bytes());
+    control[8..12].copy_from_slice(&SOL_SOCKET.to_ne_bytes());
+    control[12..16].copy_from_slice(&SCM_RIGHTS.to_ne_bytes());
+    for (i, fd) in fds.iter().enumerate() {
+        let at = CMSG_HDR_LEN + i * 4;
+        control[at..at + 4].copy_from_slice(&fd.to_ne_bytes());
+    }
+    let iov = IoVec {
+        base: buf.as_ptr() as usize,
+        len: buf.len(),
+    };
+    let msg = MsgHdr {
+        name: 0,
+        namelen: 0,
+        iov: &iov,
+        iovlen: 1,
+        control: control.as_ptr() as usize,
+        controllen: len,
+        flags: 0,
+    };
+    sys_sendmsg(fd, &msg, 0)
+}
+
+/// Receive into `buf`, the fds of files sent along go to `fds`. Returns the
+/// length received and how many fds there are.
+pub fn recv_fds(fd: usize, buf: &mut [u8], fds: &mut [i32]) -> (isize, usize) {
+    let mut control = [0u8; CMSG_HDR_LEN + MAX_FDS * 4];
+    let iov = IoVec {
+        base: buf.as_mut_ptr() as usize,
+        len: buf.len(),
+    };
+    let mut msg = MsgHdr {
+        name: 0,
+        namelen: 0,
+        iov: &iov,
+        iovlen: 1,
+        control: control.as_mut_ptr() as usize,
+        controllen: CMSG_HDR_LEN + fds.len().min(MAX_FDS) * 4,
+        flags: 0,
+    };
+    let ret = sys_recvmsg(fd, &mut msg, 0);
+    if ret < 0 || msg.controllen < CMSG_HDR_LEN {
+        return (ret, 0);
+    }
+    let len = usize::from_ne_bytes(control[..8].try_into().unwrap());
+    let count = (len - CMSG_HDR_LEN) / 4;
+    for (i, out) in fds.iter_mut().take(count).enumerate() {
+        let at = CMSG_HDR_LEN + i * 4;
+        *out = i32::from_ne_bytes(control[at..at + 4].try_into().unwrap());
+    }
+    (ret, count)
+}
+
+pub fn getsockname(fd: usize, addr: &mut SockAddrIn) -> isize {
+    sys_getsockname(fd, addr)
+}
+
+pub fn getpeername(fd: usize, addr: &mut SockAddrIn) -> isize {
+    sys_getpeername(fd, addr)
+}
+
+/// Set an option that takes an `int`, such as `SO_REUSEADDR`.
+pub fn setsockopt(fd: usize, level: usize, name: usize, value: i32) -> isize {
+    sys_setsockopt(fd, level, name, &value.to_ne_bytes())
+}
+
+/// The `int` value of an option, or the negative error.
+pub fn getsockopt(fd: usize, level: usize, name: usize) -> isize {
+    let mut value = [0u8; 4];
+    match sys_getsockopt(fd, level, name, &mut value) {
+        4 => i32::from_ne_bytes(value) as isize,
+        ret if ret < 0 => ret,
+        _ => -1,
+    }
+}
+
+/// `SO_RCVTIMEO` or `SO_SNDTIMEO` in ms, 0 waits forever.
+pub fn set_socket_timeout(fd: usize, name: usize, ms: usize) -> isize {
+    let mut timeval = [0u8; 16];
+    timeval[..8].copy_from_slice(&((ms / 1000) as i64).to_ne_bytes());
+    timeval[8..].copy_from_slice(&((ms % 1000 * 1000) as i64).to_ne_bytes());
+    sys_setsockopt(fd, SOL_SOCKET as usize, name, &timeval)
+}
+
+/// `struct ifreq`, the name of an interface and what is asked about it:
+/// an address, its flags or its hardware address.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IfReq {
+    name: [u8; 16],
+    data: [u8; 24],
+}
+
+impl IfReq {
+    pub fn new(name: &str) -> Self {
+        let mut req = Self {
+            name: [0; 16],
+            data: [0; 24],
+        };
+        let len = name.len().min(15);
+        req.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        req
+    }
+
+    pub fn name(&self) -> &str {
+        let len = self.name.iter().position(|&c| c == 0).unwrap_or(16);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("?")
+    }
+
+    pub fn addr(&self) -> [u8; 4] {
+        [self.data[4], self.data[5], self.data[6], self.data[7]]
+    }
+
+    pub fn set_addr(&mut self, ip: [u8; 4]) {
+        self.data = [0; 24];
+        self.data[..2].copy_from_slice(&(AF_INET as u16).to_ne_bytes());
+        self.data[4..8].copy_from_slice(&ip);
+    }
+
+    pub fn flags(&self) -> u16 {
+        u16::from_ne_bytes([self.data[0], self.data[1]])
+    }
+
+    pub fn hw_addr(&self) -> [u8; 6] {
+        let mut hw_addr = [0; 6];
+        hw_addr.copy_from_slice(&self.data[2..8]);
+        hw_addr
+    }
 }
 
-pub fn listen(sport: u16) -> isize {
-    sys_listen(sport)
+/// `struct ifconf`.
+#[repr(C)]
+struct IfConf {
+    len: i32,
+    buf: usize,
 }
 
-pub fn accept(socket_fd: usize) -> isize {
-    sys_accept(socket_fd)
+/// Fill `reqs` with the interfaces and their addresses through any socket
+/// `fd`, returns how many there are.
+pub fn interfaces(fd: usize, reqs: &mut [IfReq]) -> isize {
+    let mut conf = IfConf {
+        len: core::mem::size_of_val(reqs) as i32,
+        buf: reqs.as_mut_ptr() as usize,
+    };
+    match ioctl(fd, SIOCGIFCONF, &mut conf as *mut IfConf as usize) {
+        0 => conf.len as isize / core::mem::size_of::<IfReq>() as isize,
+        err => err,
+    }
 }
@@ -0,0 +1,104 @@
+use super::*;
+
+bitflags! {
+    pub struct PollEvents: i16 {
+        const IN = 0x001;
+        const PRI = 0x002;
+        const OUT = 0x004;
+        const ERR = 0x008;
+        const HUP = 0x010;
+        const NVAL = 0x020;
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PollFd {
+    pub fd: i32,
+    events: i16,
+    revents: i16,
+}
+
+impl PollFd {
+    pub fn new(fd: usize, events: PollEvents) -> Self {
+        Self {
+            fd: fd as i32,
+            events: events.bits(),
+            revents: 0,
+        }
+    }
+    pub fn revents(&self) -> PollEvents {
+        PollEvents::from_bits_truncate(self.revents)
+    }
+}
+
+/// `fd_set` for up to 1024 fds.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct FdSet([u64; 16]);
+
+impl FdSet {
+    pub fn set(&mut self, fd: usize) {
+        self.0[fd / 64] |= 1 << (fd % 64);
+    }
+    pub fn is_set(&self, fd: usize) -> bool {
+        self.0[fd / 64] & (1 << (fd % 64)) != 0
+    }
+}
+
+pub const EPOLL_CTL_ADD: usize = 1;
+pub const EPOLL_CTL_DEL: usize = 2;
+pub const EPOLL_CTL_MOD: usize = 3;
+pub const EPOLLIN: u32 = 0x001;
+pub const EPOLLOUT: u32 = 0x004;
+pub const EPOLLHUP: u32 = 0x010;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct EpollEvent {
+    pub events: u32,
+    pub data: u64,
+}
+
+fn timespec(timeout_ms: isize) -> Option<TimeSpec> {
+    if timeout_ms < 0 {
+        return None;
+    }
+    Some(TimeSpec {
+        sec: timeout_ms as u64 / 1000,
+        nsec: timeout_ms as u64 % 1000 * 1_000_000,
+    })
+}
+
+/// `timeout_ms` < 0 waits forever. Returns how many fds have events.
+pub fn poll(fds: &mut [PollFd], timeout_ms: isize) -> isize {
+    sys_ppoll(fds, timespec(timeout_ms).as_ref())
+}
+
+pub fn select(
+    nfds: usize,
+    readfds: Option<&mut FdSet>,
+    writefds: Option<&mut FdSet>,
+    exceptfds: Option<&mut FdSet>,
+    timeout_ms: isize,
+) -> isize {
+    sys_pselect6(
+        nfds,
+        readfds,
+        writefds,
+        exceptfds,
+        timespec(timeout_ms).as_ref(),
+    )
+}
+
+pub fn epoll_create() -> isize {
+    sys_epoll_create1(0)
+}
+
+pub fn epoll_ctl(epfd: usize, op: usize, fd: usize, event: Option<&EpollEvent>) -> isize {
+    sys_epoll_ctl(epfd, op, fd, event)
+}
+
+pub fn epoll_wait(epfd: usize, events: &mut [EpollEvent], timeout_ms: isize) -> isize {
+    sys_epoll_pwait(epfd, events, timeout_ms)
+}
@@ -1,6 +1,10 @@
 extern crate shared_defination;
 use shared_defination::syscall_nr::call;
 
+use core::mem::size_of;
+
+use super::{EpollEvent, FdSet, MsgHdr, PollFd, SockAddrIn, SockAddrUn};
+
 bitflags! {
     pub struct MapProtect: u8{
         const R = 1 << 0;
@@ -9,12 +13,66 @@ bitflags! {
     }
 }
 
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
 pub struct TimeVal {
     pub sec: u64,  // 自 Unix 纪元起的秒数
-    #[allow(dead_code)]
     pub usec: u64, // 微秒数
 }
 
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct TimeSpec {
+    pub sec: u64,
+    pub nsec: u64,
+}
+
+/// For `setitimer`: the timer goes off after `value`, then every
+/// `interval`. A zero `value` disarms it.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct ITimerVal {
+    pub interval: TimeVal,
+    pub value: TimeVal,
+}
+
+/// `ITimerVal` for POSIX timers and timerfds.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct ITimerSpec {
+    pub interval: TimeSpec,
+    pub value: TimeSpec,
+}
+
+/// How a POSIX timer tells that it went off, the start of `struct sigevent`.
+#[repr(C)]
+pub struct SigEvent {
+    pub value: u64,
+    pub signo: i32,
+    pub notify: i32,
+    _pad: [u64; 6],
+}
+
+impl SigEvent {
+    pub fn new(notify: i32, signo: i32) -> Self {
+        Self {
+            value: 0,
+            signo,
+            notify,
+            _pad: [0; 6],
+        }
+    }
+}
+
+/// `struct sigaction`. Only `SIG_DFL` and `SIG_IGN` can be set as handler.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct SigAction {
+    pub handler: usize,
+    pub flags: usize,
+    pub mask: u64,
+}
+
 fn syscall(id: usize, args: [usize; 3]) -> isize {
     let mut ret: isize;
     unsafe {
@@ -29,36 +87,294 @@ fn syscall(id: usize, args: [usize; 3]) -> isize {
     ret
 }
 
+fn syscall6(id: usize, args: [usize; 6]) -> isize {
+    let mut ret: isize;
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("x10") args[0] => ret,
+            in("x11") args[1],
+            in("x12") args[2],
+            in("x13") args[3],
+            in("x14") args[4],
+            in("x15") args[5],
+            in("x17") id
+        );
+    }
+    ret
+}
+
 pub fn sys_dup(fd: usize) -> isize {
     syscall(call::DUP3, [fd, 0, 0])
 }
 
-pub fn sys_connect(dest: u32, sport: u16, dport: u16) -> isize {
+pub fn sys_socket(domain: usize, ty: usize, protocol: usize) -> isize {
+    syscall(call::SOCKET, [domain, ty, protocol])
+}
+
+pub fn sys_bind(fd: usize, addr: &SockAddrIn) -> isize {
+    syscall(
+        call::BIND,
+        [fd, addr as *const _ as usize, size_of::<SockAddrIn>()],
+    )
+}
+
+pub fn sys_bind_un(fd: usize, addr: &SockAddrUn) -> isize {
+    syscall(
+        call::BIND,
+        [fd, addr as *const _ as usize, size_of::<SockAddrUn>()],
+    )
+}
+
+pub fn sys_socketpair(domain: usize, ty: usize, sv: &mut [i32; 2]) -> isize {
+    syscall6(
+        call::SOCKETPAIR,
+        [domain, ty, 0, sv.as_mut_ptr() as usize, 0, 0],
+    )
+}
+
+pub fn sys_listen(fd: usize, backlog: usize) -> isize {
+    syscall(call::LISTEN, [fd, backlog, 0])
+}
+
+pub fn sys_accept4(fd: usize, addr: Option<&mut SockAddrIn>, flags: usize) -> isize {
+    let mut addrlen = size_of::<SockAddrIn>() as u32;
+    syscall6(
+        call::ACCEPT4,
+        [
+            fd,
+            addr.map_or(0, |addr| addr as *mut _ as usize),
+            &mut addrlen as *mut u32 as usize,
+            flags,
+            0,
+            0,
+        ],
+    )
+}
+
+pub fn sys_connect(fd: usize, addr: &SockAddrIn) -> isize {
+    syscall(
+        call::CONNECT,
+        [fd, addr as *const _ as usize, size_of::<SockAddrIn>()],
+    )
+}
+
+pub fn sys_connect_un(fd: usize, addr: &SockAddrUn) -> isize {
     syscall(
         call::CONNECT,
-        [dest as usize, sport as usize, dport as usize],
+        [fd, addr as *const _ as usize, size_of::<SockAddrUn>()],
+    )
+}
+
+pub fn sys_sendmsg(fd: usize, msg: &MsgHdr, flags: usize) -> isize {
+    syscall(call::SENDMSG, [fd, msg as *const _ as usize, flags])
+}
+
+pub fn sys_recvmsg(fd: usize, msg: &mut MsgHdr, flags: usize) -> isize {
+    syscall(call::RECVMSG, [fd, msg as *mut _ as usize, flags])
+}
+
+pub fn sys_sendto(fd: usize, buf: &[u8], flags: usize, addr: Option<&SockAddrIn>) -> isize {
+    syscall6(
+        call::SENDTO,
+        [
+            fd,
+            buf.as_ptr() as usize,
+            buf.len(),
+            flags,
+            addr.map_or(0, |addr| addr as *const _ as usize),
+            size_of::<SockAddrIn>(),
+        ],
     )
 }
 
-// just listen for tcp connections now
-pub fn sys_listen(sport: u16) -> isize {
-    syscall(call::LISTEN, [sport as usize, 0, 0])
+pub fn sys_recvfrom(
+    fd: usize,
+    buf: &mut [u8],
+    flags: usize,
+    addr: Option<&mut SockAddrIn>,
+) -> isize {
+    let mut addrlen = size_of::<SockAddrIn>() as u32;
+    syscall6(
+        call::RECVFROM,
+        [
+            fd,
+            buf.as_mut_ptr() as usize,
+            buf.len(),
+            flags,
+            addr.map_or(0, |addr| addr as *mut _ as usize),
+            &mut addrlen as *mut u32 as usize,
+        ],
+    )
 }
 
-pub fn sys_accept(socket_fd: usize) -> isize {
-    syscall(call::ACCEPT, [socket_fd, 0, 0])
+pub fn sys_shutdown(fd: usize, how: usize) -> isize {
+    syscall(call::SHUTDOWN, [fd, how, 0])
+}
+
+pub fn sys_getsockname(fd: usize, addr: &mut SockAddrIn) -> isize {
+    let mut addrlen = size_of::<SockAddrIn>() as u32;
+    syscall(
+        call::GETSOCKNAME,
+        [fd, addr as *mut _ as usize, &mut addrlen as *mut u32 as usize],
+    )
+}
+
+pub fn sys_getpeername(fd: usize, addr: &mut SockAddrIn) -> isize {
+    let mut addrlen = size_of::<SockAddrIn>() as u32;
+    syscall(
+        call::GETPEERNAME,
+        [fd, addr as *mut _ as usize, &mut addrlen as *mut u32 as usize],
+    )
+}
+
+pub fn sys_setsockopt(fd: usize, level: usize, name: usize, value: &[u8]) -> isize {
+    syscall6(
+        call::SETSOCKOPT,
+        [fd, level, name, value.as_ptr() as usize, value.len(), 0],
+    )
+}
+
+pub fn sys_getsockopt(fd: usize, level: usize, name: usize, value: &mut [u8]) -> isize {
+    let mut optlen = value.len() as u32;
+    let ret = syscall6(
+        call::GETSOCKOPT,
+        [
+            fd,
+            level,
+            name,
+            value.as_mut_ptr() as usize,
+            &mut optlen as *mut u32 as usize,
+            0,
+        ],
+    );
+    if ret < 0 {
+        ret
+    } else {
+        optlen as isize
+    }
 }
 
 pub fn sys_open(path: &str, flags: u32) -> isize {
     syscall(call::OPENAT, [path.as_ptr() as usize, flags as usize, 0])
 }
 
+pub fn sys_ioctl(fd: usize, cmd: u32, arg: usize) -> isize {
+    syscall(call::IOCTL, [fd, cmd as usize, arg])
+}
+
+pub fn sys_mknodat(dirfd: isize, path: &str, mode: u32, dev: u64) -> isize {
+    syscall6(
+        call::MKNODAT,
+        [dirfd as usize, path.as_ptr() as usize, mode as usize, dev as usize, 0, 0],
+    )
+}
+
+pub fn sys_getdents64(fd: usize, buf: &mut [u8]) -> isize {
+    syscall(call::GETDENTS64, [fd, buf.as_mut_ptr() as usize, buf.len()])
+}
+
+pub fn sys_mkdirat(dirfd: isize, path: &str, mode: u32) -> isize {
+    syscall(call::MKDIRAT, [dirfd as usize, path.as_ptr() as usize, mode as usize])
+}
+
+pub fn sys_unlinkat(dirfd: isize, path: &str, flags: u32) -> isize {
+    syscall(call::UNLINKAT, [dirfd as usize, path.as_ptr() as usize, flags as usize])
+}
+
+pub fn sys_ftruncate(fd: usize, len: usize) -> isize {
+    syscall(call::FTRUNCATE, [fd, len, 0])
+}
+
+pub fn sys_mount(
+    source: &str,
+    target: &str,
+    fstype: &str,
+    flags: usize,
+    data: Option<&str>,
+) -> isize {
+    syscall6(
+        call::MOUNT,
+        [
+            source.as_ptr() as usize,
+            target.as_ptr() as usize,
+            fstype.as_ptr() as usize,
+            flags,
+            data.map_or(0, |data| data.as_ptr() as usize),
+            0,
+        ],
+    )
+}
+
+pub fn sys_ppoll(fds: &mut [PollFd], timeout: Option<&TimeSpec>) -> isize {
+    syscall6(
+        call::PPOLL,
+        [
+            fds.as_mut_ptr() as usize,
+            fds.len(),
+            timeout.map_or(0, |t| t as *const _ as usize),
+            0,
+            0,
+            0,
+        ],
+    )
+}
+
+pub fn sys_pselect6(
+    nfds: usize,
+    readfds: Option<&mut FdSet>,
+    writefds: Option<&mut FdSet>,
+    exceptfds: Option<&mut FdSet>,
+    timeout: Option<&TimeSpec>,
+) -> isize {
+    syscall6(
+        call::PSELECT6,
+        [
+            nfds,
+            readfds.map_or(0, |set| set as *mut _ as usize),
+            writefds.map_or(0, |set| set as *mut _ as usize),
+            exceptfds.map_or(0, |set| set as *mut _ as usize),
+            timeout.map_or(0, |t| t as *const _ as usize),
+            0,
+        ],
+    )
+}
+
+pub fn sys_epoll_create1(flags: usize) -> isize {
+    syscall(call::EPOLL_CREATE1, [flags, 0, 0])
+}
+
+pub fn sys_epoll_ctl(epfd: usize, op: usize, fd: usize, event: Option<&EpollEvent>) -> isize {
+    syscall6(
+        call::EPOLL_CTL,
+        [epfd, op, fd, event.map_or(0, |e| e as *const _ as usize), 0, 0],
+    )
+}
+
+pub fn sys_epoll_pwait(epfd: usize, events: &mut [EpollEvent], timeout: isize) -> isize {
+    syscall6(
+        call::EPOLL_PWAIT,
+        [
+            epfd,
+            events.as_mut_ptr() as usize,
+            events.len(),
+            timeout as usize,
+            0,
+            0,
+        ],
+    )
+}
+
 pub fn sys_close(fd: usize) -> isize {
     syscall(call::CLOSE, [fd, 0, 0])
 }
 
-pub fn sys_pipe(pipe: &mut [usize]) -> isize {
-    syscall(call::PIPE2, [pipe.as_mut_ptr() as usize, 0, 0])
+pub fn sys_pipe(pipe: &mut [usize], flags: u32) -> isize {
+    syscall(call::PIPE2, [pipe.as_mut_ptr() as usize, flags as usize, 0])
+}
+
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(call::FCNTL, [fd, cmd, arg])
 }
 
 pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize {
@@ -74,8 +390,109 @@ pub fn sys_exit(exit_code: i32) -> ! {
     panic!("sys_exit never returns!");
 }
 
-pub fn sys_sleep(time: &TimeVal) -> isize {
-    syscall(call::NANOSLEEP, [time as *const TimeVal as usize, 0, 0])
+pub fn sys_sleep(time: &TimeSpec) -> isize {
+    syscall(call::NANOSLEEP, [time as *const TimeSpec as usize, 0, 0])
+}
+
+pub fn sys_clock_gettime(clock: usize, time: &mut TimeSpec) -> isize {
+    syscall(call::CLOCK_GETTIME, [clock, time as *mut TimeSpec as usize, 0])
+}
+
+pub fn sys_clock_getres(clock: usize, res: &mut TimeSpec) -> isize {
+    syscall(call::CLOCK_GETRES, [clock, res as *mut TimeSpec as usize, 0])
+}
+
+pub fn sys_clock_nanosleep(clock: usize, flags: usize, time: &TimeSpec) -> isize {
+    syscall(
+        call::CLOCK_NANOSLEEP,
+        [clock, flags, time as *const TimeSpec as usize],
+    )
+}
+
+pub fn sys_getitimer(which: usize, curr: &mut ITimerVal) -> isize {
+    syscall(call::GETITIMER, [which, curr as *mut ITimerVal as usize, 0])
+}
+
+pub fn sys_setitimer(which: usize, new: &ITimerVal, old: Option<&mut ITimerVal>) -> isize {
+    syscall(
+        call::SETITIMER,
+        [
+            which,
+            new as *const ITimerVal as usize,
+            old.map_or(0, |old| old as *mut _ as usize),
+        ],
+    )
+}
+
+pub fn sys_timer_create(clock: usize, sev: Option<&SigEvent>, timerid: &mut i32) -> isize {
+    syscall(
+        call::TIMER_CREATE,
+        [
+            clock,
+            sev.map_or(0, |sev| sev as *const _ as usize),
+            timerid as *mut i32 as usize,
+        ],
+    )
+}
+
+pub fn sys_timer_settime(
+    timerid: usize,
+    flags: usize,
+    new: &ITimerSpec,
+    old: Option<&mut ITimerSpec>,
+) -> isize {
+    syscall6(
+        call::TIMER_SETTIME,
+        [
+            timerid,
+            flags,
+            new as *const ITimerSpec as usize,
+            old.map_or(0, |old| old as *mut _ as usize),
+            0,
+            0,
+        ],
+    )
+}
+
+pub fn sys_timer_gettime(timerid: usize, curr: &mut ITimerSpec) -> isize {
+    syscall(
+        call::TIMER_GETTIME,
+        [timerid, curr as *mut ITimerSpec as usize, 0],
+    )
+}
+
+pub fn sys_timer_delete(timerid: usize) -> isize {
+    syscall(call::TIMER_DELETE, [timerid, 0, 0])
+}
+
+pub fn sys_timerfd_create(clock: usize, flags: u32) -> isize {
+    syscall(call::TIMERFD_CREATE, [clock, flags as usize, 0])
+}
+
+pub fn sys_timerfd_settime(
+    fd: usize,
+    flags: usize,
+    new: &ITimerSpec,
+    old: Option<&mut ITimerSpec>,
+) -> isize {
+    syscall6(
+        call::TIMERFD_SETTIME,
+        [
+            fd,
+            flags,
+            new as *const ITimerSpec as usize,
+            old.map_or(0, |old| old as *mut _ as usize),
+            0,
+            0,
+        ],
+    )
+}
+
+pub fn sys_timerfd_gettime(fd: usize, curr: &mut ITimerSpec) -> isize {
+    syscall(
+        call::TIMERFD_GETTIME,
+        [fd, curr as *mut ITimerSpec as usize, 0],
+    )
 }
 
 pub fn sys_yield() -> isize {
@@ -86,13 +503,36 @@ pub fn sys_kill(pid: usize, signal: i32) -> isize {
     syscall(call::KILL, [pid, signal as usize, 0])
 }
 
+pub fn sys_sigaction(
+    signum: usize,
+    act: Option<&SigAction>,
+    oldact: Option<&mut SigAction>,
+) -> isize {
+    syscall(
+        call::RT_SIGACTION,
+        [
+            signum,
+            act.map_or(0, |act| act as *const SigAction as usize),
+            oldact.map_or(0, |oldact| oldact as *mut SigAction as usize),
+        ],
+    )
+}
+
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    syscall(call::SETPGID, [pid, pgid, 0])
+}
+
+pub fn sys_getpgid(pid: usize) -> isize {
+    syscall(call::GETPGID, [pid, 0, 0])
+}
+
 pub fn sys_get_time() -> isize {
     let mut time: TimeVal = TimeVal { sec: 0, usec: 0 };
     syscall(
         call::GETTIMEOFDAY,
         [&mut time as *mut TimeVal as usize, 0, 0],
     );
-    return (time.sec as isize) * 1000;
+    return (time.sec as isize) * 1000 + (time.usec as isize) / 1000;
 }
 
 pub fn sys_getpid() -> isize {
@@ -122,6 +562,13 @@ pub fn sys_gettid() -> isize {
     syscall(call::GETTID, [0; 3])
 }
 
+pub fn sys_getrandom(buf: &mut [u8], flags: u32) -> isize {
+    syscall(
+        call::GETRANDOM,
+        [buf.as_mut_ptr() as usize, buf.len(), flags as usize],
+    )
+}
+
 pub fn sys_waittid(tid: usize) -> isize {
     syscall(call::WAITID, [tid, 0, 0])
 }
@@ -182,8 +629,18 @@ pub fn sys_sbrk(size: isize) -> isize {
     syscall(call::BRK, [size as usize, 0, 0])
 }
 
-pub fn sys_mmap(addr: usize, len: usize, prot: MapProtect) -> isize {
-    syscall(call::MMAP, [addr, len, prot.bits() as usize])
+pub fn sys_mmap(
+    addr: usize,
+    len: usize,
+    prot: MapProtect,
+    flags: usize,
+    fd: isize,
+    offset: usize,
+) -> isize {
+    syscall6(
+        call::MMAP,
+        [addr, len, prot.bits() as usize, flags, fd as usize, offset],
+    )
 }
 
 pub fn sys_munmap(addr: usize) -> isize {
@@ -12,6 +12,15 @@ pub fn get_time() -> isize {
 pub fn getpid() -> isize {
     sys_getpid()
 }
+/// Flags for `getrandom`. Neither changes anything here: the kernel
+/// generator is always seeded and there is only one.
+pub const GRND_NONBLOCK: u32 = 1;
+pub const GRND_RANDOM: u32 = 2;
+
+/// Fill `buf` with random bytes from the kernel.
+pub fn getrandom(buf: &mut [u8], flags: u32) -> isize {
+    sys_getrandom(buf, flags)
+}
 pub fn fork() -> isize {
     sys_fork()
 }
@@ -34,10 +43,15 @@ pub fn waitpid_nb(pid: usize, exit_code: &mut i32) -> isize {
 bitflags! {
     pub struct SignalFlags: i32 {
         const SIGINT    = 1 << 2;
+        const SIGQUIT   = 1 << 3;
         const SIGILL    = 1 << 4;
         const SIGABRT   = 1 << 6;
         const SIGFPE    = 1 << 8;
         const SIGSEGV   = 1 << 11;
+        const SIGALRM   = 1 << 14;
+        const SIGTSTP   = 1 << 20;
+        const SIGVTALRM = 1 << 26;
+        const SIGPROF   = 1 << 27;
     }
 }
 
@@ -45,14 +59,144 @@ pub fn kill(pid: usize, signal: i32) -> isize {
     sys_kill(pid, signal)
 }
 
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+/// Set `signal` to `SIG_DFL` or `SIG_IGN`, returning what it was.
+pub fn signal(signal: SignalFlags, handler: usize) -> isize {
+    let act = SigAction {
+        handler,
+        ..SigAction::default()
+    };
+    let mut old = SigAction::default();
+    match sys_sigaction(signal.bits().trailing_zeros() as usize, Some(&act), Some(&mut old)) {
+        0 => old.handler as isize,
+        err => err,
+    }
+}
+
+/// Move `pid` (0 for the caller) to process group `pgid` (0 for one of
+/// its own).
+pub fn setpgid(pid: usize, pgid: usize) -> isize {
+    sys_setpgid(pid, pgid)
+}
+
+pub fn getpgid(pid: usize) -> isize {
+    sys_getpgid(pid)
+}
+
 pub fn sleep(sleep_ms: usize) {
-    let time = TimeVal {
+    let time = TimeSpec {
         sec: (sleep_ms as u64) / 1000,
-        usec: (sleep_ms as u64 % 1000) * 1000,
+        nsec: (sleep_ms as u64 % 1000) * 1_000_000,
     };
     sys_sleep(&time);
 }
 
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+pub const CLOCK_PROCESS_CPUTIME_ID: usize = 2;
+pub const CLOCK_THREAD_CPUTIME_ID: usize = 3;
+/// For `clock_nanosleep`, `time` is when to wake up instead of how long
+/// to sleep.
+pub const TIMER_ABSTIME: usize = 1;
+
+pub fn clock_gettime(clock: usize, time: &mut TimeSpec) -> isize {
+    sys_clock_gettime(clock, time)
+}
+
+pub fn clock_getres(clock: usize, res: &mut TimeSpec) -> isize {
+    sys_clock_getres(clock, res)
+}
+
+pub fn clock_nanosleep(clock: usize, flags: usize, time: &TimeSpec) -> isize {
+    sys_clock_nanosleep(clock, flags, time)
+}
+
+/// `ITIMER_REAL` raises SIGALRM, `ITIMER_VIRTUAL` SIGVTALRM and
+/// `ITIMER_PROF` SIGPROF. With no signal handlers, any of them ends the
+/// process. They are kept across `exec`.
+pub const ITIMER_REAL: usize = 0;
+pub const ITIMER_VIRTUAL: usize = 1;
+pub const ITIMER_PROF: usize = 2;
+
+pub fn setitimer(which: usize, new: &ITimerVal, old: Option<&mut ITimerVal>) -> isize {
+    sys_setitimer(which, new, old)
+}
+
+pub fn getitimer(which: usize, curr: &mut ITimerVal) -> isize {
+    sys_getitimer(which, curr)
+}
+
+/// SIGALRM in `seconds`, 0 cancels. Returns the seconds that were left of
+/// the previous alarm.
+pub fn alarm(seconds: usize) -> usize {
+    let new = ITimerVal {
+        interval: TimeVal::default(),
+        value: TimeVal {
+            sec: seconds as u64,
+            usec: 0,
+        },
+    };
+    let mut old = ITimerVal::default();
+    sys_setitimer(ITIMER_REAL, &new, Some(&mut old));
+    // a partly used second counts as one, so a pending alarm is never 0
+    (old.value.sec + (old.value.usec != 0) as u64) as usize
+}
+
+pub const SIGEV_SIGNAL: i32 = 0;
+pub const SIGEV_NONE: i32 = 1;
+
+/// A POSIX timer on `clock`, `None` raising SIGALRM. Returns its id.
+pub fn timer_create(clock: usize, sev: Option<&SigEvent>) -> isize {
+    let mut timerid = 0;
+    match sys_timer_create(clock, sev, &mut timerid) {
+        0 => timerid as isize,
+        err => err,
+    }
+}
+
+/// `flags` may be `TIMER_ABSTIME`.
+pub fn timer_settime(
+    timerid: usize,
+    flags: usize,
+    new: &ITimerSpec,
+    old: Option<&mut ITimerSpec>,
+) -> isize {
+    sys_timer_settime(timerid, flags, new, old)
+}
+
+pub fn timer_gettime(timerid: usize, curr: &mut ITimerSpec) -> isize {
+    sys_timer_gettime(timerid, curr)
+}
+
+pub fn timer_delete(timerid: usize) -> isize {
+    sys_timer_delete(timerid)
+}
+
+pub const TFD_NONBLOCK: u32 = 0o4000;
+pub const TFD_CLOEXEC: u32 = 0o2000000;
+pub const TFD_TIMER_ABSTIME: usize = 1;
+
+/// A timer read as a file: each read gives the expirations since the last
+/// one as a `u64`, and it polls readable while there were any.
+pub fn timerfd_create(clock: usize, flags: u32) -> isize {
+    sys_timerfd_create(clock, flags)
+}
+
+pub fn timerfd_settime(
+    fd: usize,
+    flags: usize,
+    new: &ITimerSpec,
+    old: Option<&mut ITimerSpec>,
+) -> isize {
+    sys_timerfd_settime(fd, flags, new, old)
+}
+
+pub fn timerfd_gettime(fd: usize, curr: &mut ITimerSpec) -> isize {
+    sys_timerfd_gettime(fd, curr)
+}
+
 pub fn thread_create(entry: usize, arg: usize) -> isize {
     sys_thread_create(entry, arg)
 }
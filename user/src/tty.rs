@@ -0,0 +1,126 @@
+use super::*;
+
+const EINVAL: isize = 22;
+
+pub const NCCS: usize = 19;
+
+// c_lflag
+pub const ISIG: u32 = 0o1;
+pub const ICANON: u32 = 0o2;
+pub const ECHO: u32 = 0o10;
+
+// c_cc
+pub const VINTR: usize = 0;
+pub const VERASE: usize = 2;
+pub const VEOF: usize = 4;
+pub const VTIME: usize = 5;
+pub const VMIN: usize = 6;
+
+pub const TCGETS: u32 = 0x5401;
+pub const TCSETS: u32 = 0x5402;
+pub const TCSETSW: u32 = 0x5403;
+pub const TCSETSF: u32 = 0x5404;
+pub const TIOCGPGRP: u32 = 0x540f;
+pub const TIOCSPGRP: u32 = 0x5410;
+pub const TIOCGWINSZ: u32 = 0x5413;
+pub const TIOCSWINSZ: u32 = 0x5414;
+pub const TIOCGPTN: u32 = 0x80045430;
+pub const TIOCSPTLCK: u32 = 0x40045431;
+
+/// For `tcsetattr`: change now, once output is written, or once output is
+/// written and unread input dropped.
+pub const TCSANOW: usize = 0;
+pub const TCSADRAIN: usize = 1;
+pub const TCSAFLUSH: usize = 2;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Termios {
+    pub iflag: u32,
+    pub oflag: u32,
+    pub cflag: u32,
+    pub lflag: u32,
+    pub line: u8,
+    pub cc: [u8; NCCS],
+}
+
+pub fn tcgetattr(fd: usize, termios: &mut Termios) -> isize {
+    ioctl(fd, TCGETS, termios as *mut Termios as usize)
+}
+
+pub fn tcsetattr(fd: usize, action: usize, termios: &Termios) -> isize {
+    let cmd = match action {
+        TCSANOW => TCSETS,
+        TCSADRAIN => TCSETSW,
+        TCSAFLUSH => TCSETSF,
+        _ => return -EINVAL,
+    };
+    ioctl(fd, cmd, termios as *const Termios as usize)
+}
+
+/// The size a terminal is drawn at, which the kernel only keeps.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct WinSize {
+    pub row: u16,
+    pub col: u16,
+    pub xpixel: u16,
+    pub ypixel: u16,
+}
+
+pub fn tcgetwinsize(fd: usize, winsize: &mut WinSize) -> isize {
+    ioctl(fd, TIOCGWINSZ, winsize as *mut WinSize as usize)
+}
+
+/// The foreground process group gets SIGWINCH if the size changed.
+pub fn tcsetwinsize(fd: usize, winsize: &WinSize) -> isize {
+    ioctl(fd, TIOCSWINSZ, winsize as *const WinSize as usize)
+}
+
+/// A new pseudo-terminal, returning the fd of its master end. Its
+/// terminal end `/dev/pts/N` can be opened after `unlockpt`.
+pub fn posix_openpt(flags: OpenFlags) -> isize {
+    open("/dev/ptmx\0", flags)
+}
+
+pub fn unlockpt(fd: usize) -> isize {
+    let lock = 0i32;
+    ioctl(fd, TIOCSPTLCK, &lock as *const i32 as usize)
+}
+
+/// The number N of the pseudo-terminal whose master is `fd`.
+pub fn ptsnum(fd: usize) -> isize {
+    let mut index = 0u32;
+    match ioctl(fd, TIOCGPTN, &mut index as *mut u32 as usize) {
+        0 => index as isize,
+        err => err,
+    }
+}
+
+/// The foreground process group of terminal `fd`, 0 if there is none.
+pub fn tcgetpgrp(fd: usize) -> isize {
+    let mut pgid = 0i32;
+    match ioctl(fd, TIOCGPGRP, &mut pgid as *mut i32 as usize) {
+        0 => pgid as isize,
+        err => err,
+    }
+}
+
+/// Make `pgid` the group that reads terminal `fd` and gets its signals.
+pub fn tcsetpgrp(fd: usize, pgid: usize) -> isize {
+    let pgid = pgid as i32;
+    ioctl(fd, TIOCSPGRP, &pgid as *const i32 as usize)
+}
+
+/// Unbuffered input without echo, one byte at a time, as for games. Returns
+/// the settings to restore.
+pub fn set_raw_mode(fd: usize) -> Termios {
+    let mut termios = Termios::default();
+    tcgetattr(fd, &mut termios);
+    let saved = termios;
+    termios.lflag &= !(ICANON | ECHO);
+    termios.cc[VMIN] = 1;
+    termios.cc[VTIME] = 0;
+    tcsetattr(fd, TCSANOW, &termios);
+    saved
+}